@@ -5,6 +5,158 @@ mod error;
 pub use self::error::{Error, ErrorKind};
 
 use crate::{asymmetric, authentication, ecdh, ecdsa, hmac, opaque, otp, rsa, template, wrap};
+use serde::{de, ser, Deserialize, Serialize};
+use std::{fmt, str::FromStr};
+
+/// Names of algorithms, as used by `yubihsm-shell` and Yubico's documentation
+const NAMES: &[(&str, Algorithm)] = &[
+    (
+        "rsa-pkcs1-sha1",
+        Algorithm::Rsa(rsa::Algorithm::Pkcs1(rsa::pkcs1::Algorithm::Sha1)),
+    ),
+    (
+        "rsa-pkcs1-sha256",
+        Algorithm::Rsa(rsa::Algorithm::Pkcs1(rsa::pkcs1::Algorithm::Sha256)),
+    ),
+    (
+        "rsa-pkcs1-sha384",
+        Algorithm::Rsa(rsa::Algorithm::Pkcs1(rsa::pkcs1::Algorithm::Sha384)),
+    ),
+    (
+        "rsa-pkcs1-sha512",
+        Algorithm::Rsa(rsa::Algorithm::Pkcs1(rsa::pkcs1::Algorithm::Sha512)),
+    ),
+    (
+        "rsa-pss-sha1",
+        Algorithm::Rsa(rsa::Algorithm::Pss(rsa::pss::Algorithm::Sha1)),
+    ),
+    (
+        "rsa-pss-sha256",
+        Algorithm::Rsa(rsa::Algorithm::Pss(rsa::pss::Algorithm::Sha256)),
+    ),
+    (
+        "rsa-pss-sha384",
+        Algorithm::Rsa(rsa::Algorithm::Pss(rsa::pss::Algorithm::Sha384)),
+    ),
+    (
+        "rsa-pss-sha512",
+        Algorithm::Rsa(rsa::Algorithm::Pss(rsa::pss::Algorithm::Sha512)),
+    ),
+    (
+        "rsa2048",
+        Algorithm::Asymmetric(asymmetric::Algorithm::Rsa2048),
+    ),
+    (
+        "rsa3072",
+        Algorithm::Asymmetric(asymmetric::Algorithm::Rsa3072),
+    ),
+    (
+        "rsa4096",
+        Algorithm::Asymmetric(asymmetric::Algorithm::Rsa4096),
+    ),
+    (
+        "ecp256",
+        Algorithm::Asymmetric(asymmetric::Algorithm::EcP256),
+    ),
+    (
+        "ecp384",
+        Algorithm::Asymmetric(asymmetric::Algorithm::EcP384),
+    ),
+    (
+        "ecp521",
+        Algorithm::Asymmetric(asymmetric::Algorithm::EcP521),
+    ),
+    (
+        "eck256",
+        Algorithm::Asymmetric(asymmetric::Algorithm::EcK256),
+    ),
+    (
+        "ecbp256",
+        Algorithm::Asymmetric(asymmetric::Algorithm::EcBp256),
+    ),
+    (
+        "ecbp384",
+        Algorithm::Asymmetric(asymmetric::Algorithm::EcBp384),
+    ),
+    (
+        "ecbp512",
+        Algorithm::Asymmetric(asymmetric::Algorithm::EcBp512),
+    ),
+    ("hmac-sha1", Algorithm::Hmac(hmac::Algorithm::Sha1)),
+    ("hmac-sha256", Algorithm::Hmac(hmac::Algorithm::Sha256)),
+    ("hmac-sha384", Algorithm::Hmac(hmac::Algorithm::Sha384)),
+    ("hmac-sha512", Algorithm::Hmac(hmac::Algorithm::Sha512)),
+    ("ecdsa-sha1", Algorithm::Ecdsa(ecdsa::Algorithm::Sha1)),
+    ("ecdh", Algorithm::Ecdh(ecdh::Algorithm::Ecdh)),
+    (
+        "rsa-oaep-sha1",
+        Algorithm::Rsa(rsa::Algorithm::Oaep(rsa::oaep::Algorithm::Sha1)),
+    ),
+    (
+        "rsa-oaep-sha256",
+        Algorithm::Rsa(rsa::Algorithm::Oaep(rsa::oaep::Algorithm::Sha256)),
+    ),
+    (
+        "rsa-oaep-sha384",
+        Algorithm::Rsa(rsa::Algorithm::Oaep(rsa::oaep::Algorithm::Sha384)),
+    ),
+    (
+        "rsa-oaep-sha512",
+        Algorithm::Rsa(rsa::Algorithm::Oaep(rsa::oaep::Algorithm::Sha512)),
+    ),
+    (
+        "aes128-ccm-wrap",
+        Algorithm::Wrap(wrap::Algorithm::Aes128Ccm),
+    ),
+    ("opaque-data", Algorithm::Opaque(opaque::Algorithm::Data)),
+    (
+        "opaque-x509-certificate",
+        Algorithm::Opaque(opaque::Algorithm::X509Certificate),
+    ),
+    ("mgf1-sha1", Algorithm::Mgf(rsa::mgf::Algorithm::Sha1)),
+    ("mgf1-sha256", Algorithm::Mgf(rsa::mgf::Algorithm::Sha256)),
+    ("mgf1-sha384", Algorithm::Mgf(rsa::mgf::Algorithm::Sha384)),
+    ("mgf1-sha512", Algorithm::Mgf(rsa::mgf::Algorithm::Sha512)),
+    (
+        "template-ssh",
+        Algorithm::Template(template::Algorithm::Ssh),
+    ),
+    (
+        "aes128-yubico-otp",
+        Algorithm::YubicoOtp(otp::Algorithm::Aes128),
+    ),
+    (
+        "aes128-yubico-authentication",
+        Algorithm::Authentication(authentication::Algorithm::YubicoAes),
+    ),
+    (
+        "aes192-yubico-otp",
+        Algorithm::YubicoOtp(otp::Algorithm::Aes192),
+    ),
+    (
+        "aes256-yubico-otp",
+        Algorithm::YubicoOtp(otp::Algorithm::Aes256),
+    ),
+    (
+        "aes192-ccm-wrap",
+        Algorithm::Wrap(wrap::Algorithm::Aes192Ccm),
+    ),
+    (
+        "aes256-ccm-wrap",
+        Algorithm::Wrap(wrap::Algorithm::Aes256Ccm),
+    ),
+    ("ecdsa-sha256", Algorithm::Ecdsa(ecdsa::Algorithm::Sha256)),
+    ("ecdsa-sha384", Algorithm::Ecdsa(ecdsa::Algorithm::Sha384)),
+    ("ecdsa-sha512", Algorithm::Ecdsa(ecdsa::Algorithm::Sha512)),
+    (
+        "ed25519",
+        Algorithm::Asymmetric(asymmetric::Algorithm::Ed25519),
+    ),
+    (
+        "ecp224",
+        Algorithm::Asymmetric(asymmetric::Algorithm::EcP224),
+    ),
+];
 
 /// Cryptographic algorithm types supported by the `YubiHSM 2`
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
@@ -86,6 +238,15 @@ impl Algorithm {
         }
     }
 
+    /// Name of this algorithm, as used by `yubihsm-shell` (e.g. `ecp256`)
+    pub fn name(self) -> &'static str {
+        NAMES
+            .iter()
+            .find(|&&(_, alg)| alg == self)
+            .map(|&(name, _)| name)
+            .expect("algorithm without a name")
+    }
+
     /// Get `asymmetric::Algorithm`
     pub fn asymmetric(self) -> Option<asymmetric::Algorithm> {
         match self {
@@ -175,7 +336,69 @@ impl Algorithm {
     }
 }
 
-impl_algorithm_serializers!(Algorithm);
+impl fmt::Display for Algorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Algorithm {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        NAMES
+            .iter()
+            .find(|&&(name, _)| name == s)
+            .map(|&(_, alg)| alg)
+            .ok_or_else(|| format_err!(ErrorKind::NameInvalid, "unknown algorithm: {:?}", s).into())
+    }
+}
+
+/// Algorithms are serialized as their names in human-readable formats
+/// (e.g. JSON), and as their tag byte otherwise
+impl Serialize for Algorithm {
+    fn serialize<S: ser::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.serialize_str(self.name())
+        } else {
+            serializer.serialize_u8(self.to_u8())
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Algorithm {
+    fn deserialize<D: de::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct AlgorithmVisitor;
+
+        impl de::Visitor<'_> for AlgorithmVisitor {
+            type Value = Algorithm;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+                formatter.write_str("an algorithm name or tag byte")
+            }
+
+            fn visit_str<E: de::Error>(self, value: &str) -> Result<Algorithm, E> {
+                value.parse().map_err(E::custom)
+            }
+
+            fn visit_u8<E: de::Error>(self, value: u8) -> Result<Algorithm, E> {
+                Algorithm::from_u8(value).map_err(E::custom)
+            }
+
+            fn visit_u64<E: de::Error>(self, value: u64) -> Result<Algorithm, E> {
+                u8::try_from(value)
+                    .map_err(|_| E::custom(format!("unknown algorithm ID: {value}")))
+                    .and_then(|byte| self.visit_u8(byte))
+            }
+        }
+
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_any(AlgorithmVisitor)
+        } else {
+            deserializer.deserialize_u8(AlgorithmVisitor)
+        }
+    }
+}
 
 impl From<asymmetric::Algorithm> for Algorithm {
     fn from(alg: asymmetric::Algorithm) -> Algorithm {
@@ -349,4 +572,14 @@ mod tests {
             assert_eq!(*tag, alg.to_u8());
         }
     }
+
+    #[test]
+    fn test_names() {
+        for (_, alg) in ALGORITHM_MAPPING {
+            assert_eq!(*alg, alg.to_string().parse().unwrap());
+        }
+
+        assert_eq!(NAMES.len(), ALGORITHM_MAPPING.len());
+        assert!("ed448".parse::<Algorithm>().is_err());
+    }
 }
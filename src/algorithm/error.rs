@@ -12,6 +12,10 @@ pub enum ErrorKind {
     /// Invalid algorithm tag
     #[error("invalid tag")]
     TagInvalid,
+
+    /// Invalid algorithm name
+    #[error("invalid name")]
+    NameInvalid,
 }
 
 impl ErrorKind {
@@ -5,11 +5,25 @@ mod algorithm;
 pub mod commands;
 mod credentials;
 mod error;
+#[cfg(feature = "passwords")]
+pub mod kdf;
 pub mod key;
+mod provider;
+mod source;
 
 pub use self::{
     algorithm::Algorithm,
     credentials::*,
     error::{Error, ErrorKind},
     key::Key,
+    provider::{SessionKeyProvider, SessionKeys},
+    source::{CredentialSource, EnvCredentials, FileCredentials},
 };
+
+pub(crate) use self::provider::Authenticator;
+
+#[cfg(feature = "passwords")]
+pub use self::kdf::Kdf;
+
+#[cfg(feature = "prompt")]
+pub use self::source::PasswordPrompt;
@@ -2,6 +2,9 @@
 
 use crate::{authentication, object};
 
+#[cfg(feature = "passwords")]
+use super::Kdf;
+
 /// Default auth key ID slot
 pub const DEFAULT_AUTHENTICATION_KEY_ID: object::Id = 1;
 
@@ -35,6 +38,17 @@ impl Credentials {
             authentication::Key::derive_from_password(password),
         )
     }
+
+    /// Create a set of credentials from the given auth key and password,
+    /// deriving the key with the given key derivation function
+    #[cfg(feature = "passwords")]
+    pub fn from_password_with_kdf(
+        authentication_key_id: object::Id,
+        password: &[u8],
+        kdf: &Kdf,
+    ) -> Result<Self, authentication::Error> {
+        Ok(Self::new(authentication_key_id, kdf.derive_key(password)?))
+    }
 }
 
 #[cfg(feature = "passwords")]
@@ -9,9 +9,30 @@ pub type Error = crate::Error<ErrorKind>;
 /// Kinds of authentication errors
 #[derive(Copy, Clone, Debug, Eq, Error, PartialEq)]
 pub enum ErrorKind {
+    /// Card cryptogram doesn't match the session keys (i.e. the HSM has a
+    /// different authentication key)
+    #[error("card cryptogram mismatch")]
+    CryptogramMismatch,
+
+    /// Key derivation function parameters are invalid
+    #[error("invalid key derivation parameters")]
+    KdfParamsInvalid,
+
+    /// Key derivation function isn't supported by this build
+    #[error("unsupported key derivation function")]
+    KdfUnsupported,
+
     /// Key size is invalid
     #[error("invalid key size")]
     KeySizeInvalid,
+
+    /// Couldn't load credentials from their source
+    #[error("couldn't load credentials")]
+    LoadFailed,
+
+    /// Key file is accessible by users other than its owner
+    #[error("insecure file permissions")]
+    PermissionsInvalid,
 }
 
 impl ErrorKind {
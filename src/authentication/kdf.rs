@@ -0,0 +1,252 @@
+//! Password-based key derivation functions for authentication keys.
+//!
+//! [`Kdf::Pbkdf2`] (the default) derives keys the same way as yubihsm-shell,
+//! i.e. with PBKDF2 and a static salt, which does little to slow down brute
+//! force attacks. When compatibility with yubihsm-shell isn't needed, the
+//! memory-hard [`Kdf::Argon2id`] (`argon2` cargo feature) and [`Kdf::Scrypt`]
+//! (`scrypt` cargo feature) functions can be used with a per-key salt.
+//!
+//! The parameters (including the salt) aren't secret, but they're needed to
+//! derive the same key from a password again, so keep them along with the
+//! rest of the HSM's configuration (e.g. in a setup report). They can be
+//! (de)serialized regardless of which cargo features are enabled, so e.g.
+//! reports can be read by builds without them.
+
+use super::{Error, ErrorKind, Key};
+use rand_core::RngCore;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::{
+    fmt::{self, Display},
+    str::FromStr,
+};
+
+#[cfg(any(feature = "argon2", feature = "scrypt"))]
+use {super::key, zeroize::Zeroizing};
+
+/// Size of randomly generated salts
+pub const SALT_SIZE: usize = 16;
+
+/// Minimum size of a salt
+pub const MIN_SALT_SIZE: usize = 8;
+
+/// Password-based key derivation function and its parameters
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(tag = "algorithm", rename_all = "kebab-case")]
+pub enum Kdf {
+    /// PBKDF2-HMAC-SHA256 with yubihsm-shell's static salt and iteration
+    /// count (see [`Key::derive_from_password`])
+    #[default]
+    Pbkdf2,
+
+    /// Argon2id (deriving keys requires the `argon2` cargo feature)
+    Argon2id {
+        /// Salt to derive the key with
+        salt: Salt,
+
+        /// Memory to use in KiB
+        memory_kib: u32,
+
+        /// Number of iterations
+        iterations: u32,
+
+        /// Degree of parallelism
+        parallelism: u32,
+    },
+
+    /// scrypt (deriving keys requires the `scrypt` cargo feature)
+    Scrypt {
+        /// Salt to derive the key with
+        salt: Salt,
+
+        /// Base-2 logarithm of the CPU/memory cost parameter `N`
+        log_n: u8,
+
+        /// Block size
+        r: u32,
+
+        /// Degree of parallelism
+        p: u32,
+    },
+}
+
+impl Kdf {
+    /// Argon2id with the given salt and the parameters recommended by OWASP
+    /// (19 MiB of memory, 2 iterations, no parallelism)
+    #[cfg(feature = "argon2")]
+    pub fn argon2id(salt: Salt) -> Self {
+        Kdf::Argon2id {
+            salt,
+            memory_kib: argon2::Params::DEFAULT_M_COST,
+            iterations: argon2::Params::DEFAULT_T_COST,
+            parallelism: argon2::Params::DEFAULT_P_COST,
+        }
+    }
+
+    /// scrypt with the given salt and recommended parameters (`N = 2^17`,
+    /// `r = 8`, `p = 1`)
+    #[cfg(feature = "scrypt")]
+    pub fn scrypt(salt: Salt) -> Self {
+        Kdf::Scrypt {
+            salt,
+            log_n: scrypt::Params::RECOMMENDED_LOG_N,
+            r: scrypt::Params::RECOMMENDED_R,
+            p: scrypt::Params::RECOMMENDED_P,
+        }
+    }
+
+    /// Name of this key derivation function
+    pub fn name(&self) -> &'static str {
+        match self {
+            Kdf::Pbkdf2 => "pbkdf2",
+            Kdf::Argon2id { .. } => "argon2id",
+            Kdf::Scrypt { .. } => "scrypt",
+        }
+    }
+
+    /// Derive an authentication key from a password
+    pub fn derive_key(&self, password: &[u8]) -> Result<Key, Error> {
+        match self {
+            Kdf::Pbkdf2 => Ok(Key::derive_from_password(password)),
+            #[cfg(feature = "argon2")]
+            Kdf::Argon2id {
+                salt,
+                memory_kib,
+                iterations,
+                parallelism,
+            } => {
+                let params =
+                    argon2::Params::new(*memory_kib, *iterations, *parallelism, Some(key::SIZE))
+                        .map_err(|e| invalid_params(self, e))?;
+
+                let mut output = Zeroizing::new([0u8; key::SIZE]);
+
+                argon2::Argon2::new(argon2::Algorithm::Argon2id, argon2::Version::V0x13, params)
+                    .hash_password_into(password, salt.check()?, output.as_mut())
+                    .map_err(|e| invalid_params(self, e))?;
+
+                Ok(Key::new(*output))
+            }
+            #[cfg(feature = "scrypt")]
+            Kdf::Scrypt { salt, log_n, r, p } => {
+                let params = scrypt::Params::new(*log_n, *r, *p, key::SIZE)
+                    .map_err(|e| invalid_params(self, e))?;
+
+                let mut output = Zeroizing::new([0u8; key::SIZE]);
+
+                scrypt::scrypt(password, salt.check()?, &params, output.as_mut())
+                    .map_err(|e| invalid_params(self, e))?;
+
+                Ok(Key::new(*output))
+            }
+            #[cfg(not(feature = "argon2"))]
+            Kdf::Argon2id { .. } => Err(not_compiled_in(self, "argon2")),
+            #[cfg(not(feature = "scrypt"))]
+            Kdf::Scrypt { .. } => Err(not_compiled_in(self, "scrypt")),
+        }
+    }
+}
+
+/// Error for a key derivation function whose cargo feature isn't enabled
+#[cfg(not(all(feature = "argon2", feature = "scrypt")))]
+fn not_compiled_in(kdf: &Kdf, feature: &str) -> Error {
+    format_err!(
+        ErrorKind::KdfUnsupported,
+        "{} support not compiled in (enable the `{}` cargo feature)",
+        kdf.name(),
+        feature
+    )
+    .into()
+}
+
+/// Error for invalid key derivation parameters
+#[cfg(any(feature = "argon2", feature = "scrypt"))]
+fn invalid_params(kdf: &Kdf, err: impl Display) -> Error {
+    format_err!(
+        ErrorKind::KdfParamsInvalid,
+        "invalid {} parameters: {}",
+        kdf.name(),
+        err
+    )
+    .into()
+}
+
+/// Salt for a key derivation function, written in hex
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Salt(Vec<u8>);
+
+impl Salt {
+    /// Generate a random salt
+    pub fn random() -> Self {
+        let mut salt = vec![0u8; SALT_SIZE];
+        rand::rng().fill_bytes(&mut salt);
+        Salt(salt)
+    }
+
+    /// Create a salt from the given bytes
+    pub fn new(bytes: impl Into<Vec<u8>>) -> Self {
+        Salt(bytes.into())
+    }
+
+    /// Borrow the salt as a byte slice
+    pub fn as_slice(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// Ensure the salt is long enough to be used
+    #[cfg(any(feature = "argon2", feature = "scrypt"))]
+    fn check(&self) -> Result<&[u8], Error> {
+        ensure!(
+            self.0.len() >= MIN_SALT_SIZE,
+            ErrorKind::KdfParamsInvalid,
+            "salt must be at least {} bytes (got {})",
+            MIN_SALT_SIZE,
+            self.0.len()
+        );
+
+        Ok(&self.0)
+    }
+}
+
+impl Display for Salt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.iter().try_for_each(|byte| write!(f, "{byte:02x}"))
+    }
+}
+
+impl FromStr for Salt {
+    type Err = Error;
+
+    fn from_str(hex: &str) -> Result<Self, Error> {
+        ensure!(
+            hex.len() % 2 == 0,
+            ErrorKind::KdfParamsInvalid,
+            "salt has an odd number of hex digits"
+        );
+
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| {
+                hex.get(i..i + 2)
+                    .and_then(|digits| u8::from_str_radix(digits, 16).ok())
+            })
+            .collect::<Option<Vec<u8>>>()
+            .map(Salt)
+            .ok_or_else(|| {
+                format_err!(ErrorKind::KdfParamsInvalid, "invalid hex digit in salt").into()
+            })
+    }
+}
+
+impl Serialize for Salt {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Salt {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
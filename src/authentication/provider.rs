@@ -0,0 +1,143 @@
+//! Session key providers: derive SCP03 session keys from an authentication
+//! key which may live outside of host memory (e.g. on a YubiKey running
+//! YubiHSM Auth, or in a separate process).
+
+use super::{Credentials, Error, Key};
+use crate::{
+    object,
+    session::securechannel::{self, CHALLENGE_SIZE, CRYPTOGRAM_SIZE, KEY_SIZE},
+};
+use std::{
+    fmt::{self, Debug},
+    sync::Arc,
+};
+use zeroize::Zeroize;
+
+/// Derives the session keys for a session from the host and card
+/// challenges exchanged when creating it.
+///
+/// The card cryptogram the HSM returned is checked against the session keys
+/// when the session is opened, but it's also passed to providers which
+/// verify it themselves (as YubiHSM Auth does).
+///
+/// [`authentication::Key`][`Key`] implements this trait in software, i.e.
+/// with the long-term key held in host memory. Implement it for an external
+/// device or process to keep the long-term key out of this one.
+pub trait SessionKeyProvider: Send + Sync {
+    /// Derive the session keys for the given host and card challenges,
+    /// given the card cryptogram the HSM returned along with its challenge
+    fn session_keys(
+        &self,
+        host_challenge: &[u8; CHALLENGE_SIZE],
+        card_challenge: &[u8; CHALLENGE_SIZE],
+        card_cryptogram: &[u8; CRYPTOGRAM_SIZE],
+    ) -> Result<SessionKeys, Error>;
+}
+
+impl SessionKeyProvider for Key {
+    fn session_keys(
+        &self,
+        host_challenge: &[u8; CHALLENGE_SIZE],
+        card_challenge: &[u8; CHALLENGE_SIZE],
+        _card_cryptogram: &[u8; CRYPTOGRAM_SIZE],
+    ) -> Result<SessionKeys, Error> {
+        Ok(securechannel::derive_session_keys(
+            self,
+            host_challenge,
+            card_challenge,
+        ))
+    }
+}
+
+impl<P: SessionKeyProvider + ?Sized> SessionKeyProvider for Box<P> {
+    fn session_keys(
+        &self,
+        host_challenge: &[u8; CHALLENGE_SIZE],
+        card_challenge: &[u8; CHALLENGE_SIZE],
+        card_cryptogram: &[u8; CRYPTOGRAM_SIZE],
+    ) -> Result<SessionKeys, Error> {
+        (**self).session_keys(host_challenge, card_challenge, card_cryptogram)
+    }
+}
+
+impl<P: SessionKeyProvider + ?Sized> SessionKeyProvider for Arc<P> {
+    fn session_keys(
+        &self,
+        host_challenge: &[u8; CHALLENGE_SIZE],
+        card_challenge: &[u8; CHALLENGE_SIZE],
+        card_cryptogram: &[u8; CRYPTOGRAM_SIZE],
+    ) -> Result<SessionKeys, Error> {
+        (**self).session_keys(host_challenge, card_challenge, card_cryptogram)
+    }
+}
+
+/// SCP03 session keys for a single session
+pub struct SessionKeys {
+    /// Session encryption key (S-ENC)
+    pub(crate) enc_key: [u8; KEY_SIZE],
+
+    /// Session command MAC key (S-MAC)
+    pub(crate) mac_key: [u8; KEY_SIZE],
+
+    /// Session response MAC key (S-RMAC)
+    pub(crate) rmac_key: [u8; KEY_SIZE],
+}
+
+impl SessionKeys {
+    /// Create session keys from S-ENC, S-MAC and S-RMAC
+    pub fn new(enc_key: [u8; KEY_SIZE], mac_key: [u8; KEY_SIZE], rmac_key: [u8; KEY_SIZE]) -> Self {
+        Self {
+            enc_key,
+            mac_key,
+            rmac_key,
+        }
+    }
+}
+
+impl Debug for SessionKeys {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Avoid leaking secrets in debug messages
+        write!(f, "yubihsm::authentication::SessionKeys(...)")
+    }
+}
+
+impl Drop for SessionKeys {
+    fn drop(&mut self) {
+        self.enc_key.zeroize();
+        self.mac_key.zeroize();
+        self.rmac_key.zeroize();
+    }
+}
+
+/// Authentication key ID along with the provider of session keys for it,
+/// i.e. everything needed to open a session
+#[derive(Clone)]
+pub(crate) struct Authenticator {
+    /// Key ID to authenticate with
+    pub(crate) authentication_key_id: object::Id,
+
+    /// Provider of session keys for the authentication key
+    pub(crate) provider: Arc<dyn SessionKeyProvider>,
+}
+
+impl Authenticator {
+    /// Create an authenticator from a key ID and session key provider
+    pub(crate) fn new(
+        authentication_key_id: object::Id,
+        provider: impl SessionKeyProvider + 'static,
+    ) -> Self {
+        Self {
+            authentication_key_id,
+            provider: Arc::new(provider),
+        }
+    }
+}
+
+impl From<Credentials> for Authenticator {
+    fn from(credentials: Credentials) -> Self {
+        Self::new(
+            credentials.authentication_key_id,
+            credentials.authentication_key,
+        )
+    }
+}
@@ -0,0 +1,277 @@
+//! Sources of credentials: environment variables, key files, password
+//! prompts, or anything else implementing [`CredentialSource`] (e.g. a
+//! client for a secret manager).
+
+use super::{key, Credentials, Error, ErrorKind, Key};
+use crate::object;
+use std::{
+    env, fs,
+    io::Read,
+    path::{Path, PathBuf},
+};
+use zeroize::Zeroizing;
+
+/// Source of [`Credentials`], e.g. an environment variable, a key file or
+/// a secret manager
+pub trait CredentialSource {
+    /// Load credentials from this source
+    fn credentials(&self) -> Result<Credentials, Error>;
+}
+
+impl CredentialSource for Credentials {
+    fn credentials(&self) -> Result<Credentials, Error> {
+        Ok(self.clone())
+    }
+}
+
+impl<S: CredentialSource + ?Sized> CredentialSource for &S {
+    fn credentials(&self) -> Result<Credentials, Error> {
+        (**self).credentials()
+    }
+}
+
+impl<S: CredentialSource + ?Sized> CredentialSource for Box<S> {
+    fn credentials(&self) -> Result<Credentials, Error> {
+        (**self).credentials()
+    }
+}
+
+/// How a secret is encoded
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum Format {
+    /// Hex-encoded authentication key
+    Key,
+
+    /// Password the authentication key is derived from
+    #[cfg(feature = "passwords")]
+    Password,
+}
+
+impl Format {
+    /// Build credentials from a secret in this format
+    fn credentials(
+        self,
+        authentication_key_id: object::Id,
+        secret: &str,
+    ) -> Result<Credentials, Error> {
+        let key = match self {
+            Format::Key => {
+                let hex = secret.trim();
+
+                ensure!(
+                    hex.len() == key::SIZE * 2,
+                    ErrorKind::KeySizeInvalid,
+                    "expected {} hex digits, got {}",
+                    key::SIZE * 2,
+                    hex.len()
+                );
+
+                let mut bytes = Zeroizing::new([0u8; key::SIZE]);
+
+                for (byte, i) in bytes.iter_mut().zip((0..hex.len()).step_by(2)) {
+                    *byte = hex
+                        .get(i..i + 2)
+                        .and_then(|digits| u8::from_str_radix(digits, 16).ok())
+                        .ok_or_else(|| format_err!(ErrorKind::LoadFailed, "invalid hex digit"))?;
+                }
+
+                Key::new(*bytes)
+            }
+            #[cfg(feature = "passwords")]
+            Format::Password => Key::derive_from_password(secret.as_bytes()),
+        };
+
+        Ok(Credentials::new(authentication_key_id, key))
+    }
+}
+
+/// Credentials read from an environment variable
+#[derive(Clone, Debug)]
+pub struct EnvCredentials {
+    /// Key ID to authenticate with
+    authentication_key_id: object::Id,
+
+    /// Name of the environment variable
+    var: String,
+
+    /// How the secret is encoded
+    format: Format,
+}
+
+impl EnvCredentials {
+    /// Hex-encoded authentication key read from the given environment
+    /// variable
+    pub fn key(authentication_key_id: object::Id, var: impl Into<String>) -> Self {
+        Self {
+            authentication_key_id,
+            var: var.into(),
+            format: Format::Key,
+        }
+    }
+
+    /// Password read from the given environment variable (see
+    /// [`Credentials::from_password`])
+    #[cfg(feature = "passwords")]
+    pub fn password(authentication_key_id: object::Id, var: impl Into<String>) -> Self {
+        Self {
+            authentication_key_id,
+            var: var.into(),
+            format: Format::Password,
+        }
+    }
+}
+
+impl CredentialSource for EnvCredentials {
+    fn credentials(&self) -> Result<Credentials, Error> {
+        let secret = Zeroizing::new(env::var(&self.var).map_err(|e| {
+            format_err!(
+                ErrorKind::LoadFailed,
+                "environment variable {}: {}",
+                self.var,
+                e
+            )
+        })?);
+
+        self.format
+            .credentials(self.authentication_key_id, &secret)
+            .map_err(|e| {
+                format_err!(
+                    ErrorKind::LoadFailed,
+                    "environment variable {}: {}",
+                    self.var,
+                    e
+                )
+                .into()
+            })
+    }
+}
+
+/// Credentials read from a file. On Unix, files which are accessible by
+/// their group or by others are refused.
+#[derive(Clone, Debug)]
+pub struct FileCredentials {
+    /// Key ID to authenticate with
+    authentication_key_id: object::Id,
+
+    /// Path to the file
+    path: PathBuf,
+
+    /// How the secret is encoded
+    format: Format,
+}
+
+impl FileCredentials {
+    /// Hex-encoded authentication key read from the given file
+    pub fn key(authentication_key_id: object::Id, path: impl AsRef<Path>) -> Self {
+        Self {
+            authentication_key_id,
+            path: path.as_ref().to_owned(),
+            format: Format::Key,
+        }
+    }
+
+    /// Password read from the given file (without its trailing newline)
+    #[cfg(feature = "passwords")]
+    pub fn password(authentication_key_id: object::Id, path: impl AsRef<Path>) -> Self {
+        Self {
+            authentication_key_id,
+            path: path.as_ref().to_owned(),
+            format: Format::Password,
+        }
+    }
+
+    /// Open the file, ensuring it isn't accessible by anyone other than its
+    /// owner. The permissions are checked on the opened file itself, so the
+    /// file can't be swapped between checking and reading it.
+    fn open(&self) -> Result<fs::File, Error> {
+        let file = fs::File::open(&self.path)
+            .map_err(|e| format_err!(ErrorKind::LoadFailed, "{}: {}", self.path.display(), e))?;
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+
+            let mode = file
+                .metadata()
+                .map_err(|e| format_err!(ErrorKind::LoadFailed, "{}: {}", self.path.display(), e))?
+                .permissions()
+                .mode();
+
+            ensure!(
+                mode & 0o077 == 0,
+                ErrorKind::PermissionsInvalid,
+                "{} is accessible by its group or others (mode {:o}): run `chmod 600` on it",
+                self.path.display(),
+                mode & 0o777
+            );
+        }
+
+        Ok(file)
+    }
+}
+
+impl CredentialSource for FileCredentials {
+    fn credentials(&self) -> Result<Credentials, Error> {
+        let mut contents = Zeroizing::new(String::new());
+
+        self.open()?
+            .read_to_string(&mut contents)
+            .map_err(|e| format_err!(ErrorKind::LoadFailed, "{}: {}", self.path.display(), e))?;
+
+        let secret = contents
+            .strip_suffix('\n')
+            .map(|line| line.strip_suffix('\r').unwrap_or(line))
+            .unwrap_or(&contents);
+
+        self.format
+            .credentials(self.authentication_key_id, secret)
+            .map_err(|e| {
+                format_err!(ErrorKind::LoadFailed, "{}: {}", self.path.display(), e).into()
+            })
+    }
+}
+
+/// Password read from the terminal without echoing it
+#[cfg(feature = "prompt")]
+#[derive(Clone, Debug)]
+pub struct PasswordPrompt {
+    /// Key ID to authenticate with
+    authentication_key_id: object::Id,
+
+    /// Prompt to display
+    prompt: String,
+}
+
+#[cfg(feature = "prompt")]
+impl PasswordPrompt {
+    /// Prompt for the password of the given authentication key
+    pub fn new(authentication_key_id: object::Id) -> Self {
+        Self {
+            authentication_key_id,
+            prompt: format!(
+                "Password for YubiHSM authentication key 0x{authentication_key_id:04x}: "
+            ),
+        }
+    }
+
+    /// Set the prompt to display
+    pub fn prompt(mut self, prompt: impl Into<String>) -> Self {
+        self.prompt = prompt.into();
+        self
+    }
+}
+
+#[cfg(feature = "prompt")]
+impl CredentialSource for PasswordPrompt {
+    fn credentials(&self) -> Result<Credentials, Error> {
+        let password =
+            Zeroizing::new(rpassword::prompt_password(&self.prompt).map_err(|e| {
+                format_err!(ErrorKind::LoadFailed, "error reading password: {}", e)
+            })?);
+
+        Ok(Credentials::from_password(
+            self.authentication_key_id,
+            password.as_bytes(),
+        ))
+    }
+}
@@ -217,117 +217,137 @@ impl Default for Capability {
     }
 }
 
+/// Names of capabilities, as used by `yubihsm-shell` and Yubico's documentation
+const NAMES: &[(&str, Capability)] = &[
+    ("derive-ecdh", Capability::DERIVE_ECDH),
+    ("decrypt-oaep", Capability::DECRYPT_OAEP),
+    ("decrypt-pkcs", Capability::DECRYPT_PKCS),
+    (
+        "generate-asymmetric-key",
+        Capability::GENERATE_ASYMMETRIC_KEY,
+    ),
+    ("sign-ecdsa", Capability::SIGN_ECDSA),
+    ("sign-eddsa", Capability::SIGN_EDDSA),
+    ("sign-pkcs", Capability::SIGN_PKCS),
+    ("sign-pss", Capability::SIGN_PSS),
+    (
+        "sign-attestation-certificate",
+        Capability::SIGN_ATTESTATION_CERTIFICATE,
+    ),
+    ("get-log-entries", Capability::GET_LOG_ENTRIES),
+    ("delete-asymmetric-key", Capability::DELETE_ASYMMETRIC_KEY),
+    (
+        "delete-authentication-key",
+        Capability::DELETE_AUTHENTICATION_KEY,
+    ),
+    ("delete-hmac-key", Capability::DELETE_HMAC_KEY),
+    ("delete-opaque", Capability::DELETE_OPAQUE),
+    ("delete-otp-aead-key", Capability::DELETE_OTP_AEAD_KEY),
+    ("delete-template", Capability::DELETE_TEMPLATE),
+    ("delete-wrap-key", Capability::DELETE_WRAP_KEY),
+    ("exportable-under-wrap", Capability::EXPORTABLE_UNDER_WRAP),
+    ("export-wrapped", Capability::EXPORT_WRAPPED),
+    ("generate-otp-aead-key", Capability::GENERATE_OTP_AEAD_KEY),
+    ("generate-wrap-key", Capability::GENERATE_WRAP_KEY),
+    ("get-opaque", Capability::GET_OPAQUE),
+    ("get-option", Capability::GET_OPTION),
+    ("get-pseudo-random", Capability::GET_PSEUDO_RANDOM),
+    ("get-template", Capability::GET_TEMPLATE),
+    ("generate-hmac-key", Capability::GENERATE_HMAC_KEY),
+    ("sign-hmac", Capability::SIGN_HMAC),
+    ("verify-hmac", Capability::VERIFY_HMAC),
+    ("import-wrapped", Capability::IMPORT_WRAPPED),
+    ("create-otp-aead", Capability::CREATE_OTP_AEAD),
+    ("randomize-otp-aead", Capability::RANDOMIZE_OTP_AEAD),
+    (
+        "rewrap-from-otp-aead-key",
+        Capability::REWRAP_FROM_OTP_AEAD_KEY,
+    ),
+    ("rewrap-to-otp-aead-key", Capability::REWRAP_TO_OTP_AEAD_KEY),
+    ("decrypt-otp", Capability::DECRYPT_OTP),
+    ("put-asymmetric-key", Capability::PUT_ASYMMETRIC_KEY),
+    ("put-authentication-key", Capability::PUT_AUTHENTICATION_KEY),
+    ("put-hmac-key", Capability::PUT_HMAC_KEY),
+    ("put-opaque", Capability::PUT_OPAQUE),
+    ("set-option", Capability::PUT_OPTION),
+    ("put-otp-aead-key", Capability::PUT_OTP_AEAD_KEY),
+    ("put-template", Capability::PUT_TEMPLATE),
+    ("put-wrap-key", Capability::PUT_WRAP_KEY),
+    ("reset-device", Capability::RESET_DEVICE),
+    ("sign-ssh-certificate", Capability::SIGN_SSH_CERTIFICATE),
+    ("unwrap-data", Capability::UNWRAP_DATA),
+    ("wrap-data", Capability::WRAP_DATA),
+    (
+        "change-authentication-key",
+        Capability::CHANGE_AUTHENTICATION_KEY,
+    ),
+];
+
+impl Capability {
+    /// Name of this capability, if it's a single known capability
+    pub fn name(self) -> Option<&'static str> {
+        NAMES
+            .iter()
+            .find(|&&(_, cap)| cap == self)
+            .map(|&(name, _)| name)
+    }
+}
+
+/// Capabilities are displayed as a comma-separated list of names (e.g.
+/// `sign-ecdsa,exportable-under-wrap`), with unnamed capabilities written in
+/// hex, or as `all` or `none`
 impl Display for Capability {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let s = match *self {
-            Capability::DERIVE_ECDH => "derive-ecdh",
-            Capability::DECRYPT_OAEP => "decrypt-oaep",
-            Capability::DECRYPT_PKCS => "decrypt-pkcs",
-            Capability::GENERATE_ASYMMETRIC_KEY => "generate-asymmetric-key",
-            Capability::SIGN_ECDSA => "sign-ecdsa",
-            Capability::SIGN_EDDSA => "sign-eddsa",
-            Capability::SIGN_PKCS => "sign-pkcs",
-            Capability::SIGN_PSS => "sign-pss",
-            Capability::SIGN_ATTESTATION_CERTIFICATE => "sign-attestation-certificate",
-            Capability::GET_LOG_ENTRIES => "get-log-entries",
-            Capability::DELETE_ASYMMETRIC_KEY => "delete-asymmetric-key",
-            Capability::DELETE_AUTHENTICATION_KEY => "delete-authentication-key",
-            Capability::DELETE_HMAC_KEY => "delete-hmac-key",
-            Capability::DELETE_OPAQUE => "delete-opaque",
-            Capability::DELETE_OTP_AEAD_KEY => "delete-otp-aead-key",
-            Capability::DELETE_TEMPLATE => "delete-template",
-            Capability::DELETE_WRAP_KEY => "delete-wrap-key",
-            Capability::EXPORTABLE_UNDER_WRAP => "exportable-under-wrap",
-            Capability::EXPORT_WRAPPED => "export-wrapped",
-            Capability::GENERATE_OTP_AEAD_KEY => "generate-otp-aead-key",
-            Capability::GENERATE_WRAP_KEY => "generate-wrap-key",
-            Capability::GET_OPAQUE => "get-opaque",
-            Capability::GET_OPTION => "get-option",
-            Capability::GET_PSEUDO_RANDOM => "get-pseudo-random",
-            Capability::GET_TEMPLATE => "get-template",
-            Capability::GENERATE_HMAC_KEY => "generate-hmac-key",
-            Capability::SIGN_HMAC => "sign-hmac",
-            Capability::VERIFY_HMAC => "verify-hmac",
-            Capability::IMPORT_WRAPPED => "import-wrapped",
-            Capability::CREATE_OTP_AEAD => "create-otp-aead",
-            Capability::RANDOMIZE_OTP_AEAD => "randomize-otp-aead",
-            Capability::REWRAP_FROM_OTP_AEAD_KEY => "rewrap-from-otp-aead-key",
-            Capability::REWRAP_TO_OTP_AEAD_KEY => "rewrap-to-otp-aead-key",
-            Capability::DECRYPT_OTP => "decrypt-otp",
-            Capability::PUT_ASYMMETRIC_KEY => "put-asymmetric-key",
-            Capability::PUT_AUTHENTICATION_KEY => "put-authentication-key",
-            Capability::PUT_HMAC_KEY => "put-hmac-key",
-            Capability::PUT_OPAQUE => "put-opaque",
-            Capability::PUT_OPTION => "set-option",
-            Capability::PUT_OTP_AEAD_KEY => "put-otp-aead-key",
-            Capability::PUT_TEMPLATE => "put-template",
-            Capability::PUT_WRAP_KEY => "put-wrap-key",
-            Capability::RESET_DEVICE => "reset-device",
-            Capability::SIGN_SSH_CERTIFICATE => "sign-ssh-certificate",
-            Capability::UNWRAP_DATA => "unwrap-data",
-            Capability::WRAP_DATA => "wrap-data",
-            Capability::CHANGE_AUTHENTICATION_KEY => "change-authentication-key",
-            _ => return Err(fmt::Error), // we don't support displaying this capability yet
-        };
-
-        write!(f, "{s}")
+        if self.is_all() {
+            return f.write_str("all");
+        }
+
+        if self.is_empty() {
+            return f.write_str("none");
+        }
+
+        for (i, cap) in self.iter().enumerate() {
+            if i > 0 {
+                f.write_str(",")?;
+            }
+
+            match cap.name() {
+                Some(name) => f.write_str(name)?,
+                None => write!(f, "0x{:x}", cap.bits())?,
+            }
+        }
+
+        Ok(())
     }
 }
 
+/// Parses the forms written by [`Display`], i.e. a comma-separated list of
+/// capability names and/or hex values, `all` or `none`
 impl FromStr for Capability {
     type Err = ();
 
     fn from_str(s: &str) -> Result<Capability, ()> {
-        Ok(match s {
-            "derive-ecdh" => Capability::DERIVE_ECDH,
-            "decrypt-oaep" => Capability::DECRYPT_OAEP,
-            "decrypt-pkcs" => Capability::DECRYPT_PKCS,
-            "generate-asymmetric-key" => Capability::GENERATE_ASYMMETRIC_KEY,
-            "sign-ecdsa" => Capability::SIGN_ECDSA,
-            "sign-eddsa" => Capability::SIGN_EDDSA,
-            "sign-pkcs" => Capability::SIGN_PKCS,
-            "sign-pss" => Capability::SIGN_PSS,
-            "sign-attestation-certificate" => Capability::SIGN_ATTESTATION_CERTIFICATE,
-            "get-log-entries" => Capability::GET_LOG_ENTRIES,
-            "delete-asymmetric-key" => Capability::DELETE_ASYMMETRIC_KEY,
-            "delete-authentication-key" => Capability::DELETE_AUTHENTICATION_KEY,
-            "delete-hmac-key" => Capability::DELETE_HMAC_KEY,
-            "delete-opaque" => Capability::DELETE_OPAQUE,
-            "delete-otp-aead-key" => Capability::DELETE_OTP_AEAD_KEY,
-            "delete-template" => Capability::DELETE_TEMPLATE,
-            "delete-wrap-key" => Capability::DELETE_WRAP_KEY,
-            "exportable-under-wrap" => Capability::EXPORTABLE_UNDER_WRAP,
-            "export-wrapped" => Capability::EXPORT_WRAPPED,
-            "generate-otp-aead-key" => Capability::GENERATE_OTP_AEAD_KEY,
-            "generate-wrap-key" => Capability::GENERATE_WRAP_KEY,
-            "get-opaque" => Capability::GET_OPAQUE,
-            "get-option" => Capability::GET_OPTION,
-            "get-pseudo-random" => Capability::GET_PSEUDO_RANDOM,
-            "get-template" => Capability::GET_TEMPLATE,
-            "generate-hmac-key" => Capability::GENERATE_HMAC_KEY,
-            "sign-hmac" => Capability::SIGN_HMAC,
-            "verify-hmac" => Capability::VERIFY_HMAC,
-            "import-wrapped" => Capability::IMPORT_WRAPPED,
-            "create-otp-aead" => Capability::CREATE_OTP_AEAD,
-            "randomize-otp-aead" => Capability::RANDOMIZE_OTP_AEAD,
-            "rewrap-from-otp-aead-key" => Capability::REWRAP_FROM_OTP_AEAD_KEY,
-            "rewrap-to-otp-aead-key" => Capability::REWRAP_TO_OTP_AEAD_KEY,
-            "decrypt-otp" => Capability::DECRYPT_OTP,
-            "put-asymmetric-key" => Capability::PUT_ASYMMETRIC_KEY,
-            "put-authentication-key" => Capability::PUT_AUTHENTICATION_KEY,
-            "put-hmac-key" => Capability::PUT_HMAC_KEY,
-            "put-opaque" => Capability::PUT_OPAQUE,
-            "set-option" => Capability::PUT_OPTION,
-            "put-otp-aead-key" => Capability::PUT_OTP_AEAD_KEY,
-            "put-template" => Capability::PUT_TEMPLATE,
-            "put-wrap-key" => Capability::PUT_WRAP_KEY,
-            "reset-device" => Capability::RESET_DEVICE,
-            "sign-ssh-certificate" => Capability::SIGN_SSH_CERTIFICATE,
-            "unwrap-data" => Capability::UNWRAP_DATA,
-            "wrap-data" => Capability::WRAP_DATA,
-            "change-authentication-key" => Capability::CHANGE_AUTHENTICATION_KEY,
-            _ => return Err(()),
-        })
+        match s.trim() {
+            "all" => return Ok(Capability::all()),
+            "none" | "" => return Ok(Capability::empty()),
+            _ => (),
+        }
+
+        s.split(',')
+            .map(str::trim)
+            .try_fold(Capability::empty(), |caps, name| {
+                let cap = match name.strip_prefix("0x") {
+                    Some(hex) => u64::from_str_radix(hex, 16)
+                        .ok()
+                        .and_then(Capability::from_bits),
+                    None => NAMES
+                        .iter()
+                        .find(|&&(cap_name, _)| cap_name == name)
+                        .map(|&(_, cap)| cap),
+                };
+
+                cap.map(|cap| caps | cap).ok_or(())
+            })
     }
 }
 
@@ -336,7 +356,11 @@ impl Serialize for Capability {
     where
         S: Serializer,
     {
-        serializer.serialize_u64(self.bits())
+        if serializer.is_human_readable() {
+            serializer.collect_str(self)
+        } else {
+            serializer.serialize_u64(self.bits())
+        }
     }
 }
 
@@ -351,7 +375,16 @@ impl<'de> Deserialize<'de> for Capability {
             type Value = Capability;
 
             fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
-                formatter.write_str("8-bytes containing capability bitflags")
+                formatter.write_str("8-bytes containing capability bitflags or capability names")
+            }
+
+            fn visit_str<E>(self, value: &str) -> Result<Capability, E>
+            where
+                E: de::Error,
+            {
+                value
+                    .parse()
+                    .map_err(|()| E::custom(format!("invalid capabilities: {value:?}")))
             }
 
             fn visit_u64<E>(self, value: u64) -> Result<Capability, E>
@@ -362,6 +395,10 @@ impl<'de> Deserialize<'de> for Capability {
             }
         }
 
-        deserializer.deserialize_u64(CapabilityVisitor)
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_any(CapabilityVisitor)
+        } else {
+            deserializer.deserialize_u64(CapabilityVisitor)
+        }
     }
 }
@@ -9,14 +9,27 @@
 
 #[macro_use]
 mod error;
+#[cfg(feature = "async")]
+mod async_client;
+mod keepalive;
+pub mod pool;
+
+pub use self::{
+    error::{Error, ErrorKind},
+    keepalive::Keepalive,
+    pool::Pool,
+};
 
-pub use self::error::{Error, ErrorKind};
+#[cfg(feature = "async")]
+pub use self::async_client::AsyncClient;
 
 use crate::{
     asymmetric::{self, commands::*, PublicKey},
     attestation::{self, commands::*},
     audit::{commands::*, *},
-    authentication::{self, commands::*, Credentials},
+    authentication::{
+        self, commands::*, Authenticator, CredentialSource, Credentials, SessionKeyProvider,
+    },
     capability::Capability,
     command::{self, Command},
     connector::Connector,
@@ -65,8 +78,19 @@ pub struct Client {
     /// Encrypted session with the HSM (if we have one open)
     session: Arc<Mutex<Option<Session>>>,
 
-    /// Cached `Credentials` for reconnecting closed sessions
-    credentials: Option<Credentials>,
+    /// Cached credentials (key ID and session key provider) for
+    /// reconnecting closed sessions
+    credentials: Option<Authenticator>,
+
+    /// Pool to borrow a session from for each command (if any). When set,
+    /// the `session` mutex above is unused.
+    pool: Option<Pool>,
+
+    /// Inactivity timeout for sessions opened by this client
+    timeout: session::Timeout,
+
+    /// Number of messages after which sessions are proactively rekeyed (if any)
+    rekey_threshold: Option<usize>,
 }
 
 impl Client {
@@ -82,7 +106,63 @@ impl Client {
         credentials: Credentials,
         reconnect: bool,
     ) -> Result<Self, Error> {
-        let mut client = Self::create(connector, credentials)?;
+        Self::open_with_authenticator(connector, credentials.into(), reconnect)
+    }
+
+    /// Open a connection via a [`Connector`] to a YubiHSM, loading the
+    /// credentials from the given [`CredentialSource`] (e.g. an environment
+    /// variable or a key file)
+    pub fn open_with_source(
+        connector: Connector,
+        source: impl CredentialSource,
+        reconnect: bool,
+    ) -> Result<Self, Error> {
+        Self::open(connector, source.credentials()?, reconnect)
+    }
+
+    /// Open a connection via a [`Connector`] to a YubiHSM, authenticating
+    /// with the given key ID and obtaining session keys from the given
+    /// [`SessionKeyProvider`] (e.g. a YubiKey holding the authentication
+    /// key) rather than deriving them from a key held in host memory
+    pub fn open_with_provider(
+        connector: Connector,
+        authentication_key_id: object::Id,
+        provider: impl SessionKeyProvider + 'static,
+        reconnect: bool,
+    ) -> Result<Self, Error> {
+        Self::open_with_authenticator(
+            connector,
+            Authenticator::new(authentication_key_id, provider),
+            reconnect,
+        )
+    }
+
+    /// Create a `yubihsm::Client`, but defer connecting until `connect()` is called.
+    pub fn create(connector: Connector, credentials: Credentials) -> Result<Self, Error> {
+        Ok(Self::with_authenticator(connector, credentials.into()))
+    }
+
+    /// Create a `yubihsm::Client` which authenticates with the given
+    /// authenticator
+    fn with_authenticator(connector: Connector, authenticator: Authenticator) -> Self {
+        Self {
+            connector,
+            session: Arc::new(Mutex::new(None)),
+            credentials: Some(authenticator),
+            pool: None,
+            timeout: session::Timeout::default(),
+            rekey_threshold: None,
+        }
+    }
+
+    /// Open a connection to a YubiHSM, authenticating with the given
+    /// authenticator
+    fn open_with_authenticator(
+        connector: Connector,
+        authenticator: Authenticator,
+        reconnect: bool,
+    ) -> Result<Self, Error> {
+        let mut client = Self::with_authenticator(connector, authenticator);
         client.connect()?;
 
         // Clear credentials if reconnecting has been disabled
@@ -93,15 +173,59 @@ impl Client {
         Ok(client)
     }
 
-    /// Create a `yubihsm::Client`, but defer connecting until `connect()` is called.
-    pub fn create(connector: Connector, credentials: Credentials) -> Result<Self, Error> {
-        let client = Self {
-            connector,
+    /// Create a `yubihsm::Client` which borrows a session from the given
+    /// [`Pool`] for each command.
+    pub fn from_pool(pool: Pool) -> Self {
+        Self {
+            connector: pool.connector().clone(),
             session: Arc::new(Mutex::new(None)),
-            credentials: Some(credentials),
+            credentials: None,
+            pool: Some(pool),
+            timeout: session::Timeout::default(),
+            rekey_threshold: None,
+        }
+    }
+
+    /// Set the inactivity timeout for sessions opened by this client.
+    ///
+    /// This applies to sessions opened after it's called, so use it with
+    /// [`Client::create`] before calling [`Client::connect`]. The YubiHSM 2
+    /// closes sessions after 30 seconds of inactivity, so setting a longer
+    /// timeout will result in errors.
+    pub fn set_session_timeout(&mut self, timeout: session::Timeout) {
+        self.timeout = timeout;
+    }
+
+    /// Get the inactivity timeout for sessions opened by this client
+    pub fn session_timeout(&self) -> session::Timeout {
+        self.timeout
+    }
+
+    /// Proactively replace the current session with a freshly authenticated
+    /// one once it has sent the given number of messages, rather than waiting
+    /// for the SCP03 message limit to be reached.
+    ///
+    /// When combined with a [`Keepalive`], rekeying happens in the background
+    /// rather than on the next command.
+    pub fn set_rekey_threshold(&mut self, messages_sent: usize) {
+        self.rekey_threshold = Some(messages_sent);
+    }
+
+    /// Spawn a background thread which keeps this client's session alive,
+    /// sending an `Echo` command whenever it has been idle for half of the
+    /// session timeout, and rekeying it once the rekey threshold is reached.
+    ///
+    /// For clients created with [`Client::from_pool`], the pool's idle
+    /// sessions are kept alive instead, using the pool's session timeout.
+    ///
+    /// The thread stops when the returned [`Keepalive`] is dropped.
+    pub fn keepalive(&self) -> Keepalive {
+        let timeout = match &self.pool {
+            Some(pool) => pool.session_timeout(),
+            None => self.timeout,
         };
 
-        Ok(client)
+        Keepalive::spawn(self.clone(), timeout.duration() / 2)
     }
 
     /// Borrow this client's YubiHSM connector (which is `Clone`able)
@@ -118,16 +242,31 @@ impl Client {
 
     /// Get current `Session` (either opening a new one or returning an already
     /// open one).
+    ///
+    /// For clients created from a [`Pool`], this borrows a session from the
+    /// pool, which is returned when the guard is dropped.
     pub fn session(&self) -> Result<session::Guard<'_>, Error> {
+        if let Some(pool) = &self.pool {
+            return Ok(session::Guard::leased(pool.get()?));
+        }
+
         // TODO(tarcieri): handle PoisonError better?
         let mut session_mutex_guard = self.session.lock().unwrap();
 
         if let Some(session) = session_mutex_guard.as_ref() {
-            if session.is_open() {
+            if session.is_open() && !self.needs_rekey(session) {
                 return Ok(session::Guard::new(session_mutex_guard));
             }
         }
 
+        // Close a session which is due to be rekeyed (a no-op for sessions
+        // which have already been closed or timed out)
+        if let Some(stale_session) = session_mutex_guard.take() {
+            if let Err(e) = stale_session.close() {
+                debug!("error closing session: {}", e);
+            }
+        }
+
         // If we don't have an open session, create a new one
         let session = Session::open(
             self.connector.clone(),
@@ -137,7 +276,7 @@ impl Client {
                     "session reconnection disabled"
                 )
             })?,
-            session::Timeout::default(),
+            self.timeout,
         )?;
 
         *session_mutex_guard = Some(session);
@@ -162,6 +301,14 @@ impl Client {
         Ok(Instant::now().duration_since(t))
     }
 
+    /// Has the given session sent enough messages that it should be rekeyed?
+    fn needs_rekey(&self, session: &Session) -> bool {
+        match (self.rekey_threshold, session.messages_sent()) {
+            (Some(threshold), Ok(messages_sent)) => messages_sent >= threshold,
+            _ => false,
+        }
+    }
+
     /// Encrypt a command, send it to the HSM, then read and decrypt the response.
     fn send_command<T: Command>(&self, command: T) -> Result<T::ResponseType, Error> {
         let mut session = self.session()?;
@@ -821,8 +968,13 @@ impl Client {
             }
         }
 
-        // Resetting the HSM invalidates our session
+        // Resetting the HSM invalidates our session (and any pooled ones)
         session.abort();
+
+        if let Some(pool) = &self.pool {
+            pool.clear();
+        }
+
         Ok(())
     }
 
@@ -847,6 +999,12 @@ impl Client {
         /// How frequently to poll the device after it's been reset (200ms)
         const DEVICE_POLL_INTERVAL_MS: u64 = 200;
 
+        ensure!(
+            self.pool.is_none(),
+            ErrorKind::CreateFailed,
+            "can't reconnect pooled clients with default credentials"
+        );
+
         // Warn people and give them a brief grace period to avoid oblitering their HSM
         warn!("factory resetting HSM device! all data will be lost!");
         thread::sleep(Duration::from_millis(DEVICE_RESET_WAIT_MS));
@@ -855,7 +1013,7 @@ impl Client {
         self.reset_device()?;
 
         // Configure default credentials
-        self.credentials = Some(Credentials::default());
+        self.credentials = Some(Credentials::default().into());
 
         let deadline = SystemTime::now() + timeout;
 
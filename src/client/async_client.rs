@@ -0,0 +1,1265 @@
+//! Asynchronous YubiHSM client.
+//!
+//! [`AsyncClient`] provides the same commands as [`Client`][`crate::Client`],
+//! but communicates with the HSM using an [`AsyncConnector`], and is
+//! available when the `async` cargo feature is enabled.
+
+use super::{Error, ErrorKind};
+use crate::{
+    asymmetric::{self, commands::*, PublicKey},
+    attestation::{self, commands::*},
+    audit::{commands::*, *},
+    authentication::{
+        self, commands::*, Authenticator, CredentialSource, Credentials, SessionKeyProvider,
+    },
+    capability::Capability,
+    command::{self, Command},
+    connector::AsyncConnector,
+    device::{self, commands::*, StorageInfo},
+    domain::Domain,
+    ecdsa::commands::*,
+    ed25519::{self, commands::*},
+    hmac::{self, commands::*},
+    object::{self, commands::*, generate},
+    opaque::{self, commands::*},
+    otp::{self, commands::*},
+    rsa::{self, oaep::commands::*, pkcs1::commands::*, pss::commands::*, SignatureAlgorithm},
+    serialization::{deserialize, serialize},
+    session::{self, AsyncSession},
+    template::{commands::*, Template},
+    uuid,
+    wrap::{self, commands::*},
+};
+use sha2::Sha256;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::sync::Mutex;
+
+#[cfg(feature = "passwords")]
+use {std::time::SystemTime, tokio::time};
+
+#[cfg(feature = "untested")]
+use crate::{
+    algorithm::Algorithm,
+    ecdh::{self, commands::*},
+    ssh::{self, commands::*},
+};
+
+#[cfg(any(doc, docsrs))]
+use crate::ecdsa;
+
+/// Asynchronous YubiHSM client: the non-blocking counterpart of
+/// [`Client`][`crate::Client`].
+///
+/// Commands have identical semantics to their [`Client`][`crate::Client`]
+/// equivalents, including transparently reopening sessions which have timed
+/// out or exceeded the SCP03 message limit.
+#[derive(Clone)]
+pub struct AsyncClient {
+    /// Connector for communicating with the HSM
+    connector: AsyncConnector,
+
+    /// Encrypted session with the HSM (if we have one open)
+    session: Arc<Mutex<Option<AsyncSession>>>,
+
+    /// Cached credentials (key ID and session key provider) for
+    /// reconnecting closed sessions
+    credentials: Option<Authenticator>,
+}
+
+impl AsyncClient {
+    /// Open a connection via an [`AsyncConnector`] to a YubiHSM, returning a
+    /// `yubihsm::AsyncClient`.
+    pub async fn open(
+        connector: AsyncConnector,
+        credentials: Credentials,
+        reconnect: bool,
+    ) -> Result<Self, Error> {
+        Self::open_with_authenticator(connector, credentials.into(), reconnect).await
+    }
+
+    /// Open a connection via an [`AsyncConnector`] to a YubiHSM,
+    /// authenticating with the given key ID and obtaining session keys from
+    /// the given [`SessionKeyProvider`]
+    pub async fn open_with_provider(
+        connector: AsyncConnector,
+        authentication_key_id: object::Id,
+        provider: impl SessionKeyProvider + 'static,
+        reconnect: bool,
+    ) -> Result<Self, Error> {
+        Self::open_with_authenticator(
+            connector,
+            Authenticator::new(authentication_key_id, provider),
+            reconnect,
+        )
+        .await
+    }
+
+    /// Open a connection to a YubiHSM, authenticating with the given
+    /// authenticator
+    async fn open_with_authenticator(
+        connector: AsyncConnector,
+        authenticator: Authenticator,
+        reconnect: bool,
+    ) -> Result<Self, Error> {
+        let mut client = Self::with_authenticator(connector, authenticator);
+        client.connect().await?;
+
+        // Clear credentials if reconnecting has been disabled
+        if !reconnect {
+            client.credentials = None;
+        }
+
+        Ok(client)
+    }
+
+    /// Open a connection via an [`AsyncConnector`] to a YubiHSM, loading the
+    /// credentials from the given [`CredentialSource`]
+    pub async fn open_with_source(
+        connector: AsyncConnector,
+        source: impl CredentialSource,
+        reconnect: bool,
+    ) -> Result<Self, Error> {
+        Self::open(connector, source.credentials()?, reconnect).await
+    }
+
+    /// Create a `yubihsm::AsyncClient`, but defer connecting until `connect()` is called.
+    pub fn create(connector: AsyncConnector, credentials: Credentials) -> Result<Self, Error> {
+        Ok(Self::with_authenticator(connector, credentials.into()))
+    }
+
+    /// Create a `yubihsm::AsyncClient` which authenticates with the given
+    /// authenticator
+    fn with_authenticator(connector: AsyncConnector, authenticator: Authenticator) -> Self {
+        Self {
+            connector,
+            session: Arc::new(Mutex::new(None)),
+            credentials: Some(authenticator),
+        }
+    }
+
+    /// Borrow this client's YubiHSM connector (which is `Clone`able)
+    pub fn connector(&self) -> &AsyncConnector {
+        &self.connector
+    }
+
+    /// Connect to the HSM (idempotently, i.e. returns success if we have
+    /// an open connection already)
+    pub async fn connect(&self) -> Result<(), Error> {
+        self.session().await?;
+        Ok(())
+    }
+
+    /// Get current `AsyncSession` (either opening a new one or returning an
+    /// already open one).
+    pub async fn session(&self) -> Result<session::AsyncGuard<'_>, Error> {
+        let mut session_mutex_guard = self.session.lock().await;
+
+        if let Some(session) = session_mutex_guard.as_ref() {
+            if session.is_open() {
+                return Ok(session::AsyncGuard::new(session_mutex_guard));
+            }
+        }
+
+        // If we don't have an open session, create a new one
+        let session = AsyncSession::open_async(
+            self.connector.clone(),
+            self.credentials.as_ref().ok_or_else(|| {
+                format_err!(
+                    ErrorKind::AuthenticationError,
+                    "session reconnection disabled"
+                )
+            })?,
+            session::Timeout::default(),
+        )
+        .await?;
+
+        *session_mutex_guard = Some(session);
+        Ok(session::AsyncGuard::new(session_mutex_guard))
+    }
+
+    /// Ping the HSM, ensuring we have a live connection and returning the
+    /// end-to-end latency.
+    pub async fn ping(&self) -> Result<Duration, Error> {
+        let t = Instant::now();
+        let uuid = uuid::new_v4().to_string();
+        let response = self.echo(uuid.as_bytes()).await?;
+
+        ensure!(
+            uuid.as_bytes() == response.as_slice(),
+            ErrorKind::ResponseError,
+            "expected {}, got {}",
+            uuid,
+            String::from_utf8_lossy(&response)
+        );
+
+        Ok(Instant::now().duration_since(t))
+    }
+
+    /// Encrypt a command, send it to the HSM, then read and decrypt the response.
+    async fn send_command<T: Command>(&self, command: T) -> Result<T::ResponseType, Error> {
+        let mut session = self.session().await?;
+
+        match session.send_command(&command).await {
+            Ok(response) => Ok(response),
+            Err(err) if *err.kind() == session::ErrorKind::CommandLimitExceeded => {
+                // Release the session mutex, then rekey by opening a new
+                // session and retry (the original command was never sent)
+                drop(session);
+                Ok(self.session().await?.send_command(&command).await?)
+            }
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    //
+    // HSM Commands
+    // <https://developers.yubico.com/YubiHSM2/Commands/>
+    //
+
+    /// Blink the HSM's LEDs (to identify it) for the given number of seconds.
+    ///
+    /// <https://developers.yubico.com/YubiHSM2/Commands/Blink_Device.html>
+    pub async fn blink_device(&self, num_seconds: u8) -> Result<(), Error> {
+        self.send_command(BlinkDeviceCommand { num_seconds })
+            .await?;
+        Ok(())
+    }
+
+    /// Decrypt data encrypted with RSA-OAEP
+    ///
+    /// <https://developers.yubico.com/YubiHSM2/Commands/Decrypt_Oaep.html>
+    pub async fn decrypt_oaep<T>(
+        &self,
+        key_id: object::Id,
+        mgf1_hash_alg: rsa::mgf::Algorithm,
+        data: T,
+        label_hash: Vec<u8>,
+    ) -> Result<rsa::oaep::DecryptedData, Error>
+    where
+        T: Into<Vec<u8>>,
+    {
+        Ok(self
+            .send_command(DecryptOaepCommand {
+                key_id,
+                mgf1_hash_alg,
+                data: data.into(),
+                label_hash,
+            })
+            .await?
+            .into())
+    }
+
+    /// Delete an object of the given ID and type.
+    ///
+    /// <https://developers.yubico.com/YubiHSM2/Commands/Delete_Object.html>
+    pub async fn delete_object(
+        &self,
+        object_id: object::Id,
+        object_type: object::Type,
+    ) -> Result<(), Error> {
+        self.send_command(DeleteObjectCommand {
+            object_id,
+            object_type,
+        })
+        .await?;
+        Ok(())
+    }
+
+    /// Elliptic Curve Diffie-Hellman: derive a shared secret via key exchange.
+    ///
+    /// **WARNING**: This functionality has not been tested and has not yet been
+    /// confirmed to actually work! USE AT YOUR OWN RISK!
+    ///
+    /// You will need to enable the `untested` cargo feature to use it.
+    ///
+    /// <https://developers.yubico.com/YubiHSM2/Commands/Derive_Ecdh.html>
+    #[cfg(feature = "untested")]
+    pub async fn derive_ecdh(
+        &self,
+        key_id: object::Id,
+        public_key: ecdh::UncompressedPoint,
+    ) -> Result<ecdh::UncompressedPoint, Error> {
+        Ok(self
+            .send_command(DeriveEcdhCommand { key_id, public_key })
+            .await?
+            .into())
+    }
+
+    /// Get information about the HSM device.
+    ///
+    /// <https://developers.yubico.com/YubiHSM2/Commands/Device_Info.html>
+    pub async fn device_info(&self) -> Result<device::Info, Error> {
+        Ok(self.send_command(DeviceInfoCommand {}).await?.into())
+    }
+
+    /// Echo a message sent to the HSM.
+    ///
+    /// <https://developers.yubico.com/YubiHSM2/Commands/Echo.html>
+    pub async fn echo<M>(&self, msg: M) -> Result<Vec<u8>, Error>
+    where
+        M: Into<Vec<u8>>,
+    {
+        Ok(self
+            .send_command(EchoCommand {
+                message: msg.into(),
+            })
+            .await?
+            .0)
+    }
+
+    /// Export an encrypted object from the HSM using the given key-wrapping key.
+    ///
+    /// <https://developers.yubico.com/YubiHSM2/Commands/Export_Wrapped.html>
+    pub async fn export_wrapped(
+        &self,
+        wrap_key_id: object::Id,
+        object_type: object::Type,
+        object_id: object::Id,
+    ) -> Result<wrap::Message, Error> {
+        Ok(self
+            .send_command(ExportWrappedCommand {
+                wrap_key_id,
+                object_type,
+                object_id,
+            })
+            .await?
+            .0)
+    }
+
+    /// Generate a new asymmetric key within the HSM.
+    ///
+    /// <https://developers.yubico.com/YubiHSM2/Commands/Generate_Asymmetric_Key.html>
+    pub async fn generate_asymmetric_key(
+        &self,
+        key_id: object::Id,
+        label: object::Label,
+        domains: Domain,
+        capabilities: Capability,
+        algorithm: asymmetric::Algorithm,
+    ) -> Result<object::Id, Error> {
+        Ok(self
+            .send_command(GenAsymmetricKeyCommand(generate::Params {
+                key_id,
+                label,
+                domains,
+                capabilities,
+                algorithm: algorithm.into(),
+            }))
+            .await?
+            .key_id)
+    }
+
+    /// Generate a new HMAC key within the HSM.
+    ///
+    /// <https://developers.yubico.com/YubiHSM2/Commands/Generate_Hmac_Key.html>
+    pub async fn generate_hmac_key(
+        &self,
+        key_id: object::Id,
+        label: object::Label,
+        domains: Domain,
+        capabilities: Capability,
+        algorithm: hmac::Algorithm,
+    ) -> Result<object::Id, Error> {
+        Ok(self
+            .send_command(GenHmacKeyCommand(generate::Params {
+                key_id,
+                label,
+                domains,
+                capabilities,
+                algorithm: algorithm.into(),
+            }))
+            .await?
+            .key_id)
+    }
+
+    /// Generate a new wrap key within the HSM.
+    ///
+    /// Delegated capabilities are the set of `Capability` bits that an object is allowed to have
+    /// when imported or exported using the wrap key.
+    ///
+    /// <https://developers.yubico.com/YubiHSM2/Commands/Generate_Wrap_Key.html>
+    pub async fn generate_wrap_key(
+        &self,
+        key_id: object::Id,
+        label: object::Label,
+        domains: Domain,
+        capabilities: Capability,
+        delegated_capabilities: Capability,
+        algorithm: wrap::Algorithm,
+    ) -> Result<object::Id, Error> {
+        Ok(self
+            .send_command(GenWrapKeyCommand {
+                params: generate::Params {
+                    key_id,
+                    label,
+                    domains,
+                    capabilities,
+                    algorithm: algorithm.into(),
+                },
+                delegated_capabilities,
+            })
+            .await?
+            .key_id)
+    }
+
+    /// Get audit logs from the HSM device.
+    ///
+    /// <https://developers.yubico.com/YubiHSM2/Commands/Get_Log_Entries.html>
+    pub async fn get_log_entries(&self) -> Result<LogEntries, Error> {
+        self.send_command(GetLogEntriesCommand {}).await
+    }
+
+    /// Get information about an object.
+    ///
+    /// <https://developers.yubico.com/YubiHSM2/Commands/Get_Object_Info.html>
+    pub async fn get_object_info(
+        &self,
+        object_id: object::Id,
+        object_type: object::Type,
+    ) -> Result<object::Info, Error> {
+        Ok(self
+            .send_command(GetObjectInfoCommand(object::Handle::new(
+                object_id,
+                object_type,
+            )))
+            .await?
+            .0)
+    }
+
+    /// Get an opaque object stored in the HSM.
+    ///
+    /// <https://developers.yubico.com/YubiHSM2/Commands/Get_Opaque.html>
+    pub async fn get_opaque(&self, object_id: object::Id) -> Result<Vec<u8>, Error> {
+        Ok(self.send_command(GetOpaqueCommand { object_id }).await?.0)
+    }
+
+    /// Get the audit policy setting for a particular command.
+    ///
+    /// <https://developers.yubico.com/YubiHSM2/Commands/Get_Option.html>
+    pub async fn get_command_audit_option(
+        &self,
+        command: command::Code,
+    ) -> Result<AuditOption, Error> {
+        let command_audit_options = self.get_commands_audit_options().await?;
+        Ok(command_audit_options
+            .iter()
+            .find(|opt| opt.command_type() == command)
+            .map(AuditCommand::audit_option)
+            .unwrap_or(AuditOption::Off))
+    }
+
+    /// Get the audit policy settings for all commands.
+    ///
+    /// <https://developers.yubico.com/YubiHSM2/Commands/Get_Option.html>
+    pub async fn get_commands_audit_options(&self) -> Result<Vec<AuditCommand>, Error> {
+        let response = self
+            .send_command(GetOptionCommand {
+                tag: AuditTag::Command,
+            })
+            .await?;
+
+        Ok(deserialize(&response.0)?)
+    }
+
+    /// Get the forced auditing global option: when enabled, the device will
+    /// refuse operations if the [log store] becomes full.
+    ///
+    /// <https://developers.yubico.com/YubiHSM2/Commands/Get_Option.html>
+    ///
+    /// [log store]: https://developers.yubico.com/YubiHSM2/Concepts/Logs.html
+    pub async fn get_force_audit_option(&self) -> Result<AuditOption, Error> {
+        let response = self
+            .send_command(GetOptionCommand {
+                tag: AuditTag::Force,
+            })
+            .await?;
+
+        ensure!(
+            response.0.len() == 1,
+            ErrorKind::ProtocolError,
+            "expected 1-byte response, got {}",
+            response.0.len()
+        );
+
+        AuditOption::from_u8(response.0[0])
+            .map_err(|e| format_err!(ErrorKind::ProtocolError, e).into())
+    }
+
+    /// Get the FIPS operation global option
+    ///
+    /// <https://developers.yubico.com/YubiHSM2/Commands/Get_Option.html>
+    pub async fn get_fips_option(&self) -> Result<AuditOption, Error> {
+        let response = self
+            .send_command(GetOptionCommand {
+                tag: AuditTag::Fips,
+            })
+            .await?;
+
+        ensure!(
+            response.0.len() == 1,
+            ErrorKind::ProtocolError,
+            "expected 1-byte response, got {}",
+            response.0.len()
+        );
+
+        AuditOption::from_u8(response.0[0])
+            .map_err(|e| format_err!(ErrorKind::ProtocolError, e).into())
+    }
+
+    /// Get some number of bytes of pseudo random data generated on the device.
+    ///
+    /// <https://developers.yubico.com/YubiHSM2/Commands/Get_Pseudo_Random.html>
+    pub async fn get_pseudo_random(&self, bytes: usize) -> Result<Vec<u8>, Error> {
+        ensure!(
+            bytes <= MAX_RAND_BYTES,
+            ErrorKind::ProtocolError,
+            "requested number of bytes too large: {} (max: {})",
+            bytes,
+            MAX_RAND_BYTES
+        );
+
+        Ok(self
+            .send_command(GetPseudoRandomCommand {
+                bytes: bytes as u16,
+            })
+            .await?
+            .bytes)
+    }
+
+    /// Get the public key for an asymmetric key stored on the device.
+    ///
+    /// <https://developers.yubico.com/YubiHSM2/Commands/Get_Public_Key.html>
+    pub async fn get_public_key(&self, key_id: object::Id) -> Result<PublicKey, Error> {
+        Ok(self
+            .send_command(GetPublicKeyCommand { key_id })
+            .await?
+            .into())
+    }
+
+    /// Get storage info (i.e. currently free storage) from the HSM device.
+    ///
+    /// <https://developers.yubico.com/YubiHSM2/Commands/Get_Storage_Info.html>
+    pub async fn get_storage_info(&self) -> Result<StorageInfo, Error> {
+        Ok(self.send_command(GetStorageInfoCommand {}).await?.into())
+    }
+
+    /// Get a certificate template (i.e. for SSH CA) stored in the HSM.
+    ///
+    /// <https://developers.yubico.com/YubiHSM2/Commands/Get_Template.html>
+    pub async fn get_template(&self, object_id: object::Id) -> Result<Vec<u8>, Error> {
+        Ok(self.send_command(GetTemplateCommand { object_id }).await?.0)
+    }
+
+    /// Import an encrypted object from the HSM using the given key-wrapping key.
+    ///
+    /// <https://developers.yubico.com/YubiHSM2/Commands/Import_Wrapped.html>
+    pub async fn import_wrapped<M>(
+        &self,
+        wrap_key_id: object::Id,
+        wrap_message: M,
+    ) -> Result<object::Handle, Error>
+    where
+        M: Into<wrap::Message>,
+    {
+        let wrap::Message { nonce, ciphertext } = wrap_message.into();
+
+        let response = self
+            .send_command(ImportWrappedCommand {
+                wrap_key_id,
+                nonce,
+                ciphertext,
+            })
+            .await?;
+
+        Ok(object::Handle::new(
+            response.object_id,
+            response.object_type,
+        ))
+    }
+
+    /// List objects visible from the current session.
+    ///
+    /// Optionally apply a set of provided `filters` which select objects
+    /// based on their attributes.
+    ///
+    /// <https://developers.yubico.com/YubiHSM2/Commands/List_Objects.html>
+    pub async fn list_objects(
+        &self,
+        filters: &[object::Filter],
+    ) -> Result<Vec<object::Entry>, Error> {
+        let mut filter_bytes = vec![];
+
+        for filter in filters {
+            filter.serialize(&mut filter_bytes)?;
+        }
+
+        Ok(self.send_command(ListObjectsCommand(filter_bytes)).await?.0)
+    }
+
+    /// Put an existing asymmetric key into the HSM.
+    ///
+    /// <https://developers.yubico.com/YubiHSM2/Commands/Put_Asymmetric.html>
+    pub async fn put_asymmetric_key<K>(
+        &self,
+        key_id: object::Id,
+        label: object::Label,
+        domains: Domain,
+        capabilities: Capability,
+        algorithm: asymmetric::Algorithm,
+        key_bytes: K,
+    ) -> Result<object::Id, Error>
+    where
+        K: Into<Vec<u8>>,
+    {
+        let data = key_bytes.into();
+
+        if data.len() != algorithm.key_len() {
+            fail!(
+                ErrorKind::ProtocolError,
+                "invalid key length for {:?}: {} (expected {})",
+                algorithm,
+                data.len(),
+                algorithm.key_len()
+            );
+        }
+
+        Ok(self
+            .send_command(PutAsymmetricKeyCommand {
+                params: object::put::Params {
+                    id: key_id,
+                    label,
+                    domains,
+                    capabilities,
+                    algorithm: algorithm.into(),
+                },
+                data,
+            })
+            .await?
+            .key_id)
+    }
+
+    /// Put an existing `authentication::Key` into the HSM.
+    ///
+    /// <https://developers.yubico.com/YubiHSM2/Commands/Put_Authentication_Key.html>
+    pub async fn put_authentication_key<K>(
+        &self,
+        key_id: object::Id,
+        label: object::Label,
+        domains: Domain,
+        capabilities: Capability,
+        delegated_capabilities: Capability,
+        algorithm: authentication::Algorithm,
+        authentication_key: K,
+    ) -> Result<object::Id, Error>
+    where
+        K: Into<authentication::Key>,
+    {
+        Ok(self
+            .send_command(PutAuthenticationKeyCommand {
+                params: object::put::Params {
+                    id: key_id,
+                    label,
+                    domains,
+                    capabilities,
+                    algorithm: algorithm.into(),
+                },
+                delegated_capabilities,
+                authentication_key: authentication_key.into(),
+            })
+            .await?
+            .key_id)
+    }
+
+    /// Put an existing HMAC key into the HSM.
+    ///
+    /// <https://developers.yubico.com/YubiHSM2/Commands/Put_Hmac_Key.html>
+    pub async fn put_hmac_key<K>(
+        &self,
+        key_id: object::Id,
+        label: object::Label,
+        domains: Domain,
+        capabilities: Capability,
+        algorithm: hmac::Algorithm,
+        key_bytes: K,
+    ) -> Result<object::Id, Error>
+    where
+        K: Into<Vec<u8>>,
+    {
+        let hmac_key = key_bytes.into();
+
+        if hmac_key.len() < HMAC_MIN_KEY_SIZE || hmac_key.len() > algorithm.max_key_len() {
+            fail!(
+                ErrorKind::ProtocolError,
+                "invalid key length for {:?}: {} (min {}, max {})",
+                algorithm,
+                hmac_key.len(),
+                HMAC_MIN_KEY_SIZE,
+                algorithm.max_key_len()
+            );
+        }
+
+        Ok(self
+            .send_command(PutHmacKeyCommand {
+                params: object::put::Params {
+                    id: key_id,
+                    label,
+                    domains,
+                    capabilities,
+                    algorithm: algorithm.into(),
+                },
+                hmac_key,
+            })
+            .await?
+            .key_id)
+    }
+
+    /// Put an opaque object (X.509 certificate or other bytestring) into the HSM.
+    ///
+    /// <https://developers.yubico.com/YubiHSM2/Commands/Put_Opaque.html>
+    pub async fn put_opaque<B>(
+        &self,
+        object_id: object::Id,
+        label: object::Label,
+        domains: Domain,
+        capabilities: Capability,
+        algorithm: opaque::Algorithm,
+        opaque_data: B,
+    ) -> Result<object::Id, Error>
+    where
+        B: Into<Vec<u8>>,
+    {
+        Ok(self
+            .send_command(PutOpaqueCommand {
+                params: object::put::Params {
+                    id: object_id,
+                    label,
+                    domains,
+                    capabilities,
+                    algorithm: algorithm.into(),
+                },
+                data: opaque_data.into(),
+            })
+            .await?
+            .object_id)
+    }
+
+    /// Put an existing OTP AEAD key into the HSM.
+    ///
+    /// <https://developers.yubico.com/YubiHSM2/Commands/Put_Otp_Aead_Key.html>
+    pub async fn put_otp_aead_key<K>(
+        &self,
+        key_id: object::Id,
+        label: object::Label,
+        domains: Domain,
+        capabilities: Capability,
+        algorithm: otp::Algorithm,
+        key_bytes: K,
+    ) -> Result<object::Id, Error>
+    where
+        K: Into<Vec<u8>>,
+    {
+        let data = key_bytes.into();
+
+        if data.len() != algorithm.key_len() {
+            fail!(
+                ErrorKind::ProtocolError,
+                "invalid key length for {:?}: {} (expected {})",
+                algorithm,
+                data.len(),
+                algorithm.key_len()
+            );
+        }
+
+        Ok(self
+            .send_command(PutOtpAeadKeyCommand {
+                params: object::put::Params {
+                    id: key_id,
+                    label,
+                    domains,
+                    capabilities,
+                    algorithm: algorithm.into(),
+                },
+                data,
+            })
+            .await?
+            .key_id)
+    }
+
+    /// Put an existing wrap key into the HSM.
+    ///
+    /// <https://developers.yubico.com/YubiHSM2/Commands/Put_Wrap_Key.html>
+    pub async fn put_wrap_key<K>(
+        &self,
+        key_id: object::Id,
+        label: object::Label,
+        domains: Domain,
+        capabilities: Capability,
+        delegated_capabilities: Capability,
+        algorithm: wrap::Algorithm,
+        key_bytes: K,
+    ) -> Result<object::Id, Error>
+    where
+        K: Into<Vec<u8>>,
+    {
+        let data = key_bytes.into();
+
+        if data.len() != algorithm.key_len() {
+            fail!(
+                ErrorKind::ProtocolError,
+                "invalid key length for {:?}: {} (expected {})",
+                algorithm,
+                data.len(),
+                algorithm.key_len()
+            );
+        }
+
+        Ok(self
+            .send_command(PutWrapKeyCommand {
+                params: object::put::Params {
+                    id: key_id,
+                    label,
+                    domains,
+                    capabilities,
+                    algorithm: algorithm.into(),
+                },
+                delegated_capabilities,
+                data,
+            })
+            .await?
+            .key_id)
+    }
+
+    /// Put a template object (i.e. for SSH CA) into the HSM.
+    ///
+    /// Use the `yubihsm::ssh::Template` type for SSH CA templates.
+    ///
+    /// <https://developers.yubico.com/YubiHSM2/Commands/Put_Template.html>
+    pub async fn put_template<T>(
+        &self,
+        object_id: object::Id,
+        label: object::Label,
+        domains: Domain,
+        capabilities: Capability,
+        template: T,
+    ) -> Result<object::Id, Error>
+    where
+        T: Into<Template>,
+    {
+        let template: Template = template.into();
+
+        Ok(self
+            .send_command(PutTemplateCommand {
+                params: object::put::Params {
+                    id: object_id,
+                    label,
+                    domains,
+                    capabilities,
+                    algorithm: template.algorithm().into(),
+                },
+                data: template.as_ref().into(),
+            })
+            .await?
+            .object_id)
+    }
+
+    /// Reset the HSM to a factory default state and reboot, clearing all
+    /// stored objects and restoring the default auth key.
+    ///
+    /// **WARNING:** This wipes all keys and other data from the HSM! Make
+    /// absolutely sure you want to use this!
+    ///
+    /// <https://developers.yubico.com/YubiHSM2/Commands/Reset_Device.html>
+    pub async fn reset_device(&self) -> Result<(), Error> {
+        let mut session = self.session().await?;
+
+        let result = session.send_command(&ResetDeviceCommand {}).await;
+        if let Err(e) = &result {
+            if *e.kind() == session::ErrorKind::ProtocolError {
+                // real devices can send protocol errors when the request has been accepted
+                debug!("error sending reset command: {}", e);
+            } else {
+                // other errors, such as insufficient permissions, should be propagated
+                result?;
+            }
+        }
+
+        // Resetting the HSM invalidates our session
+        session.abort();
+        Ok(())
+    }
+
+    /// Reset the HSM to a factory default state and reboot, clearing all
+    /// stored objects and restoring the default auth key. This method further
+    /// attempts to wait for the HSM to finish resetting and then attempts to
+    /// reauthenticate with the default credentials.
+    ///
+    /// **WARNING:** This wipes all keys and other data from the HSM! Make
+    /// absolutely sure you want to use this!
+    ///
+    /// <https://developers.yubico.com/YubiHSM2/Commands/Reset_Device.html>
+    #[cfg(feature = "passwords")]
+    pub async fn reset_device_and_reconnect(&mut self, timeout: Duration) -> Result<(), Error> {
+        /// How long to initially wait for a device reset to complete (1s)
+        const DEVICE_RESET_WAIT_MS: u64 = 1000;
+
+        /// How frequently to poll the device after it's been reset (200ms)
+        const DEVICE_POLL_INTERVAL_MS: u64 = 200;
+
+        // Warn people and give them a brief grace period to avoid oblitering their HSM
+        warn!("factory resetting HSM device! all data will be lost!");
+        time::sleep(Duration::from_millis(DEVICE_RESET_WAIT_MS)).await;
+
+        // Reset the device. This will invalidate the previous session.
+        self.reset_device().await?;
+
+        // Configure default credentials
+        self.credentials = Some(Credentials::default().into());
+
+        let deadline = SystemTime::now() + timeout;
+
+        info!("waiting for device reset to complete");
+        time::sleep(Duration::from_millis(DEVICE_RESET_WAIT_MS)).await;
+
+        // Attempt to reconnect to the device with the default credentials
+        loop {
+            match self.connect().await {
+                Ok(_) => {
+                    debug!("successfully reconnected to HSM after reset!");
+                    return Ok(());
+                }
+                Err(e) => {
+                    // If we're past the deadline, return an error
+                    if SystemTime::now() >= deadline {
+                        fail!(
+                            ErrorKind::CreateFailed,
+                            "timed out after {} seconds connecting to HSM after reset: {}",
+                            timeout.as_secs(),
+                            e
+                        )
+                    } else {
+                        debug!("error reconnecting to HSM: {}", e);
+                        time::sleep(Duration::from_millis(DEVICE_POLL_INTERVAL_MS)).await
+                    }
+                }
+            }
+        }
+    }
+
+    /// Configure the audit policy settings for a particular command, e.g. auditing
+    /// should be `On`, `Off`, or `Fix` (i.e. fixed permanently on).
+    ///
+    /// <https://developers.yubico.com/YubiHSM2/Commands/Set_Option.html>
+    pub async fn set_command_audit_option(
+        &self,
+        command: command::Code,
+        audit_option: AuditOption,
+    ) -> Result<(), Error> {
+        self.send_command(SetOptionCommand {
+            tag: AuditTag::Command,
+            length: 2,
+            value: serialize(&AuditCommand(command, audit_option))?,
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    /// Put the forced auditing global option: when enabled, the device will
+    /// refuse operations if the [log store] becomes full.
+    ///
+    /// Options are `On`, `Off`, or `Fix` (i.e. fixed permanently on)
+    ///
+    /// <https://developers.yubico.com/YubiHSM2/Commands/Put_Option.html>
+    ///
+    /// [log store]: https://developers.yubico.com/YubiHSM2/Concepts/Logs.html
+    pub async fn set_force_audit_option(&self, option: AuditOption) -> Result<(), Error> {
+        self.send_command(SetOptionCommand {
+            tag: AuditTag::Force,
+            length: 1,
+            value: vec![option.to_u8()],
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    /// Put the FIPS global option: when enabled, it disables algorithms that are
+    /// not allowed by FIPS 140.
+    ///
+    /// Options are `Off`, or `On`
+    ///
+    /// <https://developers.yubico.com/YubiHSM2/Commands/Put_Option.html>
+    pub async fn set_fips_option(&self, option: AuditOption) -> Result<(), Error> {
+        self.send_command(SetOptionCommand {
+            tag: AuditTag::Fips,
+            length: 1,
+            value: vec![option.to_u8()],
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    /// Set the index of the last consumed index of the HSM audit log.
+    ///
+    /// <https://developers.yubico.com/YubiHSM2/Commands/Set_Log_Index.html>
+    pub async fn set_log_index(&self, log_index: u16) -> Result<(), Error> {
+        self.send_command(SetLogIndexCommand { log_index }).await?;
+        Ok(())
+    }
+
+    /// Obtain an X.509 attestation certificate for a key within the HSM.
+    /// This can be used to demonstrate that a given key was generated by
+    /// and stored within a HSM in a non-exportable manner.
+    ///
+    /// The `key_id` is the subject key for which an attestation certificate
+    /// is created, and the`attestation_key_id` will be used to sign the
+    /// attestation certificate.
+    ///
+    /// If no attestation key is given, the device's default attestation key
+    /// will be used, and can be verified against Yubico's certificate.
+    ///
+    /// <https://developers.yubico.com/YubiHSM2/Commands/Sign_Attestation_Certificate.html>
+    pub async fn sign_attestation_certificate(
+        &self,
+        key_id: object::Id,
+        attestation_key_id: Option<object::Id>,
+    ) -> Result<attestation::Certificate, Error> {
+        self.send_command(SignAttestationCertificateCommand {
+            key_id,
+            attestation_key_id: attestation_key_id.unwrap_or(0),
+        })
+        .await
+    }
+
+    /// Compute an ECDSA signature of the given digest (i.e. a precomputed SHA-2 digest)
+    ///
+    /// <https://developers.yubico.com/YubiHSM2/Commands/Sign_Ecdsa.html>
+    ///
+    /// # Security Warning
+    ///
+    /// This is a low-level ECDSA API, and if used incorrectly could potentially
+    /// result in forgeable signatures.
+    ///
+    /// We recommend using the [`ecdsa::Signer`] type instead, which provides a
+    /// high-level, well-typed, misuse resistant API.
+    pub async fn sign_ecdsa_prehash_raw<T>(
+        &self,
+        key_id: object::Id,
+        digest: T,
+    ) -> Result<Vec<u8>, Error>
+    where
+        T: Into<Vec<u8>>,
+    {
+        self.send_command(SignEcdsaCommand {
+            key_id,
+            digest: digest.into(),
+        })
+        .await
+        .map(Into::into)
+    }
+
+    /// Compute an Ed25519 signature with the given key ID.
+    ///
+    /// <https://developers.yubico.com/YubiHSM2/Commands/Sign_Eddsa.html>
+    pub async fn sign_ed25519<T>(
+        &self,
+        key_id: object::Id,
+        data: T,
+    ) -> Result<ed25519::Signature, Error>
+    where
+        T: Into<Vec<u8>>,
+    {
+        self.send_command(SignEddsaCommand {
+            key_id,
+            data: data.into(),
+        })
+        .await?
+        .signature()
+    }
+
+    /// Compute an HMAC tag of the given data with the given key ID.
+    ///
+    /// <https://developers.yubico.com/YubiHSM2/Commands/Sign_Hmac.html>
+    pub async fn sign_hmac<M>(&self, key_id: object::Id, msg: M) -> Result<hmac::Tag, Error>
+    where
+        M: Into<Vec<u8>>,
+    {
+        Ok(self
+            .send_command(SignHmacCommand {
+                key_id,
+                data: msg.into(),
+            })
+            .await?
+            .into())
+    }
+
+    /// Compute an RSASSA-PKCS#1v1.5 signature of the SHA-256 hash of the given data.
+    ///
+    /// <https://developers.yubico.com/YubiHSM2/Commands/Sign_Pkcs1.html>
+    pub(crate) async fn sign_rsa_pkcs1v15<S: SignatureAlgorithm>(
+        &self,
+        key_id: object::Id,
+        data: &[u8],
+    ) -> Result<rsa::pkcs1::Signature, Error> {
+        Ok(self
+            .send_command(SignPkcs1Command {
+                key_id,
+                digest: S::digest(data).as_slice().into(),
+            })
+            .await?
+            .into())
+    }
+
+    /// Compute an RSASSA-PKCS#1v1.5 signature of the SHA-256 hash of the given data.
+    ///
+    /// <https://developers.yubico.com/YubiHSM2/Commands/Sign_Pkcs1.html>
+    pub async fn sign_rsa_pkcs1v15_sha256(
+        &self,
+        key_id: object::Id,
+        data: &[u8],
+    ) -> Result<rsa::pkcs1::Signature, Error> {
+        self.sign_rsa_pkcs1v15::<Sha256>(key_id, data).await
+    }
+
+    /// Compute an RSASSA-PSS signature of the SHA-256 hash of the given data with the given key ID.
+    ///
+    /// <https://developers.yubico.com/YubiHSM2/Commands/Sign_Pss.html>
+    pub(crate) async fn sign_rsa_pss<S: SignatureAlgorithm>(
+        &self,
+        key_id: object::Id,
+        data: &[u8],
+    ) -> Result<rsa::pss::Signature, Error> {
+        let mut hasher = S::new();
+        hasher.update(data);
+        let digest = hasher.finalize();
+
+        ensure!(
+            digest.len() < rsa::pss::MAX_MESSAGE_SIZE,
+            ErrorKind::ProtocolError,
+            "digest too large to be signed (max: {})",
+            rsa::pss::MAX_MESSAGE_SIZE
+        );
+
+        Ok(self
+            .send_command(SignPssCommand {
+                key_id,
+                mgf1_hash_alg: S::MGF_ALGORITHM,
+                salt_len: digest.as_slice().len() as u16,
+                digest: digest.as_slice().into(),
+            })
+            .await?
+            .into())
+    }
+
+    /// Compute an RSASSA-PSS signature of the SHA-256 hash of the given data with the given key ID.
+    ///
+    /// <https://developers.yubico.com/YubiHSM2/Commands/Sign_Pss.html>
+    pub async fn sign_rsa_pss_sha256(
+        &self,
+        key_id: object::Id,
+        data: &[u8],
+    ) -> Result<rsa::pss::Signature, Error> {
+        self.sign_rsa_pss::<Sha256>(key_id, data).await
+    }
+
+    /// Sign an SSH certificate using the given template.
+    ///
+    /// **WARNING**: This functionality has not been tested and has not yet been
+    /// confirmed to actually work! USE AT YOUR OWN RISK!
+    ///
+    /// You will need to enable the `untested` cargo feature to use it.
+    ///
+    /// <https://developers.yubico.com/YubiHSM2/Commands/Sign_Ssh_Certificate.html>
+    #[cfg(feature = "untested")]
+    pub async fn sign_ssh_certificate<A>(
+        &self,
+        key_id: object::Id,
+        template_id: object::Id,
+        algorithm: A,
+        timestamp: u32,
+        signature: [u8; 32],
+        request: Vec<u8>,
+    ) -> Result<ssh::Certificate, Error>
+    where
+        A: Into<Algorithm>,
+    {
+        Ok(self
+            .send_command(SignSshCertificateCommand {
+                key_id,
+                template_id,
+                algorithm: algorithm.into(),
+                timestamp,
+                signature,
+                request,
+            })
+            .await?
+            .into())
+    }
+
+    /// Decrypt data which was encrypted (using AES-CCM) under a wrap key.
+    ///
+    /// <https://developers.yubico.com/YubiHSM2/Commands/Unwrap_Data.html>
+    pub async fn unwrap_data<M>(
+        &self,
+        wrap_key_id: object::Id,
+        wrap_message: M,
+    ) -> Result<Vec<u8>, Error>
+    where
+        M: Into<wrap::Message>,
+    {
+        let wrap::Message { nonce, ciphertext } = wrap_message.into();
+
+        Ok(self
+            .send_command(UnwrapDataCommand {
+                wrap_key_id,
+                nonce,
+                ciphertext,
+            })
+            .await?
+            .0)
+    }
+
+    /// Verify an HMAC tag of the given data with the given key ID.
+    ///
+    /// <https://developers.yubico.com/YubiHSM2/Commands/Verify_Hmac.html>
+    pub async fn verify_hmac<M, T>(&self, key_id: object::Id, msg: M, tag: T) -> Result<(), Error>
+    where
+        M: Into<Vec<u8>>,
+        T: Into<hmac::Tag>,
+    {
+        let result = self
+            .send_command(VerifyHmacCommand {
+                key_id,
+                tag: tag.into(),
+                data: msg.into(),
+            })
+            .await?;
+
+        if result.0 == 0 {
+            fail!(ErrorKind::ResponseError, "HMAC verification failure")
+        }
+
+        Ok(())
+    }
+
+    /// Encrypt data (with AES-CCM) using the given wrap key.
+    ///
+    /// <https://developers.yubico.com/YubiHSM2/Commands/Wrap_Data.html>
+    pub async fn wrap_data(
+        &self,
+        wrap_key_id: object::Id,
+        plaintext: Vec<u8>,
+    ) -> Result<wrap::Message, Error> {
+        Ok(self
+            .send_command(WrapDataCommand {
+                wrap_key_id,
+                plaintext,
+            })
+            .await?
+            .0)
+    }
+}
@@ -1,7 +1,7 @@
 //! YubiHSM client errors
 
 use crate::{
-    connector, device,
+    authentication, connector, device,
     error::{BoxError, Context},
     serialization, session,
 };
@@ -63,6 +63,12 @@ impl ErrorKind {
     }
 }
 
+impl From<authentication::Error> for Error {
+    fn from(err: authentication::Error) -> Self {
+        ErrorKind::AuthenticationError.context(err).into()
+    }
+}
+
 impl From<connector::Error> for Error {
     fn from(err: connector::Error) -> Self {
         ErrorKind::ConnectorError.context(err).into()
@@ -0,0 +1,102 @@
+//! Background session keepalive.
+//!
+//! Sessions with the YubiHSM 2 expire after 30 seconds of inactivity, after
+//! which the next command has to pay for a full `CreateSession` and
+//! `AuthenticateSession` handshake. A [`Keepalive`] avoids this latency by
+//! sending an `Echo` command whenever the session has been idle for the
+//! configured interval, and by replacing sessions which are due to be rekeyed
+//! before they are needed by a command. Clients backed by a session pool
+//! have each of the pool's idle sessions kept alive instead.
+
+use super::{Client, Error};
+use std::{
+    sync::mpsc::{self, RecvTimeoutError},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+/// Handle to a background thread which keeps a [`Client`]'s session alive.
+///
+/// The thread is stopped when this handle is dropped.
+pub struct Keepalive {
+    /// Channel used to signal the thread to stop
+    stop: Option<mpsc::Sender<()>>,
+
+    /// Keepalive thread
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Keepalive {
+    /// Spawn a keepalive thread for the given client, which sends an `Echo`
+    /// once its session has been idle for `interval`.
+    ///
+    /// The interval should be comfortably shorter than the client's session
+    /// timeout.
+    pub fn spawn(client: Client, interval: Duration) -> Self {
+        let (stop, stopped) = mpsc::channel();
+
+        let thread = thread::spawn(move || {
+            let mut wait = interval;
+
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(wait) {
+                wait = refresh(&client, interval).unwrap_or_else(|e| {
+                    debug!("keepalive failed: {}", e);
+                    interval
+                });
+            }
+        });
+
+        Self {
+            stop: Some(stop),
+            thread: Some(thread),
+        }
+    }
+
+    /// Stop the keepalive thread, waiting for it to exit
+    pub fn stop(self) {
+        drop(self)
+    }
+}
+
+impl Drop for Keepalive {
+    fn drop(&mut self) {
+        drop(self.stop.take());
+
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Ping the client's session if it has been idle for at least `max_idle`,
+/// and open a new one if it's closed or due to be rekeyed. For clients backed
+/// by a [`Pool`][super::Pool], the pool's idle sessions are pinged instead.
+///
+/// Returns how long to wait before the session should next be refreshed.
+fn refresh(client: &Client, max_idle: Duration) -> Result<Duration, Error> {
+    if let Some(pool) = &client.pool {
+        return pool.refresh(max_idle);
+    }
+
+    let session_guard = client.session.lock().unwrap();
+
+    let idle_time = match session_guard.as_ref() {
+        Some(session) if session.is_open() && !client.needs_rekey(session) => session.idle_time(),
+        _ => {
+            // Release the lock and (re)open the session in the background
+            drop(session_guard);
+            debug!("keepalive: opening new session");
+            client.connect()?;
+            return Ok(max_idle);
+        }
+    };
+
+    drop(session_guard);
+
+    if idle_time < max_idle {
+        return Ok(max_idle - idle_time);
+    }
+
+    client.ping()?;
+    Ok(max_idle)
+}
@@ -0,0 +1,292 @@
+//! Pool of concurrently open sessions with the HSM.
+//!
+//! The YubiHSM 2 supports up to 16 concurrent sessions. A [`Pool`] opens
+//! several of them (each with its own SCP03 secure channel) over a shared
+//! [`Connector`], and lends one out for the duration of each command, so
+//! multiple threads can issue commands without queueing behind a single
+//! session.
+//!
+//! Sessions which have timed out or exceeded the SCP03 message limit are
+//! discarded when they are returned to the pool, and replaced with freshly
+//! authenticated ones on demand.
+
+use super::{Client, Error, ErrorKind};
+use crate::{
+    authentication::{Authenticator, Credentials},
+    connector::Connector,
+    device::commands::EchoCommand,
+    session::{self, Session},
+};
+use std::{
+    mem,
+    ops::{Deref, DerefMut},
+    sync::{Arc, Condvar, Mutex},
+    time::Duration,
+};
+
+/// Maximum number of concurrent sessions supported by the YubiHSM 2
+pub const MAX_SESSIONS: usize = 16;
+
+/// Pool of authenticated sessions with the HSM, shared between clones of
+/// the pool and of any [`Client`]s created from it.
+#[derive(Clone)]
+pub struct Pool(Arc<Inner>);
+
+/// Shared state of a session pool
+struct Inner {
+    /// Connector for communicating with the HSM
+    connector: Connector,
+
+    /// Credentials used to authenticate pooled sessions
+    credentials: Authenticator,
+
+    /// Maximum number of sessions this pool will open
+    max_sessions: usize,
+
+    /// Idle sessions and bookkeeping
+    state: Mutex<State>,
+
+    /// Signalled whenever a session is returned or discarded
+    available: Condvar,
+}
+
+/// Mutable state of a session pool
+struct State {
+    /// Sessions which are open and not currently lent out
+    idle: Vec<Session>,
+
+    /// Number of sessions which are open (idle, lent out, or being opened)
+    open: usize,
+
+    /// Inactivity timeout for newly opened sessions
+    timeout: session::Timeout,
+}
+
+impl Pool {
+    /// Create a pool of up to `max_sessions` sessions, opening all of them
+    /// before returning.
+    pub fn open(
+        connector: Connector,
+        credentials: Credentials,
+        max_sessions: usize,
+    ) -> Result<Self, Error> {
+        let pool = Self::create(connector, credentials, max_sessions)?;
+        let mut sessions = Vec::with_capacity(max_sessions);
+
+        for _ in 0..max_sessions {
+            sessions.push(pool.get()?);
+        }
+
+        Ok(pool)
+    }
+
+    /// Create a pool of up to `max_sessions` sessions, deferring opening
+    /// each of them until it's needed.
+    pub fn create(
+        connector: Connector,
+        credentials: Credentials,
+        max_sessions: usize,
+    ) -> Result<Self, Error> {
+        ensure!(
+            (1..=MAX_SESSIONS).contains(&max_sessions),
+            ErrorKind::CreateFailed,
+            "invalid session pool size: {} (must be 1-{})",
+            max_sessions,
+            MAX_SESSIONS
+        );
+
+        Ok(Pool(Arc::new(Inner {
+            connector,
+            credentials: credentials.into(),
+            max_sessions,
+            state: Mutex::new(State {
+                idle: Vec::with_capacity(max_sessions),
+                open: 0,
+                timeout: session::Timeout::default(),
+            }),
+            available: Condvar::new(),
+        })))
+    }
+
+    /// Create a [`Client`] which lends a session from this pool for each
+    /// command it sends. The returned client can be cloned and shared
+    /// across threads.
+    pub fn client(&self) -> Client {
+        Client::from_pool(self.clone())
+    }
+
+    /// Borrow the pool's YubiHSM connector
+    pub fn connector(&self) -> &Connector {
+        &self.0.connector
+    }
+
+    /// Maximum number of sessions this pool will open
+    pub fn max_sessions(&self) -> usize {
+        self.0.max_sessions
+    }
+
+    /// Set the inactivity timeout for sessions opened by this pool.
+    ///
+    /// Sessions which are already open keep their timeout, so this should be
+    /// called on a pool from [`Pool::create`] before it's used. The YubiHSM 2
+    /// closes sessions after 30 seconds of inactivity, so setting a longer
+    /// timeout will result in errors.
+    pub fn set_session_timeout(&self, timeout: session::Timeout) {
+        self.0.state.lock().unwrap().timeout = timeout;
+    }
+
+    /// Get the inactivity timeout for sessions opened by this pool
+    pub fn session_timeout(&self) -> session::Timeout {
+        self.0.state.lock().unwrap().timeout
+    }
+
+    /// Number of sessions which are currently open
+    pub fn open_sessions(&self) -> usize {
+        self.0.state.lock().unwrap().open
+    }
+
+    /// Number of open sessions which are not currently lent out
+    pub fn idle_sessions(&self) -> usize {
+        self.0.state.lock().unwrap().idle.len()
+    }
+
+    /// Discard all idle sessions (e.g. after the device has been reset)
+    pub fn clear(&self) {
+        let mut state = self.0.state.lock().unwrap();
+        state.open -= state.idle.len();
+        state.idle.clear();
+        self.0.available.notify_all();
+    }
+
+    /// Borrow an open session from the pool, opening a new one if none are
+    /// idle, or waiting for one to be returned if the pool is exhausted.
+    pub(crate) fn get(&self) -> Result<Lease, Error> {
+        let mut state = self.0.state.lock().unwrap();
+
+        loop {
+            while let Some(session) = state.idle.pop() {
+                if session.is_open() {
+                    return Ok(Lease {
+                        pool: self.clone(),
+                        session: Some(session),
+                    });
+                }
+
+                // Recycle sessions which have timed out
+                debug!("session={} discarding timed out session", session.id());
+                state.open -= 1;
+            }
+
+            if state.open < self.0.max_sessions {
+                break;
+            }
+
+            state = self.0.available.wait(state).unwrap();
+        }
+
+        // Reserve a slot, then release the lock while authenticating
+        state.open += 1;
+        let timeout = state.timeout;
+        drop(state);
+
+        match Session::open(self.0.connector.clone(), &self.0.credentials, timeout) {
+            Ok(session) => Ok(Lease {
+                pool: self.clone(),
+                session: Some(session),
+            }),
+            Err(e) => {
+                self.release_slot();
+                Err(e.into())
+            }
+        }
+    }
+
+    /// Send an `Echo` over each idle session which has been idle for at least
+    /// `max_idle`, and discard idle sessions which have closed.
+    ///
+    /// Returns how long to wait before the next idle session needs an `Echo`.
+    pub(crate) fn refresh(&self, max_idle: Duration) -> Result<Duration, Error> {
+        let mut state = self.0.state.lock().unwrap();
+        let mut wait = max_idle;
+        let mut stale = vec![];
+
+        for session in mem::take(&mut state.idle) {
+            if !session.is_open() {
+                debug!("session={} discarding timed out session", session.id());
+                state.open -= 1;
+                continue;
+            }
+
+            match max_idle.checked_sub(session.idle_time()) {
+                Some(remaining) if !remaining.is_zero() => {
+                    wait = wait.min(remaining);
+                    state.idle.push(session);
+                }
+                _ => stale.push(Lease {
+                    pool: self.clone(),
+                    session: Some(session),
+                }),
+            }
+        }
+
+        drop(state);
+        self.0.available.notify_all();
+
+        // Sessions are returned to the pool as their leases are dropped
+        for mut lease in stale {
+            lease.send_command(&EchoCommand {
+                message: b"keepalive".to_vec(),
+            })?;
+        }
+
+        Ok(wait)
+    }
+
+    /// Return a session to the pool, discarding it if it's no longer usable
+    fn put(&self, session: Session) {
+        if session.is_open() {
+            self.0.state.lock().unwrap().idle.push(session);
+            self.0.available.notify_one();
+        } else {
+            debug!("session={} discarding closed session", session.id());
+            self.release_slot();
+        }
+    }
+
+    /// Free up a slot for a new session to be opened
+    fn release_slot(&self) {
+        self.0.state.lock().unwrap().open -= 1;
+        self.0.available.notify_one();
+    }
+}
+
+/// Session borrowed from a [`Pool`], returned to it when dropped
+pub(crate) struct Lease {
+    /// Pool this session was borrowed from
+    pool: Pool,
+
+    /// Borrowed session (always `Some` until dropped)
+    session: Option<Session>,
+}
+
+impl Deref for Lease {
+    type Target = Session;
+
+    fn deref(&self) -> &Session {
+        self.session.as_ref().unwrap()
+    }
+}
+
+impl DerefMut for Lease {
+    fn deref_mut(&mut self) -> &mut Session {
+        self.session.as_mut().unwrap()
+    }
+}
+
+impl Drop for Lease {
+    fn drop(&mut self) {
+        if let Some(session) = self.session.take() {
+            self.pool.put(session);
+        }
+    }
+}
@@ -5,6 +5,10 @@
 //! - [USB][usb-connector]: communicate directly with the YubiHSM over USB using
 //!   the [rusb] crate.
 //!
+//! When the `async` cargo feature is enabled, [`AsyncConnector`] provides the
+//! same connection methods (HTTP and MockHsm) with non-blocking I/O for use
+//! with [`AsyncClient`][`crate::AsyncClient`].
+//!
 //! Additionally, this crate includes an optional development-only [mockhsm]
 //! (gated under a `mockhsm` cargo feature) which can be used as a drop-in
 //! replacement in places where you would like a simulated HSM for testing (e.g. CI).
@@ -17,11 +21,19 @@
 #[macro_use]
 mod error;
 
+#[cfg(feature = "async")]
+mod async_connectable;
+#[cfg(feature = "async")]
+mod async_connection;
+#[cfg(feature = "async")]
+mod async_connector;
 mod connectable;
 mod connection;
+pub mod failover;
 #[cfg(feature = "http")]
 pub mod http;
 mod message;
+pub mod transcript;
 #[cfg(feature = "usb")]
 pub mod usb;
 
@@ -29,14 +41,30 @@ pub use self::connection::Connection;
 pub use self::error::*;
 
 pub(crate) use self::{connectable::Connectable, message::Message};
-use std::sync::{Arc, Mutex};
+
+#[cfg(feature = "async")]
+pub use self::{
+    async_connection::{AsyncConnection, BoxFuture},
+    async_connector::AsyncConnector,
+};
+
+#[cfg(feature = "async")]
+pub(crate) use self::async_connectable::AsyncConnectable;
+use self::{
+    failover::FailoverConnector,
+    transcript::{RecordingConnector, Replay},
+};
+use crate::session::securechannel::Challenge;
+use std::{
+    path::Path,
+    sync::{Arc, Mutex},
+};
 use uuid::Uuid;
 
-#[cfg(feature = "http")]
-pub use self::http::HttpConfig;
+pub use self::failover::{Failback, FailoverConfig};
 
 #[cfg(feature = "http")]
-use self::http::HttpConnector;
+pub use self::http::{HttpConfig, HttpConnector, TlsConfig};
 
 #[cfg(feature = "usb")]
 pub use self::usb::UsbConfig;
@@ -72,6 +100,34 @@ impl Connector {
         Self::from(UsbConnector::create(config))
     }
 
+    /// Create a connector which fails over between the given connectors, in
+    /// order of priority, when they can't be reached. See the
+    /// [`failover`] module for details.
+    ///
+    /// All of the connectors should provide access to YubiHSM 2s with the
+    /// same keys and authentication keys.
+    pub fn failover(
+        connectors: impl IntoIterator<Item = Connector>,
+        config: FailoverConfig,
+    ) -> Self {
+        Self::from(FailoverConnector::create(connectors, config))
+    }
+
+    /// Create a connector which records every exchange with the given
+    /// connector to a transcript file. See the [`transcript`] module.
+    pub fn record(connector: Connector, path: impl AsRef<Path>) -> Result<Self, Error> {
+        Ok(Self::from(RecordingConnector::create(
+            connector,
+            path.as_ref(),
+        )?))
+    }
+
+    /// Create a connector which replays a transcript file recorded with
+    /// [`Connector::record`]. See the [`transcript`] module.
+    pub fn replay(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Ok(Replay::open(path)?.connector())
+    }
+
     /// Create a mock HSM connector (useful for testing)
     #[cfg(feature = "mockhsm")]
     pub fn mockhsm() -> Self {
@@ -96,6 +152,12 @@ impl Connector {
                 *connection = None;
             })
     }
+
+    /// Host challenge to use when opening the next session (if the driver
+    /// requires a particular one)
+    pub(crate) fn host_challenge(&self) -> Option<Challenge> {
+        self.driver.host_challenge()
+    }
 }
 
 impl Clone for Connector {
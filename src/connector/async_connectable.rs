@@ -0,0 +1,12 @@
+//! Trait for YubiHSM2 interfaces which can be connected to asynchronously
+
+use crate::connector::{self, AsyncConnection, BoxFuture};
+
+/// Connectors which create `AsyncConnection` objects to the HSM
+pub trait AsyncConnectable: Send + Sync {
+    /// Make a clone of this connectable as boxed trait object
+    fn box_clone(&self) -> Box<dyn AsyncConnectable>;
+
+    /// Open a connection to the HSM using this `AsyncConnector`
+    fn connect(&self) -> BoxFuture<'_, Result<Box<dyn AsyncConnection>, connector::Error>>;
+}
@@ -0,0 +1,18 @@
+//! Trait shared across all methods for asynchronously connecting to the YubiHSM2
+
+use crate::connector;
+use std::{future::Future, pin::Pin};
+use uuid::Uuid;
+
+/// Boxed future returned by asynchronous connections
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// Asynchronous connections to the HSM
+pub trait AsyncConnection: Send + Sync {
+    /// Send a command message to the HSM, then read and return the response
+    fn send_message(
+        &self,
+        uuid: Uuid,
+        msg: connector::Message,
+    ) -> BoxFuture<'_, Result<connector::Message, connector::Error>>;
+}
@@ -0,0 +1,77 @@
+//! Asynchronous counterpart of [`Connector`][`crate::Connector`]
+
+use super::{AsyncConnectable, AsyncConnection, Error, Message};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+#[cfg(feature = "http")]
+use super::{http::AsyncHttpConnector, HttpConfig};
+
+#[cfg(feature = "mockhsm")]
+use crate::mockhsm::MockHsm;
+
+/// Abstract interface to YubiHSM 2 connections which perform I/O asynchronously.
+///
+/// `AsyncConnector` is available when the `async` cargo feature is enabled.
+/// It requires a [tokio] runtime.
+///
+/// [tokio]: https://tokio.rs
+pub struct AsyncConnector {
+    /// Currently active connection (if any)
+    connection: Arc<Mutex<Option<Box<dyn AsyncConnection>>>>,
+
+    /// Backend connector driver
+    driver: Box<dyn AsyncConnectable>,
+}
+
+impl AsyncConnector {
+    /// Create a new asynchronous HTTP connector
+    #[cfg(feature = "http")]
+    pub fn http(config: &HttpConfig) -> Self {
+        Self::from(AsyncHttpConnector::create(config))
+    }
+
+    /// Create a mock HSM connector (useful for testing)
+    #[cfg(feature = "mockhsm")]
+    pub fn mockhsm() -> Self {
+        let mockhsm: Box<dyn AsyncConnectable> = MockHsm::new().into();
+        Self::from(mockhsm)
+    }
+
+    /// Send a command message to the HSM, then read and return the response
+    pub async fn send_message(&self, uuid: Uuid, msg: Message) -> Result<Message, Error> {
+        let mut connection = self.connection.lock().await;
+
+        if connection.is_none() {
+            *connection = Some(self.driver.connect().await?);
+        }
+
+        let result = connection.as_ref().unwrap().send_message(uuid, msg).await;
+
+        if result.is_err() {
+            // In the event of an error, mark this connection as invalid
+            *connection = None;
+        }
+
+        result
+    }
+}
+
+impl Clone for AsyncConnector {
+    fn clone(&self) -> Self {
+        AsyncConnector {
+            connection: self.connection.clone(),
+            driver: self.driver.box_clone(),
+        }
+    }
+}
+
+impl From<Box<dyn AsyncConnectable>> for AsyncConnector {
+    fn from(driver: Box<dyn AsyncConnectable>) -> AsyncConnector {
+        AsyncConnector {
+            connection: Arc::new(Mutex::new(None)),
+            driver,
+        }
+    }
+}
@@ -1,6 +1,9 @@
 //! Trait for YubiHSM2 interfaces which can be connected to
 
-use crate::connector::{self, Connection};
+use crate::{
+    connector::{self, Connection},
+    session::securechannel::Challenge,
+};
 
 /// Connectors which create `Connection` objects to the HSM
 pub trait Connectable: Send + Sync {
@@ -9,4 +12,11 @@ pub trait Connectable: Send + Sync {
 
     /// Open a connection to the HSM using this `Connector`
     fn connect(&self) -> Result<Box<dyn Connection>, connector::Error>;
+
+    /// Host challenge to use when opening the next session, for connectors
+    /// which need sessions to be deterministic (e.g. when replaying a
+    /// transcript). Random challenges are used if this returns `None`.
+    fn host_challenge(&self) -> Option<Challenge> {
+        None
+    }
 }
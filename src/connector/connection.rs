@@ -1,8 +1,18 @@
 //! Trait shared across all methods for connecting to the YubiHSM2
 
-use crate::connector;
+use crate::{
+    command,
+    connector::{
+        self,
+        ErrorKind::{ConnectionFailed, ResponseError},
+    },
+    response, uuid,
+};
 use uuid::Uuid;
 
+/// Data sent in `Echo` health checks
+const HEALTH_CHECK_DATA: &[u8] = b"yubihsm.rs health check";
+
 /// Connections to the HSM
 pub trait Connection: Send + Sync {
     /// Send a command message to the HSM, then read and return the response
@@ -12,3 +22,22 @@ pub trait Connection: Send + Sync {
         msg: connector::Message,
     ) -> Result<connector::Message, connector::Error>;
 }
+
+/// Check that a connection responds correctly to an `Echo` command, which
+/// the HSM answers without a session
+pub(crate) fn health_check(connection: &dyn Connection) -> Result<(), connector::Error> {
+    let command = command::Message::create(command::Code::Echo, HEALTH_CHECK_DATA)
+        .map_err(|e| format_err!(ConnectionFailed, "{}", e))?;
+
+    let response = connection.send_message(uuid::new_v4(), command.into())?;
+    let response = response::Message::parse(response)
+        .map_err(|e| format_err!(ResponseError, "health check failed: {}", e))?;
+
+    ensure!(
+        response.command() == Some(command::Code::Echo) && response.data == HEALTH_CHECK_DATA,
+        ResponseError,
+        "health check failed: unexpected response to Echo"
+    );
+
+    Ok(())
+}
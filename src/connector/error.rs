@@ -41,6 +41,14 @@ pub enum ErrorKind {
     #[error("bad response from connector")]
     ResponseError,
 
+    /// Replayed command doesn't match the recorded transcript
+    #[error("replay diverged from transcript")]
+    ReplayDiverged,
+
+    /// TLS configuration or handshake failed
+    #[error("TLS error")]
+    TlsError,
+
     /// USB operation failed
     #[cfg(feature = "usb")]
     #[error("USB error")]
@@ -76,12 +84,21 @@ impl From<http::client::Error> for Error {
                 ErrorKind::ResponseError
             }
             http::client::ErrorKind::RequestError => ErrorKind::RequestError,
+            #[cfg(feature = "tls")]
+            http::client::ErrorKind::TlsError => ErrorKind::TlsError,
         };
 
         kind.context(err).into()
     }
 }
 
+#[cfg(feature = "tls")]
+impl From<rustls::Error> for Error {
+    fn from(err: rustls::Error) -> Error {
+        ErrorKind::TlsError.context(err).into()
+    }
+}
+
 #[cfg(feature = "usb")]
 impl From<rusb::Error> for Error {
     fn from(err: rusb::Error) -> Error {
@@ -0,0 +1,280 @@
+//! Failover across several connectors which provide access to YubiHSM 2s
+//! with the same (replicated) keys.
+//!
+//! Endpoints are tried in order of priority: when a connection can't be
+//! established (or fails its health check) the next endpoint is tried, and
+//! when an established connection fails, the next connection is opened to
+//! the next endpoint.
+//!
+//! Sessions are specific to a particular YubiHSM 2, so a request which was
+//! in progress when an endpoint failed returns an error. The client will
+//! open a new session on the next endpoint for subsequent requests.
+//!
+//! After failing over, higher-priority endpoints are tried again (according
+//! to the [`Failback`] policy) whenever a new session is created, so traffic
+//! returns to them once they recover even if the endpoint which was failed
+//! over to stays healthy.
+
+use super::{
+    connection::health_check, Connectable, Connection, Connector, Error,
+    ErrorKind::ConnectionFailed, Message,
+};
+use crate::command;
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use uuid::Uuid;
+
+/// Default time before returning to a higher-priority endpoint (60 seconds)
+pub const DEFAULT_FAILBACK_SECS: u64 = 60;
+
+/// Configuration for failing over between connectors
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct FailoverConfig {
+    /// Check endpoints respond to an `Echo` command before using them
+    pub health_check: bool,
+
+    /// When to return to a higher-priority endpoint after failing over
+    pub failback: Failback,
+}
+
+impl Default for FailoverConfig {
+    fn default() -> Self {
+        Self {
+            health_check: true,
+            failback: Failback::After(Duration::from_secs(DEFAULT_FAILBACK_SECS)),
+        }
+    }
+}
+
+/// Policy for returning to a higher-priority endpoint after failing over.
+///
+/// Higher-priority endpoints are only tried again when a new connection is
+/// opened or a new session is created. Other sessions which are still open
+/// on the endpoint which was failed over to fail their next request when the
+/// connector switches back, and are re-created on the recovered endpoint.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Failback {
+    /// Keep using the endpoint which was failed over to until it fails too
+    Never,
+
+    /// Try higher-priority endpoints again once the given amount of time has
+    /// passed since they failed
+    After(Duration),
+}
+
+/// Connector driver which fails over between several connectors
+pub(crate) struct FailoverConnector {
+    /// Endpoints in order of priority
+    endpoints: Vec<Box<dyn Connectable>>,
+
+    /// Failover configuration
+    config: FailoverConfig,
+
+    /// Endpoint health, shared between clones of this connector
+    state: Arc<Mutex<State>>,
+}
+
+/// Health of the endpoints
+struct State {
+    /// Index of the endpoint currently in use
+    active: usize,
+
+    /// When each endpoint last failed (if it has)
+    failed_at: Vec<Option<Instant>>,
+}
+
+impl FailoverConnector {
+    /// Create a new `FailoverConnector` for the given connectors, in order
+    /// of priority
+    pub fn create(
+        connectors: impl IntoIterator<Item = Connector>,
+        config: FailoverConfig,
+    ) -> Box<dyn Connectable> {
+        let endpoints: Vec<_> = connectors
+            .into_iter()
+            .map(|connector| connector.driver)
+            .collect();
+
+        let state = State {
+            active: 0,
+            failed_at: vec![None; endpoints.len()],
+        };
+
+        Box::new(Self {
+            endpoints,
+            config,
+            state: Arc::new(Mutex::new(state)),
+        })
+    }
+
+    /// Make a copy of this connector which shares its endpoint health
+    fn duplicate(&self) -> Self {
+        Self {
+            endpoints: self.endpoints.iter().map(|e| e.box_clone()).collect(),
+            config: self.config,
+            state: self.state.clone(),
+        }
+    }
+
+    /// Indices of the endpoints in the order they should be tried
+    fn candidates(&self) -> Vec<usize> {
+        let state = self.state.lock().unwrap();
+        let mut candidates: Vec<usize> = (0..self.endpoints.len()).collect();
+
+        match self.config.failback {
+            Failback::Never => {
+                // Start with the active endpoint, unless it has failed
+                candidates.rotate_left(state.active);
+                candidates.sort_by_key(|&i| state.failed_at[i].is_some());
+            }
+            Failback::After(interval) => {
+                // Try endpoints which failed recently last, in priority order
+                candidates.sort_by_key(|&i| {
+                    state.failed_at[i].is_some_and(|failed_at| failed_at.elapsed() < interval)
+                });
+            }
+        }
+
+        candidates
+    }
+
+    /// Connect to the given endpoint, checking its health if configured to
+    fn connect_endpoint(&self, index: usize) -> Result<Box<dyn Connection>, Error> {
+        let connection = self.endpoints[index].connect()?;
+
+        if self.config.health_check {
+            health_check(connection.as_ref())?;
+        }
+
+        Ok(connection)
+    }
+
+    /// Record that the given endpoint has failed
+    fn mark_failed(&self, index: usize, error: &Error) {
+        warn!("yubihsm::failover: endpoint #{} failed: {}", index, error);
+        self.state.lock().unwrap().failed_at[index] = Some(Instant::now());
+    }
+
+    /// Record that the given endpoint is now in use
+    fn mark_active(&self, index: usize) {
+        let mut state = self.state.lock().unwrap();
+
+        if state.active != index {
+            warn!(
+                "yubihsm::failover: switching from endpoint #{} to #{}",
+                state.active, index
+            );
+            state.active = index;
+        }
+
+        state.failed_at[index] = None;
+    }
+}
+
+impl Connectable for FailoverConnector {
+    /// Make a clone of this connectable as boxed trait object
+    fn box_clone(&self) -> Box<dyn Connectable> {
+        Box::new(self.duplicate())
+    }
+
+    /// Connect to the highest-priority healthy endpoint
+    fn connect(&self) -> Result<Box<dyn Connection>, Error> {
+        let mut last_error = None;
+
+        for index in self.candidates() {
+            match self.connect_endpoint(index) {
+                Ok(connection) => {
+                    self.mark_active(index);
+
+                    return Ok(Box::new(FailoverConnection {
+                        active: Mutex::new(Active { index, connection }),
+                        connector: self.duplicate(),
+                    }));
+                }
+                Err(e) => {
+                    self.mark_failed(index, &e);
+                    last_error = Some(e);
+                }
+            }
+        }
+
+        match last_error {
+            Some(e) => fail!(
+                ConnectionFailed,
+                "all {} endpoints failed (last error: {})",
+                self.endpoints.len(),
+                e
+            ),
+            None => fail!(ConnectionFailed, "no endpoints to fail over between"),
+        }
+    }
+}
+
+/// Connection to one of the endpoints of a `FailoverConnector`
+struct FailoverConnection {
+    /// Connection to the endpoint currently in use
+    active: Mutex<Active>,
+
+    /// Connector for switching to a higher-priority endpoint
+    connector: FailoverConnector,
+}
+
+/// Connection to the endpoint currently in use
+struct Active {
+    /// Index of the endpoint
+    index: usize,
+
+    /// Connection to the endpoint
+    connection: Box<dyn Connection>,
+}
+
+impl FailoverConnection {
+    /// Switch to a higher-priority endpoint if one is due to be tried again
+    /// and can be connected to
+    fn failback(&self, active: &mut Active) {
+        let interval = match self.connector.config.failback {
+            Failback::Never => return,
+            Failback::After(interval) => interval,
+        };
+
+        for index in 0..active.index {
+            let due = self.connector.state.lock().unwrap().failed_at[index]
+                .is_none_or(|failed_at| failed_at.elapsed() >= interval);
+
+            if !due {
+                continue;
+            }
+
+            match self.connector.connect_endpoint(index) {
+                Ok(connection) => {
+                    self.connector.mark_active(index);
+                    *active = Active { index, connection };
+                    return;
+                }
+                Err(e) => self.connector.mark_failed(index, &e),
+            }
+        }
+    }
+}
+
+impl Connection for FailoverConnection {
+    /// Send a message to the endpoint, marking it as failed on error so the
+    /// next connection is made to another endpoint.
+    ///
+    /// Before a new session is created, higher-priority endpoints are tried
+    /// again if the failback policy allows.
+    fn send_message(&self, uuid: Uuid, msg: Message) -> Result<Message, Error> {
+        let mut active = self.active.lock().unwrap();
+
+        if msg.as_ref().first() == Some(&command::Code::CreateSession.to_u8()) {
+            self.failback(&mut active);
+        }
+
+        active
+            .connection
+            .send_message(uuid, msg)
+            .inspect_err(|e| self.connector.mark_failed(active.index, e))
+    }
+}
@@ -2,18 +2,37 @@
 //!
 //! <https://developers.yubico.com/YubiHSM2/Component_Reference/yubihsm-connector/>
 
+#[cfg(feature = "async")]
+mod async_connection;
 pub(super) mod client;
 mod config;
 mod connection;
 #[cfg(feature = "http-server")]
 mod server;
+mod status;
+#[cfg(feature = "tls")]
+mod tls;
 
-pub use self::config::HttpConfig;
 #[cfg(feature = "http-server")]
-pub use self::server::Server;
+pub use self::server::{
+    AccessPolicy, Server, ShutdownHandle, DEFAULT_MAX_BODY_SIZE, DEFAULT_MAX_CONNECTIONS,
+};
+pub use self::{
+    config::{HttpConfig, TlsConfig},
+    status::{Health, Status, STATUS_OK},
+};
 
 use self::connection::HttpConnection;
-use crate::connector::{self, Connectable, Connection};
+use crate::connector::{
+    self, connection::health_check, Connectable, Connection, ErrorKind::ConnectionFailed,
+};
+use std::time::Instant;
+
+#[cfg(feature = "async")]
+use {
+    self::async_connection::AsyncHttpConnection,
+    crate::connector::{AsyncConnectable, AsyncConnection, BoxFuture},
+};
 
 /// Connect to the HSM via HTTP(S) using `yubihsm-connector`.
 ///
@@ -29,13 +48,53 @@ use crate::connector::{self, Connectable, Connection};
 /// <https://developers.yubico.com/YubiHSM2/Component_Reference/yubihsm-connector/>
 ///
 /// [Yubico SDK]: https://developers.yubico.com/YubiHSM2/Releases/
+///
+/// Besides connecting to the HSM, `HttpConnector` can query the status of
+/// `yubihsm-connector` and check its health without opening a session, e.g.
+/// for load balancer health checks and readiness probes.
 #[derive(Clone, Default, Debug)]
-pub(crate) struct HttpConnector(HttpConfig);
+pub struct HttpConnector(HttpConfig);
 
 impl HttpConnector {
     /// Create a new `HttpConnector` with the given configuration
-    pub fn create(config: &HttpConfig) -> Box<dyn Connectable> {
-        Box::new(HttpConnector(config.clone()))
+    pub fn new(config: &HttpConfig) -> Self {
+        HttpConnector(config.clone())
+    }
+
+    /// Create a new boxed `HttpConnector` with the given configuration
+    pub(crate) fn create(config: &HttpConfig) -> Box<dyn Connectable> {
+        Box::new(Self::new(config))
+    }
+
+    /// `GET /connector/status`: get the status of `yubihsm-connector`
+    pub fn status(&self) -> Result<Status, connector::Error> {
+        HttpConnection::open(&self.0)?.status()
+    }
+
+    /// Check `yubihsm-connector` reports the YubiHSM 2 as available, and that
+    /// the YubiHSM 2 responds to an `Echo` command.
+    ///
+    /// This is a sessionless equivalent of [`Client::ping`][crate::Client::ping],
+    /// so it doesn't require credentials or use up one of the HSM's sessions.
+    pub fn health_check(&self) -> Result<Health, connector::Error> {
+        let connection = HttpConnection::open(&self.0)?;
+        let status = connection.status()?;
+
+        ensure!(
+            status.is_ok(),
+            ConnectionFailed,
+            "{} reported status {}",
+            self.0,
+            status.status
+        );
+
+        let started_at = Instant::now();
+        health_check(&connection)?;
+
+        Ok(Health {
+            status,
+            latency: started_at.elapsed(),
+        })
     }
 }
 
@@ -56,3 +115,36 @@ impl Into<Box<dyn Connectable>> for HttpConnector {
         Box::new(self)
     }
 }
+
+/// Asynchronously connect to the HSM via HTTP using `yubihsm-connector`.
+///
+/// `AsyncHttpConnector` is available when both the `http` and `async` cargo
+/// features are enabled.
+#[cfg(feature = "async")]
+#[derive(Clone, Default, Debug)]
+pub(crate) struct AsyncHttpConnector(HttpConfig);
+
+#[cfg(feature = "async")]
+impl AsyncHttpConnector {
+    /// Create a new `AsyncHttpConnector` with the given configuration
+    pub fn create(config: &HttpConfig) -> Box<dyn AsyncConnectable> {
+        Box::new(AsyncHttpConnector(config.clone()))
+    }
+}
+
+#[cfg(feature = "async")]
+impl AsyncConnectable for AsyncHttpConnector {
+    /// Make a clone of this connectable as boxed trait object
+    fn box_clone(&self) -> Box<dyn AsyncConnectable> {
+        Box::new(AsyncHttpConnector(self.0.clone()))
+    }
+
+    /// Open a connection to `yubihsm-connector`
+    fn connect(&self) -> BoxFuture<'_, Result<Box<dyn AsyncConnection>, connector::Error>> {
+        Box::pin(async move {
+            let connection: Box<dyn AsyncConnection> =
+                Box::new(AsyncHttpConnection::open(&self.0).await?);
+            Ok(connection)
+        })
+    }
+}
@@ -0,0 +1,69 @@
+//! Persistent asynchronous HTTP connection to `yubihsm-connector`
+
+use super::{client, config::HttpConfig};
+use crate::connector::{self, AsyncConnection, BoxFuture};
+use uuid::Uuid;
+
+/// Asynchronous connection to YubiHSM via HTTP requests to `yubihsm-connector`.
+///
+/// This is the non-blocking counterpart of `HttpConnection`, and speaks the
+/// same protocol.
+pub struct AsyncHttpConnection {
+    /// Path of the API endpoint
+    api_path: String,
+
+    /// HTTP connection
+    connection: client::AsyncConnection,
+}
+
+impl AsyncHttpConnection {
+    /// Open a connection to a `yubihsm-connector` service
+    pub(crate) async fn open(config: &HttpConfig) -> Result<Self, connector::Error> {
+        let opts = config.connection_options()?;
+
+        #[cfg(unix)]
+        if let Some(socket) = &config.socket {
+            let connection = client::AsyncConnection::open_unix(socket, &opts).await?;
+            return Ok(AsyncHttpConnection {
+                api_path: config.api_path(),
+                connection,
+            });
+        }
+
+        let connection = client::AsyncConnection::open(&config.addr, config.port, &opts).await?;
+
+        Ok(AsyncHttpConnection {
+            api_path: config.api_path(),
+            connection,
+        })
+    }
+
+    /// Make an HTTP POST request to a `yubihsm-connector` service
+    async fn post(
+        &self,
+        path: &str,
+        _uuid: Uuid,
+        body: &[u8],
+    ) -> Result<Vec<u8>, connector::Error> {
+        Ok(self
+            .connection
+            .post(path, &client::request::Body::new(body))
+            .await?
+            .into_vec())
+    }
+}
+
+impl AsyncConnection for AsyncHttpConnection {
+    /// `POST /connector/api` with a given command message
+    fn send_message(
+        &self,
+        uuid: Uuid,
+        cmd: connector::Message,
+    ) -> BoxFuture<'_, Result<connector::Message, connector::Error>> {
+        Box::pin(async move {
+            self.post(&self.api_path, uuid, cmd.as_ref())
+                .await
+                .map(Into::into)
+        })
+    }
+}
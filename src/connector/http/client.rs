@@ -4,6 +4,8 @@
 #[macro_use]
 pub mod error;
 
+#[cfg(feature = "async")]
+pub mod async_connection;
 pub mod connection;
 pub mod path;
 pub mod request;
@@ -11,6 +13,9 @@ pub mod response;
 
 pub use self::{connection::*, error::*};
 
+#[cfg(feature = "async")]
+pub use self::async_connection::AsyncConnection;
+
 /// HTTP version.
 pub const HTTP_VERSION: &str = "HTTP/1.1";
 
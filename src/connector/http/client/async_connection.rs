@@ -0,0 +1,205 @@
+//! Asynchronous connections to HTTP servers
+
+use super::{
+    connection::{post_request, ConnectionOptions},
+    error::Error,
+    path::PathBuf,
+    request, response,
+};
+use std::{
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncRead, AsyncWrite, AsyncWriteExt, ReadBuf},
+    net::{lookup_host, TcpStream},
+    sync::Mutex,
+    time,
+};
+
+#[cfg(feature = "tls")]
+use tokio_rustls::{client::TlsStream, TlsConnector};
+
+#[cfg(unix)]
+use {std::path::Path, tokio::net::UnixStream};
+
+/// Asynchronous HTTP connection to a remote host
+pub struct AsyncConnection {
+    /// Host header to send in HTTP requests
+    host: String,
+
+    /// Bearer token to send in HTTP requests
+    bearer_token: Option<String>,
+
+    /// Open socket to remote host
+    socket: Mutex<AsyncStream>,
+
+    /// Timeout for each request
+    timeout: Duration,
+}
+
+impl AsyncConnection {
+    /// Create a new asynchronous connection to an HTTP server
+    pub async fn open(addr: &str, port: u16, opts: &ConnectionOptions) -> Result<Self, Error> {
+        let host = format!("{addr}:{port}");
+
+        let socketaddr = lookup_host(&host).await?.next().ok_or_else(|| {
+            err!(
+                AddrInvalid,
+                "couldn't resolve DNS for {}",
+                host.split(':').next().unwrap()
+            )
+        })?;
+
+        let socket = time::timeout(opts.timeout, TcpStream::connect(socketaddr))
+            .await
+            .map_err(|_| err!(IoError, "timed out connecting to {}", host))??;
+
+        #[cfg(feature = "tls")]
+        if let Some((config, server_name)) = &opts.tls {
+            let connector = TlsConnector::from(config.clone());
+
+            let tls = time::timeout(opts.timeout, connector.connect(server_name.clone(), socket))
+                .await
+                .map_err(|_| err!(IoError, "timed out connecting to {}", host))?
+                .map_err(|e| err!(TlsError, "TLS handshake with {} failed: {}", host, e))?;
+
+            return Ok(Self {
+                host,
+                bearer_token: opts.bearer_token.clone(),
+                socket: Mutex::new(AsyncStream::Tls(Box::new(tls))),
+                timeout: opts.timeout,
+            });
+        }
+
+        Ok(Self {
+            host,
+            bearer_token: opts.bearer_token.clone(),
+            socket: Mutex::new(AsyncStream::Tcp(socket)),
+            timeout: opts.timeout,
+        })
+    }
+
+    /// Create a new asynchronous connection to an HTTP server listening on a
+    /// Unix domain socket
+    #[cfg(unix)]
+    pub async fn open_unix(socket_path: &Path, opts: &ConnectionOptions) -> Result<Self, Error> {
+        #[cfg(feature = "tls")]
+        ensure!(
+            opts.tls.is_none(),
+            RequestError,
+            "TLS is not supported over Unix domain sockets"
+        );
+
+        let socket = time::timeout(opts.timeout, UnixStream::connect(socket_path))
+            .await
+            .map_err(|_| err!(IoError, "timed out connecting to {}", socket_path.display()))?
+            .map_err(|e| {
+                err!(
+                    AddrInvalid,
+                    "couldn't connect to {}: {}",
+                    socket_path.display(),
+                    e
+                )
+            })?;
+
+        Ok(Self {
+            host: "localhost".to_owned(),
+            bearer_token: opts.bearer_token.clone(),
+            socket: Mutex::new(AsyncStream::Unix(socket)),
+            timeout: opts.timeout,
+        })
+    }
+
+    /// Make an HTTP POST request to the given path
+    pub async fn post<P: Into<PathBuf>>(
+        &self,
+        into_path: P,
+        body: &request::Body,
+    ) -> Result<response::Body, Error> {
+        let request = post_request(
+            &self.host,
+            self.bearer_token.as_deref(),
+            &into_path.into(),
+            body,
+        )?;
+        let mut socket = self.socket.lock().await;
+
+        time::timeout(self.timeout, async {
+            socket.write_all(&request).await?;
+            socket.flush().await?;
+            let reader = response::Reader::new_async(&mut *socket).await?;
+            Ok(reader.into_body())
+        })
+        .await
+        .map_err(|_| err!(IoError, "timed out waiting for response from {}", self.host))?
+    }
+}
+
+/// Asynchronous socket connected to an HTTP server
+enum AsyncStream {
+    /// Plaintext HTTP
+    Tcp(TcpStream),
+
+    /// HTTPS
+    #[cfg(feature = "tls")]
+    Tls(Box<TlsStream<TcpStream>>),
+
+    /// Plaintext HTTP over a Unix domain socket
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+impl AsyncRead for AsyncStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            AsyncStream::Tcp(socket) => Pin::new(socket).poll_read(cx, buf),
+            #[cfg(feature = "tls")]
+            AsyncStream::Tls(socket) => Pin::new(socket).poll_read(cx, buf),
+            #[cfg(unix)]
+            AsyncStream::Unix(socket) => Pin::new(socket).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for AsyncStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            AsyncStream::Tcp(socket) => Pin::new(socket).poll_write(cx, buf),
+            #[cfg(feature = "tls")]
+            AsyncStream::Tls(socket) => Pin::new(socket).poll_write(cx, buf),
+            #[cfg(unix)]
+            AsyncStream::Unix(socket) => Pin::new(socket).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            AsyncStream::Tcp(socket) => Pin::new(socket).poll_flush(cx),
+            #[cfg(feature = "tls")]
+            AsyncStream::Tls(socket) => Pin::new(socket).poll_flush(cx),
+            #[cfg(unix)]
+            AsyncStream::Unix(socket) => Pin::new(socket).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            AsyncStream::Tcp(socket) => Pin::new(socket).poll_shutdown(cx),
+            #[cfg(feature = "tls")]
+            AsyncStream::Tls(socket) => Pin::new(socket).poll_shutdown(cx),
+            #[cfg(unix)]
+            AsyncStream::Unix(socket) => Pin::new(socket).poll_shutdown(cx),
+        }
+    }
+}
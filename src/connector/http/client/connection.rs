@@ -2,7 +2,7 @@
 
 use std::{
     fmt::Write as FmtWrite,
-    io::Write,
+    io::{self, Read, Write},
     net::{TcpStream, ToSocketAddrs},
     ops::DerefMut,
     string::String,
@@ -13,18 +13,53 @@ use std::{
 
 use super::{error::Error, path::PathBuf, request, response, HTTP_VERSION, USER_AGENT};
 
+#[cfg(feature = "tls")]
+use {
+    rustls::{pki_types::ServerName, ClientConfig, ClientConnection, StreamOwned},
+    std::sync::Arc,
+};
+
+#[cfg(unix)]
+use std::{os::unix::net::UnixStream, path::Path};
+
 /// Default timeout in milliseconds (20 seconds)
 const DEFAULT_TIMEOUT_MS: u64 = 20000;
 
 /// Options when building a `Connection`
 pub struct ConnectionOptions {
-    timeout: Duration,
+    pub(super) timeout: Duration,
+
+    /// Bearer token to send in the `Authorization` header of each request
+    pub(super) bearer_token: Option<String>,
+
+    /// TLS configuration and expected server name, if connecting via HTTPS
+    #[cfg(feature = "tls")]
+    pub(super) tls: Option<(Arc<ClientConfig>, ServerName<'static>)>,
+}
+
+impl ConnectionOptions {
+    /// Authenticate each request with the given bearer token
+    pub fn bearer_token(mut self, token: impl Into<String>) -> Self {
+        self.bearer_token = Some(token.into());
+        self
+    }
+
+    /// Connect via HTTPS, verifying the server's certificate against the
+    /// given name
+    #[cfg(feature = "tls")]
+    pub fn tls(mut self, config: Arc<ClientConfig>, server_name: ServerName<'static>) -> Self {
+        self.tls = Some((config, server_name));
+        self
+    }
 }
 
 impl Default for ConnectionOptions {
     fn default() -> Self {
         Self {
             timeout: Duration::from_millis(DEFAULT_TIMEOUT_MS),
+            bearer_token: None,
+            #[cfg(feature = "tls")]
+            tls: None,
         }
     }
 }
@@ -34,8 +69,11 @@ pub struct Connection {
     /// Host header to send in HTTP requests
     host: String,
 
-    /// Open TCP socket to remote host
-    socket: Mutex<TcpStream>,
+    /// Bearer token to send in HTTP requests
+    bearer_token: Option<String>,
+
+    /// Open socket to remote host
+    socket: Mutex<Stream>,
 }
 
 impl Connection {
@@ -56,9 +94,62 @@ impl Connection {
         socket.set_read_timeout(Some(opts.timeout))?;
         socket.set_write_timeout(Some(opts.timeout))?;
 
+        #[cfg(feature = "tls")]
+        if let Some((config, server_name)) = &opts.tls {
+            let mut tls = StreamOwned::new(
+                ClientConnection::new(config.clone(), server_name.clone())
+                    .map_err(|e| err!(TlsError, "{}", e))?,
+                socket,
+            );
+
+            // Complete the handshake up front so certificate errors are
+            // reported when connecting rather than on the first request
+            while tls.conn.is_handshaking() {
+                tls.conn
+                    .complete_io(&mut tls.sock)
+                    .map_err(|e| err!(TlsError, "TLS handshake with {} failed: {}", host, e))?;
+            }
+
+            return Ok(Self {
+                host,
+                bearer_token: opts.bearer_token.clone(),
+                socket: Mutex::new(Stream::Tls(Box::new(tls))),
+            });
+        }
+
         Ok(Self {
             host,
-            socket: Mutex::new(socket),
+            bearer_token: opts.bearer_token.clone(),
+            socket: Mutex::new(Stream::Tcp(socket)),
+        })
+    }
+
+    /// Create a new connection to an HTTP server listening on a Unix domain socket
+    #[cfg(unix)]
+    pub fn open_unix(socket_path: &Path, opts: &ConnectionOptions) -> Result<Self, Error> {
+        #[cfg(feature = "tls")]
+        ensure!(
+            opts.tls.is_none(),
+            RequestError,
+            "TLS is not supported over Unix domain sockets"
+        );
+
+        let socket = UnixStream::connect(socket_path).map_err(|e| {
+            err!(
+                AddrInvalid,
+                "couldn't connect to {}: {}",
+                socket_path.display(),
+                e
+            )
+        })?;
+
+        socket.set_read_timeout(Some(opts.timeout))?;
+        socket.set_write_timeout(Some(opts.timeout))?;
+
+        Ok(Self {
+            host: "localhost".to_owned(),
+            bearer_token: opts.bearer_token.clone(),
+            socket: Mutex::new(Stream::Unix(socket)),
         })
     }
 
@@ -68,23 +159,121 @@ impl Connection {
         into_path: P,
         body: &request::Body,
     ) -> Result<response::Body, Error> {
-        let path = into_path.into();
-        let mut headers = String::new();
+        let request = post_request(
+            &self.host,
+            self.bearer_token.as_deref(),
+            &into_path.into(),
+            body,
+        )?;
 
-        writeln!(headers, "POST {path} {HTTP_VERSION}\r")?;
-        writeln!(headers, "Host: {}\r", self.host)?;
-        writeln!(headers, "User-Agent: {USER_AGENT}\r")?;
-        writeln!(headers, "Content-Length: {}\r", body.0.len())?;
-        writeln!(headers, "\r")?;
+        self.send(&request)
+    }
 
-        // Make a Nagle-friendly request by combining headers and body
-        let mut request: Vec<u8> = headers.into();
-        request.extend_from_slice(body.0.as_slice());
+    /// Make an HTTP GET request to the given path
+    pub fn get<P: Into<PathBuf>>(&self, into_path: P) -> Result<response::Body, Error> {
+        let request = get_request(&self.host, self.bearer_token.as_deref(), &into_path.into())?;
+        self.send(&request)
+    }
 
+    /// Send a serialized request and read the response body
+    fn send(&self, request: &[u8]) -> Result<response::Body, Error> {
         let mut socket = self.socket.lock().unwrap();
-        socket.write_all(&request)?;
+        socket.write_all(request)?;
+        socket.flush()?;
 
         let response_body = response::Reader::new(socket.deref_mut())?.into_body();
         Ok(response_body)
     }
 }
+
+/// Socket connected to an HTTP server
+enum Stream {
+    /// Plaintext HTTP
+    Tcp(TcpStream),
+
+    /// HTTPS
+    #[cfg(feature = "tls")]
+    Tls(Box<StreamOwned<ClientConnection, TcpStream>>),
+
+    /// Plaintext HTTP over a Unix domain socket
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Stream::Tcp(socket) => socket.read(buf),
+            #[cfg(feature = "tls")]
+            Stream::Tls(socket) => socket.read(buf),
+            #[cfg(unix)]
+            Stream::Unix(socket) => socket.read(buf),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Stream::Tcp(socket) => socket.write(buf),
+            #[cfg(feature = "tls")]
+            Stream::Tls(socket) => socket.write(buf),
+            #[cfg(unix)]
+            Stream::Unix(socket) => socket.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Stream::Tcp(socket) => socket.flush(),
+            #[cfg(feature = "tls")]
+            Stream::Tls(socket) => socket.flush(),
+            #[cfg(unix)]
+            Stream::Unix(socket) => socket.flush(),
+        }
+    }
+}
+
+/// Serialize an HTTP POST request for the given host and path
+pub(super) fn post_request(
+    host: &str,
+    bearer_token: Option<&str>,
+    path: &PathBuf,
+    body: &request::Body,
+) -> Result<Vec<u8>, Error> {
+    let mut headers = request_headers("POST", host, bearer_token, path)?;
+    writeln!(headers, "Content-Length: {}\r", body.0.len())?;
+    writeln!(headers, "\r")?;
+
+    // Make a Nagle-friendly request by combining headers and body
+    let mut request: Vec<u8> = headers.into();
+    request.extend_from_slice(body.0.as_slice());
+    Ok(request)
+}
+
+/// Serialize an HTTP GET request for the given host and path
+fn get_request(host: &str, bearer_token: Option<&str>, path: &PathBuf) -> Result<Vec<u8>, Error> {
+    let mut headers = request_headers("GET", host, bearer_token, path)?;
+    writeln!(headers, "\r")?;
+    Ok(headers.into())
+}
+
+/// Serialize the request line and headers common to all requests
+fn request_headers(
+    method: &str,
+    host: &str,
+    bearer_token: Option<&str>,
+    path: &PathBuf,
+) -> Result<String, Error> {
+    let mut headers = String::new();
+
+    writeln!(headers, "{method} {path} {HTTP_VERSION}\r")?;
+    writeln!(headers, "Host: {host}\r")?;
+    writeln!(headers, "User-Agent: {USER_AGENT}\r")?;
+
+    if let Some(token) = bearer_token {
+        writeln!(headers, "Authorization: Bearer {token}\r")?;
+    }
+
+    Ok(headers)
+}
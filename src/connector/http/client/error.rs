@@ -67,6 +67,10 @@ pub enum ErrorKind {
 
     /// Error reading response
     ResponseError,
+
+    /// TLS handshake failed
+    #[cfg(feature = "tls")]
+    TlsError,
 }
 
 impl fmt::Display for ErrorKind {
@@ -77,6 +81,8 @@ impl fmt::Display for ErrorKind {
             ErrorKind::ParseError => "parse error",
             ErrorKind::RequestError => "request error",
             ErrorKind::ResponseError => "error reading response",
+            #[cfg(feature = "tls")]
+            ErrorKind::TlsError => "TLS error",
         };
 
         write!(f, "{description}")
@@ -6,6 +6,9 @@ use super::Body;
 use crate::connector::http::client::Error;
 use std::{io::Read, str, vec::Vec};
 
+#[cfg(feature = "async")]
+use tokio::io::{AsyncRead, AsyncReadExt};
+
 const TRANSFER_ENCODING_HEADER: &str = "Transfer-Encoding: ";
 const HEADER_DELIMITER: &[u8] = b"\r\n\r\n";
 const HTTP_SUCCESS_STATUS: &str = "HTTP/1.1 200 OK";
@@ -34,16 +37,41 @@ impl Reader {
     /// Create a new `response::Reader` that consumes a response body from a socket
     #[allow(clippy::new_ret_no_self)]
     pub(crate) fn new(readable: &mut dyn Read) -> Result<Self, Error> {
-        // TODO: better buffering
-        let mut buffer = Self {
-            buffer: vec![0u8; MAX_RESPONSE_SIZE],
-            pos: 0,
-            body_offset: None,
-            content_length: 0,
-        };
+        let mut buffer = Self::empty();
 
-        buffer.read_headers(readable)?;
-        buffer.read_body(readable)?;
+        while !buffer.scan_headers()? {
+            buffer.fill_buffer(readable)?;
+        }
+
+        buffer.parse_headers()?;
+
+        while buffer.pos < buffer.body_end() {
+            buffer.fill_buffer(readable)?;
+        }
+
+        Ok(buffer)
+    }
+
+    /// Create a new `response::Reader` that consumes a response body from an
+    /// asynchronous socket
+    #[cfg(feature = "async")]
+    pub(crate) async fn new_async<R>(readable: &mut R) -> Result<Self, Error>
+    where
+        R: AsyncRead + Unpin,
+    {
+        let mut buffer = Self::empty();
+
+        while !buffer.scan_headers()? {
+            let nbytes = readable.read(&mut buffer.buffer[buffer.pos..]).await?;
+            buffer.advance(nbytes)?;
+        }
+
+        buffer.parse_headers()?;
+
+        while buffer.pos < buffer.body_end() {
+            let nbytes = readable.read(&mut buffer.buffer[buffer.pos..]).await?;
+            buffer.advance(nbytes)?;
+        }
 
         Ok(buffer)
     }
@@ -57,9 +85,25 @@ impl Reader {
         Body(Vec::from(&self.buffer[body_offset..self.pos]))
     }
 
+    /// Create an empty reader
+    fn empty() -> Self {
+        // TODO: better buffering
+        Self {
+            buffer: vec![0u8; MAX_RESPONSE_SIZE],
+            pos: 0,
+            body_offset: None,
+            content_length: 0,
+        }
+    }
+
     /// Fill the internal buffer with data from the socket
     fn fill_buffer(&mut self, readable: &mut dyn Read) -> Result<usize, Error> {
-        let nbytes = readable.read(self.buffer.as_mut())?;
+        let nbytes = readable.read(&mut self.buffer[self.pos..])?;
+        self.advance(nbytes)
+    }
+
+    /// Account for `nbytes` having been read into the internal buffer
+    fn advance(&mut self, nbytes: usize) -> Result<usize, Error> {
         self.pos += nbytes;
 
         // See: https://doc.rust-lang.org/src/std/io/mod.rs.html#571
@@ -76,37 +120,36 @@ impl Reader {
         Ok(nbytes)
     }
 
-    /// Read the response headers
-    fn read_headers(&mut self, readable: &mut dyn Read) -> Result<(), Error> {
+    /// Scan the data read so far for the end of the response headers,
+    /// returning `true` if they have been read in their entirety
+    fn scan_headers(&mut self) -> Result<bool, Error> {
         assert!(self.body_offset.is_none(), "already read headers!");
 
-        loop {
-            self.fill_buffer(readable)?;
-
-            // Scan for the header delimiter
-            // TODO: real parser
-            let mut offset = 0;
-            while self.buffer[offset..].len() > HEADER_DELIMITER.len() {
-                if self.buffer[offset..].starts_with(HEADER_DELIMITER) {
-                    self.body_offset = Some(offset + HEADER_DELIMITER.len());
-                    break;
-                } else {
-                    offset += 1;
-                }
+        // TODO: real parser
+        let mut offset = 0;
+        while self.buffer[offset..self.pos].len() >= HEADER_DELIMITER.len() {
+            if self.buffer[offset..self.pos].starts_with(HEADER_DELIMITER) {
+                self.body_offset = Some(offset + HEADER_DELIMITER.len());
+                return Ok(true);
+            } else {
+                offset += 1;
             }
+        }
 
-            if self.body_offset.is_some() {
-                break;
-            } else if self.pos + 1 >= MAX_RESPONSE_SIZE {
-                fail!(
-                    ResponseError,
-                    "exceeded {}-byte response limit reading headers",
-                    MAX_RESPONSE_SIZE
-                );
-            }
+        if self.pos + 1 >= MAX_RESPONSE_SIZE {
+            fail!(
+                ResponseError,
+                "exceeded {}-byte response limit reading headers",
+                MAX_RESPONSE_SIZE
+            );
         }
 
-        self.parse_headers()
+        Ok(false)
+    }
+
+    /// Offset of the end of the response body within the internal buffer
+    fn body_end(&self) -> usize {
+        self.content_length + self.body_offset.expect("not ready to read the body yet")
     }
 
     /// Parse the response headers
@@ -150,16 +193,4 @@ impl Reader {
 
         Ok(())
     }
-
-    /// Read the response body into the internal buffer
-    fn read_body(&mut self, readable: &mut dyn Read) -> Result<(), Error> {
-        let body_end =
-            self.content_length + self.body_offset.expect("not ready to read the body yet");
-
-        while self.pos < body_end {
-            self.fill_buffer(readable)?;
-        }
-
-        Ok(())
-    }
 }
@@ -1,13 +1,24 @@
 //! yubihsm-connector HTTP configuration
 
+use super::client::ConnectionOptions;
+use crate::{connector::Error, device::SerialNumber};
 use serde::{Deserialize, Serialize};
-use std::fmt::{self, Display};
+use std::{
+    fmt::{self, Display},
+    path::PathBuf,
+};
+
+#[cfg(not(feature = "tls"))]
+use crate::connector::ErrorKind::TlsError;
+
+#[cfg(not(unix))]
+use crate::connector::ErrorKind::AddrInvalid;
 
 /// Default timeouts for reading and writing (5 seconds)
 pub const DEFAULT_TIMEOUT_MILLIS: u64 = 5000;
 
 /// Configuration options for the HTTP (i.e. `yubihsm-connector`) connection
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Deserialize, Serialize)]
 pub struct HttpConfig {
     /// Address of `yubihsm-connector` (IP address or DNS name)
     pub addr: String,
@@ -17,6 +28,138 @@ pub struct HttpConfig {
 
     /// Timeout for connecting, reading, and writing in milliseconds
     pub timeout_ms: u64,
+
+    /// Serial number of the YubiHSM 2 to use, when `yubihsm-connector`
+    /// provides access to more than one
+    #[serde(default)]
+    pub serial: Option<SerialNumber>,
+
+    /// Bearer token to authenticate to `yubihsm-connector` with, when it
+    /// requires one
+    #[serde(default)]
+    pub bearer_token: Option<String>,
+
+    /// TLS configuration: if set, `yubihsm-connector` is accessed via HTTPS
+    /// (requires the `tls` cargo feature)
+    #[serde(default)]
+    pub tls: Option<TlsConfig>,
+
+    /// Path to a Unix domain socket: if set, `yubihsm-connector` is accessed
+    /// through this socket instead of `addr` and `port` (Unix only)
+    #[serde(default)]
+    pub socket: Option<PathBuf>,
+
+    /// Permissions to give the Unix domain socket when serving on `socket`
+    /// (e.g. `0o660` to allow the socket's group to connect). The socket is
+    /// only reachable by others once it has these permissions. If unset, the
+    /// socket's permissions are determined by the process's umask.
+    #[serde(default)]
+    pub socket_mode: Option<u32>,
+}
+
+/// TLS configuration for HTTPS.
+///
+/// When connecting to `yubihsm-connector`, the server's certificate is
+/// verified against `ca_cert` only (i.e. the CA is pinned, and the system's
+/// trust anchors are never consulted). If `cert` and `key` are set they are
+/// presented to the server as a client certificate.
+///
+/// When serving HTTPS, `cert` and `key` identify the server. If `ca_cert` is
+/// set, clients are required to present a certificate issued by that CA
+/// (i.e. mutual TLS).
+///
+/// All files are PEM encoded. HTTPS requires the `tls` cargo feature:
+/// without it, configurations which set [`HttpConfig::tls`] are rejected.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct TlsConfig {
+    /// Path to the CA certificate(s) used to verify the other end of the
+    /// connection
+    pub ca_cert: Option<PathBuf>,
+
+    /// Path to the certificate chain presented to the other end of the
+    /// connection
+    pub cert: Option<PathBuf>,
+
+    /// Path to the private key for `cert`
+    pub key: Option<PathBuf>,
+
+    /// Name the server's certificate is verified against when connecting,
+    /// if it differs from the address being connected to
+    pub server_name: Option<String>,
+}
+
+impl fmt::Debug for HttpConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut debug = f.debug_struct("HttpConfig");
+
+        debug
+            .field("addr", &self.addr)
+            .field("port", &self.port)
+            .field("timeout_ms", &self.timeout_ms)
+            .field("serial", &self.serial)
+            // Avoid leaking secrets in debug messages
+            .field("bearer_token", &self.bearer_token.as_ref().map(|_| "..."))
+            .field("tls", &self.tls)
+            .field("socket", &self.socket)
+            .field("socket_mode", &self.socket_mode)
+            .finish()
+    }
+}
+
+impl HttpConfig {
+    /// Options for connecting to `yubihsm-connector` with this configuration
+    pub(super) fn connection_options(&self) -> Result<ConnectionOptions, Error> {
+        self.check_supported()?;
+        let mut opts = ConnectionOptions::default();
+
+        if let Some(token) = &self.bearer_token {
+            opts = opts.bearer_token(token.clone());
+        }
+
+        #[cfg(feature = "tls")]
+        if let Some(tls) = &self.tls {
+            opts = opts.tls(tls.client_config()?, tls.server_name(&self.addr)?);
+        }
+
+        Ok(opts)
+    }
+
+    /// Reject options which this build or platform doesn't support
+    pub(super) fn check_supported(&self) -> Result<(), Error> {
+        #[cfg(not(feature = "tls"))]
+        ensure!(
+            self.tls.is_none(),
+            TlsError,
+            "HTTPS requires the `tls` cargo feature"
+        );
+
+        #[cfg(not(unix))]
+        ensure!(
+            self.socket.is_none(),
+            AddrInvalid,
+            "Unix domain sockets are not supported on this platform"
+        );
+
+        Ok(())
+    }
+
+    /// Path of the `yubihsm-connector` API endpoint for the configured device
+    pub(super) fn api_path(&self) -> String {
+        self.device_path("/connector/api")
+    }
+
+    /// Path of the `yubihsm-connector` status page for the configured device
+    pub(super) fn status_path(&self) -> String {
+        self.device_path("/connector/status")
+    }
+
+    /// Add the configured device's serial number (if any) to a path
+    fn device_path(&self, path: &str) -> String {
+        match self.serial {
+            Some(serial) => format!("{path}?serial={serial}"),
+            None => path.to_owned(),
+        }
+    }
 }
 
 impl Default for HttpConfig {
@@ -30,13 +173,28 @@ impl Default for HttpConfig {
 
             // 5 seconds
             timeout_ms: DEFAULT_TIMEOUT_MILLIS,
+
+            serial: None,
+
+            bearer_token: None,
+
+            tls: None,
+
+            socket: None,
+
+            socket_mode: None,
         }
     }
 }
 
 impl Display for HttpConfig {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        // TODO: HTTPS support
-        write!(f, "http://{}:{}", self.addr, self.port)
+        if let Some(socket) = &self.socket {
+            return write!(f, "unix://{}", socket.display());
+        }
+
+        let scheme = if self.tls.is_some() { "https" } else { "http" };
+
+        write!(f, "{}://{}:{}", scheme, self.addr, self.port)
     }
 }
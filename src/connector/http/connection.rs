@@ -1,7 +1,8 @@
 //! Persistent HTTP connection to `yubihsm-connector`
 
-use super::{client, config::HttpConfig};
+use super::{client, config::HttpConfig, Status};
 use crate::connector::{self, Connection};
+use std::str;
 use uuid::Uuid;
 
 /// Connection to YubiHSM via HTTP requests to `yubihsm-connector`.
@@ -14,6 +15,12 @@ use uuid::Uuid;
 ///
 /// <https://developers.yubico.com/YubiHSM2/Component_Reference/yubihsm-connector/>
 pub struct HttpConnection {
+    /// Path of the API endpoint
+    api_path: String,
+
+    /// Path of the status page
+    status_path: String,
+
     /// HTTP connection
     connection: client::Connection,
 }
@@ -21,9 +28,30 @@ pub struct HttpConnection {
 impl HttpConnection {
     /// Open a connection to a `yubihsm-connector` service
     pub(crate) fn open(config: &HttpConfig) -> Result<Self, connector::Error> {
-        let connection = client::Connection::open(&config.addr, config.port, &Default::default())?;
+        let opts = config.connection_options()?;
+
+        #[cfg(unix)]
+        if let Some(socket) = &config.socket {
+            let connection = client::Connection::open_unix(socket, &opts)?;
+            return Ok(HttpConnection {
+                api_path: config.api_path(),
+                status_path: config.status_path(),
+                connection,
+            });
+        }
+
+        let connection = client::Connection::open(&config.addr, config.port, &opts)?;
+        Ok(HttpConnection {
+            api_path: config.api_path(),
+            status_path: config.status_path(),
+            connection,
+        })
+    }
 
-        Ok(HttpConnection { connection })
+    /// `GET /connector/status` and parse the status page
+    pub(super) fn status(&self) -> Result<Status, connector::Error> {
+        let body = self.connection.get(self.status_path.as_str())?.into_vec();
+        str::from_utf8(&body)?.parse()
     }
 
     /// Make an HTTP POST request to a `yubihsm-connector` service
@@ -48,7 +76,7 @@ impl Connection for HttpConnection {
         uuid: Uuid,
         cmd: connector::Message,
     ) -> Result<connector::Message, connector::Error> {
-        self.post("/connector/api", uuid, cmd.as_ref())
+        self.post(&self.api_path, uuid, cmd.as_ref())
             .map(Into::into)
     }
 }
@@ -5,133 +5,556 @@
 //!
 //! It's primarily intended for when a Rust application accessing the YubiHSM2
 //! via USB would like to share access to it via HTTP.
+//!
+//! Requests are parsed by a minimal HTTP/1.1 implementation which only
+//! supports what `yubihsm-connector` clients need: `Content-Length` bodies
+//! (no chunked encoding) and persistent connections. Anything else is
+//! rejected with `400 Bad Request`.
+//!
+//! When the `tls` cargo feature is enabled and [`HttpConfig::tls`] is set,
+//! the server accepts HTTPS connections, optionally requiring clients to
+//! authenticate with a certificate (mutual TLS).
+//!
+//! The server can front several YubiHSM 2s, selected by serial number using
+//! a `serial` query parameter (e.g. `POST /connector/api?serial=12345678`).
+//! Requests without a `serial` are routed to the only device, if there's
+//! exactly one. USB devices can be added as they're plugged in: see
+//! [`Server::usb`].
+//!
+//! On Unix platforms the server can listen on a Unix domain socket instead
+//! of a TCP port by setting [`HttpConfig::socket`], in which case access to
+//! the server is governed by the socket's filesystem permissions (see
+//! [`HttpConfig::socket_mode`]). The socket is removed when the server is
+//! shut down or dropped.
+//!
+//! Requests can be processed by a pool of worker threads (see
+//! [`Server::run_workers`]), so a slow client doesn't hold up everyone
+//! else. Access to each device remains serialized, as each [`Connector`]
+//! sends one message at a time. Reading a request and waiting for its
+//! response are bounded by [`HttpConfig::timeout_ms`], the number of clients
+//! connected at once is limited (see [`Server::set_max_connections`]), and
+//! the server can be stopped gracefully using a [`ShutdownHandle`].
+//!
+//! By default anyone who can connect to the server can use the devices it
+//! provides access to. An [`AccessPolicy`] can require clients to present a
+//! bearer token or client certificate, restrict which IP addresses may
+//! connect, and limit how often each client can create sessions (see
+//! [`Server::set_access_policy`]).
+//!
+//! Metrics about the commands sent to devices, their latency, active
+//! sessions, and errors are exported in the Prometheus text format at
+//! `GET /metrics`.
+
+mod access;
+mod connection;
+mod devices;
+mod metrics;
+mod request;
+mod response;
 
-// TODO(tarcieri): HTTPS support (needs `openssl`, would prefer `rustls`).
-// The main use case is on localhost anyway so support is debatable
+pub use self::access::AccessPolicy;
 
+use self::{
+    access::Access, devices::Devices, metrics::Metrics, request::Request, response::Response,
+};
 use super::config::HttpConfig;
 use crate::{
+    command,
     connector::{
         Connector, Error,
-        ErrorKind::{AddrInvalid, RequestError},
+        ErrorKind::{AddrInvalid, IoError, RequestError},
         Message,
     },
+    device::SerialNumber,
     uuid,
 };
-use std::{io, process, time::Instant};
-use tiny_http as http;
+use std::{
+    io,
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, TcpStream},
+    process,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc::{self, RecvTimeoutError},
+        Arc, Mutex, RwLock,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+#[cfg(feature = "tls")]
+use rustls::{ServerConfig, ServerConnection, StreamOwned};
+
+#[cfg(feature = "usb")]
+use crate::connector::usb::UsbConfig;
+
+#[cfg(unix)]
+use std::{
+    fs,
+    os::unix::{
+        fs::{DirBuilderExt, FileTypeExt, PermissionsExt},
+        net::{UnixListener, UnixStream},
+    },
+    path::{Path, PathBuf},
+};
+
+/// Default maximum size of a `/connector/api` request body: twice the
+/// maximum command message size, which leaves ample room for the message
+/// header, session ID, MAC, and padding
+pub const DEFAULT_MAX_BODY_SIZE: usize = 2 * command::MAX_MSG_SIZE;
+
+/// Default maximum number of client connections served at once
+pub const DEFAULT_MAX_CONNECTIONS: usize = 64;
+
+/// Interval at which idle threads check whether the server is shutting down
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
 
 /// `yubihsm-connector` compatible HTTP server
 pub struct Server {
-    /// Address to bind to
+    /// Address (or socket path) the server is listening on
     addr: String,
 
-    /// Port to listen on
-    port: u16,
+    /// Port to listen on (or `None` for Unix domain sockets)
+    port: Option<u16>,
+
+    /// Requests received from clients
+    requests: Mutex<mpsc::Receiver<Request>>,
+
+    /// YubiHSM2 connectors, keyed by serial number
+    devices: Devices,
 
-    /// HTTP server
-    server: http::Server,
+    /// Policy for which clients may use the server
+    access: RwLock<Access>,
 
-    /// YubiHSM2 connector
-    connector: Connector,
+    /// Metrics about requests sent to devices
+    metrics: Metrics,
+
+    /// State shared with the listener and client connection threads
+    shared: Arc<Shared>,
 }
 
 impl Server {
     /// Create a new HTTP service which provides access to the YubiHSM2
     pub fn new(config: &HttpConfig, connector: Connector) -> Result<Server, Error> {
-        let server = http::Server::http(format!("{}:{}", &config.addr, config.port))
+        Self::bind(config, Devices::new(Some(connector)))
+    }
+
+    /// Create a new HTTP service which provides access to several YubiHSM2s,
+    /// selected by serial number
+    pub fn with_devices(
+        config: &HttpConfig,
+        devices: impl IntoIterator<Item = (SerialNumber, Connector)>,
+    ) -> Result<Server, Error> {
+        let server = Self::bind(config, Devices::new(None))?;
+
+        for (serial, connector) in devices {
+            server.add_device(serial, connector);
+        }
+
+        Ok(server)
+    }
+
+    /// Create a new HTTP service which provides access to all YubiHSM2s
+    /// connected via USB.
+    ///
+    /// Devices which are plugged in after the server has started are added
+    /// when a client requests them by serial number or the status page is
+    /// requested (looking for them at most once a second), or when
+    /// [`Server::discover_usb_devices`] is called.
+    #[cfg(feature = "usb")]
+    pub fn usb(config: &HttpConfig, usb_config: &UsbConfig) -> Result<Server, Error> {
+        let server = Self::bind(config, Devices::usb(usb_config))?;
+        server.discover_usb_devices()?;
+        Ok(server)
+    }
+
+    /// Set the maximum size of a request body in bytes (default
+    /// [`DEFAULT_MAX_BODY_SIZE`]). Larger requests are rejected with
+    /// `413 Payload Too Large`.
+    pub fn set_max_body_size(&self, max_body_size: usize) {
+        self.shared
+            .max_body_size
+            .store(max_body_size, Ordering::Relaxed);
+    }
+
+    /// Set the maximum number of client connections served at once (default
+    /// [`DEFAULT_MAX_CONNECTIONS`]). Connections beyond the limit are closed
+    /// as soon as they're accepted.
+    pub fn set_max_connections(&self, max_connections: usize) {
+        self.shared
+            .max_connections
+            .store(max_connections, Ordering::Relaxed);
+    }
+
+    /// Set the policy for which clients may use the server, replacing any
+    /// previous policy. Requests which are rejected are logged.
+    pub fn set_access_policy(&self, policy: &AccessPolicy) -> Result<(), Error> {
+        *self.access.write().unwrap() = Access::new(policy)?;
+        Ok(())
+    }
+
+    /// Get a handle which can be used to shut down this server
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        ShutdownHandle(self.shared.clone())
+    }
+
+    /// Add a device (replacing any existing device with the same serial number)
+    pub fn add_device(&self, serial: SerialNumber, connector: Connector) {
+        info!(
+            "yubihsm::http-server[{}]: serving YubiHSM 2 {}",
+            self.endpoint(),
+            serial
+        );
+
+        self.devices.insert(serial, connector);
+    }
+
+    /// Remove a device, returning its connector (if it was present)
+    pub fn remove_device(&self, serial: SerialNumber) -> Option<Connector> {
+        self.devices.remove(serial)
+    }
+
+    /// Serial numbers of the devices this server provides access to
+    pub fn serial_numbers(&self) -> Vec<SerialNumber> {
+        self.devices.serial_numbers()
+    }
+
+    /// Add any newly connected USB devices (and remove any which have been
+    /// unplugged), returning the serial numbers of all connected devices
+    #[cfg(feature = "usb")]
+    pub fn discover_usb_devices(&self) -> Result<Vec<SerialNumber>, Error> {
+        self.devices.discover_usb()
+    }
+
+    /// Listen for connections, serving requests using the given devices
+    fn bind(config: &HttpConfig, devices: Devices) -> Result<Server, Error> {
+        let (sender, requests) = mpsc::channel();
+        let request_timeout = Duration::from_millis(config.timeout_ms);
+
+        config.check_supported()?;
+
+        #[cfg(unix)]
+        if let Some(socket) = &config.socket {
+            ensure!(
+                config.tls.is_none(),
+                AddrInvalid,
+                "TLS is not supported over Unix domain sockets"
+            );
+
+            let listener = bind_unix(socket, config.socket_mode)?;
+            let wake_path = socket.clone();
+            let shared = Arc::new(Shared {
+                socket: Some(socket.clone()),
+                ..Shared::new(request_timeout, move || {
+                    let _ = UnixStream::connect(&wake_path);
+                })
+            });
+
+            let listener_shared = shared.clone();
+            thread::spawn(move || {
+                let shared = listener_shared.clone();
+                accept(
+                    || listener.accept().map(|(stream, _)| stream),
+                    &listener_shared,
+                    move |stream| connection::serve(stream, None, &shared, &sender),
+                )
+            });
+
+            let server = Self::listening(
+                socket.display().to_string(),
+                None,
+                requests,
+                devices,
+                shared,
+            );
+            return Ok(server);
+        }
+
+        #[cfg(feature = "tls")]
+        let tls = config
+            .tls
+            .as_ref()
+            .map(|tls| tls.server_config())
+            .transpose()?;
+
+        let listener = TcpListener::bind((config.addr.as_str(), config.port))
             .map_err(|e| format_err!(AddrInvalid, "couldn't create HTTP server: {}", e))?;
 
+        let wake_addr = listener.local_addr().map(wake_addr)?;
+        let shared = Arc::new(Shared::new(request_timeout, move || {
+            let _ = TcpStream::connect_timeout(&wake_addr, POLL_INTERVAL);
+        }));
+
+        let listener_shared = shared.clone();
+        thread::spawn(move || {
+            let shared = listener_shared.clone();
+            accept(
+                || listener.accept().map(|(stream, _)| stream),
+                &listener_shared,
+                move |stream| {
+                    let peer_addr = stream.peer_addr().ok();
+
+                    #[cfg(feature = "tls")]
+                    if let Some(tls) = &tls {
+                        return serve_tls(tls.clone(), stream, peer_addr, &shared, &sender);
+                    }
+
+                    connection::serve(stream, peer_addr, &shared, &sender)
+                },
+            )
+        });
+
+        Ok(Self::listening(
+            config.addr.clone(),
+            Some(config.port),
+            requests,
+            devices,
+            shared,
+        ))
+    }
+
+    /// Create a server which handles requests from a listener thread
+    fn listening(
+        addr: String,
+        port: Option<u16>,
+        requests: mpsc::Receiver<Request>,
+        devices: Devices,
+        shared: Arc<Shared>,
+    ) -> Self {
+        let server = Self {
+            addr,
+            port,
+            requests: Mutex::new(requests),
+            devices,
+            access: RwLock::new(Access::new(&AccessPolicy::default()).unwrap()),
+            metrics: Metrics::default(),
+            shared,
+        };
+
         info!(
-            "yubihsm::http-server[{}:{}]: listening for connections",
-            &config.addr, config.port
+            "yubihsm::http-server[{}]: listening for connections",
+            server.endpoint()
         );
 
-        Ok(Self {
-            addr: config.addr.clone(),
-            port: config.port,
-            server,
-            connector,
-        })
+        server
     }
 
-    /// Run the server's main loop, processing incoming requests
+    /// Run the server's main loop, processing incoming requests one at a
+    /// time until the server is shut down
     pub fn run(&self) -> Result<(), Error> {
-        loop {
-            self.handle_request()?;
-        }
+        self.run_workers(1)
+    }
+
+    /// Process incoming requests using the given number of worker threads
+    /// until the server is shut down.
+    ///
+    /// Requests for different devices are processed concurrently, while
+    /// requests for the same device are sent to it one at a time. Requests
+    /// received before shutdown are processed before this method returns.
+    pub fn run_workers(&self, workers: usize) -> Result<(), Error> {
+        ensure!(
+            workers > 0,
+            RequestError,
+            "HTTP server needs at least one worker"
+        );
+
+        thread::scope(|scope| {
+            for _ in 0..workers {
+                scope.spawn(|| {
+                    while let Some(request) = self.next_request() {
+                        if let Err(e) = self.process(request) {
+                            warn!("yubihsm::http-server[{}]: {}", self.endpoint(), e);
+                        }
+                    }
+                });
+            }
+        });
+
+        info!("yubihsm::http-server[{}]: shut down", self.endpoint());
+        Ok(())
     }
 
     /// Handle an incoming HTTP request
     pub fn handle_request(&self) -> Result<(), Error> {
-        let mut request = self.server.recv()?;
-
-        let response = match *request.method() {
-            http::Method::Get => match request.url() {
-                "/connector/status" => Some(self.status()?),
-                _ => None,
-            },
-            http::Method::Post => match request.url() {
-                "/connector/api" => Some(self.api(&mut request)?),
-                _ => None,
-            },
-            _ => None,
+        match self.next_request() {
+            Some(request) => self.process(request),
+            None => fail!(IoError, "HTTP server has shut down"),
         }
-        .unwrap_or_else(|| {
-            http::Response::new(
-                http::StatusCode::from(404),
-                vec![],
-                io::Cursor::new(vec![]),
-                None,
-                None,
-            )
-        });
+    }
 
-        request.respond(response)?;
-        Ok(())
+    /// Wait for the next request, returning `None` once the server has shut
+    /// down and all pending requests have been processed
+    fn next_request(&self) -> Option<Request> {
+        let requests = self.requests.lock().unwrap();
+
+        loop {
+            if self.shared.is_stopping() {
+                return requests.try_recv().ok();
+            }
+
+            match requests.recv_timeout(POLL_INTERVAL) {
+                Ok(request) => return Some(request),
+                Err(RecvTimeoutError::Timeout) => continue,
+                Err(RecvTimeoutError::Disconnected) => return None,
+            }
+        }
+    }
+
+    /// Process a request, sending its response to the client
+    fn process(&self, request: Request) -> Result<(), Error> {
+        // The client has already been told this request timed out
+        if request.received_at.elapsed() >= self.shared.request_timeout {
+            debug!(
+                "yubihsm::http-server[{}]: dropping expired request: {} {}",
+                self.endpoint(),
+                request.method,
+                request.path
+            );
+            return Ok(());
+        }
+
+        let client = match self.access.read().unwrap().check(&request) {
+            Ok(client) => client,
+            Err(rejection) => {
+                warn!(
+                    "yubihsm::http-server[{}]: {} {} - rejected client:{} ({})",
+                    self.endpoint(),
+                    request.method,
+                    request.path,
+                    rejection.client,
+                    rejection.reason
+                );
+
+                request.respond(Response::error(rejection.status));
+                return Ok(());
+            }
+        };
+
+        let (path, query) = request
+            .path
+            .split_once('?')
+            .unwrap_or((request.path.as_str(), ""));
+
+        let serial = match parse_serial(query) {
+            Ok(serial) => serial,
+            Err(e) => {
+                debug!("yubihsm::http-server[{}]: {}", self.endpoint(), e);
+                request.respond(Response::error(400));
+                return Ok(());
+            }
+        };
+
+        let result = match (request.method.as_str(), path) {
+            ("GET", "/connector/status") => self.status(serial),
+            ("POST", "/connector/api") => self.api(&request, &client, serial),
+            ("GET", "/metrics") => Ok(self.metrics()),
+            _ => Ok(Response::error(404)),
+        };
+
+        match result {
+            Ok(response) => {
+                request.respond(response);
+                Ok(())
+            }
+            Err(e) => {
+                request.respond(Response::error(500));
+                Err(e)
+            }
+        }
     }
 
     /// `GET /connector/status` - status page
-    fn status(&self) -> Result<http::Response<io::Cursor<Vec<u8>>>, Error> {
+    fn status(&self, serial: Option<SerialNumber>) -> Result<Response, Error> {
         info!(
-            "yubihsm::http-server[{}:{}]: GET /connector/status",
-            &self.addr, self.port
+            "yubihsm::http-server[{}]: GET /connector/status",
+            self.endpoint()
         );
 
-        let status = [
-            ("status", "OK"),
-            ("serial", "*"),
+        let (serial, available) = self.devices.status(serial);
+        let pid = process::id().to_string();
+        let port = self.port.map(|port| port.to_string());
+
+        let mut status = vec![
+            ("status", if available { "OK" } else { "NO_DEVICE" }),
+            ("serial", &serial),
             ("version", env!("CARGO_PKG_VERSION")),
-            ("pid", &process::id().to_string()),
+            ("pid", &pid),
             ("address", &self.addr),
-            ("port", &self.port.to_string()),
         ];
 
+        if let Some(port) = &port {
+            status.push(("port", port));
+        }
+
         let body = status
             .iter()
-            .map(|(k, v)| [*k, *v].join("\n"))
+            .map(|(k, v)| format!("{k}={v}"))
             .collect::<Vec<_>>()
             .join("\n");
 
-        Ok(http::Response::from_string(body))
+        Ok(Response::ok("text/plain; charset=UTF-8", body))
     }
 
     /// `POST /connector/api` - send message to the YubiHSM 2
     fn api(
         &self,
-        request: &mut http::Request,
-    ) -> Result<http::Response<io::Cursor<Vec<u8>>>, Error> {
-        let mut body = Vec::new();
-        request.as_reader().read_to_end(&mut body)?;
+        request: &Request,
+        client: &str,
+        serial: Option<SerialNumber>,
+    ) -> Result<Response, Error> {
+        let connector = match self.devices.get(serial) {
+            Some(connector) => connector,
+            None => {
+                debug!(
+                    "yubihsm::http-server[{}]: no device for serial {}",
+                    self.endpoint(),
+                    serial.map(|s| s.to_string()).unwrap_or("*".to_owned())
+                );
+
+                return Ok(Response::error(404));
+            }
+        };
 
-        let command_msg = Message::from(body);
-        let command = command_msg
-            .clone()
-            .parse()
-            .map_err(|e| format_err!(RequestError, "couldn't parse request message: {}", e))?;
+        let command_msg = Message::from(request.body.clone());
+        let command = match command_msg.clone().parse() {
+            Ok(command) => command,
+            Err(e) => {
+                debug!(
+                    "yubihsm::http-server[{}]: couldn't parse request message: {}",
+                    self.endpoint(),
+                    e
+                );
 
+                return Ok(Response::error(400));
+            }
+        };
+
+        if command.command_type == command::Code::CreateSession
+            && !self.access.read().unwrap().allow_session(client)
+        {
+            warn!(
+                "yubihsm::http-server[{}]: POST /connector/api - rejected client:{} (session rate limit exceeded)",
+                self.endpoint(),
+                client
+            );
+
+            return Ok(Response::error(429));
+        }
+
+        let in_flight = self.metrics.start();
         let started_at = Instant::now();
-        let response_msg = self.connector.send_message(uuid::new_v4(), command_msg)?;
+        let result = connector.send_message(uuid::new_v4(), command_msg);
+        let latency = started_at.elapsed();
+        drop(in_flight);
+
+        self.metrics.record(
+            serial,
+            command.command_type,
+            command.session_id,
+            latency,
+            result.as_ref().map(|_| ()),
+        );
+
+        let response_msg = result?;
 
         let session = command
             .session_id
@@ -139,14 +562,293 @@ impl Server {
             .unwrap_or_else(|| "none".to_owned());
 
         info!(
-            "yubihsm::http-server[{}:{}]: POST /connector/api - session:{} cmd:{:?} t:{}ms",
-            &self.addr,
-            self.port,
+            "yubihsm::http-server[{}]: POST /connector/api - client:{} session:{} cmd:{:?} t:{}ms",
+            self.endpoint(),
+            client,
             &session,
             command.command_type,
-            started_at.elapsed().as_millis()
+            latency.as_millis()
+        );
+
+        Ok(Response::ok("application/octet-stream", response_msg))
+    }
+
+    /// `GET /metrics` - metrics in the Prometheus text format
+    fn metrics(&self) -> Response {
+        debug!("yubihsm::http-server[{}]: GET /metrics", self.endpoint());
+        Response::ok(
+            "text/plain; version=0.0.4; charset=utf-8",
+            self.metrics.render(),
+        )
+    }
+
+    /// Address the server is listening on, for logging
+    fn endpoint(&self) -> String {
+        match self.port {
+            Some(port) => format!("{}:{}", self.addr, port),
+            None => self.addr.clone(),
+        }
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        self.shutdown_handle().shutdown();
+    }
+}
+
+/// Parse the serial number requested in a query string (e.g. `serial=12345678`)
+fn parse_serial(query: &str) -> Result<Option<SerialNumber>, Error> {
+    for param in query.split('&') {
+        if let Some(serial) = param.strip_prefix("serial=") {
+            return serial.parse().map(|n| Some(SerialNumber(n))).map_err(|_| {
+                format_err!(RequestError, "invalid serial number: {:?}", serial).into()
+            });
+        }
+    }
+
+    Ok(None)
+}
+
+/// Handle for shutting down a [`Server`].
+///
+/// Shutting down stops the server accepting new connections, closes idle
+/// connections, and causes [`Server::run`] and [`Server::run_workers`] to
+/// return once requests which have already been received are processed.
+#[derive(Clone)]
+pub struct ShutdownHandle(Arc<Shared>);
+
+impl ShutdownHandle {
+    /// Shut down the server
+    pub fn shutdown(&self) {
+        if !self.0.stopping.swap(true, Ordering::SeqCst) {
+            // Wake the listener thread, which is blocked accepting connections
+            (self.0.wake)();
+
+            #[cfg(unix)]
+            if let Some(socket) = &self.0.socket {
+                if let Err(e) = fs::remove_file(socket) {
+                    debug!(
+                        "yubihsm::http-server: couldn't remove {}: {}",
+                        socket.display(),
+                        e
+                    );
+                }
+            }
+        }
+    }
+
+    /// Has the server been shut down?
+    pub fn is_shutdown(&self) -> bool {
+        self.0.is_stopping()
+    }
+}
+
+/// State shared between the server, its listener, and client connections
+struct Shared {
+    /// Is the server shutting down?
+    stopping: AtomicBool,
+
+    /// Maximum time to spend reading a request or waiting for its response
+    request_timeout: Duration,
+
+    /// Maximum size of a request body
+    max_body_size: AtomicUsize,
+
+    /// Maximum number of client connections served at once
+    max_connections: AtomicUsize,
+
+    /// Number of client connections currently being served
+    connections: AtomicUsize,
+
+    /// Wake the listener thread by connecting to it
+    wake: Box<dyn Fn() + Send + Sync>,
+
+    /// Path of the Unix domain socket to remove on shutdown
+    #[cfg(unix)]
+    socket: Option<PathBuf>,
+}
+
+impl Shared {
+    /// Create shared state for a server
+    fn new(request_timeout: Duration, wake: impl Fn() + Send + Sync + 'static) -> Self {
+        Self {
+            stopping: AtomicBool::new(false),
+            request_timeout,
+            max_body_size: AtomicUsize::new(DEFAULT_MAX_BODY_SIZE),
+            max_connections: AtomicUsize::new(DEFAULT_MAX_CONNECTIONS),
+            connections: AtomicUsize::new(0),
+            wake: Box::new(wake),
+            #[cfg(unix)]
+            socket: None,
+        }
+    }
+
+    /// Is the server shutting down?
+    fn is_stopping(&self) -> bool {
+        self.stopping.load(Ordering::SeqCst)
+    }
+
+    /// Maximum size of a request body
+    fn max_body_size(&self) -> usize {
+        self.max_body_size.load(Ordering::Relaxed)
+    }
+}
+
+/// Accept incoming connections until the server shuts down, serving each of
+/// them on its own thread (up to the server's connection limit)
+fn accept<S, F>(mut next: impl FnMut() -> io::Result<S>, shared: &Arc<Shared>, serve: F)
+where
+    S: Send + 'static,
+    F: Fn(S) + Send + Sync + 'static,
+{
+    let serve = Arc::new(serve);
+
+    while !shared.is_stopping() {
+        match next() {
+            Ok(_) if shared.is_stopping() => break,
+            Ok(stream) => {
+                let slot = match ConnectionSlot::acquire(shared) {
+                    Some(slot) => slot,
+                    None => {
+                        debug!(
+                            "yubihsm::http-server: too many connections; closing new connection"
+                        );
+                        continue;
+                    }
+                };
+
+                let serve = serve.clone();
+                thread::spawn(move || {
+                    serve(stream);
+                    drop(slot);
+                });
+            }
+            Err(e) => {
+                debug!("yubihsm::http-server: error accepting connection: {}", e);
+
+                // Avoid spinning if e.g. we've run out of file descriptors
+                thread::sleep(POLL_INTERVAL);
+            }
+        }
+    }
+}
+
+/// A client connection counted against the server's connection limit, which
+/// is released when the connection is closed
+struct ConnectionSlot(Arc<Shared>);
+
+impl ConnectionSlot {
+    /// Count a new connection, unless the server is already serving as many
+    /// connections as it allows
+    fn acquire(shared: &Arc<Shared>) -> Option<Self> {
+        let max_connections = shared.max_connections.load(Ordering::Relaxed);
+
+        shared
+            .connections
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| {
+                (n < max_connections).then_some(n + 1)
+            })
+            .ok()?;
+
+        Some(Self(shared.clone()))
+    }
+}
+
+impl Drop for ConnectionSlot {
+    fn drop(&mut self) {
+        self.0.connections.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Address to connect to in order to wake a listener bound to `addr`
+fn wake_addr(addr: SocketAddr) -> SocketAddr {
+    match addr {
+        SocketAddr::V4(a) if a.ip().is_unspecified() => (Ipv4Addr::LOCALHOST, a.port()).into(),
+        SocketAddr::V6(a) if a.ip().is_unspecified() => (Ipv6Addr::LOCALHOST, a.port()).into(),
+        addr => addr,
+    }
+}
+
+/// Bind a Unix domain socket, replacing any stale socket left at its path,
+/// and give it the requested permissions
+#[cfg(unix)]
+fn bind_unix(path: &Path, mode: Option<u32>) -> Result<UnixListener, Error> {
+    if let Ok(metadata) = fs::symlink_metadata(path) {
+        ensure!(
+            metadata.file_type().is_socket(),
+            AddrInvalid,
+            "{} exists and is not a socket",
+            path.display()
         );
 
-        Ok(http::Response::from_data(response_msg.as_ref()))
+        ensure!(
+            UnixStream::connect(path).is_err(),
+            AddrInvalid,
+            "{} is in use by another server",
+            path.display()
+        );
+
+        fs::remove_file(path)?;
+    }
+
+    let bind = |path: &Path| {
+        UnixListener::bind(path)
+            .map_err(|e| format_err!(AddrInvalid, "couldn't bind to {}: {}", path.display(), e))
+    };
+
+    let Some(mode) = mode else {
+        return Ok(bind(path)?);
+    };
+
+    // The socket is created with permissions from the umask, so create it in
+    // a private directory, where no one else can connect to it, until it's
+    // been given the requested permissions
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let dir =
+        path.parent()
+            .unwrap_or_else(|| Path::new(""))
+            .join(format!(".{}.{}", name, process::id()));
+
+    fs::DirBuilder::new()
+        .mode(0o700)
+        .create(&dir)
+        .map_err(|e| format_err!(AddrInvalid, "couldn't create {}: {}", dir.display(), e))?;
+
+    let private_path = dir.join("s");
+    let result = bind(&private_path).and_then(|listener| {
+        fs::set_permissions(&private_path, fs::Permissions::from_mode(mode)).map_err(|e| {
+            format_err!(
+                AddrInvalid,
+                "couldn't set permissions of {} to {:o}: {}",
+                path.display(),
+                mode,
+                e
+            )
+        })?;
+
+        fs::rename(&private_path, path)
+            .map_err(|e| format_err!(AddrInvalid, "couldn't bind to {}: {}", path.display(), e))?;
+
+        Ok(listener)
+    });
+
+    let _ = fs::remove_file(&private_path);
+    let _ = fs::remove_dir(&dir);
+    Ok(result?)
+}
+
+/// Serve a client connection over TLS
+#[cfg(feature = "tls")]
+fn serve_tls(
+    config: Arc<ServerConfig>,
+    stream: TcpStream,
+    peer_addr: Option<SocketAddr>,
+    shared: &Shared,
+    requests: &mpsc::Sender<Request>,
+) {
+    match ServerConnection::new(config) {
+        Ok(conn) => connection::serve(StreamOwned::new(conn, stream), peer_addr, shared, requests),
+        Err(e) => debug!("yubihsm::http-server: TLS error: {}", e),
     }
 }
@@ -0,0 +1,305 @@
+//! Access control for the HTTP server: client authentication, source IP
+//! allowlisting, and session creation rate limits
+
+use super::request::Request;
+use crate::connector::{Error, ErrorKind::AddrInvalid};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt,
+    net::IpAddr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use subtle::ConstantTimeEq;
+
+#[cfg(feature = "tls")]
+use {super::connection::Peer, rustls::pki_types::ServerName};
+
+#[cfg(not(feature = "tls"))]
+use crate::connector::ErrorKind::TlsError;
+
+/// Window over which session creation is rate limited
+const RATE_LIMIT_WINDOW: Duration = Duration::from_secs(60);
+
+/// Access policy for the HTTP server.
+///
+/// By default any client which can connect to the server may use it. When
+/// bearer tokens or client certificate names are configured, clients must
+/// authenticate with one of them.
+#[derive(Clone, Default, Deserialize, Serialize)]
+pub struct AccessPolicy {
+    /// Bearer tokens which authenticate clients, sent by clients in an
+    /// `Authorization: Bearer <token>` header (see
+    /// [`HttpConfig::bearer_token`][crate::connector::HttpConfig::bearer_token])
+    #[serde(default)]
+    pub bearer_tokens: Vec<String>,
+
+    /// DNS names which authenticate clients presenting a certificate valid
+    /// for one of them. Requires the server to verify client certificates
+    /// (i.e. [`TlsConfig::ca_cert`][crate::connector::TlsConfig::ca_cert]),
+    /// so they're rejected without the `tls` cargo feature.
+    #[serde(default)]
+    pub client_names: Vec<String>,
+
+    /// IP addresses or networks in CIDR notation (e.g. `10.0.0.0/8`) which
+    /// are allowed to use the server. Any address is allowed if empty.
+    ///
+    /// Doesn't apply to Unix domain sockets.
+    #[serde(default)]
+    pub allowed_ips: Vec<String>,
+
+    /// Maximum number of sessions each client may create per minute
+    #[serde(default)]
+    pub max_sessions_per_minute: Option<u32>,
+}
+
+impl fmt::Debug for AccessPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AccessPolicy")
+            // Avoid leaking secrets in debug messages
+            .field(
+                "bearer_tokens",
+                &format_args!("[...; {}]", self.bearer_tokens.len()),
+            )
+            .field("client_names", &self.client_names)
+            .field("allowed_ips", &self.allowed_ips)
+            .field("max_sessions_per_minute", &self.max_sessions_per_minute)
+            .finish()
+    }
+}
+
+/// Access policy, parsed and ready to check requests against
+pub(super) struct Access {
+    /// Accepted bearer tokens
+    bearer_tokens: Vec<String>,
+
+    /// Accepted client certificate names
+    #[cfg(feature = "tls")]
+    client_names: Vec<(String, ServerName<'static>)>,
+
+    /// Allowed source networks
+    allowed_ips: Vec<IpNetwork>,
+
+    /// Maximum number of sessions each client may create per minute
+    max_sessions_per_minute: Option<u32>,
+
+    /// Times at which each client recently created sessions
+    sessions: Mutex<HashMap<String, VecDeque<Instant>>>,
+}
+
+impl Access {
+    /// Parse an access policy
+    pub(super) fn new(policy: &AccessPolicy) -> Result<Self, Error> {
+        #[cfg(not(feature = "tls"))]
+        ensure!(
+            policy.client_names.is_empty(),
+            TlsError,
+            "client certificate names require the `tls` cargo feature"
+        );
+
+        #[cfg(feature = "tls")]
+        let client_names = policy
+            .client_names
+            .iter()
+            .map(|name| {
+                ServerName::try_from(name.as_str())
+                    .map(|server_name| (name.clone(), server_name.to_owned()))
+                    .map_err(|e| format_err!(AddrInvalid, "invalid client name {:?}: {}", name, e))
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self {
+            bearer_tokens: policy.bearer_tokens.clone(),
+            #[cfg(feature = "tls")]
+            client_names,
+            allowed_ips: policy
+                .allowed_ips
+                .iter()
+                .map(|network| IpNetwork::parse(network))
+                .collect::<Result<_, _>>()?,
+            max_sessions_per_minute: policy.max_sessions_per_minute,
+            sessions: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Check whether a request is allowed, returning a description of the
+    /// client which made it (used for logging and rate limiting)
+    pub(super) fn check(&self, request: &Request) -> Result<String, Rejection> {
+        let ip = request.peer.addr.map(|addr| addr.ip().to_canonical());
+
+        if let Some(ip) = ip {
+            if !self.allowed_ips.is_empty()
+                && !self.allowed_ips.iter().any(|network| network.contains(ip))
+            {
+                return Err(Rejection::new(403, ip, "address not allowed"));
+            }
+        }
+
+        let client = ip.map(|ip| ip.to_string()).unwrap_or("local".to_owned());
+
+        if !self.requires_authentication() {
+            return Ok(client);
+        }
+
+        if let Some(token) = bearer_token(request) {
+            let position = self
+                .bearer_tokens
+                .iter()
+                .position(|t| bool::from(t.as_bytes().ct_eq(token.as_bytes())));
+
+            return match position {
+                Some(n) => Ok(format!("{client}/token#{n}")),
+                None => Err(Rejection::new(401, client, "invalid bearer token")),
+            };
+        }
+
+        #[cfg(feature = "tls")]
+        if let Some(name) = self.certificate_name(&request.peer) {
+            return Ok(format!("{client}/{name}"));
+        }
+
+        Err(Rejection::new(401, client, "not authenticated"))
+    }
+
+    /// Record a session being created by the given client, returning `false`
+    /// if the client has exceeded its rate limit
+    pub(super) fn allow_session(&self, client: &str) -> bool {
+        let max_sessions = match self.max_sessions_per_minute {
+            Some(max) => max as usize,
+            None => return true,
+        };
+
+        let now = Instant::now();
+        let mut sessions = self.sessions.lock().unwrap();
+
+        // Forget clients which haven't created sessions recently
+        sessions.retain(|_, times| {
+            while times
+                .front()
+                .is_some_and(|&t| now.duration_since(t) >= RATE_LIMIT_WINDOW)
+            {
+                times.pop_front();
+            }
+
+            !times.is_empty()
+        });
+
+        let times = sessions.entry(client.to_owned()).or_default();
+
+        if times.len() >= max_sessions {
+            return false;
+        }
+
+        times.push_back(now);
+        true
+    }
+
+    /// Do clients need to authenticate?
+    fn requires_authentication(&self) -> bool {
+        #[cfg(feature = "tls")]
+        if !self.client_names.is_empty() {
+            return true;
+        }
+
+        !self.bearer_tokens.is_empty()
+    }
+
+    /// Name of an accepted client certificate presented by the peer
+    #[cfg(feature = "tls")]
+    fn certificate_name(&self, peer: &Peer) -> Option<&str> {
+        let cert = webpki::EndEntityCert::try_from(peer.certificate.as_ref()?).ok()?;
+
+        self.client_names
+            .iter()
+            .find(|(_, server_name)| cert.verify_is_valid_for_subject_name(server_name).is_ok())
+            .map(|(name, _)| name.as_str())
+    }
+}
+
+/// Request rejected by the access policy
+pub(super) struct Rejection {
+    /// HTTP status to respond with
+    pub(super) status: u16,
+
+    /// Description of the client
+    pub(super) client: String,
+
+    /// Reason the request was rejected
+    pub(super) reason: &'static str,
+}
+
+impl Rejection {
+    /// Create a new rejection
+    fn new(status: u16, client: impl ToString, reason: &'static str) -> Self {
+        Self {
+            status,
+            client: client.to_string(),
+            reason,
+        }
+    }
+}
+
+/// IP network in CIDR notation (or a single address)
+struct IpNetwork {
+    /// Network address
+    addr: IpAddr,
+
+    /// Number of leading bits which identify the network
+    prefix_len: u32,
+}
+
+impl IpNetwork {
+    /// Parse a network (e.g. `10.0.0.0/8`) or single address (e.g. `::1`)
+    fn parse(network: &str) -> Result<Self, Error> {
+        let invalid = || format_err!(AddrInvalid, "invalid IP network: {:?}", network);
+
+        let (addr, prefix_len) = match network.split_once('/') {
+            Some((addr, prefix_len)) => (addr, Some(prefix_len)),
+            None => (network, None),
+        };
+
+        let addr = addr
+            .parse::<IpAddr>()
+            .map_err(|_| invalid())?
+            .to_canonical();
+        let max_len = if addr.is_ipv4() { 32 } else { 128 };
+
+        let prefix_len = match prefix_len {
+            Some(len) => len.parse().map_err(|_| invalid())?,
+            None => max_len,
+        };
+
+        if prefix_len > max_len {
+            return Err(invalid().into());
+        }
+
+        Ok(Self { addr, prefix_len })
+    }
+
+    /// Is the given address part of this network?
+    fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, ip) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix_len).unwrap_or(0);
+                u32::from(network) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix_len).unwrap_or(0);
+                u128::from(network) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Bearer token presented in a request's `Authorization` header
+fn bearer_token(request: &Request) -> Option<&str> {
+    let (scheme, token) = request.header("Authorization")?.split_once(' ')?;
+
+    if scheme.eq_ignore_ascii_case("Bearer") {
+        Some(token.trim())
+    } else {
+        None
+    }
+}
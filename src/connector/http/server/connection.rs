@@ -0,0 +1,257 @@
+//! Connections from HTTP clients
+
+use super::{
+    request::{ReadError, Request},
+    response::Response,
+    Shared, POLL_INTERVAL,
+};
+use std::{
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{SocketAddr, TcpStream},
+    sync::mpsc::{self, RecvTimeoutError},
+    time::{Duration, Instant},
+};
+
+#[cfg(feature = "tls")]
+use rustls::{pki_types::CertificateDer, ServerConnection, StreamOwned};
+
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+
+/// Streams the server accepts client connections on
+pub(super) trait Stream: Read + Write {
+    /// Set the timeout for reads from this stream
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()>;
+
+    /// Set the timeout for writes to this stream
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()>;
+
+    /// Certificate presented by the client, if it authenticated with one
+    #[cfg(feature = "tls")]
+    fn peer_certificate(&self) -> Option<CertificateDer<'static>> {
+        None
+    }
+}
+
+/// Client which sent a request
+#[derive(Clone, Debug, Default)]
+pub(super) struct Peer {
+    /// Client's address (`None` for Unix domain sockets)
+    pub(super) addr: Option<SocketAddr>,
+
+    /// Certificate presented by the client
+    #[cfg(feature = "tls")]
+    pub(super) certificate: Option<CertificateDer<'static>>,
+}
+
+impl Stream for TcpStream {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        TcpStream::set_read_timeout(self, timeout)
+    }
+
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        TcpStream::set_write_timeout(self, timeout)
+    }
+}
+
+#[cfg(unix)]
+impl Stream for UnixStream {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        UnixStream::set_read_timeout(self, timeout)
+    }
+
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        UnixStream::set_write_timeout(self, timeout)
+    }
+}
+
+#[cfg(feature = "tls")]
+impl Stream for StreamOwned<ServerConnection, TcpStream> {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.sock.set_read_timeout(timeout)
+    }
+
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.sock.set_write_timeout(timeout)
+    }
+
+    fn peer_certificate(&self) -> Option<CertificateDer<'static>> {
+        self.conn
+            .peer_certificates()
+            .and_then(|certs| certs.first())
+            .map(|cert| cert.clone().into_owned())
+    }
+}
+
+/// Stream which enforces a deadline for reading an entire request, so a
+/// client can't hold its connection open by sending a request a few bytes
+/// at a time
+struct Deadline<S> {
+    /// Stream being read from
+    stream: S,
+
+    /// Time by which the current request must have been read
+    deadline: Option<Instant>,
+}
+
+impl<S: Stream> Read for Deadline<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if let Some(deadline) = self.deadline {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+
+            if remaining.is_zero() {
+                return Err(io::ErrorKind::TimedOut.into());
+            }
+
+            self.stream.set_read_timeout(Some(remaining))?;
+        }
+
+        self.stream.read(buf)
+    }
+}
+
+impl<S: Stream> Write for Deadline<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.stream.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+/// Read requests from a client connection and forward them to the server,
+/// writing back each response, until the client disconnects or the server
+/// shuts down.
+///
+/// Connections are persistent (HTTP/1.1 keep-alive) unless the client asks
+/// for them to be closed. Reading a request (its headers and body together)
+/// and waiting for its response are each bounded by the server's request
+/// timeout.
+pub(super) fn serve<S: Stream>(
+    stream: S,
+    peer_addr: Option<SocketAddr>,
+    shared: &Shared,
+    requests: &mpsc::Sender<Request>,
+) {
+    if let Err(e) = stream.set_write_timeout(Some(shared.request_timeout)) {
+        debug!("yubihsm::http-server: error configuring connection: {}", e);
+        return;
+    }
+
+    let mut stream = BufReader::new(Deadline {
+        stream,
+        deadline: None,
+    });
+
+    while wait_for_request(&mut stream, shared) {
+        let peer = Peer {
+            addr: peer_addr,
+            #[cfg(feature = "tls")]
+            certificate: stream.get_ref().stream.peer_certificate(),
+        };
+
+        stream.get_mut().deadline = Some(Instant::now() + shared.request_timeout);
+        let request = Request::read(&mut stream, peer, shared.max_body_size());
+        stream.get_mut().deadline = None;
+
+        let (request, response) = match request {
+            Ok(Some(request)) => request,
+            Ok(None) => return,
+            Err(ReadError::Malformed(e)) => {
+                debug!(
+                    "yubihsm::http-server: bad request from {:?}: {}",
+                    peer_addr, e
+                );
+                let _ = Response::error(400).write_to(stream.get_mut(), true);
+                return;
+            }
+            Err(ReadError::TooLarge(size)) => {
+                debug!(
+                    "yubihsm::http-server: {}-byte request from {:?} exceeds maximum size",
+                    size, peer_addr
+                );
+                let _ = Response::error(413).write_to(stream.get_mut(), true);
+                return;
+            }
+            Err(ReadError::Io(e)) => {
+                debug!(
+                    "yubihsm::http-server: error reading request from {:?}: {}",
+                    peer_addr, e
+                );
+                return;
+            }
+        };
+
+        // Don't accept new work once the server is shutting down
+        if shared.is_stopping() {
+            let _ = Response::error(503).write_to(stream.get_mut(), true);
+            return;
+        }
+
+        let close = request.wants_close();
+
+        // Stop serving this connection if the server has shut down
+        if requests.send(request).is_err() {
+            return;
+        }
+
+        let response = match response.recv_timeout(shared.request_timeout) {
+            Ok(response) => response,
+            Err(RecvTimeoutError::Timeout) => {
+                debug!(
+                    "yubihsm::http-server: request from {:?} timed out",
+                    peer_addr
+                );
+                let _ = Response::error(503).write_to(stream.get_mut(), true);
+                return;
+            }
+            Err(RecvTimeoutError::Disconnected) => return,
+        };
+
+        if let Err(e) = response.write_to(stream.get_mut(), close) {
+            debug!(
+                "yubihsm::http-server: error writing response to {:?}: {}",
+                peer_addr, e
+            );
+            return;
+        }
+
+        if close {
+            return;
+        }
+    }
+}
+
+/// Wait for the client to start sending a request, returning `false` if the
+/// client disconnected or the server is shutting down.
+///
+/// Idle connections are polled so they can be closed promptly on shutdown.
+fn wait_for_request<S: Stream>(stream: &mut BufReader<Deadline<S>>, shared: &Shared) -> bool {
+    if let Err(e) = stream
+        .get_ref()
+        .stream
+        .set_read_timeout(Some(POLL_INTERVAL))
+    {
+        debug!("yubihsm::http-server: error configuring connection: {}", e);
+        return false;
+    }
+
+    while !shared.is_stopping() {
+        match stream.fill_buf() {
+            Ok(buf) => return !buf.is_empty(),
+            Err(e) if is_retryable(&e) => continue,
+            Err(_) => return false,
+        }
+    }
+
+    false
+}
+
+/// Is this error from polling an idle connection worth retrying?
+fn is_retryable(error: &io::Error) -> bool {
+    matches!(
+        error.kind(),
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut | io::ErrorKind::Interrupted
+    )
+}
@@ -0,0 +1,286 @@
+//! Devices served by the HTTP server, keyed by serial number
+
+use crate::{
+    command,
+    connector::{
+        Connector, Error,
+        ErrorKind::{RequestError, ResponseError},
+    },
+    device::{self, SerialNumber},
+    response,
+    serialization::deserialize,
+    uuid,
+};
+use std::{
+    collections::BTreeMap,
+    sync::{Mutex, OnceLock, RwLock},
+    time::{Duration, Instant},
+};
+
+#[cfg(feature = "usb")]
+use {
+    crate::connector::usb::{Devices as UsbDevices, UsbConfig},
+    std::collections::BTreeSet,
+};
+
+/// Minimum time between looking for new USB devices, or asking the default
+/// device for its serial number, in response to requests
+const RESCAN_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Connectors for the YubiHSM 2s the server provides access to
+pub(super) struct Devices {
+    /// Connector for a single device whose serial number isn't known, which
+    /// handles all requests that don't ask for a particular serial number
+    default: Option<Connector>,
+
+    /// Serial number of the default device, once it's known
+    default_serial: OnceLock<SerialNumber>,
+
+    /// When the default device was last asked for its serial number
+    default_queried: Rescan,
+
+    /// Connectors for devices with known serial numbers
+    by_serial: RwLock<BTreeMap<SerialNumber, Connector>>,
+
+    /// Configuration for USB devices discovered by the server
+    #[cfg(feature = "usb")]
+    usb: Option<UsbConfig>,
+
+    /// Serial numbers of devices which were discovered via USB
+    #[cfg(feature = "usb")]
+    discovered: RwLock<BTreeSet<SerialNumber>>,
+
+    /// When USB devices were last enumerated
+    #[cfg(feature = "usb")]
+    usb_scanned: Rescan,
+}
+
+impl Devices {
+    /// Create a set of devices, with an optional default connector
+    pub(super) fn new(default: Option<Connector>) -> Self {
+        Self {
+            default,
+            default_serial: OnceLock::new(),
+            default_queried: Rescan::default(),
+            by_serial: RwLock::new(BTreeMap::new()),
+            #[cfg(feature = "usb")]
+            usb: None,
+            #[cfg(feature = "usb")]
+            discovered: RwLock::new(BTreeSet::new()),
+            #[cfg(feature = "usb")]
+            usb_scanned: Rescan::default(),
+        }
+    }
+
+    /// Create a set of devices which are discovered via USB
+    #[cfg(feature = "usb")]
+    pub(super) fn usb(config: &UsbConfig) -> Self {
+        Self {
+            usb: Some(config.clone()),
+            ..Self::new(None)
+        }
+    }
+
+    /// Add a device, returning the connector it replaced (if any)
+    pub(super) fn insert(&self, serial: SerialNumber, connector: Connector) -> Option<Connector> {
+        self.by_serial.write().unwrap().insert(serial, connector)
+    }
+
+    /// Remove a device, returning its connector (if it was present)
+    pub(super) fn remove(&self, serial: SerialNumber) -> Option<Connector> {
+        self.by_serial.write().unwrap().remove(&serial)
+    }
+
+    /// Serial numbers of the devices with known serial numbers
+    pub(super) fn serial_numbers(&self) -> Vec<SerialNumber> {
+        self.by_serial.read().unwrap().keys().cloned().collect()
+    }
+
+    /// Find the connector for the requested device.
+    ///
+    /// If no serial number is requested, the default connector is used, or
+    /// the only device if there's exactly one.
+    pub(super) fn get(&self, serial: Option<SerialNumber>) -> Option<Connector> {
+        let serial = match serial {
+            Some(serial) => serial,
+            None => return self.default.clone().or_else(|| self.only_device()),
+        };
+
+        if let Some(connector) = self.by_serial.read().unwrap().get(&serial) {
+            return Some(connector.clone());
+        }
+
+        // The device may have been plugged in since we last looked
+        #[cfg(feature = "usb")]
+        if self.rescan_usb() {
+            return self.by_serial.read().unwrap().get(&serial).cloned();
+        }
+
+        None
+    }
+
+    /// Serial number to report in the status of the requested device (`*`
+    /// if the request could be served by more than one device, or the
+    /// default device's serial number can't be determined), and whether the
+    /// device is available
+    pub(super) fn status(&self, serial: Option<SerialNumber>) -> (String, bool) {
+        #[cfg(feature = "usb")]
+        self.rescan_usb();
+
+        if serial.is_none() {
+            if let Some(default) = &self.default {
+                let serial = self.default_serial(default);
+                return (
+                    serial.map_or_else(|| "*".to_owned(), |s| s.to_string()),
+                    true,
+                );
+            }
+        }
+
+        let by_serial = self.by_serial.read().unwrap();
+
+        match serial {
+            Some(serial) => (serial.to_string(), by_serial.contains_key(&serial)),
+            None => match by_serial.keys().collect::<Vec<_>>().as_slice() {
+                [serial] => (serial.to_string(), true),
+                serials => ("*".to_owned(), !serials.is_empty()),
+            },
+        }
+    }
+
+    /// Look for newly connected USB devices, unless we've looked recently,
+    /// returning whether we looked
+    #[cfg(feature = "usb")]
+    fn rescan_usb(&self) -> bool {
+        if self.usb.is_none() || !self.usb_scanned.due() {
+            return false;
+        }
+
+        if let Err(e) = self.discover_usb() {
+            debug!("yubihsm::http-server: USB discovery failed: {}", e);
+        }
+
+        true
+    }
+
+    /// Add connectors for newly connected USB devices and remove those which
+    /// have been unplugged, returning the serial numbers of all connected
+    /// devices
+    #[cfg(feature = "usb")]
+    pub(super) fn discover_usb(&self) -> Result<Vec<SerialNumber>, Error> {
+        let config = match &self.usb {
+            Some(config) => config,
+            None => fail!(RequestError, "USB discovery is not enabled for this server"),
+        };
+
+        self.usb_scanned.reset();
+
+        let serials = UsbDevices::serial_numbers()?;
+        let mut by_serial = self.by_serial.write().unwrap();
+        let mut discovered = self.discovered.write().unwrap();
+
+        discovered.retain(|serial| {
+            if serials.contains(serial) {
+                return true;
+            }
+
+            info!("yubihsm::http-server: YubiHSM 2 {} removed", serial);
+            by_serial.remove(serial);
+            false
+        });
+
+        for &serial in &serials {
+            if discovered.insert(serial) {
+                info!("yubihsm::http-server: YubiHSM 2 {} connected", serial);
+
+                let usb_config = UsbConfig {
+                    serial: Some(serial),
+                    ..config.clone()
+                };
+
+                by_serial.insert(serial, Connector::usb(&usb_config));
+            }
+        }
+
+        Ok(serials)
+    }
+
+    /// Serial number of the default device, asking the device for it if it
+    /// isn't known yet (and we haven't asked recently)
+    fn default_serial(&self, default: &Connector) -> Option<SerialNumber> {
+        if let Some(&serial) = self.default_serial.get() {
+            return Some(serial);
+        }
+
+        if !self.default_queried.due() {
+            return None;
+        }
+
+        match query_serial(default) {
+            Ok(serial) => Some(*self.default_serial.get_or_init(|| serial)),
+            Err(e) => {
+                debug!("yubihsm::http-server: couldn't get serial number: {}", e);
+                None
+            }
+        }
+    }
+
+    /// The only device with a known serial number, if there's exactly one
+    fn only_device(&self) -> Option<Connector> {
+        let by_serial = self.by_serial.read().unwrap();
+
+        if by_serial.len() == 1 {
+            by_serial.values().next().cloned()
+        } else {
+            None
+        }
+    }
+}
+
+/// Tracks when an expensive check was last made, so it's made at most once
+/// per [`RESCAN_INTERVAL`]
+#[derive(Default)]
+struct Rescan(Mutex<Option<Instant>>);
+
+impl Rescan {
+    /// Is the check due? If so, it's recorded as having been made now
+    fn due(&self) -> bool {
+        let mut last = self.0.lock().unwrap();
+
+        if last.is_some_and(|last| last.elapsed() < RESCAN_INTERVAL) {
+            return false;
+        }
+
+        *last = Some(Instant::now());
+        true
+    }
+
+    /// Record that the check has just been made
+    #[cfg(feature = "usb")]
+    fn reset(&self) {
+        *self.0.lock().unwrap() = Some(Instant::now());
+    }
+}
+
+/// Ask a device for its serial number with the (unauthenticated)
+/// `Device Info` command
+fn query_serial(connector: &Connector) -> Result<SerialNumber, Error> {
+    let command = command::Message::create(command::Code::DeviceInfo, vec![])
+        .map_err(|e| format_err!(RequestError, "{}", e))?;
+
+    let response = connector.send_message(uuid::new_v4(), command.into())?;
+    let response = response::Message::parse(response)
+        .map_err(|e| format_err!(ResponseError, "device info failed: {}", e))?;
+
+    ensure!(
+        response.command() == Some(command::Code::DeviceInfo),
+        ResponseError,
+        "device info failed: {:?}",
+        response.code
+    );
+
+    let info: device::Info = deserialize(&response.data)
+        .map_err(|e| format_err!(ResponseError, "device info failed: {}", e))?;
+
+    Ok(info.serial_number)
+}
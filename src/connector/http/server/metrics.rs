@@ -0,0 +1,221 @@
+//! Metrics for the HTTP server, exported in the Prometheus text format
+
+use crate::{command, connector, device::SerialNumber, session};
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+/// Upper bounds of the request latency histogram buckets (in seconds)
+const LATENCY_BUCKETS: [f64; 12] = [
+    0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0,
+];
+
+/// Sessions which haven't been used for this long are no longer counted as
+/// active (the YubiHSM 2 closes sessions after 30 seconds of inactivity)
+const SESSION_ACTIVITY_WINDOW: Duration = Duration::from_secs(30);
+
+/// Metrics collected by the server
+#[derive(Default)]
+pub(super) struct Metrics {
+    /// Number of requests currently being sent to a device
+    in_flight: AtomicU64,
+
+    /// Counters which are updated together
+    state: Mutex<State>,
+}
+
+/// Metrics protected by the lock
+#[derive(Default)]
+struct State {
+    /// Request counts and latencies, by command
+    commands: BTreeMap<command::Code, Histogram>,
+
+    /// When each session was last used, keyed by device and session ID
+    sessions: BTreeMap<(Option<SerialNumber>, session::Id), Instant>,
+
+    /// Errors sending messages to devices, by error kind
+    errors: BTreeMap<String, u64>,
+}
+
+/// Latency histogram for a command
+#[derive(Default)]
+struct Histogram {
+    /// Number of requests which completed within each bucket's bound
+    buckets: [u64; LATENCY_BUCKETS.len()],
+
+    /// Total number of requests
+    count: u64,
+
+    /// Total time spent on requests (in seconds)
+    sum: f64,
+}
+
+impl Metrics {
+    /// Record that a request is being sent to a device, returning a guard
+    /// which records its completion when dropped
+    pub(super) fn start(&self) -> InFlight<'_> {
+        self.in_flight.fetch_add(1, Ordering::Relaxed);
+        InFlight(self)
+    }
+
+    /// Record a completed request
+    pub(super) fn record(
+        &self,
+        serial: Option<SerialNumber>,
+        command: command::Code,
+        session_id: Option<session::Id>,
+        latency: Duration,
+        result: Result<(), &connector::Error>,
+    ) {
+        let mut state = self.state.lock().unwrap();
+        let histogram = state.commands.entry(command).or_default();
+        let seconds = latency.as_secs_f64();
+
+        for (count, &bound) in histogram.buckets.iter_mut().zip(&LATENCY_BUCKETS) {
+            if seconds <= bound {
+                *count += 1;
+            }
+        }
+
+        histogram.count += 1;
+        histogram.sum += seconds;
+
+        if let Some(id) = session_id {
+            state.sessions.insert((serial, id), Instant::now());
+        }
+
+        if let Err(e) = result {
+            *state.errors.entry(format!("{:?}", e.kind())).or_default() += 1;
+        }
+    }
+
+    /// Render metrics in the Prometheus text exposition format
+    pub(super) fn render(&self) -> String {
+        let mut state = self.state.lock().unwrap();
+        state
+            .sessions
+            .retain(|_, last_used| last_used.elapsed() < SESSION_ACTIVITY_WINDOW);
+
+        let mut out = String::new();
+
+        metric_header(
+            &mut out,
+            "yubihsm_http_server_requests_total",
+            "counter",
+            "Requests sent to devices, by command",
+        );
+
+        for (command, histogram) in &state.commands {
+            writeln!(
+                out,
+                "yubihsm_http_server_requests_total{{command=\"{:?}\"}} {}",
+                command, histogram.count
+            )
+            .unwrap();
+        }
+
+        metric_header(
+            &mut out,
+            "yubihsm_http_server_request_duration_seconds",
+            "histogram",
+            "Time taken by devices to respond to requests, by command",
+        );
+
+        for (command, histogram) in &state.commands {
+            let name = "yubihsm_http_server_request_duration_seconds";
+
+            for (count, bound) in histogram.buckets.iter().zip(&LATENCY_BUCKETS) {
+                writeln!(
+                    out,
+                    "{name}_bucket{{command=\"{command:?}\",le=\"{bound}\"}} {count}"
+                )
+                .unwrap();
+            }
+
+            writeln!(
+                out,
+                "{name}_bucket{{command=\"{command:?}\",le=\"+Inf\"}} {}",
+                histogram.count
+            )
+            .unwrap();
+            writeln!(
+                out,
+                "{name}_sum{{command=\"{command:?}\"}} {}",
+                histogram.sum
+            )
+            .unwrap();
+            writeln!(
+                out,
+                "{name}_count{{command=\"{command:?}\"}} {}",
+                histogram.count
+            )
+            .unwrap();
+        }
+
+        metric_header(
+            &mut out,
+            "yubihsm_http_server_requests_in_flight",
+            "gauge",
+            "Requests currently being processed by devices",
+        );
+
+        writeln!(
+            out,
+            "yubihsm_http_server_requests_in_flight {}",
+            self.in_flight.load(Ordering::Relaxed)
+        )
+        .unwrap();
+
+        metric_header(
+            &mut out,
+            "yubihsm_http_server_active_sessions",
+            "gauge",
+            "Session IDs used within the last 30 seconds",
+        );
+
+        writeln!(
+            out,
+            "yubihsm_http_server_active_sessions {}",
+            state.sessions.len()
+        )
+        .unwrap();
+
+        metric_header(
+            &mut out,
+            "yubihsm_http_server_connector_errors_total",
+            "counter",
+            "Errors sending requests to devices, by kind",
+        );
+
+        for (kind, count) in &state.errors {
+            writeln!(
+                out,
+                "yubihsm_http_server_connector_errors_total{{kind=\"{kind}\"}} {count}"
+            )
+            .unwrap();
+        }
+
+        out
+    }
+}
+
+/// Guard for a request which is being processed by a device
+pub(super) struct InFlight<'a>(&'a Metrics);
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        self.0.in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Write the `HELP` and `TYPE` lines which precede a metric
+fn metric_header(out: &mut String, name: &str, kind: &str, help: &str) {
+    writeln!(out, "# HELP {name} {help}").unwrap();
+    writeln!(out, "# TYPE {name} {kind}").unwrap();
+}
@@ -0,0 +1,214 @@
+//! HTTP requests received by the server
+
+use super::{connection::Peer, response::Response};
+use crate::{
+    connector::{
+        Error,
+        ErrorKind::{self, RequestError},
+    },
+    error::Context,
+};
+use std::{
+    io::{self, BufRead, BufReader, Read, Write},
+    str,
+    sync::mpsc,
+    time::Instant,
+};
+
+/// Maximum size of a request line and headers
+const MAX_HEADER_SIZE: usize = 8192;
+
+/// HTTP request received from a client
+pub(super) struct Request {
+    /// Request method (e.g. `GET`)
+    pub(super) method: String,
+
+    /// Request target (e.g. `/connector/status`)
+    pub(super) path: String,
+
+    /// Request headers
+    pub(super) headers: Vec<(String, String)>,
+
+    /// Request body
+    pub(super) body: Vec<u8>,
+
+    /// Client which sent the request
+    pub(super) peer: Peer,
+
+    /// When the request was received
+    pub(super) received_at: Instant,
+
+    /// Channel for sending the response back to the client's connection
+    responder: mpsc::Sender<Response>,
+}
+
+/// Errors which can occur while reading a request
+pub(super) enum ReadError {
+    /// The request was malformed (`400 Bad Request`)
+    Malformed(Error),
+
+    /// The request body exceeds the maximum size (`413 Payload Too Large`)
+    TooLarge(usize),
+
+    /// I/O error reading from the client (including timeouts)
+    Io(io::Error),
+}
+
+impl From<Context<ErrorKind>> for ReadError {
+    fn from(context: Context<ErrorKind>) -> ReadError {
+        ReadError::Malformed(context.into())
+    }
+}
+
+impl From<io::Error> for ReadError {
+    fn from(err: io::Error) -> ReadError {
+        ReadError::Io(err)
+    }
+}
+
+impl Request {
+    /// Read the next request from a client connection, returning `None` if
+    /// the client closed the connection.
+    ///
+    /// Returns the request along with a receiver for its response. Requests
+    /// with bodies larger than `max_body_size` are rejected without reading
+    /// the body.
+    pub(super) fn read<S: Read + Write>(
+        stream: &mut BufReader<S>,
+        peer: Peer,
+        max_body_size: usize,
+    ) -> Result<Option<(Self, mpsc::Receiver<Response>)>, ReadError> {
+        let mut head = Vec::new();
+
+        // Read the request line and headers, up to the blank line which ends them
+        loop {
+            let nbytes = Read::take(&mut *stream, (MAX_HEADER_SIZE - head.len()) as u64)
+                .read_until(b'\n', &mut head)?;
+
+            if nbytes == 0 {
+                ensure!(
+                    head.is_empty(),
+                    RequestError,
+                    "connection closed mid-request"
+                );
+                return Ok(None);
+            }
+
+            if head.ends_with(b"\r\n\r\n") || head.ends_with(b"\n\n") {
+                break;
+            }
+
+            ensure!(
+                head.len() < MAX_HEADER_SIZE,
+                RequestError,
+                "request headers too large"
+            );
+        }
+
+        let head = str::from_utf8(&head)
+            .map_err(|e| format_err!(RequestError, "invalid request headers: {}", e))?;
+        let mut lines = head.lines();
+
+        let mut request_line = lines.next().unwrap_or_default().split(' ');
+        let (method, path, version) = match (
+            request_line.next(),
+            request_line.next(),
+            request_line.next(),
+        ) {
+            (Some(method), Some(path), Some(version)) if version.starts_with("HTTP/1.") => {
+                (method, path, version)
+            }
+            _ => fail!(RequestError, "malformed request line"),
+        };
+
+        let mut headers = vec![];
+
+        for line in lines.take_while(|line| !line.is_empty()) {
+            let (name, value) = line
+                .split_once(':')
+                .ok_or_else(|| format_err!(RequestError, "malformed header: {:?}", line))?;
+
+            headers.push((name.trim().to_owned(), value.trim().to_owned()));
+        }
+
+        // HTTP/1.0 clients don't keep connections alive unless they ask to
+        if version == "HTTP/1.0" && !has_header(&headers, "Connection", "keep-alive") {
+            headers.push(("Connection".to_owned(), "close".to_owned()));
+        }
+
+        ensure!(
+            header(&headers, "Transfer-Encoding").is_none(),
+            RequestError,
+            "chunked requests are not supported"
+        );
+
+        let content_length = match header(&headers, "Content-Length") {
+            Some(length) => length.parse::<usize>().map_err(|e| {
+                format_err!(RequestError, "invalid Content-Length {:?}: {}", length, e)
+            })?,
+            None => 0,
+        };
+
+        if content_length > max_body_size {
+            return Err(ReadError::TooLarge(content_length));
+        }
+
+        // Clients like curl wait for permission before sending the body
+        if content_length > 0 && has_header(&headers, "Expect", "100-continue") {
+            stream
+                .get_mut()
+                .write_all(b"HTTP/1.1 100 Continue\r\n\r\n")?;
+            stream.get_mut().flush()?;
+        }
+
+        let mut body = vec![0u8; content_length];
+        stream.read_exact(&mut body)?;
+
+        let (responder, response) = mpsc::channel();
+
+        let request = Self {
+            method: method.to_owned(),
+            path: path.to_owned(),
+            headers,
+            body,
+            peer,
+            received_at: Instant::now(),
+            responder,
+        };
+
+        Ok(Some((request, response)))
+    }
+
+    /// Find the value of a header (case-insensitively)
+    pub(super) fn header(&self, name: &str) -> Option<&str> {
+        header(&self.headers, name)
+    }
+
+    /// Should the connection be closed after this request?
+    pub(super) fn wants_close(&self) -> bool {
+        has_header(&self.headers, "Connection", "close")
+    }
+
+    /// Send a response to this request. Responses to clients which have
+    /// since disconnected are discarded.
+    pub(super) fn respond(self, response: Response) {
+        let _ = self.responder.send(response);
+    }
+}
+
+/// Find the value of a header (case-insensitively)
+fn header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers
+        .iter()
+        .find(|(n, _)| n.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.as_str())
+}
+
+/// Does the given header contain the given (comma-delimited) token?
+fn has_header(headers: &[(String, String)], name: &str, token: &str) -> bool {
+    header(headers, name).is_some_and(|value| {
+        value
+            .split(',')
+            .any(|t| t.trim().eq_ignore_ascii_case(token))
+    })
+}
@@ -0,0 +1,74 @@
+//! HTTP responses sent by the server
+
+use std::io::{self, Write};
+
+/// HTTP response to be sent to a client
+pub(super) struct Response {
+    /// HTTP status code
+    status: u16,
+
+    /// Value of the `Content-Type` header
+    content_type: &'static str,
+
+    /// Response body
+    body: Vec<u8>,
+}
+
+impl Response {
+    /// `200 OK` response with the given body
+    pub(super) fn ok(content_type: &'static str, body: impl Into<Vec<u8>>) -> Self {
+        Self {
+            status: 200,
+            content_type,
+            body: body.into(),
+        }
+    }
+
+    /// Response with the given error status and an empty body
+    pub(super) fn error(status: u16) -> Self {
+        Self {
+            status,
+            content_type: "text/plain",
+            body: vec![],
+        }
+    }
+
+    /// Serialize this response to the given stream
+    pub(super) fn write_to(&self, stream: &mut dyn Write, close: bool) -> io::Result<()> {
+        let mut response = format!(
+            "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\n",
+            self.status,
+            reason_phrase(self.status),
+            self.content_type,
+            self.body.len()
+        );
+
+        if close {
+            response.push_str("Connection: close\r\n");
+        }
+
+        response.push_str("\r\n");
+
+        // Combine headers and body into a single write
+        let mut response = response.into_bytes();
+        response.extend_from_slice(&self.body);
+        stream.write_all(&response)?;
+        stream.flush()
+    }
+}
+
+/// Reason phrase for the status codes used by the server
+fn reason_phrase(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        413 => "Payload Too Large",
+        429 => "Too Many Requests",
+        500 => "Internal Server Error",
+        503 => "Service Unavailable",
+        _ => "Unknown",
+    }
+}
@@ -0,0 +1,98 @@
+//! Status of `yubihsm-connector`, as reported by `GET /connector/status`
+
+use crate::{
+    connector::{Error, ErrorKind::ResponseError},
+    device::SerialNumber,
+};
+use serde::{Deserialize, Serialize};
+use std::{str::FromStr, time::Duration};
+
+/// Status reported when `yubihsm-connector` has access to a YubiHSM 2
+pub const STATUS_OK: &str = "OK";
+
+/// Status of a `yubihsm-connector` service
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Status {
+    /// Status of the YubiHSM 2 (`OK` if it's available, e.g. `NO_DEVICE`
+    /// otherwise)
+    pub status: String,
+
+    /// Serial number of the YubiHSM 2 (`None` if any device may be used)
+    pub serial: Option<SerialNumber>,
+
+    /// Version of `yubihsm-connector`
+    pub version: String,
+
+    /// Process ID of `yubihsm-connector`
+    pub pid: u32,
+
+    /// Address `yubihsm-connector` is listening on
+    pub address: String,
+
+    /// Port `yubihsm-connector` is listening on (absent for Unix sockets)
+    pub port: Option<u16>,
+}
+
+impl Status {
+    /// Is the YubiHSM 2 available?
+    pub fn is_ok(&self) -> bool {
+        self.status == STATUS_OK
+    }
+}
+
+impl FromStr for Status {
+    type Err = Error;
+
+    /// Parse the `key=value` lines of a status page
+    fn from_str(page: &str) -> Result<Self, Error> {
+        let mut status = None;
+        let mut serial = None;
+        let mut version = None;
+        let mut pid = None;
+        let mut address = None;
+        let mut port = None;
+
+        for line in page.lines().map(str::trim).filter(|line| !line.is_empty()) {
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| format_err!(ResponseError, "malformed status line: {:?}", line))?;
+
+            match key {
+                "status" => status = Some(value.to_owned()),
+                "serial" if value == "*" => serial = None,
+                "serial" => {
+                    serial = Some(value.parse().map_err(|e| {
+                        format_err!(ResponseError, "invalid serial {:?}: {}", value, e)
+                    })?)
+                }
+                "version" => version = Some(value.to_owned()),
+                "pid" => pid = Some(value.parse()?),
+                "address" => address = Some(value.to_owned()),
+                "port" => port = Some(value.parse()?),
+                // Ignore fields added by newer versions
+                _ => (),
+            }
+        }
+
+        let missing = |key| format_err!(ResponseError, "status is missing {:?}", key);
+
+        Ok(Self {
+            status: status.ok_or_else(|| missing("status"))?,
+            serial,
+            version: version.ok_or_else(|| missing("version"))?,
+            pid: pid.ok_or_else(|| missing("pid"))?,
+            address: address.ok_or_else(|| missing("address"))?,
+            port,
+        })
+    }
+}
+
+/// Result of a successful health check
+#[derive(Clone, Debug)]
+pub struct Health {
+    /// Status reported by `yubihsm-connector`
+    pub status: Status,
+
+    /// Time taken by the YubiHSM 2 to respond to an `Echo` command
+    pub latency: Duration,
+}
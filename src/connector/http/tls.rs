@@ -0,0 +1,128 @@
+//! TLS configuration for HTTPS connections to `yubihsm-connector`, and for
+//! serving HTTPS from the `yubihsm-connector` compatible [`Server`].
+//!
+//! TLS is implemented using [rustls] with the *ring* crypto provider.
+//!
+//! [`Server`]: https://docs.rs/yubihsm/latest/yubihsm/connector/http/struct.Server.html
+//! [rustls]: https://github.com/rustls/rustls
+
+use super::TlsConfig;
+use crate::connector::{Error, ErrorKind::TlsError};
+use rustls::{
+    crypto::{ring, CryptoProvider},
+    pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer, ServerName},
+    ClientConfig, RootCertStore,
+};
+use std::{path::Path, sync::Arc};
+
+#[cfg(feature = "http-server")]
+use rustls::{server::WebPkiClientVerifier, ServerConfig};
+
+impl TlsConfig {
+    /// Build a rustls client configuration which pins `ca_cert`
+    pub(crate) fn client_config(&self) -> Result<Arc<ClientConfig>, Error> {
+        let ca_cert = self
+            .ca_cert
+            .as_ref()
+            .ok_or_else(|| format_err!(TlsError, "`ca_cert` is required to connect using TLS"))?;
+
+        let builder = ClientConfig::builder_with_provider(provider())
+            .with_safe_default_protocol_versions()?
+            .with_root_certificates(load_roots(ca_cert)?);
+
+        let config = match self.cert_and_key()? {
+            Some((certs, key)) => builder.with_client_auth_cert(certs, key)?,
+            None => builder.with_no_client_auth(),
+        };
+
+        Ok(Arc::new(config))
+    }
+
+    /// Name to verify the server's certificate against when connecting to `addr`
+    pub(crate) fn server_name(&self, addr: &str) -> Result<ServerName<'static>, Error> {
+        let name = self.server_name.as_deref().unwrap_or(addr);
+
+        ServerName::try_from(name.to_owned())
+            .map_err(|e| format_err!(TlsError, "invalid server name `{}`: {}", name, e).into())
+    }
+
+    /// Build a rustls server configuration, requiring client certificates
+    /// if `ca_cert` is set
+    #[cfg(feature = "http-server")]
+    pub(crate) fn server_config(&self) -> Result<Arc<ServerConfig>, Error> {
+        let (certs, key) = self
+            .cert_and_key()?
+            .ok_or_else(|| format_err!(TlsError, "`cert` and `key` are required to serve TLS"))?;
+
+        let builder = ServerConfig::builder_with_provider(provider())
+            .with_safe_default_protocol_versions()?;
+
+        let builder = match &self.ca_cert {
+            Some(ca_cert) => {
+                let verifier = WebPkiClientVerifier::builder_with_provider(
+                    Arc::new(load_roots(ca_cert)?),
+                    provider(),
+                )
+                .build()
+                .map_err(|e| format_err!(TlsError, "invalid client CA: {}", e))?;
+
+                builder.with_client_cert_verifier(verifier)
+            }
+            None => builder.with_no_client_auth(),
+        };
+
+        Ok(Arc::new(builder.with_single_cert(certs, key)?))
+    }
+
+    /// Load the configured certificate chain and private key, if any
+    #[allow(clippy::type_complexity)]
+    fn cert_and_key(
+        &self,
+    ) -> Result<Option<(Vec<CertificateDer<'static>>, PrivateKeyDer<'static>)>, Error> {
+        match (&self.cert, &self.key) {
+            (Some(cert), Some(key)) => {
+                let key = PrivateKeyDer::from_pem_file(key).map_err(|e| {
+                    format_err!(TlsError, "couldn't load key {}: {}", key.display(), e)
+                })?;
+
+                Ok(Some((load_certs(cert)?, key)))
+            }
+            (None, None) => Ok(None),
+            _ => fail!(TlsError, "`cert` and `key` must be configured together"),
+        }
+    }
+}
+
+/// Crypto provider used for all TLS connections
+fn provider() -> Arc<CryptoProvider> {
+    Arc::new(ring::default_provider())
+}
+
+/// Load the PEM encoded certificates in the given file
+fn load_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>, Error> {
+    let certs = CertificateDer::pem_file_iter(path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| format_err!(TlsError, "couldn't load {}: {}", path.display(), e))?;
+
+    ensure!(
+        !certs.is_empty(),
+        TlsError,
+        "no certificates found in {}",
+        path.display()
+    );
+
+    Ok(certs)
+}
+
+/// Load the PEM encoded CA certificates in the given file as trust anchors
+fn load_roots(path: &Path) -> Result<RootCertStore, Error> {
+    let mut roots = RootCertStore::empty();
+
+    for cert in load_certs(path)? {
+        roots
+            .add(cert)
+            .map_err(|e| format_err!(TlsError, "invalid CA in {}: {}", path.display(), e))?;
+    }
+
+    Ok(roots)
+}
@@ -0,0 +1,320 @@
+//! Recording and replaying transcripts of the messages exchanged with an HSM.
+//!
+//! [`Connector::record`] wraps a connector, saving each command sent through
+//! it along with the HSM's response to a transcript file. A [`Replay`] (or
+//! [`Connector::replay`]) serves those responses back in order, returning a
+//! [`ReplayDiverged`] error as soon as a command
+//! differs from the one which was recorded.
+//!
+//! When replaying, the host challenge for each session is taken from the
+//! transcript, which makes session keys (and therefore every encrypted
+//! command) deterministic. This means a transcript captured against real
+//! hardware can be replayed in CI, provided the same operations are
+//! performed in the same order, one session at a time.
+//!
+//! Transcripts are text files containing one exchange per line: the UUID of
+//! the command, then the hex-encoded command and response messages. Lines
+//! beginning with `#` are comments.
+
+use super::{
+    Connectable, Connection, Connector, Error,
+    ErrorKind::{IoError, ReplayDiverged, ResponseError},
+    Message,
+};
+use crate::{
+    command,
+    session::securechannel::{Challenge, CHALLENGE_SIZE},
+};
+use std::{
+    fmt::{self, Write as FmtWrite},
+    fs::{self, File},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+use uuid::Uuid;
+
+/// First line of transcript files
+const HEADER: &str = "# yubihsm.rs connector transcript: uuid command response";
+
+/// Offset of the host challenge in a `CreateSession` command message (after
+/// the 3-byte header and 2-byte authentication key ID)
+const HOST_CHALLENGE_OFFSET: usize = 5;
+
+/// Command sent to the HSM and the response it returned
+#[derive(Clone, Debug)]
+struct Exchange {
+    /// UUID of the command
+    uuid: Uuid,
+
+    /// Command message
+    command: Vec<u8>,
+
+    /// Response message
+    response: Vec<u8>,
+}
+
+impl Exchange {
+    /// Parse an exchange from a line of a transcript
+    fn parse(line: &str) -> Result<Self, Error> {
+        let mut fields = line.split_whitespace();
+
+        let (uuid, command, response) = match (fields.next(), fields.next(), fields.next()) {
+            (Some(uuid), Some(command), Some(response)) if fields.next().is_none() => {
+                (uuid, command, response)
+            }
+            _ => fail!(ResponseError, "malformed transcript line: {:?}", line),
+        };
+
+        Ok(Self {
+            uuid: Uuid::parse_str(uuid)
+                .map_err(|e| format_err!(ResponseError, "invalid UUID {:?}: {}", uuid, e))?,
+            command: decode_hex(command)?,
+            response: decode_hex(response)?,
+        })
+    }
+
+    /// Host challenge sent in this exchange, if it's a `CreateSession` command
+    fn host_challenge(&self) -> Option<Challenge> {
+        if self.command.first() != Some(&command::Code::CreateSession.to_u8()) {
+            return None;
+        }
+
+        self.command
+            .get(HOST_CHALLENGE_OFFSET..HOST_CHALLENGE_OFFSET + CHALLENGE_SIZE)
+            .map(Challenge::from_slice)
+    }
+}
+
+impl fmt::Display for Exchange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {}",
+            self.uuid,
+            encode_hex(&self.command),
+            encode_hex(&self.response)
+        )
+    }
+}
+
+/// Connector driver which records exchanges with another connector
+pub(crate) struct RecordingConnector {
+    /// Connector being recorded
+    inner: Box<dyn Connectable>,
+
+    /// Transcript file
+    file: Arc<Mutex<File>>,
+}
+
+impl RecordingConnector {
+    /// Create a new `RecordingConnector` which writes a transcript of its
+    /// exchanges with the given connector to a file at the given path
+    pub fn create(connector: Connector, path: &Path) -> Result<Box<dyn Connectable>, Error> {
+        let mut file = File::create(path)
+            .map_err(|e| format_err!(IoError, "couldn't create {}: {}", path.display(), e))?;
+
+        writeln!(file, "{HEADER}")?;
+
+        Ok(Box::new(Self {
+            inner: connector.driver,
+            file: Arc::new(Mutex::new(file)),
+        }))
+    }
+}
+
+impl Connectable for RecordingConnector {
+    /// Make a clone of this connectable as boxed trait object
+    fn box_clone(&self) -> Box<dyn Connectable> {
+        Box::new(Self {
+            inner: self.inner.box_clone(),
+            file: self.file.clone(),
+        })
+    }
+
+    /// Open a connection using the connector being recorded
+    fn connect(&self) -> Result<Box<dyn Connection>, Error> {
+        Ok(Box::new(RecordingConnection {
+            connection: self.inner.connect()?,
+            file: self.file.clone(),
+        }))
+    }
+
+    /// Use the host challenge required by the connector being recorded
+    fn host_challenge(&self) -> Option<Challenge> {
+        self.inner.host_challenge()
+    }
+}
+
+/// Connection which records exchanges to a transcript file
+struct RecordingConnection {
+    /// Connection being recorded
+    connection: Box<dyn Connection>,
+
+    /// Transcript file
+    file: Arc<Mutex<File>>,
+}
+
+impl Connection for RecordingConnection {
+    /// Send a message to the HSM, recording it and the response
+    fn send_message(&self, uuid: Uuid, msg: Message) -> Result<Message, Error> {
+        let response = self.connection.send_message(uuid, msg.clone())?;
+
+        let exchange = Exchange {
+            uuid,
+            command: msg.into(),
+            response: response.0.clone(),
+        };
+
+        let mut file = self.file.lock().unwrap();
+        writeln!(file, "{exchange}")?;
+        file.flush()?;
+
+        Ok(response)
+    }
+}
+
+/// Transcript being replayed
+#[derive(Clone)]
+pub struct Replay(Arc<ReplayState>);
+
+/// State shared by a replay and the connectors it creates
+struct ReplayState {
+    /// Path to the transcript (for error messages)
+    path: PathBuf,
+
+    /// Recorded exchanges
+    exchanges: Vec<Exchange>,
+
+    /// Index of the next exchange to be replayed
+    position: Mutex<usize>,
+}
+
+impl Replay {
+    /// Load a transcript from the given path
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let transcript = fs::read_to_string(path)
+            .map_err(|e| format_err!(IoError, "couldn't read {}: {}", path.display(), e))?;
+
+        let exchanges = transcript
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(Exchange::parse)
+            .collect::<Result<_, _>>()?;
+
+        Ok(Replay(Arc::new(ReplayState {
+            path: path.to_owned(),
+            exchanges,
+            position: Mutex::new(0),
+        })))
+    }
+
+    /// Create a connector which replays this transcript
+    pub fn connector(&self) -> Connector {
+        Connector::from(Box::new(ReplayConnector(self.0.clone())) as Box<dyn Connectable>)
+    }
+
+    /// Number of exchanges which haven't been replayed yet
+    pub fn remaining(&self) -> usize {
+        self.0.exchanges.len() - *self.0.position.lock().unwrap()
+    }
+
+    /// Ensure the whole transcript was replayed
+    pub fn finish(&self) -> Result<(), Error> {
+        let remaining = self.remaining();
+
+        ensure!(
+            remaining == 0,
+            ReplayDiverged,
+            "{}: {} exchanges were not replayed",
+            self.0.path.display(),
+            remaining
+        );
+
+        Ok(())
+    }
+}
+
+/// Connector driver which replays a transcript
+struct ReplayConnector(Arc<ReplayState>);
+
+impl Connectable for ReplayConnector {
+    /// Make a clone of this connectable as boxed trait object
+    fn box_clone(&self) -> Box<dyn Connectable> {
+        Box::new(ReplayConnector(self.0.clone()))
+    }
+
+    /// Open a connection which replays the transcript
+    fn connect(&self) -> Result<Box<dyn Connection>, Error> {
+        Ok(Box::new(ReplayConnection(self.0.clone())))
+    }
+
+    /// Use the host challenge from the next exchange if it opens a session
+    fn host_challenge(&self) -> Option<Challenge> {
+        let position = *self.0.position.lock().unwrap();
+        self.0.exchanges.get(position)?.host_challenge()
+    }
+}
+
+/// Connection which replays a transcript
+struct ReplayConnection(Arc<ReplayState>);
+
+impl Connection for ReplayConnection {
+    /// Check a message matches the next one in the transcript, and return
+    /// the recorded response
+    fn send_message(&self, _uuid: Uuid, msg: Message) -> Result<Message, Error> {
+        let mut position = self.0.position.lock().unwrap();
+
+        let exchange = self.0.exchanges.get(*position).ok_or_else(|| {
+            format_err!(
+                ReplayDiverged,
+                "{}: unexpected command after end of transcript: {}",
+                self.0.path.display(),
+                encode_hex(msg.as_ref())
+            )
+        })?;
+
+        ensure!(
+            exchange.command == msg.as_ref(),
+            ReplayDiverged,
+            "{}: exchange #{} ({}) diverged: expected command {}, got {}",
+            self.0.path.display(),
+            *position + 1,
+            exchange.uuid,
+            encode_hex(&exchange.command),
+            encode_hex(msg.as_ref())
+        );
+
+        *position += 1;
+        Ok(exchange.response.clone().into())
+    }
+}
+
+/// Encode bytes as lower-case hexadecimal
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut hex, byte| {
+        write!(hex, "{byte:02x}").unwrap();
+        hex
+    })
+}
+
+/// Decode hexadecimal into bytes
+fn decode_hex(hex: &str) -> Result<Vec<u8>, Error> {
+    ensure!(
+        hex.len() % 2 == 0 && hex.is_ascii(),
+        ResponseError,
+        "invalid hex in transcript: {:?}",
+        hex
+    );
+
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| {
+            u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| {
+                format_err!(ResponseError, "invalid hex in transcript: {:?}", hex).into()
+            })
+        })
+        .collect()
+}
@@ -6,6 +6,11 @@
 //! To enumerate available USB devices (e.g. in the case there is more than
 //! one YubiHSM connected to the same computer), use [`Devices`].
 //!
+//! If a YubiHSM 2 is unplugged or re-enumerated (e.g. after a reset), the
+//! connector reconnects to the device with the same serial number when it
+//! reappears. To be notified when devices are connected and disconnected,
+//! use a [`Monitor`].
+//!
 //! [`Connector::usb`]: https://docs.rs/yubihsm/latest/yubihsm/connector/struct.Connector.html#method.usb
 
 #[macro_use]
@@ -14,15 +19,29 @@ mod macros;
 mod config;
 mod connection;
 mod device;
+mod monitor;
 mod timeout;
+mod transport;
+
+#[cfg(all(test, feature = "mockhsm"))]
+mod fake;
 
 pub use self::{
     config::UsbConfig,
     connection::UsbConnection,
     device::{Device, Devices},
+    monitor::{HotplugEvent, Monitor},
     timeout::UsbTimeout,
 };
-use crate::connector::{self, Connectable, Connection};
+use crate::{
+    connector::{self, Connectable, Connection},
+    device::SerialNumber,
+};
+use std::{
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
 
 /// USB vendor ID for Yubico
 pub const YUBICO_VENDOR_ID: u16 = 0x1050;
@@ -39,6 +58,9 @@ pub const YUBIHSM2_BULK_OUT_ENDPOINT: u8 = 1;
 /// YubiHSM 2 bulk in endpoint
 pub const YUBIHSM2_BULK_IN_ENDPOINT: u8 = 0x81;
 
+/// Interval between scans for a device which is being reconnected to
+const RESCAN_INTERVAL: Duration = Duration::from_millis(250);
+
 /// Connect to the HSM via USB.
 ///
 /// `UsbConnector` is available when the `usb` cargo feature is enabled.
@@ -47,24 +69,80 @@ pub const YUBIHSM2_BULK_IN_ENDPOINT: u8 = 0x81;
 ///
 /// [Yubico SDK]: https://developers.yubico.com/YubiHSM2/Releases/
 #[derive(Clone, Default, Debug)]
-pub struct UsbConnector(UsbConfig);
+pub struct UsbConnector {
+    /// USB configuration
+    config: UsbConfig,
+
+    /// Serial number of the device this connector has connected to, which
+    /// it reconnects to (shared between clones of this connector)
+    connected: Arc<Mutex<Option<SerialNumber>>>,
+}
 
 impl UsbConnector {
     /// Create a new `UsbConnector` with the given configuration
     pub fn create(config: &UsbConfig) -> Box<dyn Connectable> {
-        Box::new(UsbConnector(config.clone()))
+        Box::new(UsbConnector {
+            config: config.clone(),
+            connected: Arc::new(Mutex::new(None)),
+        })
+    }
+
+    /// Reconnect to the device with the given serial number, rescanning
+    /// until it reappears or the reconnect timeout elapses.
+    ///
+    /// Scanning doesn't reset devices: only the device being reconnected to
+    /// is reset when it's opened, so other YubiHSM 2s in use aren't disturbed.
+    fn reconnect(&self, serial: SerialNumber) -> Result<UsbConnection, connector::Error> {
+        let timeout = UsbTimeout::from_millis(self.config.timeout_ms);
+        let reconnect_timeout = Duration::from_millis(self.config.reconnect_timeout_ms);
+
+        retry(serial, reconnect_timeout, || {
+            Devices::enumerate(timeout, false)?
+                .find(serial)?
+                .open(timeout)
+        })
+    }
+}
+
+/// Retry connecting to the device with the given serial number every
+/// `RESCAN_INTERVAL` until it succeeds or the timeout elapses
+fn retry<T>(
+    serial: SerialNumber,
+    timeout: Duration,
+    mut connect: impl FnMut() -> Result<T, connector::Error>,
+) -> Result<T, connector::Error> {
+    let started_at = Instant::now();
+
+    loop {
+        match connect() {
+            Ok(connection) => return Ok(connection),
+            Err(e) if started_at.elapsed() + RESCAN_INTERVAL < timeout => {
+                debug!("USB: waiting for YubiHSM 2 (serial #{}): {}", serial, e);
+                thread::sleep(RESCAN_INTERVAL);
+            }
+            Err(e) => return Err(e),
+        }
     }
 }
 
 impl Connectable for UsbConnector {
     /// Make a clone of this connectable as boxed trait object
     fn box_clone(&self) -> Box<dyn Connectable> {
-        Box::new(UsbConnector(self.0.clone()))
+        Box::new(self.clone())
     }
 
-    /// Open a connection to `yubihsm-connector`
+    /// Open a connection to the YubiHSM 2, or reconnect to the one which was
+    /// previously connected to
     fn connect(&self) -> Result<Box<dyn Connection>, connector::Error> {
-        Ok(Box::new(UsbConnection::open(&self.0)?))
+        let connected = *self.connected.lock().unwrap();
+
+        let connection = match connected {
+            Some(serial) => self.reconnect(serial)?,
+            None => UsbConnection::open(&self.config)?,
+        };
+
+        *self.connected.lock().unwrap() = Some(connection.device().serial_number);
+        Ok(Box::new(connection))
     }
 }
 
@@ -73,3 +151,51 @@ impl Into<Box<dyn Connectable>> for UsbConnector {
         Box::new(self)
     }
 }
+
+#[cfg(all(test, feature = "mockhsm"))]
+mod tests {
+    use super::*;
+    use crate::connector::ErrorKind::UsbError;
+    use fake::FakeDevice;
+
+    fn serial() -> SerialNumber {
+        "0123456789".parse().unwrap()
+    }
+
+    #[test]
+    fn reconnect_when_device_reappears() {
+        let mut attempts = 0;
+
+        retry(serial(), Duration::from_secs(5), || {
+            attempts += 1;
+
+            if attempts < 3 {
+                fail!(UsbError, "no YubiHSM 2 found with serial number");
+            }
+
+            Ok(FakeDevice::new())
+        })
+        .unwrap();
+
+        assert_eq!(attempts, 3);
+    }
+
+    #[test]
+    fn reconnect_timeout() {
+        let mut attempts = 0;
+        let started_at = Instant::now();
+
+        let result = retry(
+            serial(),
+            RESCAN_INTERVAL * 2,
+            || -> Result<FakeDevice, _> {
+                attempts += 1;
+                fail!(UsbError, "no YubiHSM 2 found with serial number")
+            },
+        );
+
+        assert_eq!(*result.err().unwrap().kind(), UsbError);
+        assert_eq!(attempts, 2);
+        assert!(started_at.elapsed() < RESCAN_INTERVAL * 2);
+    }
+}
@@ -11,11 +11,26 @@ pub struct UsbConfig {
 
     /// Timeout for USB operations (default 1s)
     pub timeout_ms: u64,
+
+    /// How long to keep rescanning for a YubiHSM 2 which has been unplugged
+    /// or re-enumerated (e.g. after a reset) before giving up on reconnecting
+    /// to it (default 10s)
+    #[serde(default = "UsbConfig::default_reconnect_timeout_ms")]
+    pub reconnect_timeout_ms: u64,
 }
 
 impl UsbConfig {
     /// Default timeout for USB communication (30 seconds)
     pub const DEFAULT_TIMEOUT_MILLIS: u64 = 30_000;
+
+    /// Default time to wait for a device to reappear when reconnecting
+    /// (10 seconds)
+    pub const DEFAULT_RECONNECT_TIMEOUT_MILLIS: u64 = 10_000;
+
+    /// Default value of `reconnect_timeout_ms` (for serde)
+    fn default_reconnect_timeout_ms() -> u64 {
+        Self::DEFAULT_RECONNECT_TIMEOUT_MILLIS
+    }
 }
 
 impl Default for UsbConfig {
@@ -23,6 +38,7 @@ impl Default for UsbConfig {
         UsbConfig {
             serial: None,
             timeout_ms: Self::DEFAULT_TIMEOUT_MILLIS,
+            reconnect_timeout_ms: Self::DEFAULT_RECONNECT_TIMEOUT_MILLIS,
         }
     }
 }
@@ -1,13 +1,14 @@
 //! Connections to the YubiHSM 2 via USB
 
 use super::{
-    Device, Devices, UsbConfig, UsbTimeout, YUBIHSM2_BULK_IN_ENDPOINT, YUBIHSM2_BULK_OUT_ENDPOINT,
+    transport::Transport, Device, Devices, UsbConfig, UsbTimeout, YUBIHSM2_BULK_IN_ENDPOINT,
+    YUBIHSM2_BULK_OUT_ENDPOINT,
 };
 use crate::{
     command::MAX_MSG_SIZE,
     connector::{self, Connection, ErrorKind::UsbError, Message},
 };
-use std::sync::Mutex;
+use std::{sync::Mutex, time::Duration};
 use uuid::Uuid;
 
 /// Number of times to retry a bulk message receive operation before giving up
@@ -59,8 +60,8 @@ impl Connection for UsbConnection {
     /// Send a command to the YubiHSM and read its response
     fn send_message(&self, _uuid: Uuid, cmd: Message) -> Result<Message, connector::Error> {
         let handle = self.handle.lock().unwrap();
-        send_message(&handle, cmd.as_ref(), self.timeout)?;
-        recv_message(&handle, self.timeout)
+        send_message(&*handle, cmd.as_ref(), self.timeout)?;
+        recv_message(&*handle, self.timeout)
     }
 }
 
@@ -70,17 +71,18 @@ impl Default for UsbConnection {
     }
 }
 
-/// Write a bulk message to the YubiHSM 2
+/// Write a bulk message to the YubiHSM 2.
+///
+/// The whole message is written in a single bulk transfer, which libusb
+/// splits into packets.
 fn send_message(
-    handle: &rusb::DeviceHandle<rusb::Context>,
+    handle: &impl Transport,
     data: &[u8],
     timeout: UsbTimeout,
 ) -> Result<usize, connector::Error> {
     let nbytes = handle.write_bulk(YUBIHSM2_BULK_OUT_ENDPOINT, data, timeout.duration())?;
 
-    if data.len() == nbytes {
-        Ok(nbytes)
-    } else {
+    if data.len() != nbytes {
         fail!(
             UsbError,
             "incomplete bulk transfer: {} of {} bytes",
@@ -88,13 +90,12 @@ fn send_message(
             data.len()
         );
     }
+
+    Ok(nbytes)
 }
 
 /// Receive a message
-fn recv_message(
-    handle: &rusb::DeviceHandle<rusb::Context>,
-    timeout: UsbTimeout,
-) -> Result<Message, connector::Error> {
+fn recv_message(handle: &impl Transport, timeout: UsbTimeout) -> Result<Message, connector::Error> {
     // Allocate a buffer which is the maximum size we expect to receive
     let mut response = vec![0u8; MAX_MSG_SIZE];
 
@@ -119,3 +120,136 @@ fn recv_message(
 
     fail!(UsbError, "irrecoverable I/O error receiving bulk message")
 }
+
+/// Flush any unconsumed messages still in the buffer to get the connection
+/// back into a clean state
+pub(super) fn flush(handle: &impl Transport) -> Result<(), connector::Error> {
+    let mut buffer = [0u8; MAX_MSG_SIZE];
+
+    // Use a near instantaneous (but non-zero) timeout to drain the buffer.
+    // Zero is interpreted as wait forever.
+    let timeout = Duration::from_millis(1);
+
+    match handle.read_bulk(YUBIHSM2_BULK_IN_ENDPOINT, &mut buffer, timeout) {
+        Ok(_) | Err(rusb::Error::Timeout) => Ok(()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[cfg(all(test, feature = "mockhsm"))]
+mod tests {
+    use super::*;
+    use crate::{command, connector::usb::fake::FakeDevice, response};
+
+    /// Serialize an `Echo` command with the given data
+    fn echo_command(data: &[u8]) -> Vec<u8> {
+        let message: Message = command::Message::create(command::Code::Echo, data)
+            .unwrap()
+            .into();
+
+        message.into()
+    }
+
+    /// Timeout for tests (the fake device never blocks)
+    fn timeout() -> UsbTimeout {
+        UsbTimeout::from_millis(10)
+    }
+
+    /// Send an `Echo` command, returning the data in the response
+    fn echo(device: &FakeDevice, data: &[u8]) -> Result<Vec<u8>, connector::Error> {
+        send_message(device, &echo_command(data), timeout())?;
+        let response = response::Message::parse(recv_message(device, timeout())?).unwrap();
+        assert_eq!(response.command(), Some(command::Code::Echo));
+        Ok(response.data)
+    }
+
+    #[test]
+    fn round_trip() {
+        let device = FakeDevice::new();
+        assert_eq!(echo(&device, b"hello").unwrap(), b"hello");
+        assert_eq!(device.transfers(), [8]);
+    }
+
+    #[test]
+    fn single_transfer() {
+        // Messages longer than a packet are still written in one transfer
+        let device = FakeDevice::new();
+        let data = [0x42; 200];
+        assert_eq!(echo(&device, &data).unwrap(), data);
+        assert_eq!(device.transfers(), [203]);
+    }
+
+    #[test]
+    fn full_packet() {
+        // 3-byte header + 61 bytes of data fills a packet exactly, and
+        // isn't followed by an empty transfer
+        let device = FakeDevice::new();
+        let data = [0x42; 61];
+        assert_eq!(echo(&device, &data).unwrap(), data);
+        assert_eq!(device.transfers(), [64]);
+    }
+
+    #[test]
+    fn incomplete_write() {
+        let device = FakeDevice::new();
+        device.limit_writes(4);
+
+        let err = send_message(&device, &echo_command(b"hello"), timeout()).unwrap_err();
+        assert_eq!(*err.kind(), UsbError);
+    }
+
+    #[test]
+    fn write_error() {
+        let device = FakeDevice::new();
+        device.fail_write(rusb::Error::Pipe);
+
+        let err = send_message(&device, &echo_command(b"hello"), timeout()).unwrap_err();
+        assert_eq!(*err.kind(), UsbError);
+    }
+
+    #[test]
+    fn read_timeout() {
+        let device = FakeDevice::new();
+        let err = recv_message(&device, timeout()).unwrap_err();
+        assert_eq!(*err.kind(), UsbError);
+    }
+
+    #[test]
+    fn read_retries() {
+        let device = FakeDevice::new();
+
+        for _ in 0..MAX_RECV_RETRIES - 1 {
+            device.fail_read(rusb::Error::Io);
+        }
+
+        assert_eq!(echo(&device, b"hello").unwrap(), b"hello");
+
+        for _ in 0..MAX_RECV_RETRIES {
+            device.fail_read(rusb::Error::Io);
+        }
+
+        let err = echo(&device, b"hello").unwrap_err();
+        assert_eq!(*err.kind(), UsbError);
+    }
+
+    #[test]
+    fn flush_stale_response() {
+        let device = FakeDevice::new();
+        device.queue_response(&[0x81, 0x00, 0x01, 0x00]);
+
+        flush(&device).unwrap();
+        assert_eq!(device.pending_responses(), 0);
+
+        // Flushing when there's nothing to flush times out harmlessly
+        flush(&device).unwrap();
+        assert_eq!(echo(&device, b"hello").unwrap(), b"hello");
+    }
+
+    #[test]
+    fn flush_error() {
+        let device = FakeDevice::new();
+        device.queue_response(&[0x81, 0x00, 0x01, 0x00]);
+        device.fail_read(rusb::Error::NoDevice);
+        assert!(flush(&device).is_err());
+    }
+}
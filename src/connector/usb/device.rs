@@ -1,11 +1,10 @@
 //! Support for connecting to the YubiHSM 2 USB device using rusb
 
 use super::{
-    UsbConnection, UsbTimeout, YUBICO_VENDOR_ID, YUBIHSM2_BULK_IN_ENDPOINT, YUBIHSM2_INTERFACE_NUM,
+    connection, UsbConnection, UsbTimeout, YUBICO_VENDOR_ID, YUBIHSM2_INTERFACE_NUM,
     YUBIHSM2_PRODUCT_ID,
 };
 use crate::{
-    command::MAX_MSG_SIZE,
     connector::{
         self,
         ErrorKind::{AddrInvalid, DeviceBusyError, UsbError},
@@ -15,7 +14,6 @@ use crate::{
 use std::{
     fmt::{self, Debug},
     slice::Iter,
-    time::Duration,
     vec::IntoIter,
 };
 
@@ -23,9 +21,12 @@ use std::{
 pub struct Devices(Vec<Device>);
 
 impl Devices {
-    /// Return the serial numbers of all connected YubiHSM 2s
+    /// Return the serial numbers of all connected YubiHSM 2s.
+    ///
+    /// Unlike [`Devices::detect`], this doesn't reset the devices, so it's
+    /// safe to call while they're in use.
     pub fn serial_numbers() -> Result<Vec<SerialNumber>, connector::Error> {
-        let devices = Self::detect(UsbTimeout::default())?;
+        let devices = Self::enumerate(UsbTimeout::default(), false)?;
         let serials: Vec<_> = devices.iter().map(|a| a.serial_number).collect();
         Ok(serials)
     }
@@ -39,17 +40,7 @@ impl Devices {
         let mut devices = Self::detect(timeout)?;
 
         if let Some(sn) = serial_number {
-            while let Some(device) = devices.0.pop() {
-                if device.serial_number == sn {
-                    return device.open(timeout);
-                }
-            }
-
-            fail!(
-                UsbError,
-                "no YubiHSM 2 found with serial number: {:?}",
-                serial_number
-            )
+            devices.find(sn)?.open(timeout)
         } else {
             match devices.0.len() {
                 1 => devices.0.remove(0).open(timeout),
@@ -71,6 +62,11 @@ impl Devices {
 
     /// Detect connected YubiHSM 2s, returning a collection of them
     pub fn detect(timeout: UsbTimeout) -> Result<Self, connector::Error> {
+        Self::enumerate(timeout, true)
+    }
+
+    /// Enumerate connected YubiHSM 2s, optionally resetting each of them
+    pub(super) fn enumerate(timeout: UsbTimeout, reset: bool) -> Result<Self, connector::Error> {
         use rusb::UsbContext;
         let device_list = rusb::Context::new()?.devices()?;
         let mut devices = vec![];
@@ -90,15 +86,17 @@ impl Devices {
                 .open()
                 .map_err(|e| usb_err!(device, "error opening device: {}", e))?;
 
-            handle.reset().map_err(|error| match error {
-                rusb::Error::NoDevice => format_err!(
-                    DeviceBusyError,
-                    "USB(bus={},addr={}): couldn't reset device (already in use or disconnected)",
-                    device.bus_number(),
-                    device.address()
-                ),
-                other => usb_err!(device, "error resetting device: {}", other),
-            })?;
+            if reset {
+                handle.reset().map_err(|error| match error {
+                    rusb::Error::NoDevice => format_err!(
+                        DeviceBusyError,
+                        "USB(bus={},addr={}): couldn't reset device (already in use or disconnected)",
+                        device.bus_number(),
+                        device.address()
+                    ),
+                    other => usb_err!(device, "error resetting device: {}", other),
+                })?;
+            }
 
             let language = *handle
                 .read_languages(timeout.duration())?
@@ -137,6 +135,22 @@ impl Devices {
         Ok(Devices(devices))
     }
 
+    /// Take the device with the given serial number out of the collection
+    pub(super) fn find(self, serial_number: SerialNumber) -> Result<Device, connector::Error> {
+        match self
+            .0
+            .into_iter()
+            .find(|d| d.serial_number == serial_number)
+        {
+            Some(device) => Ok(device),
+            None => fail!(
+                UsbError,
+                "no YubiHSM 2 found with serial number: {:?}",
+                Some(serial_number)
+            ),
+        }
+    }
+
     /// Number of detected devices
     pub fn len(&self) -> usize {
         self.0.len()
@@ -222,12 +236,12 @@ impl Device {
     pub(super) fn open_handle(
         &self,
     ) -> Result<rusb::DeviceHandle<rusb::Context>, connector::Error> {
-        let mut handle = self.device.open()?;
+        let handle = self.device.open()?;
         handle.reset()?;
         handle.claim_interface(YUBIHSM2_INTERFACE_NUM)?;
 
         // Flush any unconsumed messages still in the buffer
-        flush(&mut handle)?;
+        connection::flush(&handle)?;
 
         Ok(handle)
     }
@@ -244,18 +258,3 @@ impl Debug for Device {
         )
     }
 }
-
-/// Flush any unconsumed messages still in the buffer to get the connection
-/// back into a clean state
-fn flush(handle: &mut rusb::DeviceHandle<rusb::Context>) -> Result<(), connector::Error> {
-    let mut buffer = [0u8; MAX_MSG_SIZE];
-
-    // Use a near instantaneous (but non-zero) timeout to drain the buffer.
-    // Zero is interpreted as wait forever.
-    let timeout = Duration::from_millis(1);
-
-    match handle.read_bulk(YUBIHSM2_BULK_IN_ENDPOINT, &mut buffer, timeout) {
-        Ok(_) | Err(rusb::Error::Timeout) => Ok(()),
-        Err(e) => Err(e.into()),
-    }
-}
@@ -0,0 +1,137 @@
+//! In-memory fake YubiHSM 2 USB device backed by the MockHsm, for testing
+//! the USB message framing without a physical device.
+//!
+//! Each write is recorded as a single bulk transfer: splitting transfers
+//! into packets is left to libusb, so it isn't simulated here.
+
+use super::transport::Transport;
+use crate::{connector::Connector, uuid};
+use std::{collections::VecDeque, sync::Mutex, time::Duration};
+
+/// Fake YubiHSM 2 USB device
+pub(super) struct FakeDevice(Mutex<State>);
+
+/// State of the fake device
+struct State {
+    /// HSM which processes the messages sent to the device
+    hsm: Connector,
+
+    /// Responses waiting to be read
+    outgoing: VecDeque<Vec<u8>>,
+
+    /// Sizes of all bulk transfers written to the device
+    transfers: Vec<usize>,
+
+    /// Errors to return from upcoming writes
+    write_errors: VecDeque<rusb::Error>,
+
+    /// Errors to return from upcoming reads
+    read_errors: VecDeque<rusb::Error>,
+
+    /// Maximum number of bytes accepted by each write
+    write_limit: Option<usize>,
+}
+
+impl FakeDevice {
+    /// Create a new fake device
+    pub(super) fn new() -> Self {
+        FakeDevice(Mutex::new(State {
+            hsm: Connector::mockhsm(),
+            outgoing: VecDeque::new(),
+            transfers: vec![],
+            write_errors: VecDeque::new(),
+            read_errors: VecDeque::new(),
+            write_limit: None,
+        }))
+    }
+
+    /// Return the given error from the next write which would otherwise
+    /// succeed
+    pub(super) fn fail_write(&self, error: rusb::Error) {
+        self.0.lock().unwrap().write_errors.push_back(error);
+    }
+
+    /// Return the given error from the next read which would otherwise
+    /// succeed
+    pub(super) fn fail_read(&self, error: rusb::Error) {
+        self.0.lock().unwrap().read_errors.push_back(error);
+    }
+
+    /// Only accept up to the given number of bytes per write
+    pub(super) fn limit_writes(&self, limit: usize) {
+        self.0.lock().unwrap().write_limit = Some(limit);
+    }
+
+    /// Queue a response which wasn't requested (e.g. one left over from a
+    /// previous connection)
+    pub(super) fn queue_response(&self, response: &[u8]) {
+        self.0.lock().unwrap().outgoing.push_back(response.to_vec());
+    }
+
+    /// Number of responses which haven't been read
+    pub(super) fn pending_responses(&self) -> usize {
+        self.0.lock().unwrap().outgoing.len()
+    }
+
+    /// Sizes of the bulk transfers written to the device
+    pub(super) fn transfers(&self) -> Vec<usize> {
+        self.0.lock().unwrap().transfers.clone()
+    }
+}
+
+impl Transport for FakeDevice {
+    /// Pass the message written in this transfer to the MockHsm
+    fn write_bulk(
+        &self,
+        _endpoint: u8,
+        data: &[u8],
+        _timeout: Duration,
+    ) -> Result<usize, rusb::Error> {
+        let mut state = self.0.lock().unwrap();
+
+        if let Some(error) = state.write_errors.pop_front() {
+            return Err(error);
+        }
+
+        let len = state.write_limit.map_or(data.len(), |n| n.min(data.len()));
+
+        state.transfers.push(len);
+
+        // Messages the MockHsm can't parse (e.g. truncated ones) are dropped
+        if let Ok(response) = state
+            .hsm
+            .send_message(uuid::new_v4(), data[..len].to_vec().into())
+        {
+            state.outgoing.push_back(response.into());
+        }
+
+        Ok(len)
+    }
+
+    /// Read the next response, timing out immediately if there isn't one
+    fn read_bulk(
+        &self,
+        _endpoint: u8,
+        buffer: &mut [u8],
+        _timeout: Duration,
+    ) -> Result<usize, rusb::Error> {
+        let mut state = self.0.lock().unwrap();
+
+        if state.outgoing.is_empty() {
+            return Err(rusb::Error::Timeout);
+        }
+
+        if let Some(error) = state.read_errors.pop_front() {
+            return Err(error);
+        }
+
+        let response = state.outgoing.pop_front().unwrap();
+
+        if response.len() > buffer.len() {
+            return Err(rusb::Error::Overflow);
+        }
+
+        buffer[..response.len()].copy_from_slice(&response);
+        Ok(response.len())
+    }
+}
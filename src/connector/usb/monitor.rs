@@ -0,0 +1,153 @@
+//! Monitoring for YubiHSM 2s being connected and disconnected
+
+use super::{Devices, UsbTimeout};
+use crate::device::SerialNumber;
+use std::{
+    collections::BTreeSet,
+    sync::mpsc::{self, RecvTimeoutError, Sender},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+/// YubiHSM 2 being connected or disconnected
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum HotplugEvent {
+    /// A YubiHSM 2 with the given serial number was connected (or
+    /// re-enumerated at a new address)
+    Arrived(SerialNumber),
+
+    /// The YubiHSM 2 with the given serial number was disconnected
+    Removed(SerialNumber),
+}
+
+/// Background thread which periodically rescans for YubiHSM 2s, reporting
+/// devices which have been connected or disconnected to a callback.
+///
+/// Devices which are already connected when the monitor is started are
+/// reported as having arrived. Scanning doesn't reset devices, so it's safe
+/// to monitor devices which are in use. The monitor stops when dropped.
+pub struct Monitor {
+    /// Dropped to stop the monitor thread
+    stop: Option<Sender<()>>,
+
+    /// Monitor thread
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Monitor {
+    /// Start monitoring, rescanning at the given interval
+    pub fn start<F>(interval: Duration, mut callback: F) -> Self
+    where
+        F: FnMut(HotplugEvent) + Send + 'static,
+    {
+        let (stop, stopped) = mpsc::channel();
+
+        let thread = thread::spawn(move || {
+            let mut present = Present::new();
+
+            loop {
+                match Devices::enumerate(UsbTimeout::default(), false) {
+                    Ok(devices) => {
+                        let devices = devices
+                            .iter()
+                            .map(|d| (d.serial_number, d.bus_number(), d.address()))
+                            .collect();
+
+                        for event in changes(&present, &devices) {
+                            callback(event);
+                        }
+
+                        present = devices;
+                    }
+                    Err(e) => debug!("USB: error scanning for devices: {}", e),
+                }
+
+                match stopped.recv_timeout(interval) {
+                    Err(RecvTimeoutError::Timeout) => continue,
+                    _ => break,
+                }
+            }
+        });
+
+        Self {
+            stop: Some(stop),
+            thread: Some(thread),
+        }
+    }
+
+    /// Stop monitoring, waiting for the monitor thread to exit
+    pub fn stop(self) {
+        drop(self)
+    }
+}
+
+impl Drop for Monitor {
+    fn drop(&mut self) {
+        self.stop.take();
+
+        if let Some(thread) = self.thread.take() {
+            // Don't wait on ourselves if the callback dropped the monitor
+            if thread.thread().id() != thread::current().id() {
+                let _ = thread.join();
+            }
+        }
+    }
+}
+
+/// Devices are identified by their address as well as their serial number,
+/// so re-enumerations are noticed
+type Present = BTreeSet<(SerialNumber, u8, u8)>;
+
+/// Events for the devices which were removed or arrived between two scans
+fn changes(previous: &Present, current: &Present) -> Vec<HotplugEvent> {
+    let removed = previous
+        .difference(current)
+        .map(|&(serial, _, _)| HotplugEvent::Removed(serial));
+
+    let arrived = current
+        .difference(previous)
+        .map(|&(serial, _, _)| HotplugEvent::Arrived(serial));
+
+    removed.chain(arrived).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn serial(n: &str) -> SerialNumber {
+        n.parse().unwrap()
+    }
+
+    #[test]
+    fn arrived_and_removed() {
+        let a = (serial("0000000001"), 1, 4);
+        let b = (serial("0000000002"), 1, 5);
+
+        let none = Present::new();
+        let both: Present = [a, b].into();
+        let only_b: Present = [b].into();
+
+        assert_eq!(
+            changes(&none, &both),
+            [HotplugEvent::Arrived(a.0), HotplugEvent::Arrived(b.0)]
+        );
+        assert_eq!(changes(&both, &both), []);
+        assert_eq!(changes(&both, &only_b), [HotplugEvent::Removed(a.0)]);
+        assert_eq!(changes(&only_b, &none), [HotplugEvent::Removed(b.0)]);
+    }
+
+    #[test]
+    fn reenumerated() {
+        let before: Present = [(serial("0000000001"), 1, 4)].into();
+        let after: Present = [(serial("0000000001"), 1, 6)].into();
+
+        assert_eq!(
+            changes(&before, &after),
+            [
+                HotplugEvent::Removed(serial("0000000001")),
+                HotplugEvent::Arrived(serial("0000000001"))
+            ]
+        );
+    }
+}
@@ -0,0 +1,44 @@
+//! Bulk transfers to and from the YubiHSM 2, abstracted so the message
+//! framing in `UsbConnection` can be tested without a physical device
+
+use std::time::Duration;
+
+/// Bulk transfer endpoints of a USB device
+pub(super) trait Transport {
+    /// Write data to a bulk out endpoint, returning the number of bytes
+    /// written
+    fn write_bulk(
+        &self,
+        endpoint: u8,
+        data: &[u8],
+        timeout: Duration,
+    ) -> Result<usize, rusb::Error>;
+
+    /// Read data from a bulk in endpoint, returning the number of bytes read
+    fn read_bulk(
+        &self,
+        endpoint: u8,
+        buffer: &mut [u8],
+        timeout: Duration,
+    ) -> Result<usize, rusb::Error>;
+}
+
+impl Transport for rusb::DeviceHandle<rusb::Context> {
+    fn write_bulk(
+        &self,
+        endpoint: u8,
+        data: &[u8],
+        timeout: Duration,
+    ) -> Result<usize, rusb::Error> {
+        rusb::DeviceHandle::write_bulk(self, endpoint, data, timeout)
+    }
+
+    fn read_bulk(
+        &self,
+        endpoint: u8,
+        buffer: &mut [u8],
+        timeout: Duration,
+    ) -> Result<usize, rusb::Error> {
+        rusb::DeviceHandle::read_bulk(self, endpoint, buffer, timeout)
+    }
+}
@@ -8,7 +8,7 @@ pub use self::error::{Error, ErrorKind};
 
 use bitflags::bitflags;
 use serde::{de, ser, Deserialize, Serialize};
-use std::fmt;
+use std::{fmt, str::FromStr};
 
 /// All domains as an array of bitflag types
 pub const DOMAINS: [Domain; 16] = [
@@ -72,12 +72,63 @@ impl Domain {
     }
 }
 
+/// Domains are displayed as a comma-separated list of domain numbers (e.g.
+/// `1,2,5`), or as `all` or `none`
+impl fmt::Display for Domain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_all() {
+            return f.write_str("all");
+        }
+
+        if self.is_empty() {
+            return f.write_str("none");
+        }
+
+        let numbers = DOMAINS
+            .iter()
+            .enumerate()
+            .filter(|(_, domain)| self.contains(**domain))
+            .map(|(i, _)| (i + 1).to_string())
+            .collect::<Vec<_>>();
+
+        f.write_str(&numbers.join(","))
+    }
+}
+
+/// Parses the forms written by [`fmt::Display`], i.e. a comma-separated list
+/// of domain numbers, `all` or `none`
+impl FromStr for Domain {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        match s.trim() {
+            "all" => return Ok(Domain::all()),
+            "none" | "" => return Ok(Domain::empty()),
+            _ => (),
+        }
+
+        s.split(',')
+            .map(str::trim)
+            .try_fold(Domain::empty(), |domains, number| {
+                let index = number.parse().map_err(|_| {
+                    format_err!(ErrorKind::DomainInvalid, "invalid domain: {:?}", number)
+                })?;
+
+                Ok(domains | Domain::at(index)?)
+            })
+    }
+}
+
 impl Serialize for Domain {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: ser::Serializer,
     {
-        serializer.serialize_u16(self.bits())
+        if serializer.is_human_readable() {
+            serializer.collect_str(self)
+        } else {
+            serializer.serialize_u16(self.bits())
+        }
     }
 }
 
@@ -92,7 +143,23 @@ impl<'de> Deserialize<'de> for Domain {
             type Value = Domain;
 
             fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
-                formatter.write_str("2-bytes containing domain bitflags")
+                formatter.write_str("2-bytes containing domain bitflags or domain numbers")
+            }
+
+            fn visit_str<E>(self, value: &str) -> Result<Domain, E>
+            where
+                E: de::Error,
+            {
+                value.parse().map_err(E::custom)
+            }
+
+            fn visit_u64<E>(self, value: u64) -> Result<Domain, E>
+            where
+                E: de::Error,
+            {
+                u16::try_from(value)
+                    .map_err(|_| E::custom("invalid domain bitflags"))
+                    .and_then(|bits| self.visit_u16(bits))
             }
 
             fn visit_u16<E>(self, value: u16) -> Result<Domain, E>
@@ -103,6 +170,10 @@ impl<'de> Deserialize<'de> for Domain {
             }
         }
 
-        deserializer.deserialize_u16(DomainVisitor)
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_any(DomainVisitor)
+        } else {
+            deserializer.deserialize_u16(DomainVisitor)
+        }
     }
 }
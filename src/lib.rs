@@ -89,6 +89,8 @@ pub mod wrap;
 pub use crate::connector::HttpConfig;
 #[cfg(feature = "usb")]
 pub use crate::connector::UsbConfig;
+#[cfg(feature = "async")]
+pub use crate::{client::AsyncClient, connector::AsyncConnector};
 
 pub use crate::{
     algorithm::Algorithm, audit::AuditOption, authentication::Credentials, capability::Capability,
@@ -21,6 +21,9 @@ pub use self::{
 };
 use crate::connector::{self, Connectable, Connection};
 
+#[cfg(feature = "async")]
+use crate::connector::{AsyncConnectable, AsyncConnection, BoxFuture};
+
 /// Mock serial number for the MockHsm
 pub const MOCK_SERIAL_NUMBER: &str = "0123456789";
 
@@ -55,6 +58,20 @@ impl Connectable for MockHsm {
     }
 }
 
+#[cfg(feature = "async")]
+impl AsyncConnectable for MockHsm {
+    /// Make a clone of this connectable as boxed trait object
+    fn box_clone(&self) -> Box<dyn AsyncConnectable> {
+        Box::new(MockHsm(self.0.clone()))
+    }
+
+    /// Create a new connection with a clone of the MockHsm state
+    fn connect(&self) -> BoxFuture<'_, Result<Box<dyn AsyncConnection>, connector::Error>> {
+        let connection: Box<dyn AsyncConnection> = Box::new(MockConnection::new(self));
+        Box::pin(async move { Ok(connection) })
+    }
+}
+
 impl Default for MockHsm {
     fn default() -> Self {
         Self::new()
@@ -66,3 +83,10 @@ impl Into<Box<dyn Connectable>> for MockHsm {
         Box::new(self)
     }
 }
+
+#[cfg(feature = "async")]
+impl Into<Box<dyn AsyncConnectable>> for MockHsm {
+    fn into(self) -> Box<dyn AsyncConnectable> {
+        Box::new(self)
+    }
+}
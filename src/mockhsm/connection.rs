@@ -8,6 +8,9 @@ use crate::{
 use std::sync::{Arc, Mutex};
 use uuid::Uuid;
 
+#[cfg(feature = "async")]
+use crate::connector::{AsyncConnection, BoxFuture};
+
 /// A mocked connection to the MockHsm
 pub struct MockConnection(Arc<Mutex<State>>);
 
@@ -34,8 +37,23 @@ impl Connection for MockConnection {
             Code::CreateSession => command::create_session(&mut state, &command),
             Code::AuthenticateSession => command::authenticate_session(&mut state, &command),
             Code::SessionMessage => command::session_message(&mut state, command),
+            Code::DeviceInfo => Ok(command::device_info().into()),
+            Code::Echo => Ok(command::echo(&command.data).into()),
             unsupported => fail!(ConnectionFailed, "unsupported command: {:?}", unsupported),
         }
         .map(Message::from)
     }
 }
+
+#[cfg(feature = "async")]
+impl AsyncConnection for MockConnection {
+    /// Send a message to the MockHsm (which completes immediately)
+    fn send_message(
+        &self,
+        uuid: Uuid,
+        message: Message,
+    ) -> BoxFuture<'_, Result<Message, connector::Error>> {
+        let response = Connection::send_message(self, uuid, message);
+        Box::pin(async move { response })
+    }
+}
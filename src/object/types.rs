@@ -81,9 +81,15 @@ impl FromStr for Type {
     }
 }
 
+/// Object types are serialized as their names in human-readable formats
+/// (e.g. JSON), and as their type byte otherwise
 impl Serialize for Type {
     fn serialize<S: ser::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
-        serializer.serialize_u8(self.to_u8())
+        if serializer.is_human_readable() {
+            serializer.collect_str(self)
+        } else {
+            serializer.serialize_u8(self.to_u8())
+        }
     }
 }
 
@@ -95,7 +101,13 @@ impl<'de> Deserialize<'de> for Type {
             type Value = Type;
 
             fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
-                formatter.write_str("an unsigned byte between 0x01 and 0x07")
+                formatter.write_str("an object type name or an unsigned byte between 0x01 and 0x07")
+            }
+
+            fn visit_str<E: de::Error>(self, value: &str) -> Result<Type, E> {
+                value
+                    .parse()
+                    .map_err(|()| E::custom(format!("unknown object type: {value:?}")))
             }
 
             fn visit_u8<E: de::Error>(self, value: u8) -> Result<Type, E> {
@@ -108,6 +120,10 @@ impl<'de> Deserialize<'de> for Type {
             }
         }
 
-        deserializer.deserialize_u8(TypeVisitor)
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_any(TypeVisitor)
+        } else {
+            deserializer.deserialize_u8(TypeVisitor)
+        }
     }
 }
@@ -12,7 +12,10 @@ mod guard;
 mod id;
 pub(crate) mod securechannel;
 mod timeout;
+pub mod trace;
 
+#[cfg(feature = "async")]
+pub use self::guard::AsyncGuard;
 pub use self::{
     error::{Error, ErrorKind},
     guard::Guard,
@@ -20,15 +23,19 @@ pub use self::{
     timeout::Timeout,
 };
 
-use self::{commands::CloseSessionCommand, securechannel::SecureChannel};
+use self::{commands::CloseSessionCommand, securechannel::SecureChannel, trace::Trace};
 use crate::{
-    authentication::Credentials,
+    authentication::Authenticator,
     command::{self, Command},
-    connector::Connector,
-    device, response,
+    connector::{self, Connector},
+    device, object, response,
     serialization::deserialize,
 };
 use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+#[cfg(feature = "async")]
+use crate::connector::AsyncConnector;
 
 /// Timeout fuzz factor: to avoid races/skew with the YubiHSM's clock,
 /// we consider sessions to be timed out slightly earlier than the actual
@@ -42,12 +49,19 @@ const TIMEOUT_FUZZ_FACTOR: Duration = Duration::from_secs(1);
 ///
 /// `Session`s are automatically closed on `Drop`, releasing HSM session
 /// resources and wiping the ephemeral keys used to encrypt the session.
-pub struct Session {
+///
+/// The `C` parameter is the connector the session communicates over: either
+/// a blocking [`Connector`] (the default) or, when the `async` cargo feature
+/// is enabled, an [`AsyncConnector`][`crate::connector::AsyncConnector`].
+pub struct Session<C = Connector> {
     /// ID for this session
     id: Id,
 
     /// Connector which communicates with the HSM (HTTP or USB)
-    connector: Connector,
+    connector: C,
+
+    /// Authentication key this session was opened with
+    authentication_key_id: object::Id,
 
     /// Encrypted channel (SCP03) to the HSM
     secure_channel: Option<SecureChannel>,
@@ -62,35 +76,136 @@ pub struct Session {
     timeout: Timeout,
 }
 
+/// Session with the HSM which communicates over an [`AsyncConnector`]
+#[cfg(feature = "async")]
+pub type AsyncSession = Session<AsyncConnector>;
+
 impl Session {
-    /// Connect to the HSM using the given configuration and credentials
+    /// Connect to the HSM using the given configuration and authenticator
     pub(super) fn open(
         connector: Connector,
-        credentials: &Credentials,
+        authenticator: &Authenticator,
         timeout: Timeout,
     ) -> Result<Self, Error> {
-        ensure!(
-            timeout.duration() > TIMEOUT_FUZZ_FACTOR,
-            ErrorKind::CreateFailed,
-            "timeout too low: must be longer than {:?}",
-            TIMEOUT_FUZZ_FACTOR
-        );
+        check_timeout(timeout)?;
+
+        let channel = SecureChannel::open(&connector, authenticator)?;
+        let mut session = Session::new(connector, channel, authenticator, timeout);
+        session.authenticate(authenticator)?;
+
+        Ok(session)
+    }
+
+    /// Close this session, consuming it in the process.
+    pub fn close(mut self) -> Result<(), Error> {
+        // Only attempt to close the session if we have an active secure
+        // channel and our session hasn't already timed out
+        if self.secure_channel.is_none() || self.is_timed_out() {
+            return Ok(());
+        }
+
+        session_debug!(self, "closing session");
+        self.send_command(&CloseSessionCommand {})?;
+        Ok(())
+    }
+
+    /// Encrypt a command, send it to the HSM, then read and decrypt the response
+    pub(crate) fn send_command<C: Command>(
+        &mut self,
+        command: &C,
+    ) -> Result<C::ResponseType, Error> {
+        let encrypted_msg = self.encrypt_command(command)?;
+        let uuid = encrypted_msg.uuid;
+        let encrypted_response = self.send_message(encrypted_msg)?;
+        self.decrypt_response::<C>(uuid, encrypted_response)
+    }
+
+    /// Send a command message to the HSM and parse the response
+    fn send_message(&mut self, cmd: command::Message) -> Result<response::Message, Error> {
+        let uuid = cmd.uuid;
+        self.before_send_message(&cmd)?;
+        let result = self.connector.send_message(uuid, cmd.into());
+        self.after_send_message(uuid, result)
+    }
+
+    /// Authenticate the current session with the HSM
+    fn authenticate(&mut self, authenticator: &Authenticator) -> Result<(), Error> {
+        let command = self.begin_authenticate(authenticator)?;
+        let response = self.send_message(command)?;
+        self.finish_authenticate(authenticator, &response)
+    }
+}
+
+#[cfg(feature = "async")]
+impl AsyncSession {
+    /// Connect to the HSM asynchronously using the given connector and authenticator
+    pub(crate) async fn open_async(
+        connector: AsyncConnector,
+        authenticator: &Authenticator,
+        timeout: Timeout,
+    ) -> Result<Self, Error> {
+        check_timeout(timeout)?;
+
+        let channel = SecureChannel::open_async(&connector, authenticator).await?;
+        let mut session = Session::new(connector, channel, authenticator, timeout);
+
+        let command = session.begin_authenticate(authenticator)?;
+        let response = session.send_message(command).await?;
+        session.finish_authenticate(authenticator, &response)?;
+
+        Ok(session)
+    }
+
+    /// Close this session, consuming it in the process.
+    pub async fn close(mut self) -> Result<(), Error> {
+        if self.secure_channel.is_none() || self.is_timed_out() {
+            return Ok(());
+        }
+
+        session_debug!(self, "closing session");
+        self.send_command(&CloseSessionCommand {}).await?;
+        Ok(())
+    }
+
+    /// Encrypt a command, send it to the HSM, then read and decrypt the response
+    pub(crate) async fn send_command<C: Command>(
+        &mut self,
+        command: &C,
+    ) -> Result<C::ResponseType, Error> {
+        let encrypted_msg = self.encrypt_command(command)?;
+        let uuid = encrypted_msg.uuid;
+        let encrypted_response = self.send_message(encrypted_msg).await?;
+        self.decrypt_response::<C>(uuid, encrypted_response)
+    }
+
+    /// Send a command message to the HSM and parse the response
+    async fn send_message(&mut self, cmd: command::Message) -> Result<response::Message, Error> {
+        let uuid = cmd.uuid;
+        self.before_send_message(&cmd)?;
+        let result = self.connector.send_message(uuid, cmd.into()).await;
+        self.after_send_message(uuid, result)
+    }
+}
 
-        let channel = SecureChannel::open(&connector, credentials)?;
+impl<T> Session<T> {
+    /// Create a session from a freshly opened (but not yet authenticated) channel
+    fn new(
+        connector: T,
+        channel: SecureChannel,
+        authenticator: &Authenticator,
+        timeout: Timeout,
+    ) -> Self {
         let now = Instant::now();
 
-        let mut session = Session {
+        Session {
             id: channel.id(),
             connector,
+            authentication_key_id: authenticator.authentication_key_id,
             secure_channel: Some(channel),
             created_at: now,
             last_active: now,
             timeout,
-        };
-
-        session.authenticate(credentials)?;
-
-        Ok(session)
+        }
     }
 
     /// Is this `Session` still open?
@@ -103,6 +218,11 @@ impl Session {
         self.id
     }
 
+    /// Object ID of the authentication key this session was opened with
+    pub fn authentication_key_id(&self) -> object::Id {
+        self.authentication_key_id
+    }
+
     /// How long has this session been open?
     pub fn duration(&self) -> Duration {
         Instant::now().duration_since(self.created_at)
@@ -116,24 +236,15 @@ impl Session {
             .map(SecureChannel::counter)
     }
 
+    /// How long has it been since this session was last active?
+    pub fn idle_time(&self) -> Duration {
+        Instant::now().duration_since(self.last_active)
+    }
+
     /// Has this session timed out?
     pub fn is_timed_out(&self) -> bool {
-        let idle_time = Instant::now().duration_since(self.last_active);
         let timeout_with_fuzz = self.timeout.duration() - TIMEOUT_FUZZ_FACTOR;
-        idle_time >= timeout_with_fuzz
-    }
-
-    /// Close this session, consuming it in the process.
-    pub fn close(mut self) -> Result<(), Error> {
-        // Only attempt to close the session if we have an active secure
-        // channel and our session hasn't already timed out
-        if self.secure_channel.is_none() || self.is_timed_out() {
-            return Ok(());
-        }
-
-        session_debug!(self, "closing session");
-        self.send_command(&CloseSessionCommand {})?;
-        Ok(())
+        self.idle_time() >= timeout_with_fuzz
     }
 
     /// Abort this session, terminating it without closing it
@@ -141,13 +252,15 @@ impl Session {
         self.secure_channel = None;
     }
 
-    /// Encrypt a command, send it to the HSM, then read and decrypt the response
-    pub(crate) fn send_command<C: Command>(
-        &mut self,
-        command: &C,
-    ) -> Result<C::ResponseType, Error> {
+    /// Serialize and encrypt a command to be sent to the HSM
+    fn encrypt_command<C: Command>(&mut self, command: &C) -> Result<command::Message, Error> {
         let plaintext_msg = command.to_message()?;
-        let cmd_type = plaintext_msg.command_type;
+        session_trace!(
+            self,
+            "uuid={} >> {}",
+            plaintext_msg.uuid,
+            Trace::command(&plaintext_msg)
+        );
 
         let encrypted_msg = self
             .secure_channel()?
@@ -157,17 +270,23 @@ impl Session {
                 self.abort();
             })?;
 
-        let uuid = encrypted_msg.uuid;
         session_debug!(
             self,
             "n={} uuid={} cmd={:?}",
             self.messages_sent()?,
-            uuid,
+            encrypted_msg.uuid,
             C::COMMAND_CODE
         );
 
-        let encrypted_response = self.send_message(encrypted_msg)?;
+        Ok(encrypted_msg)
+    }
 
+    /// Verify and decrypt the HSM's response to the command `C`
+    fn decrypt_response<C: Command>(
+        &mut self,
+        uuid: Uuid,
+        encrypted_response: response::Message,
+    ) -> Result<C::ResponseType, Error> {
         let response = self
             .secure_channel()?
             .decrypt_response(encrypted_response)
@@ -176,13 +295,30 @@ impl Session {
                 self.abort();
             })?;
 
+        session_trace!(self, "uuid={} << {}", uuid, Trace::response(&response));
+
         if response.is_err() {
             if let Some(kind) = device::ErrorKind::from_response_message(&response) {
-                session_debug!(self, "uuid={} failed={:?} error={:?}", uuid, cmd_type, kind);
+                session_debug!(
+                    self,
+                    "uuid={} failed={:?} error={:?}",
+                    uuid,
+                    C::COMMAND_CODE,
+                    kind
+                );
                 return Err(kind.into());
             } else {
-                session_debug!(self, "uuid={} failed={:?} error=unknown", uuid, cmd_type);
-                fail!(ErrorKind::ResponseError, "{:?} failed: HSM error", cmd_type);
+                session_debug!(
+                    self,
+                    "uuid={} failed={:?} error=unknown",
+                    uuid,
+                    C::COMMAND_CODE
+                );
+                fail!(
+                    ErrorKind::ResponseError,
+                    "{:?} failed: HSM error",
+                    C::COMMAND_CODE
+                );
             }
         }
 
@@ -198,24 +334,31 @@ impl Session {
         deserialize(response.data.as_ref()).map_err(Into::into)
     }
 
-    /// Send a command message to the HSM and parse the response
-    fn send_message(&mut self, cmd: command::Message) -> Result<response::Message, Error> {
-        let cmd_type = cmd.command_type;
-        let uuid = cmd.uuid;
+    /// Record activity on this session and log an outgoing message
+    fn before_send_message(&mut self, cmd: &command::Message) -> Result<(), Error> {
         self.last_active = Instant::now();
 
         // We log the plaintext of all `SessionMessage` commands, so ignore those
-        if cmd_type != command::Code::SessionMessage {
+        if cmd.command_type != command::Code::SessionMessage {
             session_debug!(
                 self,
                 "n={} uuid={} msg={:?}",
                 self.messages_sent()?,
-                &uuid,
-                cmd_type
+                &cmd.uuid,
+                cmd.command_type
             );
         }
 
-        let response = match self.connector.send_message(uuid, cmd.into()) {
+        Ok(())
+    }
+
+    /// Parse the connector's response to a message sent to the HSM
+    fn after_send_message(
+        &mut self,
+        uuid: Uuid,
+        result: Result<connector::Message, connector::Error>,
+    ) -> Result<response::Message, Error> {
+        let response = match result {
             Ok(response_bytes) => response::Message::parse(response_bytes)?,
             Err(e) => {
                 // Abort the session in the event of errors
@@ -237,34 +380,40 @@ impl Session {
         Ok(response)
     }
 
-    /// Authenticate the current session with the HSM
-    fn authenticate(&mut self, credentials: &Credentials) -> Result<(), Error> {
+    /// Compute the host's message for authenticating the current session
+    fn begin_authenticate(
+        &mut self,
+        authenticator: &Authenticator,
+    ) -> Result<command::Message, Error> {
         session_debug!(
             self,
             "command={:?} key={}",
             command::Code::AuthenticateSession,
-            credentials.authentication_key_id
+            authenticator.authentication_key_id
         );
 
-        let command = self.secure_channel()?.authenticate_session()?;
-        let response = self.send_message(command)?;
+        self.secure_channel()?.authenticate_session()
+    }
 
-        if let Err(e) = self
-            .secure_channel()?
-            .finish_authenticate_session(&response)
-        {
+    /// Handle the HSM's response to the host's authentication message
+    fn finish_authenticate(
+        &mut self,
+        authenticator: &Authenticator,
+        response: &response::Message,
+    ) -> Result<(), Error> {
+        if let Err(e) = self.secure_channel()?.finish_authenticate_session(response) {
             session_error!(
                 self,
                 "failed={:?} key={} err={:?}",
                 command::Code::AuthenticateSession,
-                credentials.authentication_key_id,
+                authenticator.authentication_key_id,
                 e.to_string()
             );
 
             return Err(e);
         }
 
-        session_debug!(self, "auth=OK key={}", credentials.authentication_key_id);
+        session_debug!(self, "auth=OK key={}", authenticator.authentication_key_id);
         Ok(())
     }
 
@@ -275,3 +424,15 @@ impl Session {
             .ok_or_else(|| format_err!(ErrorKind::ClosedError, "session is already closed").into())
     }
 }
+
+/// Ensure the given session timeout is usable
+fn check_timeout(timeout: Timeout) -> Result<(), Error> {
+    ensure!(
+        timeout.duration() > TIMEOUT_FUZZ_FACTOR,
+        ErrorKind::CreateFailed,
+        "timeout too low: must be longer than {:?}",
+        TIMEOUT_FUZZ_FACTOR
+    );
+
+    Ok(())
+}
@@ -1,11 +1,24 @@
 //! MutexGuard wrapper protecting an optional session which is always true
 
 use super::Session;
+use crate::client::pool::Lease;
 use std::ops::{Deref, DerefMut};
 use std::sync::MutexGuard;
 
+#[cfg(feature = "async")]
+use super::AsyncSession;
+
 /// Mutex-guarded wrapper type containing a locked session
-pub struct Guard<'mutex>(MutexGuard<'mutex, Option<Session>>);
+pub struct Guard<'mutex>(Locked<'mutex>);
+
+/// Ways in which a session can be held exclusively
+enum Locked<'mutex> {
+    /// Session stored in a client's session mutex
+    Mutex(MutexGuard<'mutex, Option<Session>>),
+
+    /// Session borrowed from a `client::Pool`
+    Pool(Lease),
+}
 
 impl<'mutex> Guard<'mutex> {
     /// Create a session guard from a `MutexGuard`ed `Session`
@@ -14,7 +27,12 @@ impl<'mutex> Guard<'mutex> {
             mutex_guard.is_some(),
             "session::Guard must wrap an active session"
         );
-        Guard(mutex_guard)
+        Guard(Locked::Mutex(mutex_guard))
+    }
+
+    /// Create a session guard from a session borrowed from a `client::Pool`
+    pub(crate) fn leased(lease: Lease) -> Self {
+        Guard(Locked::Pool(lease))
     }
 }
 
@@ -22,12 +40,50 @@ impl Deref for Guard<'_> {
     type Target = Session;
 
     fn deref(&self) -> &Session {
-        self.0.deref().as_ref().unwrap()
+        match &self.0 {
+            Locked::Mutex(guard) => guard.deref().as_ref().unwrap(),
+            Locked::Pool(lease) => lease,
+        }
     }
 }
 
 impl DerefMut for Guard<'_> {
     fn deref_mut(&mut self) -> &mut Session {
+        match &mut self.0 {
+            Locked::Mutex(guard) => guard.deref_mut().as_mut().unwrap(),
+            Locked::Pool(lease) => lease,
+        }
+    }
+}
+
+/// Mutex-guarded wrapper type containing a locked asynchronous session
+#[cfg(feature = "async")]
+pub struct AsyncGuard<'mutex>(tokio::sync::MutexGuard<'mutex, Option<AsyncSession>>);
+
+#[cfg(feature = "async")]
+impl<'mutex> AsyncGuard<'mutex> {
+    /// Create a session guard from a `MutexGuard`ed `AsyncSession`
+    pub(crate) fn new(mutex_guard: tokio::sync::MutexGuard<'mutex, Option<AsyncSession>>) -> Self {
+        assert!(
+            mutex_guard.is_some(),
+            "session::AsyncGuard must wrap an active session"
+        );
+        AsyncGuard(mutex_guard)
+    }
+}
+
+#[cfg(feature = "async")]
+impl Deref for AsyncGuard<'_> {
+    type Target = AsyncSession;
+
+    fn deref(&self) -> &AsyncSession {
+        self.0.deref().as_ref().unwrap()
+    }
+}
+
+#[cfg(feature = "async")]
+impl DerefMut for AsyncGuard<'_> {
+    fn deref_mut(&mut self) -> &mut AsyncSession {
         self.0.deref_mut().as_mut().unwrap()
     }
 }
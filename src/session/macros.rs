@@ -8,6 +8,16 @@ macro_rules! session_debug {
     };
 }
 
+/// Write consistent `trace!(...) lines for sessions
+macro_rules! session_trace {
+    ($session:expr, $msg:expr) => {
+        trace!("session={} {}", $session.id().to_u8(), $msg);
+    };
+    ($session:expr, $fmt:expr, $($arg:tt)+) => {
+        trace!(concat!("session={} ", $fmt), $session.id().to_u8(), $($arg)+);
+    };
+}
+
 /// Write consistent `error!(...) lines for sessions
 macro_rules! session_error {
     ($session:expr, $msg:expr) => {
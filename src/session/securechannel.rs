@@ -31,10 +31,12 @@ pub(crate) use self::{
     mac::Mac,
 };
 use super::commands::{CreateSessionCommand, CreateSessionResponse};
+#[cfg(feature = "async")]
+use crate::connector::AsyncConnector;
 use crate::{
-    authentication::{self, Credentials},
+    authentication::{self, Authenticator, SessionKeys},
     command::{self, Command},
-    connector::Connector,
+    connector::{self, Connector},
     device, response,
     serialization::deserialize,
     session::{self, ErrorKind},
@@ -103,18 +105,50 @@ impl SecureChannel {
     /// establishing a session key
     pub(crate) fn open(
         connector: &Connector,
-        credentials: &Credentials,
+        authenticator: &Authenticator,
+    ) -> Result<Self, session::Error> {
+        let host_challenge = connector.host_challenge().unwrap_or_else(Challenge::new);
+        let command_message = Self::create_session_command(authenticator, host_challenge)?;
+
+        let uuid = command_message.uuid;
+        let response_body = connector.send_message(uuid, command_message.into())?;
+        Self::from_create_session_response(authenticator, host_challenge, response_body)
+    }
+
+    /// Open a SecureChannel using an asynchronous connector
+    #[cfg(feature = "async")]
+    pub(crate) async fn open_async(
+        connector: &AsyncConnector,
+        authenticator: &Authenticator,
     ) -> Result<Self, session::Error> {
         let host_challenge = Challenge::new();
+        let command_message = Self::create_session_command(authenticator, host_challenge)?;
+
+        let uuid = command_message.uuid;
+        let response_body = connector.send_message(uuid, command_message.into()).await?;
 
-        let command_message = CreateSessionCommand {
-            authentication_key_id: credentials.authentication_key_id,
+        Self::from_create_session_response(authenticator, host_challenge, response_body)
+    }
+
+    /// Build the `CreateSession` command which begins opening a channel
+    fn create_session_command(
+        authenticator: &Authenticator,
+        host_challenge: Challenge,
+    ) -> Result<command::Message, session::Error> {
+        CreateSessionCommand {
+            authentication_key_id: authenticator.authentication_key_id,
             host_challenge,
         }
-        .to_message()?;
+        .to_message()
+    }
 
-        let uuid = command_message.uuid;
-        let response_body = connector.send_message(uuid, command_message.into())?;
+    /// Handle the card's response to `CreateSession`, deriving session keys
+    /// and verifying the card cryptogram
+    fn from_create_session_response(
+        authenticator: &Authenticator,
+        host_challenge: Challenge,
+        response_body: connector::Message,
+    ) -> Result<Self, session::Error> {
         let response_message = response::Message::parse(response_body)?;
 
         if response_message.is_err() {
@@ -122,7 +156,7 @@ impl SecureChannel {
                 Some(device::ErrorKind::ObjectNotFound) => fail!(
                     ErrorKind::AuthenticationError,
                     "auth key not found: 0x{:04x}",
-                    credentials.authentication_key_id
+                    authenticator.authentication_key_id
                 ),
                 Some(kind) => return Err(kind.into()),
                 None => fail!(
@@ -151,11 +185,29 @@ impl SecureChannel {
         // Derive session keys from the combination of host and card challenges.
         // If either of them are incorrect (indicating a key mismatch) it will
         // result in a cryptogram verification failure.
-        let channel = Self::new(
+        let host_challenge_bytes = host_challenge.to_bytes();
+        let card_challenge_bytes = session_response.card_challenge.to_bytes();
+
+        let session_keys = authenticator
+            .provider
+            .session_keys(
+                &host_challenge_bytes,
+                &card_challenge_bytes,
+                &session_response.card_cryptogram.to_bytes(),
+            )
+            .map_err(|e| {
+                format_err!(
+                    ErrorKind::AuthenticationError,
+                    "error deriving session keys for authentication key #{}: {}",
+                    authenticator.authentication_key_id,
+                    e
+                )
+            })?;
+
+        let channel = Self::with_session_keys(
             id,
-            &credentials.authentication_key,
-            host_challenge,
-            session_response.card_challenge,
+            Context::from_challenges(host_challenge, session_response.card_challenge),
+            &session_keys,
         );
 
         if channel
@@ -167,8 +219,8 @@ impl SecureChannel {
             fail!(
                 ErrorKind::AuthenticationError,
                 "(session: {}) invalid credentials for authentication key #{} (cryptogram mismatch)",
-                channel.id().to_u8(),
-                credentials.authentication_key_id,
+                id.to_u8(),
+                authenticator.authentication_key_id,
             );
         }
 
@@ -176,27 +228,37 @@ impl SecureChannel {
     }
 
     /// Create a new channel with the given ID, auth key, and host/card challenges
+    #[cfg(feature = "mockhsm")]
     pub(crate) fn new(
         id: session::Id,
         authentication_key: &authentication::Key,
         host_challenge: Challenge,
         card_challenge: Challenge,
     ) -> Self {
-        let context = Context::from_challenges(host_challenge, card_challenge);
-        let enc_key = derive_key(authentication_key.enc_key(), 0b100, &context);
-        let mac_key = derive_key(authentication_key.mac_key(), 0b110, &context);
-        let rmac_key = derive_key(authentication_key.mac_key(), 0b111, &context);
-        let mac_chaining_value = [0u8; Mac::BYTE_SIZE * 2];
+        let session_keys = derive_session_keys(
+            authentication_key,
+            &host_challenge.to_bytes(),
+            &card_challenge.to_bytes(),
+        );
+
+        Self::with_session_keys(
+            id,
+            Context::from_challenges(host_challenge, card_challenge),
+            &session_keys,
+        )
+    }
 
+    /// Create a new channel with the given ID, context and session keys
+    fn with_session_keys(id: session::Id, context: Context, session_keys: &SessionKeys) -> Self {
         Self {
             id,
             counter: 0,
             security_level: SecurityLevel::None,
             context,
-            enc_key,
-            mac_key,
-            rmac_key,
-            mac_chaining_value,
+            enc_key: session_keys.enc_key,
+            mac_key: session_keys.mac_key,
+            rmac_key: session_keys.rmac_key,
+            mac_chaining_value: [0u8; Mac::BYTE_SIZE * 2],
         }
     }
 
@@ -610,6 +672,24 @@ pub(crate) enum SecurityLevel {
     Terminated,
 }
 
+/// Derive the session keys for an authentication key held in host memory
+pub(crate) fn derive_session_keys(
+    authentication_key: &authentication::Key,
+    host_challenge: &[u8; CHALLENGE_SIZE],
+    card_challenge: &[u8; CHALLENGE_SIZE],
+) -> SessionKeys {
+    let context = Context::from_challenges(
+        Challenge::from_slice(host_challenge),
+        Challenge::from_slice(card_challenge),
+    );
+
+    SessionKeys::new(
+        derive_key(authentication_key.enc_key(), 0b100, &context),
+        derive_key(authentication_key.mac_key(), 0b110, &context),
+        derive_key(authentication_key.mac_key(), 0b111, &context),
+    )
+}
+
 /// Derive a key using the SCP03 KDF
 fn derive_key(parent_key: &[u8], derivation_constant: u8, context: &Context) -> [u8; KEY_SIZE] {
     let mut key = [0u8; KEY_SIZE];
@@ -22,7 +22,6 @@ impl Challenge {
     /// Create a new challenge from a slice
     ///
     /// Panics if the slice is not 8-bytes
-    #[cfg(all(test, feature = "mockhsm"))]
     pub fn from_slice(slice: &[u8]) -> Self {
         assert_eq!(slice.len(), 8, "challenge must be 8-bytes long");
 
@@ -31,6 +30,11 @@ impl Challenge {
         Challenge(challenge)
     }
 
+    /// Get the challenge value as a byte array
+    pub fn to_bytes(self) -> [u8; CHALLENGE_SIZE] {
+        self.0
+    }
+
     /// Borrow the challenge value as a slice
     #[allow(clippy::trivially_copy_pass_by_ref)]
     pub fn as_slice(&self) -> &[u8] {
@@ -25,6 +25,11 @@ impl Cryptogram {
         Cryptogram(cryptogram)
     }
 
+    /// Get the cryptogram value as a byte array
+    pub fn to_bytes(&self) -> [u8; CRYPTOGRAM_SIZE] {
+        self.0
+    }
+
     /// Borrow the cryptogram value as a slice
     pub fn as_slice(&self) -> &[u8] {
         &self.0
@@ -0,0 +1,542 @@
+//! Decoding of the messages exchanged with the HSM, for debugging.
+//!
+//! When `trace`-level logging is enabled, sessions log the plaintext of each
+//! command they send and each response they receive, decoded into named
+//! fields (object IDs, algorithms, sizes, error codes, etc). Key material
+//! and other secret values are redacted: only their sizes are logged.
+//!
+//! [`decode_command`] and [`decode_response`] decode messages captured from
+//! a connector (e.g. in a [transcript][crate::connector::transcript]). Only
+//! the parts of the protocol which happen outside of a session (e.g. opening
+//! sessions, getting device information, and errors) can be fully decoded
+//! this way: the contents of session messages are encrypted.
+
+use super::{securechannel::CHALLENGE_SIZE, Error, Id};
+use crate::{
+    command, connector, device,
+    object::{self, LABEL_SIZE},
+    response, wrap, Algorithm, Capability, Domain,
+};
+use std::fmt;
+
+/// Size of the signature included in SSH certificate signing requests
+const SSH_REQUEST_SIGNATURE_SIZE: usize = 32;
+
+/// Decode a command message sent to a connector
+pub fn decode_command(bytes: &[u8]) -> Result<Trace, Error> {
+    let message = command::Message::parse(bytes.to_vec())?;
+    Ok(Trace::command(&message))
+}
+
+/// Decode a response message received from a connector
+pub fn decode_response(bytes: &[u8]) -> Result<Trace, Error> {
+    let message = response::Message::parse(connector::Message::from(bytes.to_vec()))?;
+    Ok(Trace::response(&message))
+}
+
+/// Decoded command or response message
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Trace {
+    /// Command or response code
+    pub code: Code,
+
+    /// Session ID included in the message (if any)
+    pub session_id: Option<Id>,
+
+    /// Size of the message data (excluding the session ID and MAC)
+    pub size: usize,
+
+    /// Fields decoded from the message data
+    pub fields: Vec<Field>,
+}
+
+impl Trace {
+    /// Decode a command message
+    pub(crate) fn command(message: &command::Message) -> Self {
+        let mut decoder = Decoder::new(&message.data);
+        decoder.command(message.command_type);
+
+        Self {
+            code: Code::Command(message.command_type),
+            session_id: message.session_id,
+            size: message.data.len(),
+            fields: decoder.finish(),
+        }
+    }
+
+    /// Decode a response message
+    pub(crate) fn response(message: &response::Message) -> Self {
+        let mut decoder = Decoder::new(&message.data);
+
+        match device::ErrorKind::from_response_message(message) {
+            Some(kind) => {
+                decoder.read(1);
+                decoder.push("error", Value::Error(kind));
+            }
+            None => {
+                if let Some(command_type) = message.command() {
+                    decoder.response(command_type);
+                }
+            }
+        }
+
+        Self {
+            code: Code::Response(message.code),
+            session_id: message.session_id,
+            size: message.data.len(),
+            fields: decoder.finish(),
+        }
+    }
+
+    /// Get the value of the field with the given name (if present)
+    pub fn field(&self, name: &str) -> Option<&Value> {
+        self.fields
+            .iter()
+            .find(|field| field.name == name)
+            .map(|field| &field.value)
+    }
+}
+
+impl fmt::Display for Trace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.code)?;
+
+        if let Some(id) = self.session_id {
+            write!(f, " session={id}")?;
+        }
+
+        write!(f, " size={}", self.size)?;
+
+        for field in &self.fields {
+            write!(f, " {}={}", field.name, field.value)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Type of a decoded message
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Code {
+    /// Command sent to the HSM
+    Command(command::Code),
+
+    /// Response returned by the HSM
+    Response(response::Code),
+}
+
+impl fmt::Display for Code {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Code::Command(code) => write!(f, "cmd={code:?}"),
+            Code::Response(response::Code::Success(code)) => write!(f, "rsp={code:?}"),
+            Code::Response(code) => write!(f, "rsp={code:?}"),
+        }
+    }
+}
+
+/// Named field decoded from a message
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Field {
+    /// Name of the field
+    pub name: &'static str,
+
+    /// Decoded value
+    pub value: Value,
+}
+
+/// Value of a decoded field
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Value {
+    /// Object ID
+    ObjectId(object::Id),
+
+    /// Object type
+    ObjectType(object::Type),
+
+    /// Algorithm
+    Algorithm(Algorithm),
+
+    /// Capabilities
+    Capabilities(Capability),
+
+    /// Domains
+    Domains(Domain),
+
+    /// Object label
+    Label(String),
+
+    /// Integer
+    Number(u64),
+
+    /// Error returned by the HSM
+    Error(device::ErrorKind),
+
+    /// Data which isn't decoded any further: only its size is shown
+    Data(usize),
+
+    /// Secret data (e.g. key material): only its size is shown
+    Redacted(usize),
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::ObjectId(id) => write!(f, "0x{id:04x}"),
+            Value::ObjectType(object_type) => write!(f, "{object_type}"),
+            Value::Algorithm(algorithm) => write!(f, "{algorithm:?}"),
+            Value::Capabilities(capabilities) => write!(f, "[{capabilities}]"),
+            Value::Domains(domains) => write!(f, "{domains:?}"),
+            Value::Label(label) => write!(f, "{label:?}"),
+            Value::Number(n) => write!(f, "{n}"),
+            Value::Error(kind) => write!(f, "{kind:?}"),
+            Value::Data(size) => write!(f, "<{size} bytes>"),
+            Value::Redacted(size) => write!(f, "<redacted: {size} bytes>"),
+        }
+    }
+}
+
+/// Decodes the fields of a message's data
+struct Decoder<'a> {
+    /// Data which hasn't been decoded yet
+    data: &'a [u8],
+
+    /// Fields decoded so far
+    fields: Vec<Field>,
+}
+
+impl<'a> Decoder<'a> {
+    /// Create a new decoder for the given message data
+    fn new(data: &'a [u8]) -> Self {
+        Self {
+            data,
+            fields: vec![],
+        }
+    }
+
+    /// Decode the data of a command
+    fn command(&mut self, code: command::Code) -> Option<()> {
+        use command::Code::*;
+
+        match code {
+            CreateSession => {
+                self.object_id("authentication_key_id")?;
+                self.bytes("host_challenge", CHALLENGE_SIZE)?;
+            }
+            AuthenticateSession => self.bytes("host_cryptogram", CHALLENGE_SIZE)?,
+            SessionMessage => self.data("ciphertext"),
+            BlinkDevice => self.number("seconds", 1)?,
+            GetPseudoRandom => self.number("bytes", 2)?,
+            SetLogIndex => self.number("log_index", 2)?,
+            GetOption => self.number("tag", 1)?,
+            SetOption => {
+                self.number("tag", 1)?;
+                self.number("length", 2)?;
+                self.data("value");
+            }
+            GetOpaqueObject | GetTemplate | RandomizeOtpAead => self.object_id("object_id")?,
+            GetPublicKey => self.object_id("key_id")?,
+            GetObjectInfo | DeleteObject => {
+                self.object_id("object_id")?;
+                self.object_type("object_type")?;
+            }
+            ListObjects => self.data("filters"),
+            PutOpaqueObject | PutTemplate => {
+                self.object_params()?;
+                self.data("data");
+            }
+            PutAsymmetricKey | PutHmacKey | PutOtpAead => {
+                self.object_params()?;
+                self.secret("key");
+            }
+            PutAuthenticationKey | PutWrapKey => {
+                self.object_params()?;
+                self.capabilities("delegated_capabilities")?;
+                self.secret("key");
+            }
+            GenerateAsymmetricKey | GenerateHmacKey | GenerateOtpAead => self.object_params()?,
+            GenerateWrapKey => {
+                self.object_params()?;
+                self.capabilities("delegated_capabilities")?;
+            }
+            ChangeAuthenticationKey => {
+                self.object_id("key_id")?;
+                self.algorithm("algorithm")?;
+                self.secret("key");
+            }
+            SignPkcs1 | SignEcdsa => {
+                self.object_id("key_id")?;
+                self.data("digest");
+            }
+            SignPss => {
+                self.object_id("key_id")?;
+                self.algorithm("mgf1_hash_alg")?;
+                self.number("salt_len", 2)?;
+                self.data("digest");
+            }
+            SignEddsa | SignHmac | VerifyHmac => {
+                self.object_id("key_id")?;
+                self.data("data");
+            }
+            DecryptPkcs1 | UnwrapData => {
+                self.object_id("key_id")?;
+                self.data("ciphertext");
+            }
+            DecryptOaep => {
+                self.object_id("key_id")?;
+                self.algorithm("mgf1_hash_alg")?;
+                self.data("ciphertext");
+            }
+            DeriveEcdh => {
+                self.object_id("key_id")?;
+                self.data("public_key");
+            }
+            ExportWrapped => {
+                self.object_id("wrap_key_id")?;
+                self.object_type("object_type")?;
+                self.object_id("object_id")?;
+            }
+            ImportWrapped => {
+                self.object_id("wrap_key_id")?;
+                self.bytes("nonce", wrap::nonce::SIZE)?;
+                self.data("ciphertext");
+            }
+            WrapData | CreateOtpAead => {
+                self.object_id("key_id")?;
+                self.secret("plaintext");
+            }
+            DecryptOtp => {
+                self.object_id("key_id")?;
+                self.data("aead");
+            }
+            RewrapOtpAead => {
+                self.object_id("key_id")?;
+                self.object_id("new_key_id")?;
+                self.data("aead");
+            }
+            SignAttestationCertificate => {
+                self.object_id("key_id")?;
+                self.object_id("attestation_key_id")?;
+            }
+            SignSshCertificate => {
+                self.object_id("key_id")?;
+                self.object_id("template_id")?;
+                self.algorithm("algorithm")?;
+                self.number("timestamp", 4)?;
+                self.bytes("signature", SSH_REQUEST_SIGNATURE_SIZE)?;
+                self.data("request");
+            }
+            _ => self.data("data"),
+        }
+
+        Some(())
+    }
+
+    /// Decode the data of a successful response
+    fn response(&mut self, code: command::Code) -> Option<()> {
+        use command::Code::*;
+
+        match code {
+            CreateSession => {
+                self.bytes("card_challenge", CHALLENGE_SIZE)?;
+                self.bytes("card_cryptogram", CHALLENGE_SIZE)?;
+            }
+            SessionMessage => self.data("ciphertext"),
+            DeviceInfo => {
+                self.number("major_version", 1)?;
+                self.number("minor_version", 1)?;
+                self.number("build_version", 1)?;
+                self.number("serial_number", 4)?;
+                self.number("log_store_capacity", 1)?;
+                self.number("log_store_used", 1)?;
+                self.data("algorithms");
+            }
+            GetStorageInfo => {
+                self.number("total_records", 2)?;
+                self.number("free_records", 2)?;
+                self.number("total_pages", 2)?;
+                self.number("free_pages", 2)?;
+                self.number("page_size", 2)?;
+            }
+            PutOpaqueObject
+            | PutTemplate
+            | PutAsymmetricKey
+            | PutHmacKey
+            | PutOtpAead
+            | PutAuthenticationKey
+            | PutWrapKey
+            | GenerateAsymmetricKey
+            | GenerateHmacKey
+            | GenerateOtpAead
+            | GenerateWrapKey
+            | ChangeAuthenticationKey
+            | DeleteObject => self.object_id("object_id")?,
+            ImportWrapped => {
+                self.object_type("object_type")?;
+                self.object_id("object_id")?;
+            }
+            GetObjectInfo => {
+                self.capabilities("capabilities")?;
+                self.object_id("object_id")?;
+                self.number("length", 2)?;
+                self.domains("domains")?;
+                self.object_type("object_type")?;
+                self.algorithm("algorithm")?;
+                self.number("sequence", 1)?;
+                self.number("origin", 1)?;
+                self.label("label")?;
+                self.capabilities("delegated_capabilities")?;
+            }
+            GetPublicKey => {
+                self.algorithm("algorithm")?;
+                self.data("public_key");
+            }
+            GetLogEntries => {
+                self.number("unlogged_boot_events", 2)?;
+                self.number("unlogged_auth_events", 2)?;
+                self.number("num_entries", 1)?;
+                self.data("entries");
+            }
+            SignPkcs1 | SignPss | SignEcdsa | SignEddsa | SignHmac | SignSshCertificate => {
+                self.data("signature")
+            }
+            VerifyHmac => self.number("verified", 1)?,
+            DecryptPkcs1 | DecryptOaep | UnwrapData | DecryptOtp => self.secret("plaintext"),
+            DeriveEcdh => self.secret("shared_secret"),
+            GetPseudoRandom => self.secret("random"),
+            ExportWrapped | WrapData => self.data("ciphertext"),
+            CreateOtpAead | RandomizeOtpAead | RewrapOtpAead => self.data("aead"),
+            SignAttestationCertificate => self.data("certificate"),
+            ListObjects => self.data("objects"),
+            GetOption => self.data("value"),
+            _ => self.data("data"),
+        }
+
+        Some(())
+    }
+
+    /// Decode the parameters common to commands which create objects
+    fn object_params(&mut self) -> Option<()> {
+        self.object_id("object_id")?;
+        self.label("label")?;
+        self.domains("domains")?;
+        self.capabilities("capabilities")?;
+        self.algorithm("algorithm")
+    }
+
+    /// Decode an object ID
+    fn object_id(&mut self, name: &'static str) -> Option<()> {
+        let id = self.read_uint(2)? as object::Id;
+        self.push(name, Value::ObjectId(id));
+        Some(())
+    }
+
+    /// Decode an object type
+    fn object_type(&mut self, name: &'static str) -> Option<()> {
+        let byte = self.read_uint(1)? as u8;
+
+        match object::Type::from_u8(byte) {
+            Ok(object_type) => self.push(name, Value::ObjectType(object_type)),
+            Err(_) => self.push(name, Value::Number(byte.into())),
+        }
+
+        Some(())
+    }
+
+    /// Decode an algorithm
+    fn algorithm(&mut self, name: &'static str) -> Option<()> {
+        let byte = self.read_uint(1)? as u8;
+
+        match Algorithm::from_u8(byte) {
+            Ok(algorithm) => self.push(name, Value::Algorithm(algorithm)),
+            Err(_) => self.push(name, Value::Number(byte.into())),
+        }
+
+        Some(())
+    }
+
+    /// Decode a set of capabilities
+    fn capabilities(&mut self, name: &'static str) -> Option<()> {
+        let capabilities = Capability::from_bits_retain(self.read_uint(8)?);
+        self.push(name, Value::Capabilities(capabilities));
+        Some(())
+    }
+
+    /// Decode a set of domains
+    fn domains(&mut self, name: &'static str) -> Option<()> {
+        let domains = Domain::from_bits_retain(self.read_uint(2)? as u16);
+        self.push(name, Value::Domains(domains));
+        Some(())
+    }
+
+    /// Decode an object label
+    fn label(&mut self, name: &'static str) -> Option<()> {
+        let label = object::Label::from_bytes(self.read(LABEL_SIZE)?).ok()?;
+        self.push(name, Value::Label(label.to_string()));
+        Some(())
+    }
+
+    /// Decode a big endian integer of the given size
+    fn number(&mut self, name: &'static str, size: usize) -> Option<()> {
+        let n = self.read_uint(size)?;
+        self.push(name, Value::Number(n));
+        Some(())
+    }
+
+    /// Decode a fixed-size field which isn't decoded any further
+    fn bytes(&mut self, name: &'static str, size: usize) -> Option<()> {
+        self.read(size)?;
+        self.push(name, Value::Data(size));
+        Some(())
+    }
+
+    /// Decode the rest of the data as a field which isn't decoded any further
+    fn data(&mut self, name: &'static str) {
+        let size = self.data.len();
+        self.data = &[];
+        self.push(name, Value::Data(size));
+    }
+
+    /// Decode the rest of the data as a secret
+    fn secret(&mut self, name: &'static str) {
+        let size = self.data.len();
+        self.data = &[];
+        self.push(name, Value::Redacted(size));
+    }
+
+    /// Add a decoded field
+    fn push(&mut self, name: &'static str, value: Value) {
+        self.fields.push(Field { name, value });
+    }
+
+    /// Read a big endian integer of the given size
+    fn read_uint(&mut self, size: usize) -> Option<u64> {
+        let bytes = self.read(size)?;
+        Some(bytes.iter().fold(0, |n, &byte| (n << 8) | u64::from(byte)))
+    }
+
+    /// Read the given number of bytes
+    fn read(&mut self, size: usize) -> Option<&'a [u8]> {
+        if self.data.len() < size {
+            return None;
+        }
+
+        let (bytes, rest) = self.data.split_at(size);
+        self.data = rest;
+        Some(bytes)
+    }
+
+    /// Finish decoding, noting any data which couldn't be decoded
+    fn finish(self) -> Vec<Field> {
+        let mut fields = self.fields;
+
+        if !self.data.is_empty() {
+            fields.push(Field {
+                name: "undecoded",
+                value: Value::Data(self.data.len()),
+            });
+        }
+
+        fields
+    }
+}
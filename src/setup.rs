@@ -1,12 +1,21 @@
 //! Initial YubiHSM 2 setup functionality using declarative device profiles.
 
+mod ceremony;
+mod config;
 mod error;
+mod objects;
+mod options;
+mod plan;
 mod profile;
 pub mod report;
 mod role;
 
 pub use self::{
+    ceremony::{Share, SplitWrapKey},
     error::{Error, ErrorKind},
+    objects::Object,
+    options::Setting,
+    plan::{Change, Difference, Plan},
     profile::Profile,
     report::Report,
     role::Role,
@@ -44,6 +53,9 @@ pub fn init_with_profile(client: Client, profile: Profile) -> Result<Report, Err
         .setup_auth_key_id
         .ok_or_else(|| format_err!(ErrorKind::SetupFailed, "profile setup_auth_key_id unset!"))?;
 
+    // Refuse profiles whose report won't fit before touching the device
+    profile.check_report_size(&client)?;
+
     let temp_auth_key = authentication::Key::random();
 
     client
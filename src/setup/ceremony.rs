@@ -0,0 +1,377 @@
+//! Key ceremonies: wrap keys split into Shamir shares held by custodians.
+//!
+//! A [`SplitWrapKey`] is a wrap key together with `N` [`Share`]s, any `M` of
+//! which recover the key, e.g. to import it into another HSM so backups made
+//! with it can be restored there. Shares have a printable encoding with a
+//! checksum, so they can be written down and typed back in:
+//!
+//! ```text
+//! 0001-2-1-5d9b0c...-8f3a41e2
+//! ```
+//!
+//! i.e. the wrap key ID (hex), the threshold, the index of the share, the
+//! share itself (hex) and a checksum of everything before it.
+
+use super::{report, Error, ErrorKind};
+use crate::{object, wrap, Capability, Domain};
+use rand_core::RngCore;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::BTreeSet,
+    fmt::{self, Debug, Write},
+    str::FromStr,
+};
+use zeroize::Zeroizing;
+
+/// Length of the share checksum in bytes
+const CHECKSUM_LEN: usize = 4;
+
+/// Wrap key split into shares for custodians
+#[derive(Clone)]
+pub struct SplitWrapKey {
+    /// The wrap key
+    pub(super) key: wrap::Key,
+
+    /// Number of shares needed to recover the key
+    threshold: u8,
+
+    /// Shares of the key
+    shares: Vec<Share>,
+}
+
+impl SplitWrapKey {
+    /// Generate a random wrap key and split it into `shares` shares, any
+    /// `threshold` of which recover it
+    pub fn generate(
+        key_id: object::Id,
+        algorithm: wrap::Algorithm,
+        threshold: u8,
+        shares: u8,
+    ) -> Result<Self, Error> {
+        Self::split(
+            wrap::Key::generate_random(key_id, algorithm),
+            threshold,
+            shares,
+        )
+    }
+
+    /// Split an existing wrap key into `shares` shares, any `threshold` of
+    /// which recover it
+    pub fn split(key: wrap::Key, threshold: u8, shares: u8) -> Result<Self, Error> {
+        ensure!(
+            threshold >= 1 && threshold <= shares,
+            ErrorKind::SetupFailed,
+            "invalid threshold: {} of {} shares",
+            threshold,
+            shares
+        );
+
+        let key_id = key.import_params.id;
+        let mut rng = rand::rng();
+        let mut shares = (1..=shares)
+            .map(|index| Share {
+                key_id,
+                threshold,
+                index,
+                data: Zeroizing::new(vec![0; key.data.len()]),
+            })
+            .collect::<Vec<_>>();
+
+        // Each byte of the key is the constant term of a random polynomial
+        // of degree `threshold - 1`, evaluated at each share's index
+        let mut coefficients = Zeroizing::new(vec![0u8; usize::from(threshold)]);
+
+        for (i, &byte) in key.data.iter().enumerate() {
+            coefficients[0] = byte;
+            rng.fill_bytes(&mut coefficients[1..]);
+
+            for share in &mut shares {
+                share.data[i] = coefficients
+                    .iter()
+                    .rev()
+                    .fold(0, |acc, &c| gf256::mul(acc, share.index) ^ c);
+            }
+        }
+
+        Ok(Self {
+            key,
+            threshold,
+            shares,
+        })
+    }
+
+    /// Recover a wrap key from (at least `threshold` of) its shares.
+    ///
+    /// Shares only record the wrap key's ID: its label, capabilities and
+    /// domains need to be set on the returned key before importing it.
+    pub fn recover(shares: &[Share]) -> Result<wrap::Key, Error> {
+        let first = shares
+            .first()
+            .ok_or_else(|| format_err!(ErrorKind::SetupFailed, "no shares given"))?;
+
+        let mut indexes = BTreeSet::new();
+
+        for share in shares {
+            ensure!(
+                share.key_id == first.key_id
+                    && share.threshold == first.threshold
+                    && share.data.len() == first.data.len(),
+                ErrorKind::SetupFailed,
+                "share {} doesn't belong to the same key as share {}",
+                share.index,
+                first.index
+            );
+
+            ensure!(
+                indexes.insert(share.index),
+                ErrorKind::SetupFailed,
+                "share {} given more than once",
+                share.index
+            );
+        }
+
+        ensure!(
+            shares.len() >= usize::from(first.threshold),
+            ErrorKind::SetupFailed,
+            "{} shares are needed to recover wrap key 0x{:04x} (got {})",
+            first.threshold,
+            first.key_id,
+            shares.len()
+        );
+
+        // Interpolate each byte's polynomial at zero
+        let shares = &shares[..usize::from(first.threshold)];
+        let mut data = Zeroizing::new(vec![0u8; first.data.len()]);
+
+        for share in shares {
+            let basis = shares
+                .iter()
+                .filter(|other| other.index != share.index)
+                .fold(1, |acc, other| {
+                    gf256::mul(acc, gf256::div(other.index, other.index ^ share.index))
+                });
+
+            for (byte, &y) in data.iter_mut().zip(share.data.iter()) {
+                *byte ^= gf256::mul(basis, y);
+            }
+        }
+
+        wrap::Key::from_bytes(first.key_id, &data).map_err(|e| {
+            format_err!(
+                ErrorKind::SetupFailed,
+                "error recovering wrap key 0x{:04x}: {}",
+                first.key_id,
+                e
+            )
+            .into()
+        })
+    }
+
+    /// Set the label of the wrap key
+    pub fn label(mut self, label: object::Label) -> Self {
+        self.key = self.key.label(label);
+        self
+    }
+
+    /// Set the domains the wrap key can be used in
+    pub fn domains(mut self, domains: Domain) -> Self {
+        self.key = self.key.domains(domains);
+        self
+    }
+
+    /// Set the capabilities of the wrap key
+    pub fn capabilities(mut self, capabilities: Capability) -> Self {
+        self.key = self.key.capabilities(capabilities);
+        self
+    }
+
+    /// Set the delegated capabilities of the wrap key
+    pub fn delegated_capabilities(mut self, capabilities: Capability) -> Self {
+        self.key = self.key.delegated_capabilities(capabilities);
+        self
+    }
+
+    /// The wrap key
+    pub fn key(&self) -> &wrap::Key {
+        &self.key
+    }
+
+    /// Number of shares needed to recover the key
+    pub fn threshold(&self) -> u8 {
+        self.threshold
+    }
+
+    /// Shares of the key, to hand out to custodians
+    pub fn shares(&self) -> &[Share] {
+        &self.shares
+    }
+
+    /// Describe how this key was split for the provisioning report
+    pub(super) fn report(&self) -> report::SplitWrapKey {
+        report::SplitWrapKey {
+            wrap_key_id: self.key.import_params.id,
+            label: self.key.import_params.label.to_string(),
+            threshold: self.threshold,
+            shares: self.shares.len() as u8,
+        }
+    }
+}
+
+impl Debug for SplitWrapKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SplitWrapKey")
+            .field("key", &self.key)
+            .field("threshold", &self.threshold)
+            .field("shares", &self.shares.len())
+            .finish()
+    }
+}
+
+/// Share of a wrap key
+#[derive(Clone)]
+pub struct Share {
+    /// ID of the wrap key this is a share of
+    key_id: object::Id,
+
+    /// Number of shares needed to recover the key
+    threshold: u8,
+
+    /// Index of this share (the point the key's polynomials are evaluated at)
+    index: u8,
+
+    /// Share data
+    data: Zeroizing<Vec<u8>>,
+}
+
+impl Share {
+    /// ID of the wrap key this is a share of
+    pub fn key_id(&self) -> object::Id {
+        self.key_id
+    }
+
+    /// Number of shares needed to recover the key
+    pub fn threshold(&self) -> u8 {
+        self.threshold
+    }
+
+    /// Index of this share, starting at 1
+    pub fn index(&self) -> u8 {
+        self.index
+    }
+
+    /// Encode this share in its printable form
+    pub fn encode(&self) -> Zeroizing<String> {
+        let mut encoded = Zeroizing::new(format!(
+            "{:04x}-{}-{}-",
+            self.key_id, self.threshold, self.index
+        ));
+
+        for byte in self.data.iter() {
+            write!(encoded, "{byte:02x}").unwrap();
+        }
+
+        let checksum = checksum(&encoded);
+        write!(encoded, "-{checksum}").unwrap();
+        encoded
+    }
+}
+
+impl FromStr for Share {
+    type Err = Error;
+
+    /// Decode a share from its printable form (ignoring whitespace)
+    fn from_str(s: &str) -> Result<Self, Error> {
+        let encoded = Zeroizing::new(
+            s.chars()
+                .filter(|c| !c.is_whitespace())
+                .collect::<String>()
+                .to_ascii_lowercase(),
+        );
+
+        let invalid = || format_err!(ErrorKind::SetupFailed, "malformed share");
+
+        let (body, checksum_hex) = encoded.rsplit_once('-').ok_or_else(invalid)?;
+
+        ensure!(
+            checksum_hex == checksum(body),
+            ErrorKind::SetupFailed,
+            "share checksum mismatch (mistyped share?)"
+        );
+
+        let fields = body.split('-').collect::<Vec<_>>();
+
+        let [key_id, threshold, index, data_hex] = fields[..] else {
+            return Err(invalid().into());
+        };
+
+        let key_id = u16::from_str_radix(key_id, 16).map_err(|_| invalid())?;
+        let threshold = threshold.parse::<u8>().map_err(|_| invalid())?;
+        let index = index.parse::<u8>().map_err(|_| invalid())?;
+
+        if threshold == 0 || index == 0 || data_hex.len() % 2 != 0 {
+            return Err(invalid().into());
+        }
+
+        let data = (0..data_hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(data_hex.get(i..i + 2)?, 16).ok())
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(invalid)?;
+
+        Ok(Share {
+            key_id,
+            threshold,
+            index,
+            data: Zeroizing::new(data),
+        })
+    }
+}
+
+impl Debug for Share {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Avoid leaking shares in debug messages
+        f.debug_struct("Share")
+            .field("key_id", &self.key_id)
+            .field("threshold", &self.threshold)
+            .field("index", &self.index)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Checksum of the encoded share (everything before the checksum)
+fn checksum(encoded: &str) -> String {
+    Sha256::digest(encoded.as_bytes())[..CHECKSUM_LEN]
+        .iter()
+        .fold(String::new(), |mut hex, byte| {
+            write!(hex, "{byte:02x}").unwrap();
+            hex
+        })
+}
+
+/// Arithmetic in GF(2^8), using the AES polynomial
+mod gf256 {
+    /// Multiply two field elements
+    pub(super) fn mul(mut a: u8, mut b: u8) -> u8 {
+        let mut product = 0;
+
+        for _ in 0..8 {
+            product ^= a & 0u8.wrapping_sub(b & 1);
+            a = (a << 1) ^ (0x1b & 0u8.wrapping_sub(a >> 7));
+            b >>= 1;
+        }
+
+        product
+    }
+
+    /// Divide two field elements (`b` must be non-zero)
+    pub(super) fn div(a: u8, b: u8) -> u8 {
+        // b^254 is the inverse of b
+        let mut inverse = 1;
+
+        for _ in 0..254 {
+            inverse = mul(inverse, b);
+        }
+
+        mul(a, inverse)
+    }
+}
@@ -0,0 +1,979 @@
+//! Declarative provisioning profiles loaded from TOML or JSON files.
+//!
+//! Example profile:
+//!
+//! ```toml
+//! audit = "on"
+//! report_object_id = 0xfffe
+//! report_signing_key_id = 0x101
+//!
+//! [command_audit]
+//! sign-ecdsa = "on"
+//! put-authentication-key = "fix"
+//!
+//! [[roles]]
+//! authentication_key_id = 2
+//! label = "admin"
+//! credentials = { password_env = "YUBIHSM_ADMIN_PASSWORD" }
+//! capabilities = "all"
+//! delegated_capabilities = "all"
+//! domains = "all"
+//!
+//! [[roles]]
+//! authentication_key_id = 3
+//! label = "signer"
+//! credentials = { key_env = "YUBIHSM_SIGNER_KEY" }
+//! capabilities = ["sign-ecdsa", "sign-eddsa"]
+//! domains = [1, 2]
+//!
+//! [[wrap_keys]]
+//! id = 1
+//! label = "backup"
+//! key = { key_env = "YUBIHSM_BACKUP_WRAP_KEY" }
+//! capabilities = "export-wrapped,import-wrapped"
+//! delegated_capabilities = "all"
+//! domains = "all"
+//!
+//! [[objects]]
+//! type = "asymmetric-key"
+//! id = 0x100
+//! label = "signing key"
+//! algorithm = "ed25519"
+//! capabilities = ["sign-eddsa"]
+//! domains = [1]
+//! attest = true
+//!
+//! [[objects]]
+//! type = "asymmetric-key"
+//! id = 0x101
+//! label = "report signing key"
+//! algorithm = "ecp256"
+//! capabilities = ["sign-ecdsa"]
+//! domains = [1]
+//!
+//! [[objects]]
+//! type = "opaque"
+//! id = 0x100
+//! label = "signing key certificate"
+//! algorithm = "opaque-x509-certificate"
+//! data_file = "signing-key.der"
+//! capabilities = ["get-opaque"]
+//! domains = [1]
+//! ```
+//!
+//! Capabilities and domains are either lists or strings in yubihsm-shell's
+//! syntax, e.g. `"sign-ecdsa,exportable-under-wrap"`, `"1,2,5"` or `"all"`.
+//!
+//! Asymmetric and HMAC keys are generated within the device unless a `key`
+//! to import is given. Opaque data is read from `data_file`, relative to the
+//! profile's directory.
+//!
+//! Secrets are never stored in profiles directly: credentials are either
+//! passwords or hex-encoded keys, read from the named environment variables
+//! when the profile is loaded.
+//!
+//! Authentication keys are derived from passwords the same way as
+//! yubihsm-shell does (PBKDF2 with a static salt) unless a role gives a
+//! stronger `kdf`, e.g. `kdf = { algorithm = "argon2id" }` (`argon2` cargo
+//! feature) or `kdf = { algorithm = "scrypt", log_n = 15 }` (`scrypt` cargo
+//! feature). Unless a `salt` (hex) is given, a random one is generated. The
+//! parameters are recorded in the provisioning report, as they're needed to
+//! derive the key from the password again.
+
+use super::{
+    options,
+    profile::{DEFAULT_REPORT_OBJECT_ID, DEFAULT_SETUP_KEY_ID},
+    Error, ErrorKind, Object, Profile, Role,
+};
+use crate::{
+    audit::AuditCommand,
+    authentication::{self, Kdf},
+    command, object, wrap, Algorithm, AuditOption, Capability, Credentials, Domain,
+};
+use serde::{de, Deserialize, Deserializer};
+use serde_json::value::RawValue;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    env,
+    fmt::Display,
+    fs,
+    ops::Range,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use zeroize::Zeroizing;
+
+#[cfg(any(feature = "argon2", feature = "scrypt"))]
+use crate::authentication::kdf::Salt;
+
+/// Default time to wait for the device to reset (in seconds)
+const DEFAULT_RESET_DEVICE_TIMEOUT_SECS: u64 = 10;
+
+/// Profile as it appears in a configuration file
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(super) struct ProfileConfig {
+    /// Key ID to use for provisioning the device
+    #[serde(default = "default_setup_auth_key_id")]
+    setup_auth_key_id: object::Id,
+
+    /// Should the setup auth key be deleted when provisioning is complete?
+    #[serde(default = "default_true")]
+    delete_setup_auth_key: bool,
+
+    /// Auditing mode to configure the device with
+    #[serde(default = "default_audit_option", deserialize_with = "audit_option")]
+    audit: AuditOption,
+
+    /// Auditing options for particular commands, keyed by command name
+    #[serde(default, deserialize_with = "command_audit_options")]
+    command_audit: Vec<AuditCommand>,
+
+    /// FIPS mode to configure the device with
+    #[serde(default, deserialize_with = "optional_audit_option")]
+    fips: Option<AuditOption>,
+
+    /// Store the provisioning report in the device?
+    #[serde(default = "default_true")]
+    store_report: bool,
+
+    /// Object ID to store the provisioning report in
+    #[serde(default = "default_report_object_id")]
+    report_object_id: object::Id,
+
+    /// ECDSA (NIST P-256) key to sign the provisioning report with
+    #[serde(default)]
+    report_signing_key_id: Option<object::Id>,
+
+    /// How long to wait for the device to reset (in seconds)
+    #[serde(default = "default_reset_device_timeout")]
+    reset_device_timeout: u64,
+
+    /// Roles to create on the device
+    #[serde(default)]
+    roles: Vec<RoleConfig>,
+
+    /// Wrap keys to provision the device with
+    #[serde(default)]
+    wrap_keys: Vec<WrapKeyConfig>,
+
+    /// Asymmetric keys, HMAC keys and opaque objects to create
+    #[serde(default)]
+    objects: Vec<ObjectConfig>,
+}
+
+impl ProfileConfig {
+    /// Check the profile as a whole and build a `Profile` from it, reading
+    /// files relative to the given directory
+    fn into_profile(self, dir: &Path) -> Result<Profile, Invalid> {
+        let config = self;
+        let mut auth_key_ids = BTreeSet::from([config.setup_auth_key_id]);
+
+        for (index, role) in config.roles.iter().enumerate() {
+            if !auth_key_ids.insert(role.authentication_key_id) {
+                return Err(Invalid::new(
+                    ("roles", index, Some("authentication_key_id")),
+                    format!(
+                        "duplicate authentication key ID: 0x{:04x}",
+                        role.authentication_key_id
+                    ),
+                ));
+            }
+        }
+
+        let mut wrap_key_ids = BTreeSet::new();
+
+        for (index, wrap_key) in config.wrap_keys.iter().enumerate() {
+            if !wrap_key_ids.insert(wrap_key.id) {
+                return Err(Invalid::new(
+                    ("wrap_keys", index, Some("id")),
+                    format!("duplicate wrap key ID: 0x{:04x}", wrap_key.id),
+                ));
+            }
+        }
+
+        let roles = config
+            .roles
+            .into_iter()
+            .enumerate()
+            .map(|(index, role)| {
+                let key_id = role.authentication_key_id;
+                let at = ("roles", index, Some("kdf"));
+
+                let role_for_key = match (role.credentials, role.kdf) {
+                    (RoleCredentials::Password(password), Some(kdf)) => {
+                        Role::from_password(key_id, &password, kdf.into_kdf())
+                            .map_err(|e| Invalid::new(at, format!("role 0x{key_id:04x}: {e}")))?
+                    }
+                    (RoleCredentials::Password(password), None) => {
+                        Role::new(Credentials::from_password(key_id, &password))
+                    }
+                    (RoleCredentials::Key(key), None) => Role::new(Credentials::new(key_id, key)),
+                    (RoleCredentials::Key(_), Some(_)) => {
+                        return Err(Invalid::new(
+                            at,
+                            format!(
+                                "role 0x{key_id:04x}: kdf only applies to password credentials"
+                            ),
+                        ))
+                    }
+                };
+
+                Ok(role_for_key
+                    .authentication_key_label(role.label)
+                    .capabilities(role.capabilities)
+                    .delegated_capabilities(role.delegated_capabilities)
+                    .domains(role.domains))
+            })
+            .collect::<Result<Vec<_>, Invalid>>()?;
+
+        let wrap_keys = config
+            .wrap_keys
+            .into_iter()
+            .enumerate()
+            .map(|(index, wrap_key)| {
+                Ok(wrap::Key::from_bytes(wrap_key.id, &wrap_key.key)
+                    .map_err(|e| Invalid::new(("wrap_keys", index, Some("key")), e.to_string()))?
+                    .label(wrap_key.label)
+                    .capabilities(wrap_key.capabilities)
+                    .delegated_capabilities(wrap_key.delegated_capabilities)
+                    .domains(wrap_key.domains))
+            })
+            .collect::<Result<Vec<_>, Invalid>>()?;
+
+        let mut object_handles = BTreeSet::new();
+
+        for (index, object) in config.objects.iter().enumerate() {
+            if !object_handles.insert((object.object_type, object.id)) {
+                return Err(Invalid::new(
+                    ("objects", index, Some("id")),
+                    format!("duplicate {} ID: 0x{:04x}", object.object_type, object.id),
+                ));
+            }
+        }
+
+        let objects = config
+            .objects
+            .into_iter()
+            .enumerate()
+            .map(|(index, object)| {
+                object
+                    .into_object(dir)
+                    .map_err(|(field, message)| Invalid::new(("objects", index, field), message))
+            })
+            .collect::<Result<Vec<_>, Invalid>>()?;
+
+        let mut profile = Profile::new();
+
+        if let Some(fips) = config.fips {
+            profile = profile.fips_option(fips);
+        }
+
+        Ok(profile
+            .setup_auth_key_id(Some(config.setup_auth_key_id))
+            .delete_setup_auth_key(config.delete_setup_auth_key)
+            .audit_option(config.audit)
+            .command_audit_options(config.command_audit)
+            .report_object_id(Some(config.report_object_id).filter(|_| config.store_report))
+            .report_signing_key_id(config.report_signing_key_id)
+            .reset_device_timeout(Duration::from_secs(config.reset_device_timeout))
+            .roles(roles)
+            .wrap_keys(wrap_keys)
+            .objects(objects))
+    }
+}
+
+/// Entry in one of a profile's lists (`roles`, `wrap_keys` or `objects`),
+/// by its index, along with one of the entry's fields
+type Location = (&'static str, usize, Option<&'static str>);
+
+/// Error found when checking a profile as a whole, which is located in the
+/// profile's source after it's been deserialized
+struct Invalid {
+    /// Entry (and field) the error was found in
+    location: Location,
+
+    /// Error message
+    message: String,
+}
+
+impl Invalid {
+    /// Create a new error found in the given entry
+    fn new(location: Location, message: String) -> Self {
+        Self { location, message }
+    }
+}
+
+/// Spans of the entries in a profile's lists, and of their fields, for
+/// locating `Invalid` errors in TOML profiles
+#[derive(Deserialize)]
+struct Spans {
+    /// Roles and their fields
+    #[serde(default)]
+    roles: Vec<EntrySpans>,
+
+    /// Wrap keys and their fields
+    #[serde(default)]
+    wrap_keys: Vec<EntrySpans>,
+
+    /// Objects and their fields
+    #[serde(default)]
+    objects: Vec<EntrySpans>,
+}
+
+/// Span of an entry in one of a profile's lists, and of each of its fields
+type EntrySpans = toml::Spanned<BTreeMap<String, toml::Spanned<de::IgnoredAny>>>;
+
+impl Spans {
+    /// Find the span of the given entry's field, or of the whole entry if
+    /// the field isn't given
+    fn find(source: &str, (list, index, field): Location) -> Option<Range<usize>> {
+        let spans = toml::from_str::<Spans>(source).ok()?;
+
+        let entry = match list {
+            "roles" => spans.roles.into_iter().nth(index),
+            "wrap_keys" => spans.wrap_keys.into_iter().nth(index),
+            "objects" => spans.objects.into_iter().nth(index),
+            _ => None,
+        }?;
+
+        Some(
+            field
+                .and_then(|field| entry.get_ref().get(field))
+                .map_or_else(|| entry.span(), toml::Spanned::span),
+        )
+    }
+}
+
+/// Entries in a profile's lists as raw JSON, for locating `Invalid` errors
+/// in JSON profiles
+#[derive(Deserialize)]
+struct JsonSpans<'a> {
+    /// Roles
+    #[serde(default, borrow)]
+    roles: Vec<&'a RawValue>,
+
+    /// Wrap keys
+    #[serde(default, borrow)]
+    wrap_keys: Vec<&'a RawValue>,
+
+    /// Objects
+    #[serde(default, borrow)]
+    objects: Vec<&'a RawValue>,
+}
+
+impl JsonSpans<'_> {
+    /// Find the span of the given entry's field, or of the whole entry if
+    /// the field isn't given
+    fn find(source: &str, (list, index, field): Location) -> Option<Range<usize>> {
+        let spans = serde_json::from_str::<JsonSpans<'_>>(source).ok()?;
+
+        let entry = match list {
+            "roles" => spans.roles.into_iter().nth(index),
+            "wrap_keys" => spans.wrap_keys.into_iter().nth(index),
+            "objects" => spans.objects.into_iter().nth(index),
+            _ => None,
+        }?;
+
+        // Raw values borrow from the source, so their offset in it is the
+        // distance between the two
+        let value = field
+            .and_then(|field| {
+                serde_json::from_str::<BTreeMap<String, &RawValue>>(entry.get())
+                    .ok()?
+                    .remove(field)
+            })
+            .unwrap_or(entry)
+            .get();
+
+        let start = value.as_ptr() as usize - source.as_ptr() as usize;
+        Some(start..start + value.len())
+    }
+}
+
+/// Role as it appears in a configuration file
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RoleConfig {
+    /// Object ID of the role's authentication key
+    authentication_key_id: object::Id,
+
+    /// Label to place on the role's authentication key
+    #[serde(default, deserialize_with = "label")]
+    label: object::Label,
+
+    /// Source of the role's authentication key
+    #[serde(deserialize_with = "role_credentials")]
+    credentials: RoleCredentials,
+
+    /// Key derivation function for password credentials (PBKDF2, as used
+    /// by yubihsm-shell, if absent)
+    #[serde(default)]
+    kdf: Option<KdfConfig>,
+
+    /// Capabilities of the role
+    #[serde(default, deserialize_with = "capabilities")]
+    capabilities: Capability,
+
+    /// Capabilities the role may set on objects it creates
+    #[serde(default, deserialize_with = "capabilities")]
+    delegated_capabilities: Capability,
+
+    /// Domains the role has access to
+    #[serde(default = "Domain::empty", deserialize_with = "domains")]
+    domains: Domain,
+}
+
+/// Role credentials, read from their source
+enum RoleCredentials {
+    /// Password to derive the authentication key from
+    Password(Zeroizing<Vec<u8>>),
+
+    /// Authentication key
+    Key(authentication::Key),
+}
+
+/// Password-based key derivation function as it appears in a configuration
+/// file, e.g. `kdf = { algorithm = "argon2id" }`. Parameters which aren't
+/// given take their recommended values, and a random salt is generated if
+/// none is given (it's recorded in the provisioning report).
+#[derive(Deserialize)]
+#[serde(tag = "algorithm", rename_all = "kebab-case", deny_unknown_fields)]
+enum KdfConfig {
+    /// PBKDF2 with yubihsm-shell's static salt
+    Pbkdf2,
+
+    /// Argon2id
+    #[cfg(feature = "argon2")]
+    Argon2id {
+        /// Salt (hex)
+        salt: Option<Salt>,
+
+        /// Memory to use in KiB
+        memory_kib: Option<u32>,
+
+        /// Number of iterations
+        iterations: Option<u32>,
+
+        /// Degree of parallelism
+        parallelism: Option<u32>,
+    },
+
+    /// scrypt
+    #[cfg(feature = "scrypt")]
+    Scrypt {
+        /// Salt (hex)
+        salt: Option<Salt>,
+
+        /// Base-2 logarithm of the CPU/memory cost parameter `N`
+        log_n: Option<u8>,
+
+        /// Block size
+        r: Option<u32>,
+
+        /// Degree of parallelism
+        p: Option<u32>,
+    },
+}
+
+impl KdfConfig {
+    /// Fill in any missing parameters
+    fn into_kdf(self) -> Kdf {
+        match self {
+            KdfConfig::Pbkdf2 => Kdf::Pbkdf2,
+            #[cfg(feature = "argon2")]
+            KdfConfig::Argon2id {
+                salt,
+                memory_kib,
+                iterations,
+                parallelism,
+            } => Kdf::Argon2id {
+                salt: salt.unwrap_or_else(Salt::random),
+                memory_kib: memory_kib.unwrap_or(argon2::Params::DEFAULT_M_COST),
+                iterations: iterations.unwrap_or(argon2::Params::DEFAULT_T_COST),
+                parallelism: parallelism.unwrap_or(argon2::Params::DEFAULT_P_COST),
+            },
+            #[cfg(feature = "scrypt")]
+            KdfConfig::Scrypt { salt, log_n, r, p } => Kdf::Scrypt {
+                salt: salt.unwrap_or_else(Salt::random),
+                log_n: log_n.unwrap_or(scrypt::Params::RECOMMENDED_LOG_N),
+                r: r.unwrap_or(scrypt::Params::RECOMMENDED_R),
+                p: p.unwrap_or(scrypt::Params::RECOMMENDED_P),
+            },
+        }
+    }
+}
+
+/// Wrap key as it appears in a configuration file
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct WrapKeyConfig {
+    /// Object ID of the wrap key
+    id: object::Id,
+
+    /// Label to place on the wrap key
+    #[serde(default, deserialize_with = "label")]
+    label: object::Label,
+
+    /// Source of the wrap key
+    #[serde(deserialize_with = "wrap_key")]
+    key: Zeroizing<Vec<u8>>,
+
+    /// Capabilities of the wrap key
+    #[serde(default, deserialize_with = "capabilities")]
+    capabilities: Capability,
+
+    /// Capabilities the wrap key may set on objects it imports
+    #[serde(default, deserialize_with = "capabilities")]
+    delegated_capabilities: Capability,
+
+    /// Domains the wrap key can be used in
+    #[serde(default = "Domain::empty", deserialize_with = "domains")]
+    domains: Domain,
+}
+
+/// Asymmetric key, HMAC key or opaque object as it appears in a
+/// configuration file
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ObjectConfig {
+    /// Type of the object
+    #[serde(rename = "type", deserialize_with = "object_type")]
+    object_type: object::Type,
+
+    /// Object ID of the object
+    id: object::Id,
+
+    /// Label to place on the object
+    #[serde(default, deserialize_with = "label")]
+    label: object::Label,
+
+    /// Algorithm of the object
+    #[serde(deserialize_with = "algorithm")]
+    algorithm: Algorithm,
+
+    /// Capabilities of the object
+    #[serde(default, deserialize_with = "capabilities")]
+    capabilities: Capability,
+
+    /// Domains the object can be used in
+    #[serde(default = "Domain::empty", deserialize_with = "domains")]
+    domains: Domain,
+
+    /// Source of a key to import (keys are generated if absent)
+    #[serde(default, deserialize_with = "object_key")]
+    key: Option<Zeroizing<Vec<u8>>>,
+
+    /// File to read opaque data from
+    data_file: Option<PathBuf>,
+
+    /// Record the fingerprint of an attestation certificate in the report?
+    #[serde(default)]
+    attest: bool,
+
+    /// Key to sign the attestation certificate with
+    attestation_key_id: Option<object::Id>,
+}
+
+impl ObjectConfig {
+    /// Build an `Object`, reading any data file relative to the given
+    /// directory
+    fn into_object(self, dir: &Path) -> Result<Object, (Option<&'static str>, String)> {
+        let describe = || format!("{} 0x{:04x}", self.object_type, self.id);
+
+        let object = match (self.object_type, self.algorithm, self.key.as_ref()) {
+            (object::Type::AsymmetricKey, Algorithm::Asymmetric(alg), None) => {
+                Object::generate_asymmetric(self.id, alg)
+            }
+            (object::Type::AsymmetricKey, Algorithm::Asymmetric(alg), Some(key)) => {
+                Object::put_asymmetric(self.id, alg, key.as_slice())
+            }
+            (object::Type::HmacKey, Algorithm::Hmac(alg), None) => {
+                Object::generate_hmac(self.id, alg)
+            }
+            (object::Type::HmacKey, Algorithm::Hmac(alg), Some(key)) => {
+                Object::put_hmac(self.id, alg, key.as_slice())
+            }
+            (object::Type::Opaque, Algorithm::Opaque(alg), None) => {
+                let path = self
+                    .data_file
+                    .as_ref()
+                    .map(|file| dir.join(file))
+                    .ok_or_else(|| (None, format!("{}: missing data_file", describe())))?;
+
+                let data = fs::read(&path).map_err(|e| {
+                    let message =
+                        format!("{}: couldn't read {}: {}", describe(), path.display(), e);
+                    (Some("data_file"), message)
+                })?;
+
+                Object::put_opaque(self.id, alg, data)
+            }
+            (object::Type::Opaque, Algorithm::Opaque(_), Some(_)) => {
+                return Err((
+                    Some("key"),
+                    format!("{}: opaque objects don't have keys", describe()),
+                ))
+            }
+            (object::Type::AsymmetricKey | object::Type::HmacKey | object::Type::Opaque, ..) => {
+                return Err((
+                    Some("algorithm"),
+                    format!(
+                        "{}: algorithm {:?} doesn't match the object type",
+                        describe(),
+                        self.algorithm
+                    ),
+                ))
+            }
+            (other, ..) => {
+                return Err((
+                    Some("type"),
+                    format!(
+                        "{}: {} objects aren't supported (use roles or wrap_keys)",
+                        describe(),
+                        other
+                    ),
+                ))
+            }
+        };
+
+        if self.data_file.is_some() && self.object_type != object::Type::Opaque {
+            return Err((
+                Some("data_file"),
+                format!("{}: only opaque objects have data files", describe()),
+            ));
+        }
+
+        let object = object
+            .label(self.label)
+            .capabilities(self.capabilities)
+            .domains(self.domains);
+
+        Ok(match (self.attest, self.object_type) {
+            (false, _) => object,
+            (true, object::Type::AsymmetricKey) => object.attest(self.attestation_key_id),
+            (true, _) => {
+                return Err((
+                    Some("attest"),
+                    format!("{}: only asymmetric keys can be attested", describe()),
+                ))
+            }
+        })
+    }
+}
+
+/// Where to find a secret. Exactly one source must be given, e.g.
+/// `credentials = { password_env = "YUBIHSM_PASSWORD" }`
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+enum Secret {
+    /// Password read from the given environment variable
+    PasswordEnv(String),
+
+    /// Hex-encoded key read from the given environment variable
+    KeyEnv(String),
+}
+
+impl Secret {
+    /// Read the secret from its source, returning whether it's a password
+    /// along with its bytes
+    fn read(&self) -> Result<(bool, Zeroizing<Vec<u8>>), String> {
+        match self {
+            Secret::PasswordEnv(var) => Ok((true, Zeroizing::new(env_var(var)?.into_bytes()))),
+            Secret::KeyEnv(var) => {
+                let hex = Zeroizing::new(env_var(var)?);
+                decode_hex(hex.trim())
+                    .map(|bytes| (false, bytes))
+                    .map_err(|e| format!("environment variable {var}: {e}"))
+            }
+        }
+    }
+}
+
+fn default_audit_option() -> AuditOption {
+    AuditOption::Off
+}
+
+fn default_setup_auth_key_id() -> object::Id {
+    DEFAULT_SETUP_KEY_ID
+}
+
+fn default_report_object_id() -> object::Id {
+    DEFAULT_REPORT_OBJECT_ID
+}
+
+fn default_reset_device_timeout() -> u64 {
+    DEFAULT_RESET_DEVICE_TIMEOUT_SECS
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// Parse an audit option from its name
+fn audit_option<'de, D: Deserializer<'de>>(deserializer: D) -> Result<AuditOption, D::Error> {
+    let name = String::deserialize(deserializer)?;
+
+    options::parse_audit_option(&name).ok_or_else(|| {
+        de::Error::invalid_value(de::Unexpected::Str(&name), &"\"off\", \"on\", or \"fix\"")
+    })
+}
+
+/// Parse an optional audit option from its name
+fn optional_audit_option<'de, D>(deserializer: D) -> Result<Option<AuditOption>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    audit_option(deserializer).map(Some)
+}
+
+/// Parse per-command audit options, keyed by command name
+fn command_audit_options<'de, D>(deserializer: D) -> Result<Vec<AuditCommand>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    /// Command name
+    #[derive(Eq, Ord, PartialEq, PartialOrd)]
+    struct Command(command::Code);
+
+    impl<'de> Deserialize<'de> for Command {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            let name = String::deserialize(deserializer)?;
+
+            options::parse_command(&name)
+                .map(Command)
+                .ok_or_else(|| custom(format!("unknown command: {name:?}")))
+        }
+    }
+
+    /// Audit option name
+    struct Audit(AuditOption);
+
+    impl<'de> Deserialize<'de> for Audit {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            audit_option(deserializer).map(Audit)
+        }
+    }
+
+    let options = BTreeMap::<Command, Audit>::deserialize(deserializer)?;
+
+    Ok(options
+        .into_iter()
+        .map(|(command, option)| AuditCommand(command.0, option.0))
+        .collect())
+}
+
+/// Parse an object label, ensuring it fits
+fn label<'de, D: Deserializer<'de>>(deserializer: D) -> Result<object::Label, D::Error> {
+    String::deserialize(deserializer)?
+        .parse()
+        .map_err(de::Error::custom)
+}
+
+/// Either a string (e.g. `"all"`) or a list of items
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StringOrList<T> {
+    String(String),
+    List(Vec<T>),
+}
+
+/// Parse capabilities from either a list of capability names or a string
+/// (e.g. `"all"` or `"sign-ecdsa,exportable-under-wrap"`)
+fn capabilities<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Capability, D::Error> {
+    match string_or_list::<D, String>(deserializer)? {
+        StringOrList::String(s) => s.parse().map_err(|()| not_all_or_list(&s)),
+        StringOrList::List(names) => names.iter().try_fold(Capability::empty(), |caps, name| {
+            name.parse::<Capability>()
+                .map(|cap| caps | cap)
+                .map_err(|()| custom(format!("unknown capability: {name:?}")))
+        }),
+    }
+}
+
+/// Parse domains from either a list of domain numbers or a string (e.g.
+/// `"all"` or `"1,2,5"`)
+fn domains<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Domain, D::Error> {
+    match string_or_list::<D, usize>(deserializer)? {
+        StringOrList::String(s) => s.parse().map_err(|_| not_all_or_list(&s)),
+        StringOrList::List(numbers) => numbers.iter().try_fold(Domain::empty(), |domains, &n| {
+            Domain::at(n)
+                .map(|dom| domains | dom)
+                .map_err(|_| custom(format!("invalid domain: {n} (valid domains are 1-16)")))
+        }),
+    }
+}
+
+/// Parse either a string or a list of items
+fn string_or_list<'de, D, T>(deserializer: D) -> Result<StringOrList<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    StringOrList::deserialize(deserializer).map_err(|_| custom("expected \"all\" or a list"))
+}
+
+/// Error for a string which isn't `"all"` or a valid comma-separated list
+fn not_all_or_list<E: de::Error>(s: &str) -> E {
+    custom(format!("expected \"all\" or a list (got {s:?})"))
+}
+
+/// Read a role's password or authentication key from its source
+fn role_credentials<'de, D>(deserializer: D) -> Result<RoleCredentials, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match Secret::deserialize(deserializer)?.read().map_err(custom)? {
+        (true, password) => Ok(RoleCredentials::Password(password)),
+        (false, key) => authentication::Key::from_slice(&key)
+            .map(RoleCredentials::Key)
+            .map_err(custom),
+    }
+}
+
+/// Read a wrap key from its source
+fn wrap_key<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Zeroizing<Vec<u8>>, D::Error> {
+    match Secret::deserialize(deserializer)?.read().map_err(custom)? {
+        (true, _) => Err(custom("wrap keys can't be derived from passwords")),
+        (false, key) if matches!(key.len(), 16 | 24 | 32) => Ok(key),
+        (false, key) => Err(custom(format!(
+            "expected 16, 24, or 32-byte wrap key (got {})",
+            key.len()
+        ))),
+    }
+}
+
+/// Read a key to import into the device from its source
+fn object_key<'de, D>(deserializer: D) -> Result<Option<Zeroizing<Vec<u8>>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match Secret::deserialize(deserializer)?.read().map_err(custom)? {
+        (true, _) => Err(custom("keys can't be derived from passwords")),
+        (false, key) => Ok(Some(key)),
+    }
+}
+
+/// Parse an object type from its name
+fn object_type<'de, D: Deserializer<'de>>(deserializer: D) -> Result<object::Type, D::Error> {
+    let name = String::deserialize(deserializer)?;
+
+    name.parse()
+        .map_err(|()| custom(format!("unknown object type: {name:?}")))
+}
+
+/// Parse an algorithm from its name
+fn algorithm<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Algorithm, D::Error> {
+    let name = String::deserialize(deserializer)?;
+
+    name.parse()
+        .map_err(|_| custom(format!("unknown algorithm: {name:?}")))
+}
+
+/// Read an environment variable
+fn env_var(name: &str) -> Result<String, String> {
+    env::var(name).map_err(|e| match e {
+        env::VarError::NotPresent => format!("environment variable {name} is not set"),
+        env::VarError::NotUnicode(_) => format!("environment variable {name} isn't valid UTF-8"),
+    })
+}
+
+/// Decode a hex string
+fn decode_hex(hex: &str) -> Result<Zeroizing<Vec<u8>>, String> {
+    if hex.len() % 2 != 0 {
+        return Err("odd number of hex digits".to_owned());
+    }
+
+    let mut bytes = Zeroizing::new(Vec::with_capacity(hex.len() / 2));
+
+    for pair in hex.as_bytes().chunks(2) {
+        let byte = std::str::from_utf8(pair)
+            .ok()
+            .and_then(|digits| u8::from_str_radix(digits, 16).ok())
+            .ok_or("invalid hex digit")?;
+
+        bytes.push(byte);
+    }
+
+    Ok(bytes)
+}
+
+/// Create a custom deserialization error
+fn custom<E: de::Error>(msg: impl Display) -> E {
+    E::custom(msg)
+}
+
+/// Compute the 1-based line and column of a byte offset
+fn line_and_column(source: &str, offset: usize) -> (usize, usize) {
+    let before = &source[..offset.min(source.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before.rsplit('\n').next().unwrap().chars().count() + 1;
+    (line, column)
+}
+
+/// Parse a profile from TOML, read from the given path (if any)
+pub(super) fn from_toml(source: &str, path: Option<&Path>) -> Result<Profile, Error> {
+    toml::from_str::<ProfileConfig>(source)
+        .map_err(|e| {
+            let message = e.message().trim_end();
+
+            match e.span() {
+                Some(span) => {
+                    let (line, column) = line_and_column(source, span.start);
+                    format!("line {line}, column {column}: {message}")
+                }
+                None => message.to_owned(),
+            }
+        })
+        .and_then(|config| {
+            config.into_profile(profile_dir(path)).map_err(|e| {
+                match Spans::find(source, e.location) {
+                    Some(span) => {
+                        let (line, column) = line_and_column(source, span.start);
+                        format!("line {line}, column {column}: {}", e.message)
+                    }
+                    None => e.message,
+                }
+            })
+        })
+        .map_err(|message| invalid(path, message))
+}
+
+/// Parse a profile from JSON, read from the given path (if any)
+pub(super) fn from_json(source: &str, path: Option<&Path>) -> Result<Profile, Error> {
+    serde_json::from_str::<ProfileConfig>(source)
+        .map_err(|e| {
+            // Move the position to the front, to match TOML errors
+            let message = e.to_string();
+            let suffix = format!(" at line {} column {}", e.line(), e.column());
+            let message = message.strip_suffix(&suffix).unwrap_or(&message);
+            format!("line {}, column {}: {}", e.line(), e.column(), message)
+        })
+        .and_then(|config| {
+            config.into_profile(profile_dir(path)).map_err(|e| {
+                match JsonSpans::find(source, e.location) {
+                    Some(span) => {
+                        let (line, column) = line_and_column(source, span.start);
+                        format!("line {line}, column {column}: {}", e.message)
+                    }
+                    None => e.message,
+                }
+            })
+        })
+        .map_err(|message| invalid(path, message))
+}
+
+/// Directory a profile was read from, which files it names are relative to
+fn profile_dir(path: Option<&Path>) -> &Path {
+    path.and_then(Path::parent).unwrap_or_else(|| Path::new(""))
+}
+
+/// Create an invalid profile error, prefixed with the profile's path
+fn invalid(path: Option<&Path>, message: String) -> Error {
+    match path {
+        Some(path) => {
+            format_err!(ErrorKind::ProfileInvalid, "{}: {}", path.display(), message).into()
+        }
+        None => format_err!(ErrorKind::ProfileInvalid, "{}", message).into(),
+    }
+}
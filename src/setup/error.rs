@@ -13,10 +13,18 @@ pub enum ErrorKind {
     #[error("invalid label")]
     LabelInvalid,
 
+    /// Invalid provisioning profile
+    #[error("invalid profile")]
+    ProfileInvalid,
+
     /// Errors involving setup report generation
     #[error("report failed")]
     ReportFailed,
 
+    /// Report signature is missing or invalid
+    #[error("invalid report signature")]
+    SignatureInvalid,
+
     /// Error performing setup
     #[error("setup failed")]
     SetupFailed,
@@ -29,6 +37,12 @@ impl ErrorKind {
     }
 }
 
+impl From<crate::authentication::Error> for Error {
+    fn from(authentication_error: crate::authentication::Error) -> Error {
+        ErrorKind::SetupFailed.context(authentication_error).into()
+    }
+}
+
 impl From<crate::client::Error> for Error {
     fn from(client_error: crate::client::Error) -> Error {
         ErrorKind::SetupFailed.context(client_error).into()
@@ -0,0 +1,259 @@
+//! Asymmetric keys, HMAC keys and opaque objects to create on the device
+
+use super::{report, Error, ErrorKind};
+use crate::{asymmetric, hmac, object, opaque, Algorithm, Capability, Client, Domain};
+use std::fmt::{self, Debug};
+use zeroize::Zeroizing;
+
+/// Object to generate or import when provisioning the device (other than
+/// authentication keys, which are created from roles, and wrap keys)
+#[derive(Clone)]
+pub struct Object {
+    /// ID of the object
+    pub(super) id: object::Id,
+
+    /// Label to place on the object
+    pub(super) label: object::Label,
+
+    /// Capabilities of the object
+    pub(super) capabilities: Capability,
+
+    /// Domains the object can be used in
+    pub(super) domains: Domain,
+
+    /// How the object is created
+    pub(super) source: Source,
+
+    /// Record the fingerprint of an attestation certificate for this key in
+    /// the report?
+    pub(super) attest: bool,
+
+    /// Key to sign the attestation certificate with (`None` for the
+    /// device's attestation key)
+    pub(super) attestation_key_id: Option<object::Id>,
+}
+
+/// How an object is created
+#[derive(Clone)]
+pub(super) enum Source {
+    /// Generate an asymmetric key within the device
+    GenerateAsymmetric(asymmetric::Algorithm),
+
+    /// Import an existing asymmetric key
+    PutAsymmetric(asymmetric::Algorithm, Zeroizing<Vec<u8>>),
+
+    /// Generate an HMAC key within the device
+    GenerateHmac(hmac::Algorithm),
+
+    /// Import an existing HMAC key
+    PutHmac(hmac::Algorithm, Zeroizing<Vec<u8>>),
+
+    /// Import opaque data (e.g. a certificate)
+    PutOpaque(opaque::Algorithm, Vec<u8>),
+}
+
+impl Object {
+    /// Generate an asymmetric key within the device
+    pub fn generate_asymmetric(id: object::Id, algorithm: asymmetric::Algorithm) -> Self {
+        Self::new(id, Source::GenerateAsymmetric(algorithm))
+    }
+
+    /// Import an existing asymmetric key
+    pub fn put_asymmetric<K>(id: object::Id, algorithm: asymmetric::Algorithm, key: K) -> Self
+    where
+        K: Into<Vec<u8>>,
+    {
+        Self::new(
+            id,
+            Source::PutAsymmetric(algorithm, Zeroizing::new(key.into())),
+        )
+    }
+
+    /// Generate an HMAC key within the device
+    pub fn generate_hmac(id: object::Id, algorithm: hmac::Algorithm) -> Self {
+        Self::new(id, Source::GenerateHmac(algorithm))
+    }
+
+    /// Import an existing HMAC key
+    pub fn put_hmac<K>(id: object::Id, algorithm: hmac::Algorithm, key: K) -> Self
+    where
+        K: Into<Vec<u8>>,
+    {
+        Self::new(id, Source::PutHmac(algorithm, Zeroizing::new(key.into())))
+    }
+
+    /// Import opaque data, e.g. a certificate for a key in the device
+    pub fn put_opaque<D>(id: object::Id, algorithm: opaque::Algorithm, data: D) -> Self
+    where
+        D: Into<Vec<u8>>,
+    {
+        Self::new(id, Source::PutOpaque(algorithm, data.into()))
+    }
+
+    fn new(id: object::Id, source: Source) -> Self {
+        Self {
+            id,
+            label: Default::default(),
+            capabilities: Capability::empty(),
+            domains: Domain::empty(),
+            source,
+            attest: false,
+            attestation_key_id: None,
+        }
+    }
+
+    /// Set the label for this object
+    pub fn label<L>(mut self, label: L) -> Self
+    where
+        L: Into<object::Label>,
+    {
+        self.label = label.into();
+        self
+    }
+
+    /// Set the capabilities of this object
+    pub fn capabilities(mut self, capabilities: Capability) -> Self {
+        self.capabilities = capabilities;
+        self
+    }
+
+    /// Set the domains this object can be used in
+    pub fn domains(mut self, domains: Domain) -> Self {
+        self.domains = domains;
+        self
+    }
+
+    /// Record the fingerprint of an attestation certificate for this
+    /// (asymmetric) key in the provisioning report, signed by the given
+    /// attestation key, or by the device's attestation key if `None`
+    pub fn attest(mut self, attestation_key_id: Option<object::Id>) -> Self {
+        self.attest = true;
+        self.attestation_key_id = attestation_key_id;
+        self
+    }
+
+    /// Type of this object
+    pub fn object_type(&self) -> object::Type {
+        match self.source {
+            Source::GenerateAsymmetric(_) | Source::PutAsymmetric(..) => {
+                object::Type::AsymmetricKey
+            }
+            Source::GenerateHmac(_) | Source::PutHmac(..) => object::Type::HmacKey,
+            Source::PutOpaque(..) => object::Type::Opaque,
+        }
+    }
+
+    /// Algorithm of this object
+    pub fn algorithm(&self) -> Algorithm {
+        match self.source {
+            Source::GenerateAsymmetric(alg) | Source::PutAsymmetric(alg, _) => alg.into(),
+            Source::GenerateHmac(alg) | Source::PutHmac(alg, _) => alg.into(),
+            Source::PutOpaque(alg, _) => alg.into(),
+        }
+    }
+
+    /// Create this object within the YubiHSM 2 device
+    pub fn create(&self, client: &Client) -> Result<(), Error> {
+        let label = self.label.clone();
+
+        match &self.source {
+            Source::GenerateAsymmetric(alg) => client.generate_asymmetric_key(
+                self.id,
+                label,
+                self.domains,
+                self.capabilities,
+                *alg,
+            ),
+            Source::PutAsymmetric(alg, key) => client.put_asymmetric_key(
+                self.id,
+                label,
+                self.domains,
+                self.capabilities,
+                *alg,
+                key.as_slice(),
+            ),
+            Source::GenerateHmac(alg) => {
+                client.generate_hmac_key(self.id, label, self.domains, self.capabilities, *alg)
+            }
+            Source::PutHmac(alg, key) => client.put_hmac_key(
+                self.id,
+                label,
+                self.domains,
+                self.capabilities,
+                *alg,
+                key.as_slice(),
+            ),
+            Source::PutOpaque(alg, data) => client.put_opaque(
+                self.id,
+                label,
+                self.domains,
+                self.capabilities,
+                *alg,
+                data.as_slice(),
+            ),
+        }
+        .map_err(|e| {
+            format_err!(
+                ErrorKind::SetupFailed,
+                "error creating {} 0x{:04x}: {}",
+                self.object_type(),
+                self.id,
+                e
+            )
+        })?;
+
+        Ok(())
+    }
+
+    /// Describe this object for the provisioning report, including the
+    /// fingerprints of its public key and attestation certificate (if
+    /// requested)
+    pub(super) fn report(&self, client: &Client) -> Result<report::Object, Error> {
+        let mut object = self.draft_report();
+
+        if object.public_key_sha256.is_some() {
+            object.public_key_sha256 =
+                Some(report::fingerprint(&client.get_public_key(self.id)?.bytes));
+        }
+
+        if object.attestation_certificate_sha256.is_some() {
+            let certificate =
+                client.sign_attestation_certificate(self.id, self.attestation_key_id)?;
+
+            object.attestation_certificate_sha256 = Some(report::fingerprint(certificate.as_ref()));
+        }
+
+        Ok(object)
+    }
+
+    /// Describe this object for the provisioning report before it's been
+    /// created, with placeholders for its fingerprints
+    pub(super) fn draft_report(&self) -> report::Object {
+        let asymmetric = self.object_type() == object::Type::AsymmetricKey;
+        let placeholder = || report::fingerprint(&[]);
+
+        report::Object {
+            object_id: self.id,
+            object_type: self.object_type().to_string(),
+            label: self.label.to_string(),
+            algorithm: self.algorithm().to_string(),
+            public_key_sha256: asymmetric.then(placeholder),
+            attestation_certificate_sha256: (asymmetric && self.attest).then(placeholder),
+        }
+    }
+}
+
+impl Debug for Object {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Avoid leaking imported keys in debug messages
+        f.debug_struct("Object")
+            .field("id", &self.id)
+            .field("object_type", &self.object_type())
+            .field("algorithm", &self.algorithm())
+            .field("label", &self.label)
+            .field("capabilities", &self.capabilities)
+            .field("domains", &self.domains)
+            .field("attest", &self.attest)
+            .finish_non_exhaustive()
+    }
+}
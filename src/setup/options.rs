@@ -0,0 +1,120 @@
+//! Device-wide settings: forced auditing, per-command auditing and FIPS mode
+
+use super::{report, Error};
+use crate::{command, AuditOption, Client};
+use std::fmt::{self, Display};
+
+/// Device-wide setting which can be declared in a profile
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Setting {
+    /// FIPS mode (only supported by FIPS devices)
+    Fips,
+
+    /// Auditing of a particular command
+    CommandAudit(command::Code),
+
+    /// Forced auditing: refuse operations when the audit log is full
+    ForceAudit,
+}
+
+impl Setting {
+    /// Read this setting from the device
+    pub(super) fn get(self, client: &Client) -> Result<AuditOption, Error> {
+        Ok(match self {
+            Setting::Fips => client.get_fips_option()?,
+            Setting::CommandAudit(command) => client.get_command_audit_option(command)?,
+            Setting::ForceAudit => client.get_force_audit_option()?,
+        })
+    }
+
+    /// Change this setting on the device
+    pub(super) fn set(self, client: &Client, option: AuditOption) -> Result<(), Error> {
+        match self {
+            Setting::Fips => client.set_fips_option(option)?,
+            Setting::CommandAudit(command) => client.set_command_audit_option(command, option)?,
+            Setting::ForceAudit => client.set_force_audit_option(option)?,
+        }
+
+        Ok(())
+    }
+}
+
+impl Display for Setting {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Setting::Fips => f.write_str("fips"),
+            Setting::CommandAudit(command) => write!(f, "command-audit {}", command_name(*command)),
+            Setting::ForceAudit => f.write_str("force-audit"),
+        }
+    }
+}
+
+/// Read the given settings back from the device for the provisioning report
+pub(super) fn read(client: &Client, settings: &[Setting]) -> Result<report::Options, Error> {
+    let settings = settings
+        .iter()
+        .map(|&setting| Ok((setting, setting.get(client)?)))
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    Ok(to_report(&settings))
+}
+
+/// Record the given settings and their options for the provisioning report
+pub(super) fn to_report(settings: &[(Setting, AuditOption)]) -> report::Options {
+    let mut options = report::Options::default();
+
+    for &(setting, option) in settings {
+        let name = audit_option_name(option).to_owned();
+
+        match setting {
+            Setting::Fips => options.fips = Some(name),
+            Setting::CommandAudit(command) => {
+                options.command_audit.insert(command_name(command), name);
+            }
+            Setting::ForceAudit => options.force_audit = name,
+        }
+    }
+
+    options
+}
+
+/// Name of an audit option, as it's spelled in profiles
+pub(super) fn audit_option_name(option: AuditOption) -> &'static str {
+    match option {
+        AuditOption::Off => "off",
+        AuditOption::On => "on",
+        AuditOption::Fix => "fix",
+    }
+}
+
+/// Parse an audit option from its name
+pub(super) fn parse_audit_option(name: &str) -> Option<AuditOption> {
+    match name {
+        "off" => Some(AuditOption::Off),
+        "on" => Some(AuditOption::On),
+        "fix" => Some(AuditOption::Fix),
+        _ => None,
+    }
+}
+
+/// Name of a command, as it's spelled in profiles (e.g. `sign-ecdsa`)
+pub(super) fn command_name(command: command::Code) -> String {
+    let mut name = String::new();
+
+    for (i, c) in format!("{command:?}").char_indices() {
+        if c.is_ascii_uppercase() && i > 0 {
+            name.push('-');
+        }
+
+        name.push(c.to_ascii_lowercase());
+    }
+
+    name
+}
+
+/// Parse a command from its name
+pub(super) fn parse_command(name: &str) -> Option<command::Code> {
+    (0..=u8::MAX)
+        .filter_map(|byte| command::Code::from_u8(byte).ok())
+        .find(|&command| command_name(command) == name)
+}
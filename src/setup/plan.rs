@@ -0,0 +1,618 @@
+//! Reconciliation of an already-provisioned device against a profile.
+//!
+//! Rather than resetting the device and creating every object from scratch,
+//! a [`Plan`] is computed from the objects currently on the device, which
+//! can be reviewed before it's applied.
+
+use super::{
+    objects::Source as ObjectSource, options::audit_option_name, Error, ErrorKind, Object, Profile,
+    Report, Role, Setting,
+};
+use crate::{
+    authentication, client, device, object, wrap, Algorithm, AuditOption, Capability, Client,
+    Domain,
+};
+use std::fmt::{self, Display};
+
+/// Types of objects managed by profiles. Objects of these types which
+/// aren't in the profile are deleted.
+const MANAGED_TYPES: &[object::Type] = &[
+    object::Type::AuthenticationKey,
+    object::Type::WrapKey,
+    object::Type::AsymmetricKey,
+    object::Type::HmacKey,
+    object::Type::Opaque,
+];
+
+/// Changes needed to bring a device in line with a profile.
+///
+/// Display a plan for review before applying it, e.g.:
+///
+/// ```text
+/// create authentication-key 0x0003 "signer"
+/// replace wrap-key 0x0001 "backup" (domains: 1 -> 1,2)
+/// replace asymmetric-key 0x0100 "signing key" (label: "signer" -> "signing key") [destroys device-generated key]
+/// delete authentication-key 0x0001 "DEFAULT AUTHKEY CHANGE THIS"
+/// set force-audit: off -> on
+/// note: wrap-key 0x0002 "escrow": secret not verifiable
+/// ```
+#[derive(Debug)]
+pub struct Plan<'a> {
+    /// Profile the plan was computed from
+    profile: &'a Profile,
+
+    /// Changes to make, in the order they'll be applied
+    changes: Vec<Change>,
+
+    /// Objects which otherwise match the profile, but whose secrets can't
+    /// be read back from the device to check them
+    unverified: Vec<(object::Handle, object::Label)>,
+}
+
+impl<'a> Plan<'a> {
+    /// Compare the objects and settings on the device to the given profile
+    pub(super) fn compute(profile: &'a Profile, client: &Client) -> Result<Self, Error> {
+        profile.check_report_size(client)?;
+
+        let mut changes = vec![];
+        let mut settings = vec![];
+
+        for (setting, desired) in profile.settings() {
+            let current = setting.get(client)?;
+
+            if current != desired {
+                let change = Change::Set {
+                    setting,
+                    current,
+                    desired,
+                };
+
+                // FIPS mode can only be changed before objects are created
+                if setting == Setting::Fips {
+                    changes.push(change);
+                } else {
+                    settings.push(change);
+                }
+            }
+        }
+
+        let desired = desired_objects(profile);
+        let mut unverified = vec![];
+
+        let session_key = object::Handle::new(
+            client.session()?.authentication_key_id(),
+            object::Type::AuthenticationKey,
+        );
+
+        for object in &desired {
+            match client.get_object_info(object.handle.object_id, object.handle.object_type) {
+                Ok(info) => {
+                    let mut differences = object.differences(&info);
+
+                    if differences.is_empty() {
+                        match object.check_secret(client, &info)? {
+                            Secret::Matches => (),
+                            Secret::Differs(difference) => differences.push(difference),
+                            Secret::Unverifiable => {
+                                unverified.push((object.handle.clone(), info.label.clone()))
+                            }
+                        }
+                    }
+
+                    if differences.is_empty() {
+                        continue;
+                    }
+
+                    // Replacing the key we're authenticated with would lock us
+                    // out if anything went wrong partway through
+                    ensure!(
+                        object.handle != session_key,
+                        ErrorKind::SetupFailed,
+                        "can't replace {} as the session is authenticated with it; \
+                         authenticate with a different key to apply this profile",
+                        DisplayObject(&object.handle, &info.label)
+                    );
+
+                    changes.push(Change::Replace {
+                        handle: object.handle.clone(),
+                        label: info.label,
+                        differences,
+                        destructive: object.is_generated(),
+                    });
+                }
+                Err(e) if e.device_error() == Some(device::ErrorKind::ObjectNotFound) => {
+                    changes.push(Change::Create {
+                        handle: object.handle.clone(),
+                        label: object.label.clone(),
+                    });
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        // Never delete the key we're authenticated with, a setup key which
+        // the profile says to keep, or the provisioning report
+        let mut kept = vec![session_key];
+
+        if let Some(id) = profile.setup_auth_key_id {
+            if !profile.delete_setup_auth_key {
+                kept.push(object::Handle::new(id, object::Type::AuthenticationKey));
+            }
+        }
+
+        if let Some(id) = profile.report_object_id {
+            kept.push(object::Handle::new(id, object::Type::Opaque));
+        }
+
+        for &object_type in MANAGED_TYPES {
+            for entry in client.list_objects(&[object::Filter::Type(object_type)])? {
+                let handle = object::Handle::new(entry.object_id, entry.object_type);
+
+                // Object ID 0 holds the device's attestation key and certificate
+                if entry.object_id == 0
+                    || kept.contains(&handle)
+                    || desired.iter().any(|object| object.handle == handle)
+                {
+                    continue;
+                }
+
+                let info = client.get_object_info(entry.object_id, entry.object_type)?;
+
+                changes.push(Change::Delete {
+                    handle,
+                    label: info.label,
+                });
+            }
+        }
+
+        changes.extend(settings);
+
+        Ok(Self {
+            profile,
+            changes,
+            unverified,
+        })
+    }
+
+    /// Changes which will be made, in the order they'll be applied
+    pub fn changes(&self) -> &[Change] {
+        &self.changes
+    }
+
+    /// Objects which otherwise match the profile, but whose secrets (e.g.
+    /// the key material of wrap keys) can't be read back from the device to
+    /// check they match it. These are left as they are.
+    pub fn unverified(&self) -> impl Iterator<Item = &object::Handle> {
+        self.unverified.iter().map(|(handle, _)| handle)
+    }
+
+    /// Is the device already in line with the profile?
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// Make the planned changes, then generate (and store, if the profile
+    /// says to) a new provisioning report.
+    ///
+    /// The device may have changed since the plan was computed, in which
+    /// case some changes may fail.
+    pub fn apply(&self, client: &Client) -> Result<Report, Error> {
+        let desired = desired_objects(self.profile);
+        let find = |handle: &object::Handle| {
+            desired
+                .iter()
+                .find(|object| object.handle == *handle)
+                .expect("planned object missing from profile")
+        };
+
+        for change in &self.changes {
+            info!("applying change: {}", change);
+
+            match change {
+                Change::Create { handle, .. } => find(handle).create(client)?,
+                Change::Replace { handle, .. } => {
+                    client.delete_object(handle.object_id, handle.object_type)?;
+                    find(handle).create(client)?;
+                }
+                Change::Delete { handle, .. } => {
+                    client.delete_object(handle.object_id, handle.object_type)?
+                }
+                Change::Set {
+                    setting, desired, ..
+                } => setting.set(client, *desired)?,
+            }
+        }
+
+        self.profile.report(client)
+    }
+}
+
+impl Display for Plan<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for change in &self.changes {
+            writeln!(f, "{change}")?;
+        }
+
+        for (handle, label) in &self.unverified {
+            writeln!(
+                f,
+                "note: {}: secret not verifiable",
+                DisplayObject(handle, label)
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Change to make to the device
+#[derive(Clone, Debug, PartialEq)]
+pub enum Change {
+    /// Create an object which is missing from the device
+    Create {
+        /// Object to create
+        handle: object::Handle,
+
+        /// Label of the object to create
+        label: object::Label,
+    },
+
+    /// Delete and recreate an object whose attributes differ from the
+    /// profile, as objects can't be modified in place
+    Replace {
+        /// Object to replace
+        handle: object::Handle,
+
+        /// Current label of the object
+        label: object::Label,
+
+        /// Attributes which differ from the profile
+        differences: Vec<Difference>,
+
+        /// Is the object a key generated within the device, which will be
+        /// lost (and replaced with a newly generated key)?
+        destructive: bool,
+    },
+
+    /// Delete an object which isn't in the profile
+    Delete {
+        /// Object to delete
+        handle: object::Handle,
+
+        /// Current label of the object
+        label: object::Label,
+    },
+
+    /// Change a device-wide setting
+    Set {
+        /// Setting to change
+        setting: Setting,
+
+        /// Current option
+        current: AuditOption,
+
+        /// Option in the profile
+        desired: AuditOption,
+    },
+}
+
+impl Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Change::Create { handle, label } => {
+                write!(f, "create {}", DisplayObject(handle, label))
+            }
+            Change::Replace {
+                handle,
+                label,
+                differences,
+                destructive,
+            } => {
+                write!(f, "replace {} (", DisplayObject(handle, label))?;
+
+                for (i, difference) in differences.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+
+                    write!(f, "{difference}")?;
+                }
+
+                f.write_str(")")?;
+
+                if *destructive {
+                    f.write_str(" [destroys device-generated key]")?;
+                }
+
+                Ok(())
+            }
+            Change::Delete { handle, label } => {
+                write!(f, "delete {}", DisplayObject(handle, label))
+            }
+            Change::Set {
+                setting,
+                current,
+                desired,
+            } => write!(
+                f,
+                "set {}: {} -> {}",
+                setting,
+                audit_option_name(*current),
+                audit_option_name(*desired)
+            ),
+        }
+    }
+}
+
+/// Attribute of an object on the device which differs from the profile
+#[derive(Clone, Debug, PartialEq)]
+pub enum Difference {
+    /// Object labels differ
+    Label {
+        /// Label on the device
+        current: object::Label,
+
+        /// Label in the profile
+        desired: object::Label,
+    },
+
+    /// Algorithms (e.g. wrap key sizes) differ
+    Algorithm {
+        /// Algorithm on the device
+        current: Algorithm,
+
+        /// Algorithm in the profile
+        desired: Algorithm,
+    },
+
+    /// Capabilities differ
+    Capabilities {
+        /// Capabilities on the device
+        current: Capability,
+
+        /// Capabilities in the profile
+        desired: Capability,
+    },
+
+    /// Delegated capabilities differ
+    DelegatedCapabilities {
+        /// Delegated capabilities on the device
+        current: Capability,
+
+        /// Delegated capabilities in the profile
+        desired: Capability,
+    },
+
+    /// Domains differ
+    Domains {
+        /// Domains on the device
+        current: Domain,
+
+        /// Domains in the profile
+        desired: Domain,
+    },
+
+    /// The role's credentials don't authenticate with the authentication
+    /// key on the device
+    AuthenticationKey,
+
+    /// Opaque data differs
+    Data,
+}
+
+impl Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Difference::Label { current, desired } => write!(
+                f,
+                "label: {:?} -> {:?}",
+                current.to_string(),
+                desired.to_string()
+            ),
+            Difference::Algorithm { current, desired } => {
+                write!(f, "algorithm: {current} -> {desired}")
+            }
+            Difference::Capabilities { current, desired } => {
+                write!(f, "capabilities: {current} -> {desired}")
+            }
+            Difference::DelegatedCapabilities { current, desired } => {
+                write!(f, "delegated capabilities: {current} -> {desired}")
+            }
+            Difference::Domains { current, desired } => {
+                write!(f, "domains: {current} -> {desired}")
+            }
+            Difference::AuthenticationKey => f.write_str("authentication key differs"),
+            Difference::Data => f.write_str("data differs"),
+        }
+    }
+}
+
+/// Object which the profile says should exist on the device
+struct Desired<'a> {
+    handle: object::Handle,
+    label: object::Label,
+    algorithm: Algorithm,
+    capabilities: Capability,
+    delegated_capabilities: Capability,
+    domains: Domain,
+    source: Source<'a>,
+}
+
+/// Part of the profile a desired object comes from
+enum Source<'a> {
+    Role(&'a Role),
+    WrapKey(&'a wrap::Key),
+    Object(&'a Object),
+}
+
+/// Does the secret part of an object (its key material or data) match the
+/// profile?
+enum Secret {
+    Matches,
+    Differs(Difference),
+    Unverifiable,
+}
+
+impl Desired<'_> {
+    /// Compare this object to an object on the device
+    fn differences(&self, info: &object::Info) -> Vec<Difference> {
+        let mut differences = vec![];
+
+        if info.label != self.label {
+            differences.push(Difference::Label {
+                current: info.label.clone(),
+                desired: self.label.clone(),
+            });
+        }
+
+        if info.algorithm != self.algorithm {
+            differences.push(Difference::Algorithm {
+                current: info.algorithm,
+                desired: self.algorithm,
+            });
+        }
+
+        if info.capabilities != self.capabilities {
+            differences.push(Difference::Capabilities {
+                current: info.capabilities,
+                desired: self.capabilities,
+            });
+        }
+
+        if info.delegated_capabilities != self.delegated_capabilities {
+            differences.push(Difference::DelegatedCapabilities {
+                current: info.delegated_capabilities,
+                desired: self.delegated_capabilities,
+            });
+        }
+
+        if info.domains != self.domains {
+            differences.push(Difference::Domains {
+                current: info.domains,
+                desired: self.domains,
+            });
+        }
+
+        differences
+    }
+
+    /// Compare the secret part of this object to the object on the device,
+    /// where that's possible.
+    ///
+    /// Authentication keys are checked by opening a session with the role's
+    /// credentials, and opaque objects by reading them back (if they have
+    /// the `get-opaque` capability). Keys generated within the device have
+    /// nothing in the profile to compare against.
+    fn check_secret(&self, client: &Client, info: &object::Info) -> Result<Secret, Error> {
+        let object = match self.source {
+            Source::Role(role) => {
+                return match Client::open(
+                    client.connector().clone(),
+                    role.credentials.clone(),
+                    false,
+                ) {
+                    Ok(_) => Ok(Secret::Matches),
+                    Err(e)
+                        if *e.kind() == client::ErrorKind::AuthenticationError
+                            || e.device_error()
+                                == Some(device::ErrorKind::AuthenticationFailed) =>
+                    {
+                        Ok(Secret::Differs(Difference::AuthenticationKey))
+                    }
+                    Err(e) => Err(e.into()),
+                }
+            }
+            Source::WrapKey(_) => return Ok(Secret::Unverifiable),
+            Source::Object(object) => object,
+        };
+
+        match &object.source {
+            ObjectSource::GenerateAsymmetric(_) | ObjectSource::GenerateHmac(_) => {
+                Ok(Secret::Matches)
+            }
+            ObjectSource::PutOpaque(_, data)
+                if info.capabilities.contains(Capability::GET_OPAQUE) =>
+            {
+                if client.get_opaque(info.object_id)? == *data {
+                    Ok(Secret::Matches)
+                } else {
+                    Ok(Secret::Differs(Difference::Data))
+                }
+            }
+            _ => Ok(Secret::Unverifiable),
+        }
+    }
+
+    /// Is this a key generated within the device?
+    fn is_generated(&self) -> bool {
+        match self.source {
+            Source::Object(object) => matches!(
+                object.source,
+                ObjectSource::GenerateAsymmetric(_) | ObjectSource::GenerateHmac(_)
+            ),
+            _ => false,
+        }
+    }
+
+    /// Create this object on the device
+    fn create(&self, client: &Client) -> Result<(), Error> {
+        match self.source {
+            Source::Role(role) => role.create(client),
+            Source::WrapKey(wrap_key) => Ok(wrap_key.create(client)?),
+            Source::Object(object) => object.create(client),
+        }
+    }
+}
+
+/// Objects the profile says should exist on the device
+fn desired_objects(profile: &Profile) -> Vec<Desired<'_>> {
+    let roles = profile.roles.iter().map(|role| Desired {
+        handle: object::Handle::new(
+            role.credentials.authentication_key_id,
+            object::Type::AuthenticationKey,
+        ),
+        label: role.authentication_key_label.clone(),
+        algorithm: authentication::Algorithm::YubicoAes.into(),
+        capabilities: role.capabilities,
+        delegated_capabilities: role.delegated_capabilities,
+        domains: role.domains,
+        source: Source::Role(role),
+    });
+
+    let wrap_keys = profile.all_wrap_keys().map(|wrap_key| Desired {
+        handle: object::Handle::new(wrap_key.import_params.id, object::Type::WrapKey),
+        label: wrap_key.import_params.label.clone(),
+        algorithm: wrap_key.import_params.algorithm,
+        capabilities: wrap_key.import_params.capabilities,
+        delegated_capabilities: wrap_key.delegated_capabilities,
+        domains: wrap_key.import_params.domains,
+        source: Source::WrapKey(wrap_key),
+    });
+
+    let objects = profile.objects.iter().map(|object| Desired {
+        handle: object::Handle::new(object.id, object.object_type()),
+        label: object.label.clone(),
+        algorithm: object.algorithm(),
+        capabilities: object.capabilities,
+        delegated_capabilities: Capability::empty(),
+        domains: object.domains,
+        source: Source::Object(object),
+    });
+
+    roles.chain(wrap_keys).chain(objects).collect()
+}
+
+/// Object type, ID and label
+struct DisplayObject<'a>(&'a object::Handle, &'a object::Label);
+
+impl Display for DisplayObject<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} 0x{:04x} {:?}",
+            self.0.object_type,
+            self.0.object_id,
+            self.1.to_string()
+        )
+    }
+}
@@ -1,8 +1,11 @@
 //! Device provisioning profiles: all attributes required to initialize a device
 
-use super::{role::Role, Error, Report};
-use crate::{object, wrap, AuditOption, Client};
-use std::time::Duration;
+use super::{
+    ceremony::SplitWrapKey, config, objects::Object, options, plan::Plan, report, role::Role,
+    Error, ErrorKind, Report, Setting,
+};
+use crate::{audit::AuditCommand, device, object, wrap, AuditOption, Client};
+use std::{fs, path::Path, time::Duration};
 
 /// Temporary account key to use for device provisioning.
 /// Uses key ID #65534 as 65535 is reserved for internal use.
@@ -25,6 +28,12 @@ pub struct Profile {
     /// Auditing mode to configure the device with.
     pub(super) audit_option: AuditOption,
 
+    /// Auditing settings for particular commands
+    pub(super) command_audit_options: Vec<AuditCommand>,
+
+    /// FIPS mode to configure the device with (if any)
+    pub(super) fips_option: Option<AuditOption>,
+
     /// Set of roles to create on the new device. These roles are accounts with
     /// unique credentials and different capabilities/domain access.
     pub(super) roles: Vec<Role>,
@@ -34,10 +43,19 @@ pub struct Profile {
     /// imported into other devices.
     pub(super) wrap_keys: Vec<wrap::Key>,
 
+    /// Wrap keys split into shares for custodians during a key ceremony
+    pub(super) split_wrap_keys: Vec<SplitWrapKey>,
+
+    /// Asymmetric keys, HMAC keys and opaque objects to generate or import
+    pub(super) objects: Vec<Object>,
+
     /// Store a JSON copy of the provisioning report in the given opaque
     /// object slot
     pub(super) report_object_id: Option<object::Id>,
 
+    /// Sign the provisioning report with the given ECDSA (NIST P-256) key
+    pub(super) report_signing_key_id: Option<object::Id>,
+
     /// How long to wait for the device to reset before giving up
     pub(super) reset_device_timeout: Duration,
 }
@@ -48,9 +66,14 @@ impl Default for Profile {
             setup_auth_key_id: Some(DEFAULT_SETUP_KEY_ID),
             delete_setup_auth_key: true,
             audit_option: AuditOption::Off,
+            command_audit_options: Vec::new(),
+            fips_option: None,
             roles: Vec::new(),
             wrap_keys: Vec::new(),
+            split_wrap_keys: Vec::new(),
+            objects: Vec::new(),
             report_object_id: Some(DEFAULT_REPORT_OBJECT_ID),
+            report_signing_key_id: None,
             reset_device_timeout: Duration::from_secs(10),
         }
     }
@@ -62,12 +85,58 @@ impl Profile {
         Self::default()
     }
 
+    /// Parse a profile from a TOML document.
+    ///
+    /// Errors in the document are reported along with the line and column
+    /// where they occurred.
+    pub fn from_toml(toml: &str) -> Result<Self, Error> {
+        config::from_toml(toml, None)
+    }
+
+    /// Parse a profile from a JSON document.
+    ///
+    /// Errors in the document are reported along with the line and column
+    /// where they occurred.
+    pub fn from_json(json: &str) -> Result<Self, Error> {
+        config::from_json(json, None)
+    }
+
+    /// Load a profile from a `.toml` or `.json` file
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref();
+
+        let source = fs::read_to_string(path).map_err(|e| {
+            format_err!(
+                ErrorKind::ProfileInvalid,
+                "couldn't read {}: {}",
+                path.display(),
+                e
+            )
+        })?;
+
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => config::from_toml(&source, Some(path)),
+            Some("json") => config::from_json(&source, Some(path)),
+            _ => fail!(
+                ErrorKind::ProfileInvalid,
+                "unknown profile format (expected .toml or .json): {}",
+                path.display()
+            ),
+        }
+    }
+
     /// Configure the auth key ID to use when performing device setup
     pub fn setup_auth_key_id(mut self, key_id: Option<object::Id>) -> Self {
         self.setup_auth_key_id = key_id;
         self
     }
 
+    /// Delete the setup auth key when provisioning is complete (default: true)
+    pub fn delete_setup_auth_key(mut self, value: bool) -> Self {
+        self.delete_setup_auth_key = value;
+        self
+    }
+
     /// Enable mandatory consumption of the audit log. See:
     ///
     /// <https://docs.rs/yubihsm/latest/yubihsm/client/struct.Client.html#method.set_force_audit_option>
@@ -76,6 +145,26 @@ impl Profile {
         self
     }
 
+    /// Set the auditing options for particular commands. See:
+    ///
+    /// <https://docs.rs/yubihsm/latest/yubihsm/client/struct.Client.html#method.set_command_audit_option>
+    pub fn command_audit_options<I>(mut self, options: I) -> Self
+    where
+        I: IntoIterator<Item = AuditCommand>,
+    {
+        self.command_audit_options = options.into_iter().collect();
+        self
+    }
+
+    /// Enable FIPS mode, which is only supported by FIPS devices and must be
+    /// set before any other objects are created. See:
+    ///
+    /// <https://docs.rs/yubihsm/latest/yubihsm/client/struct.Client.html#method.set_fips_option>
+    pub fn fips_option(mut self, value: AuditOption) -> Self {
+        self.fips_option = Some(value);
+        self
+    }
+
     /// Set the initial roles to provision
     pub fn roles<I>(mut self, roles: I) -> Self
     where
@@ -94,33 +183,213 @@ impl Profile {
         self
     }
 
-    /// Use this profile to provision the YubiHSM 2 with the given client
+    /// Set the wrap keys to provision which have been split into shares for
+    /// custodians. The provisioning report records how each key was split
+    /// (but not the shares).
+    pub fn split_wrap_keys<I>(mut self, keys: I) -> Self
+    where
+        I: IntoIterator<Item = SplitWrapKey>,
+    {
+        self.split_wrap_keys = keys.into_iter().collect();
+        self
+    }
+
+    /// Set the asymmetric keys, HMAC keys and opaque objects to generate or
+    /// import
+    pub fn objects<I>(mut self, objects: I) -> Self
+    where
+        I: IntoIterator<Item = Object>,
+    {
+        self.objects = objects.into_iter().collect();
+        self
+    }
+
+    /// Store the provisioning report in the given opaque object slot, or
+    /// don't store it if `None`
+    pub fn report_object_id(mut self, object_id: Option<object::Id>) -> Self {
+        self.report_object_id = object_id;
+        self
+    }
+
+    /// Sign the provisioning report with the given ECDSA (NIST P-256) key,
+    /// e.g. one of the profile's objects, or don't sign it if `None`
+    pub fn report_signing_key_id(mut self, key_id: Option<object::Id>) -> Self {
+        self.report_signing_key_id = key_id;
+        self
+    }
+
+    /// Set how long to wait for the device to reset before giving up
+    pub fn reset_device_timeout(mut self, timeout: Duration) -> Self {
+        self.reset_device_timeout = timeout;
+        self
+    }
+
+    /// Use this profile to provision the YubiHSM 2 with the given client.
+    ///
+    /// Fails before making any changes if the provisioning report wouldn't
+    /// fit in the device.
     pub fn provision(&self, client: &Client) -> Result<Report, Error> {
+        self.check_report_size(client)?;
+
+        let settings = self.settings();
+        let (fips, settings) = settings.split_at(usize::from(self.fips_option.is_some()));
+
+        // FIPS mode can only be changed before objects are created
+        for &(setting, option) in fips {
+            info!("setting {} to: {:?}", setting, option);
+            setting.set(client, option)?;
+        }
+
         for role in &self.roles {
             info!("installing role: {}", role.authentication_key_label);
             role.create(client)?;
         }
 
-        for wrap_key in &self.wrap_keys {
+        for wrap_key in self.all_wrap_keys() {
             info!("installing wrap key: {}", &wrap_key.import_params.label);
             wrap_key.create(client)?;
         }
 
-        if self.audit_option != AuditOption::Off {
-            info!("setting force audit to: {:?}", self.audit_option);
-            client.set_force_audit_option(self.audit_option)?;
+        for object in &self.objects {
+            info!("creating {}: {}", object.object_type(), &object.label);
+            object.create(client)?;
+        }
+
+        for &(setting, option) in settings {
+            info!("setting {} to: {:?}", setting, option);
+            setting.set(client, option)?;
         }
 
-        let report = Report::new(client.device_info()?.serial_number);
+        self.report(client)
+    }
+
+    /// Compare the device the given client is connected to against this
+    /// profile, computing a plan for bringing it in line with the profile.
+    ///
+    /// Unlike `provision`, this doesn't require a freshly reset device:
+    /// missing objects are created, objects whose label, capabilities,
+    /// domains, authentication key or data differ are replaced, and objects
+    /// of the types profiles manage which aren't in the profile are deleted
+    /// (except for the key the client is authenticated with and the
+    /// provisioning report). Replacing a key which was generated within the
+    /// device generates a new key, so review the plan before applying it.
+    /// Nothing is changed until it's applied.
+    ///
+    /// Fails if the key the client is authenticated with would need to be
+    /// replaced: authenticate with a different key to apply such a profile.
+    /// Also fails if the provisioning report wouldn't fit in the device.
+    pub fn plan(&self, client: &Client) -> Result<Plan<'_>, Error> {
+        Plan::compute(self, client)
+    }
+
+    /// All wrap keys in this profile, whether split or not
+    pub(super) fn all_wrap_keys(&self) -> impl Iterator<Item = &wrap::Key> {
+        self.wrap_keys
+            .iter()
+            .chain(self.split_wrap_keys.iter().map(|split| &split.key))
+    }
+
+    /// Settings declared by this profile, in the order they should be
+    /// applied: FIPS mode (if declared) first, forced auditing last
+    pub(super) fn settings(&self) -> Vec<(Setting, AuditOption)> {
+        let fips = self.fips_option.map(|option| (Setting::Fips, option));
+
+        let commands = self
+            .command_audit_options
+            .iter()
+            .map(|command| (Setting::CommandAudit(command.0), command.1));
+
+        fips.into_iter()
+            .chain(commands)
+            .chain([(Setting::ForceAudit, self.audit_option)])
+            .collect()
+    }
+
+    /// Check the provisioning report will fit in the device (if it's to be
+    /// stored there) before making any changes, using a draft of the report
+    /// with placeholders for the fingerprints and signature which can only
+    /// be computed once the objects exist
+    pub(super) fn check_report_size(&self, client: &Client) -> Result<(), Error> {
+        if self.report_object_id.is_none() {
+            return Ok(());
+        }
+
+        let mut report = self.draft_report(client)?;
+        report.options = Some(options::to_report(&self.settings()));
+
+        if let Some(key_id) = self.report_signing_key_id {
+            report.sign_placeholder(key_id);
+        }
+
+        report.check_size()
+    }
+
+    /// Generate a provisioning report, storing it in the device if
+    /// configured to do so (replacing any previous report)
+    pub(super) fn report(&self, client: &Client) -> Result<Report, Error> {
+        let mut report = self.draft_report(client)?;
+
+        report.objects = self
+            .objects
+            .iter()
+            .map(|object| object.report(client))
+            .collect::<Result<_, _>>()?;
+
+        let settings = self
+            .settings()
+            .into_iter()
+            .map(|(setting, _)| setting)
+            .collect::<Vec<_>>();
+
+        report.options = Some(options::read(client, &settings)?);
+
+        if let Some(key_id) = self.report_signing_key_id {
+            info!("signing provisioning report with key 0x{:x}", key_id);
+            report.sign(client, key_id)?;
+        }
 
         if let Some(report_object_id) = self.report_object_id {
             info!(
                 "storing provisioning report in opaque object 0x{:x}",
                 report_object_id
             );
+
+            match client.delete_object(report_object_id, object::Type::Opaque) {
+                Err(e) if e.device_error() != Some(device::ErrorKind::ObjectNotFound) => {
+                    return Err(e.into())
+                }
+                _ => (),
+            }
+
             report.store(client, report_object_id)?;
         }
 
         Ok(report)
     }
+
+    /// Provisioning report for this profile without the device's settings,
+    /// and with placeholders for its objects' fingerprints
+    fn draft_report(&self, client: &Client) -> Result<Report, Error> {
+        let mut report = Report::new(client.device_info()?.serial_number);
+        report.objects = self.objects.iter().map(Object::draft_report).collect();
+
+        report.split_wrap_keys = self
+            .split_wrap_keys
+            .iter()
+            .map(SplitWrapKey::report)
+            .collect();
+
+        report.password_kdfs = self
+            .roles
+            .iter()
+            .filter_map(|role| {
+                Some(report::PasswordKdf {
+                    authentication_key_id: role.credentials.authentication_key_id,
+                    kdf: role.password_kdf.clone()?,
+                })
+            })
+            .collect();
+
+        Ok(report)
+    }
 }
@@ -1,25 +1,52 @@
 //! YubiHSM 2 provisioning reports which record the server where the HSM was
 //! provisioned, the username which performed the provisioning operation,
 //! and the date provisioning occurred.
+//!
+//! Reports can be signed by an ECDSA (NIST P-256) key within the HSM, making
+//! them tamper-evident, and compared against the current state of the
+//! device to find out what changed since it was provisioned. Signed reports
+//! are serialized as the exact JSON which was signed, alongside its
+//! signature, so verifying them doesn't depend on how the JSON is produced.
 
-use super::{Error, ErrorKind};
+use super::{options, Error, ErrorKind, Setting};
 use crate::{
-    device::SerialNumber,
+    authentication::Kdf,
+    device::{self, SerialNumber},
+    ecdsa::{
+        signature::{Signer as _, Verifier as _},
+        NistP256,
+    },
     object, opaque,
     uuid::{self, Uuid},
     Capability, Client, Domain,
 };
+use ::ecdsa::VerifyingKey;
 use serde::{Deserialize, Serialize};
-use std::{env, str::FromStr};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::BTreeMap,
+    env,
+    fmt::{self, Display, Write},
+    str::FromStr,
+};
 use time::OffsetDateTime as DateTime;
 
 /// Label string for the provisioning report object
 pub const REPORT_OBJECT_LABEL: &str = "yubihsm.rs setup report";
 
+/// Maximum size of a stored report: the largest opaque object the YubiHSM 2
+/// can hold
+pub const MAX_REPORT_SIZE: usize = 1968;
+
 /// Report versions
-#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[derive(Copy, Clone, Debug, Default, Deserialize, Eq, Ord, PartialEq, PartialOrd, Serialize)]
 pub struct Version(usize);
 
+impl Version {
+    /// Version of the reports generated by this crate
+    pub const CURRENT: Version = Version(4);
+}
+
 impl From<Version> for usize {
     fn from(version: Version) -> usize {
         version.0
@@ -49,6 +76,129 @@ pub struct Report {
 
     /// Software that performed the provisioning
     pub software: String,
+
+    /// Asymmetric keys, HMAC keys and opaque objects in the profile
+    #[serde(default)]
+    pub objects: Vec<Object>,
+
+    /// Device settings declared in the profile, as read back from the
+    /// device after provisioning
+    #[serde(default)]
+    pub options: Option<Options>,
+
+    /// Wrap keys which were split into shares for custodians
+    #[serde(default)]
+    pub split_wrap_keys: Vec<SplitWrapKey>,
+
+    /// How the authentication keys of roles created from passwords were
+    /// derived from them
+    #[serde(default)]
+    pub password_kdfs: Vec<PasswordKdf>,
+
+    /// Signature over the rest of the report by a key within the device.
+    ///
+    /// Signatures are only preserved by [`Report::to_json`] and parsing
+    /// reports with [`str::parse`], not by serializing reports with serde.
+    #[serde(skip)]
+    pub signature: Option<Signature>,
+}
+
+/// ECDSA (NIST P-256) signature over a report
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Signature {
+    /// ID of the key within the device which signed the report
+    pub key_id: object::Id,
+
+    /// Hex-encoded (SEC1) public key of the signing key
+    pub public_key: String,
+
+    /// Hex-encoded signature (`r || s`) over `report`
+    pub signature: String,
+
+    /// Exact JSON serialization of the report (without its signature)
+    /// which was signed
+    #[serde(skip)]
+    pub report: String,
+}
+
+/// Serialization of a signed report
+#[derive(Deserialize, Serialize)]
+struct SignedReport {
+    /// JSON serialization of the report which was signed, stored verbatim
+    report: String,
+
+    /// Signature over `report`
+    signature: Signature,
+}
+
+/// Device settings declared in the profile, named as they're spelled in
+/// profiles
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Options {
+    /// Forced auditing option
+    pub force_audit: String,
+
+    /// FIPS mode (`None` if the profile doesn't configure it)
+    #[serde(default)]
+    pub fips: Option<String>,
+
+    /// Auditing options for the commands configured by the profile
+    #[serde(default)]
+    pub command_audit: BTreeMap<String, String>,
+}
+
+/// Record of a wrap key split into shares (the shares themselves are never
+/// recorded)
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SplitWrapKey {
+    /// ID of the wrap key
+    pub wrap_key_id: object::Id,
+
+    /// Label of the wrap key
+    pub label: String,
+
+    /// Number of shares needed to recover the key
+    pub threshold: u8,
+
+    /// Number of shares the key was split into
+    pub shares: u8,
+}
+
+/// Record of how a role's authentication key was derived from its password
+/// (the password itself is never recorded)
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PasswordKdf {
+    /// ID of the role's authentication key
+    pub authentication_key_id: object::Id,
+
+    /// Key derivation function and its parameters
+    pub kdf: Kdf,
+}
+
+/// Record of an object in the profile
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Object {
+    /// ID of the object
+    pub object_id: object::Id,
+
+    /// Type of the object (e.g. `asymmetric-key`)
+    pub object_type: String,
+
+    /// Label of the object
+    pub label: String,
+
+    /// Algorithm of the object (e.g. `ecp256`)
+    pub algorithm: String,
+
+    /// Hex-encoded SHA-256 fingerprint of the public key (asymmetric keys
+    /// only)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub public_key_sha256: Option<String>,
+
+    /// Hex-encoded SHA-256 fingerprint of the DER attestation certificate
+    /// (asymmetric keys only, if requested by the profile)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attestation_certificate_sha256: Option<String>,
 }
 
 impl Report {
@@ -56,23 +206,70 @@ impl Report {
     pub fn new(serial_number: SerialNumber) -> Self {
         // TODO: handle these better on operating systems other than *IX
         Report {
-            version: Version(1),
+            version: Version::CURRENT,
             uuid: uuid::new_v4(),
             device_serial_number: serial_number.to_string(),
             username: env::var("LOGNAME").ok(),
             hostname: env::var("HOSTNAME").ok(),
             date: DateTime::now_utc(),
             software: format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")),
+            objects: Vec::new(),
+            options: None,
+            split_wrap_keys: Vec::new(),
+            password_kdfs: Vec::new(),
+            signature: None,
         }
     }
 
-    /// Serialize a report as JSON
+    /// Load a report stored in the YubiHSM at the given object ID
+    pub fn load(client: &Client, report_object_id: object::Id) -> Result<Self, Error> {
+        let json = client.get_opaque(report_object_id).map_err(|e| {
+            format_err!(
+                ErrorKind::ReportFailed,
+                "error loading report from opaque object 0x{:04x}: {}",
+                report_object_id,
+                e
+            )
+        })?;
+
+        String::from_utf8(json)
+            .map_err(|e| format_err!(ErrorKind::ReportFailed, "report isn't UTF-8: {}", e))?
+            .parse()
+    }
+
+    /// Serialize a report as JSON. Signed reports are serialized as the
+    /// JSON which was signed, alongside its signature.
     pub fn to_json(&self) -> String {
-        serde_json::to_string(self).unwrap()
+        match &self.signature {
+            Some(signature) => serde_json::to_string(&SignedReport {
+                report: signature.report.clone(),
+                signature: signature.clone(),
+            }),
+            None => serde_json::to_string(self),
+        }
+        .unwrap()
+    }
+
+    /// Check this report is small enough to store in the YubiHSM
+    pub fn check_size(&self) -> Result<(), Error> {
+        let len = self.to_json().len();
+
+        ensure!(
+            len <= MAX_REPORT_SIZE,
+            ErrorKind::ReportFailed,
+            "report is too large to store in the device ({} bytes, maximum {})",
+            len,
+            MAX_REPORT_SIZE
+        );
+
+        Ok(())
     }
 
     /// Store this report in the YubiHSM at the given object ID
     pub fn store(&self, client: &Client, report_object_id: object::Id) -> Result<(), Error> {
+        self.check_size()?;
+        let json = self.to_json();
+
         client
             .put_opaque(
                 report_object_id,
@@ -80,12 +277,338 @@ impl Report {
                 Domain::all(),
                 Capability::GET_OPAQUE,
                 opaque::Algorithm::Data,
-                self.to_json(),
+                json,
             )
             .map_err(|e| format_err!(ErrorKind::ReportFailed, "{}", e))?;
 
         Ok(())
     }
+
+    /// Sign this report with the given ECDSA (NIST P-256) key in the YubiHSM,
+    /// replacing any previous signature
+    pub fn sign(&mut self, client: &Client, key_id: object::Id) -> Result<(), Error> {
+        self.signature = None;
+
+        let signer =
+            crate::ecdsa::Signer::<NistP256>::create(client.clone(), key_id).map_err(|e| {
+                format_err!(
+                    ErrorKind::ReportFailed,
+                    "can't sign report with key 0x{:04x}: {}",
+                    key_id,
+                    e
+                )
+            })?;
+
+        let report = self.to_json();
+        let signature: crate::ecdsa::Signature<NistP256> = signer
+            .try_sign(report.as_bytes())
+            .map_err(|e| format_err!(ErrorKind::ReportFailed, "error signing report: {}", e))?;
+
+        self.signature = Some(Signature {
+            key_id,
+            public_key: encode_hex(signer.public_key().as_bytes()),
+            signature: encode_hex(&signature.to_bytes()),
+            report,
+        });
+
+        Ok(())
+    }
+
+    /// Sign this report with a placeholder signature the same size as the
+    /// one `sign` would make with the given key, for checking the size of
+    /// reports which can't be signed yet
+    pub(super) fn sign_placeholder(&mut self, key_id: object::Id) {
+        self.signature = None;
+        let report = self.to_json();
+
+        // Uncompressed SEC1 public key, and `r || s`
+        self.signature = Some(Signature {
+            key_id,
+            public_key: encode_hex(&[0; 65]),
+            signature: encode_hex(&[0; 64]),
+            report,
+        });
+    }
+
+    /// Verify this report's signature using the signing key in the YubiHSM,
+    /// and that the report hasn't been modified since it was signed
+    pub fn verify_signature(&self, client: &Client) -> Result<(), Error> {
+        let signature = self
+            .signature
+            .as_ref()
+            .ok_or_else(|| format_err!(ErrorKind::SignatureInvalid, "report isn't signed"))?;
+
+        let public_key = client
+            .get_public_key(signature.key_id)?
+            .ecdsa::<NistP256>()
+            .ok_or_else(|| {
+                format_err!(
+                    ErrorKind::SignatureInvalid,
+                    "key 0x{:04x} isn't a NIST P-256 key",
+                    signature.key_id
+                )
+            })?;
+
+        ensure!(
+            encode_hex(public_key.as_bytes()) == signature.public_key,
+            ErrorKind::SignatureInvalid,
+            "report wasn't signed by key 0x{:04x}",
+            signature.key_id
+        );
+
+        let verifying_key = VerifyingKey::<NistP256>::from_encoded_point(&public_key)
+            .map_err(|e| format_err!(ErrorKind::SignatureInvalid, "{}", e))?;
+
+        let bytes = decode_hex(&signature.signature)
+            .and_then(|bytes| crate::ecdsa::Signature::<NistP256>::from_slice(&bytes).ok())
+            .ok_or_else(|| format_err!(ErrorKind::SignatureInvalid, "malformed signature"))?;
+
+        verifying_key
+            .verify(signature.report.as_bytes(), &bytes)
+            .map_err(|_| format_err!(ErrorKind::SignatureInvalid, "report was tampered with"))?;
+
+        let signed: Report = signature.report.parse()?;
+        let unsigned = Report {
+            signature: None,
+            ..self.clone()
+        };
+
+        ensure!(
+            signed.to_json() == unsigned.to_json(),
+            ErrorKind::SignatureInvalid,
+            "report was modified after it was signed"
+        );
+
+        Ok(())
+    }
+
+    /// Compare this report to the current state of the device the given
+    /// client is connected to
+    pub fn diff(&self, client: &Client) -> Result<Vec<Discrepancy>, Error> {
+        let mut discrepancies = vec![];
+        let serial_number = client.device_info()?.serial_number.to_string();
+
+        if serial_number != self.device_serial_number {
+            discrepancies.push(Discrepancy::SerialNumber {
+                reported: self.device_serial_number.clone(),
+                actual: serial_number,
+            });
+        }
+
+        let objects = self
+            .objects
+            .iter()
+            .map(|object| {
+                (
+                    object.object_id,
+                    object.object_type.as_str(),
+                    &object.label,
+                    Some(&object.algorithm),
+                    object.public_key_sha256.as_ref(),
+                )
+            })
+            .chain(self.split_wrap_keys.iter().map(|wrap_key| {
+                (
+                    wrap_key.wrap_key_id,
+                    "wrap-key",
+                    &wrap_key.label,
+                    None,
+                    None,
+                )
+            }));
+
+        for (object_id, object_type, label, algorithm, public_key) in objects {
+            let object_type = object_type.parse::<object::Type>().map_err(|_| {
+                format_err!(
+                    ErrorKind::ReportFailed,
+                    "unknown object type in report: {:?}",
+                    object_type
+                )
+            })?;
+
+            let handle = object::Handle::new(object_id, object_type);
+
+            let info = match client.get_object_info(object_id, object_type) {
+                Ok(info) => info,
+                Err(e) if e.device_error() == Some(device::ErrorKind::ObjectNotFound) => {
+                    discrepancies.push(Discrepancy::Missing { handle });
+                    continue;
+                }
+                Err(e) => return Err(e.into()),
+            };
+
+            if info.label.to_string() != *label {
+                discrepancies.push(Discrepancy::Label {
+                    handle: handle.clone(),
+                    reported: label.clone(),
+                    actual: info.label.to_string(),
+                });
+            }
+
+            if let Some(algorithm) = algorithm {
+                let actual = info.algorithm.to_string();
+
+                if actual != *algorithm {
+                    discrepancies.push(Discrepancy::Algorithm {
+                        handle: handle.clone(),
+                        reported: algorithm.clone(),
+                        actual,
+                    });
+                }
+            }
+
+            if let Some(public_key) = public_key {
+                if fingerprint(&client.get_public_key(object_id)?.bytes) != *public_key {
+                    discrepancies.push(Discrepancy::PublicKey { handle });
+                }
+            }
+        }
+
+        if let Some(options) = &self.options {
+            for (setting, reported) in options.settings()? {
+                let actual = options::audit_option_name(setting.get(client)?);
+
+                if actual != reported {
+                    discrepancies.push(Discrepancy::Setting {
+                        setting,
+                        reported: reported.to_owned(),
+                        actual: actual.to_owned(),
+                    });
+                }
+            }
+        }
+
+        Ok(discrepancies)
+    }
+}
+
+impl Options {
+    /// Settings recorded in the report, along with their option names
+    fn settings(&self) -> Result<Vec<(Setting, &str)>, Error> {
+        let mut settings = vec![(Setting::ForceAudit, self.force_audit.as_str())];
+
+        if let Some(fips) = &self.fips {
+            settings.push((Setting::Fips, fips.as_str()));
+        }
+
+        for (name, option) in &self.command_audit {
+            let command = options::parse_command(name).ok_or_else(|| {
+                format_err!(
+                    ErrorKind::ReportFailed,
+                    "unknown command in report: {:?}",
+                    name
+                )
+            })?;
+
+            settings.push((Setting::CommandAudit(command), option.as_str()));
+        }
+
+        Ok(settings)
+    }
+}
+
+/// Difference between a report and the current state of the device
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Discrepancy {
+    /// The report is for a different device
+    SerialNumber {
+        /// Serial number in the report
+        reported: String,
+
+        /// Serial number of the device
+        actual: String,
+    },
+
+    /// An object in the report doesn't exist on the device
+    Missing {
+        /// Object type and ID
+        handle: object::Handle,
+    },
+
+    /// An object's label differs from the report
+    Label {
+        /// Object type and ID
+        handle: object::Handle,
+
+        /// Label in the report
+        reported: String,
+
+        /// Label on the device
+        actual: String,
+    },
+
+    /// An object's algorithm differs from the report
+    Algorithm {
+        /// Object type and ID
+        handle: object::Handle,
+
+        /// Algorithm in the report
+        reported: String,
+
+        /// Algorithm on the device
+        actual: String,
+    },
+
+    /// An asymmetric key's public key differs from the report
+    PublicKey {
+        /// Object type and ID
+        handle: object::Handle,
+    },
+
+    /// A device setting differs from the report
+    Setting {
+        /// Setting which differs
+        setting: Setting,
+
+        /// Option in the report
+        reported: String,
+
+        /// Option on the device
+        actual: String,
+    },
+}
+
+impl Display for Discrepancy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Discrepancy::SerialNumber { reported, actual } => {
+                write!(f, "serial number: reported {reported}, actual {actual}")
+            }
+            Discrepancy::Missing { handle } => write!(
+                f,
+                "{} 0x{:04x}: missing",
+                handle.object_type, handle.object_id
+            ),
+            Discrepancy::Label {
+                handle,
+                reported,
+                actual,
+            } => write!(
+                f,
+                "{} 0x{:04x} label: reported {:?}, actual {:?}",
+                handle.object_type, handle.object_id, reported, actual
+            ),
+            Discrepancy::Algorithm {
+                handle,
+                reported,
+                actual,
+            } => write!(
+                f,
+                "{} 0x{:04x} algorithm: reported {}, actual {}",
+                handle.object_type, handle.object_id, reported, actual
+            ),
+            Discrepancy::PublicKey { handle } => write!(
+                f,
+                "{} 0x{:04x}: public key differs",
+                handle.object_type, handle.object_id
+            ),
+            Discrepancy::Setting {
+                setting,
+                reported,
+                actual,
+            } => write!(f, "{setting}: reported {reported}, actual {actual}"),
+        }
+    }
 }
 
 impl FromStr for Report {
@@ -93,13 +616,64 @@ impl FromStr for Report {
 
     /// Parse a `yubihsm::setup::Report` from its JSON serialization
     fn from_str(s: &str) -> Result<Self, Error> {
-        serde_json::from_str(s).map_err(|e| {
+        if let Ok(SignedReport { report, signature }) = serde_json::from_str(s) {
+            let mut parsed: Self = report.parse()?;
+
+            ensure!(
+                parsed.signature.is_none(),
+                ErrorKind::ReportFailed,
+                "signed report contains another signed report"
+            );
+
+            parsed.signature = Some(Signature {
+                report,
+                ..signature
+            });
+
+            return Ok(parsed);
+        }
+
+        let report: Self = serde_json::from_str(s).map_err(|e| {
             format_err!(
                 ErrorKind::ReportFailed,
                 "error parsing yubihsm::setup::Report JSON: {}",
                 e
             )
-            .into()
-        })
+        })?;
+
+        ensure!(
+            report.version <= Version::CURRENT,
+            ErrorKind::ReportFailed,
+            "unsupported report version {} (newer than {})",
+            report.version.0,
+            Version::CURRENT.0
+        );
+
+        Ok(report)
     }
 }
+
+/// Encode bytes as lower-case hex
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut hex, byte| {
+        write!(hex, "{byte:02x}").unwrap();
+        hex
+    })
+}
+
+/// Hex-encoded SHA-256 fingerprint of the given bytes
+pub(super) fn fingerprint(bytes: &[u8]) -> String {
+    encode_hex(&Sha256::digest(bytes))
+}
+
+/// Decode hex, returning `None` if it's malformed
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
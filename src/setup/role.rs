@@ -1,7 +1,10 @@
 //! Roles for interacting with the YubiHSM 2
 
 use super::{Error, ErrorKind};
-use crate::Client;
+use crate::{
+    authentication::{CredentialSource, Kdf},
+    Client,
+};
 pub use crate::{object, Capability, Credentials, Domain};
 
 /// Roles represent accounts on the device with specific permissions
@@ -21,6 +24,10 @@ pub struct Role {
 
     /// Domains (logical partitions in the YubiHSM 2) this role has access to
     pub(super) domains: Domain,
+
+    /// Key derivation function the authentication key was derived from a
+    /// password with (if the role was created from a password)
+    pub(super) password_kdf: Option<Kdf>,
 }
 
 impl Role {
@@ -32,9 +39,35 @@ impl Role {
             capabilities: Capability::empty(),
             delegated_capabilities: Capability::empty(),
             domains: Domain::empty(),
+            password_kdf: None,
         }
     }
 
+    /// Create a new role object whose authentication key is derived from a
+    /// password with the given key derivation function. The function and its
+    /// parameters are recorded in the provisioning report, so the key can be
+    /// derived from the password again.
+    pub fn from_password(
+        authentication_key_id: object::Id,
+        password: &[u8],
+        kdf: Kdf,
+    ) -> Result<Self, Error> {
+        let mut role = Self::new(Credentials::from_password_with_kdf(
+            authentication_key_id,
+            password,
+            &kdf,
+        )?);
+
+        role.password_kdf = Some(kdf);
+        Ok(role)
+    }
+
+    /// Create a new role object, loading its credentials from the given
+    /// source (e.g. an environment variable or a key file)
+    pub fn from_source(source: impl CredentialSource) -> Result<Self, Error> {
+        Ok(Self::new(source.credentials()?))
+    }
+
     /// Set the label for this role's authentication key
     pub fn authentication_key_label<L>(mut self, label: L) -> Self
     where
@@ -7,7 +7,7 @@ mod error;
 mod info;
 mod key;
 mod message;
-mod nonce;
+pub(crate) mod nonce;
 
 pub use self::{
     algorithm::Algorithm,
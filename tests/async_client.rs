@@ -0,0 +1,93 @@
+//! `AsyncClient` integration tests (using MockHsm)
+
+#![cfg(all(feature = "async", feature = "mockhsm"))]
+
+use ed25519_dalek::Verifier;
+use yubihsm::{asymmetric, object, AsyncClient, AsyncConnector, Capability, Domain};
+
+/// Key ID to use for testing keygen/signing
+const TEST_KEY_ID: object::Id = 100;
+
+/// Message to sign when performing tests
+const TEST_MESSAGE: &[u8] = b"The YubiHSM 2 is a simple, affordable, and secure HSM solution";
+
+/// Open an `AsyncClient` connected to a fresh MockHsm
+async fn open_client() -> AsyncClient {
+    AsyncClient::open(AsyncConnector::mockhsm(), Default::default(), true)
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn ping_test() {
+    let client = open_client().await;
+    client.ping().await.unwrap();
+}
+
+#[tokio::test]
+async fn ed25519_sign_test() {
+    let client = open_client().await;
+
+    client
+        .generate_asymmetric_key(
+            TEST_KEY_ID,
+            "async test key".into(),
+            Domain::DOM1,
+            Capability::SIGN_EDDSA,
+            asymmetric::Algorithm::Ed25519,
+        )
+        .await
+        .unwrap();
+
+    let public_key = client.get_public_key(TEST_KEY_ID).await.unwrap();
+    let signature = client
+        .sign_ed25519(TEST_KEY_ID, TEST_MESSAGE)
+        .await
+        .unwrap();
+
+    assert!(
+        ed25519_dalek::VerifyingKey::try_from(public_key.ed25519().unwrap().as_ref())
+            .unwrap()
+            .verify(TEST_MESSAGE, &signature)
+            .is_ok()
+    );
+}
+
+#[tokio::test]
+async fn concurrent_commands_test() {
+    let client = open_client().await;
+
+    let tasks = (0..8u8)
+        .map(|n| {
+            let client = client.clone();
+            tokio::spawn(async move { client.echo(vec![n; 16]).await.unwrap() })
+        })
+        .collect::<Vec<_>>();
+
+    for (n, task) in tasks.into_iter().enumerate() {
+        assert_eq!(task.await.unwrap(), vec![n as u8; 16]);
+    }
+}
+
+/// Exercise `AsyncConnector::http` against the `yubihsm-connector`
+/// compatible HTTP server, backed by a MockHsm
+#[cfg(feature = "http-server")]
+#[tokio::test]
+async fn http_connector_test() {
+    use yubihsm::{connector::http::Server, Connector, HttpConfig};
+
+    let config = HttpConfig {
+        port: 12360,
+        ..Default::default()
+    };
+
+    let server = Server::new(&config, Connector::mockhsm()).unwrap();
+    std::thread::spawn(move || server.run());
+
+    let client = AsyncClient::open(AsyncConnector::http(&config), Default::default(), true)
+        .await
+        .unwrap();
+
+    assert_eq!(client.echo(TEST_MESSAGE).await.unwrap(), TEST_MESSAGE);
+    assert!(client.device_info().await.is_ok());
+}
@@ -0,0 +1,86 @@
+//! Tests for querying the status and health of `yubihsm-connector`, using
+//! the compatible HTTP server backed by a MockHsm
+
+#![cfg(all(feature = "http-server", feature = "mockhsm"))]
+
+use std::thread;
+use yubihsm::{
+    connector::{
+        http::{Server, Status},
+        HttpConnector,
+    },
+    Connector, HttpConfig,
+};
+
+/// Configuration for the server on the given port
+fn config(port: u16) -> HttpConfig {
+    HttpConfig {
+        port,
+        ..Default::default()
+    }
+}
+
+/// Start a server on the given port backed by the given connector
+fn start_server(port: u16, connector: Connector) {
+    let server = Server::new(&config(port), connector).unwrap();
+    thread::spawn(move || server.run());
+}
+
+#[test]
+fn parse_test() {
+    let status: Status =
+        "status=OK\nserial=*\nversion=3.0.4\npid=4242\naddress=localhost\nport=12345\n"
+            .parse()
+            .unwrap();
+
+    assert!(status.is_ok());
+    assert_eq!(status.serial, None);
+    assert_eq!(status.version, "3.0.4");
+    assert_eq!(status.pid, 4242);
+    assert_eq!(status.address, "localhost");
+    assert_eq!(status.port, Some(12345));
+
+    let status: Status = "status=NO_DEVICE\nserial=0001234567\nversion=3.0.4\npid=1\naddress=::1"
+        .parse()
+        .unwrap();
+
+    assert!(!status.is_ok());
+    assert_eq!(status.serial.unwrap().to_string(), "0001234567");
+    assert_eq!(status.port, None);
+
+    assert!("status=OK\nversion=3.0.4".parse::<Status>().is_err());
+    assert!("status OK".parse::<Status>().is_err());
+}
+
+#[test]
+fn status_test() {
+    start_server(12396, Connector::mockhsm());
+
+    let status = HttpConnector::new(&config(12396)).status().unwrap();
+    assert!(status.is_ok());
+    assert_eq!(status.serial, "0123456789".parse().ok());
+    assert_eq!(status.version, env!("CARGO_PKG_VERSION"));
+    assert_eq!(status.pid, std::process::id());
+    assert_eq!(status.port, Some(12396));
+}
+
+#[test]
+fn health_check_test() {
+    start_server(12397, Connector::mockhsm());
+
+    let health = HttpConnector::new(&config(12397)).health_check().unwrap();
+    assert!(health.status.is_ok());
+}
+
+#[test]
+fn unhealthy_test() {
+    // The server is reachable, but the HSM behind it isn't
+    start_server(12398, Connector::http(&config(12399)));
+
+    let connector = HttpConnector::new(&config(12398));
+    assert!(connector.status().unwrap().is_ok());
+    assert!(connector.health_check().is_err());
+
+    // Nothing is listening on this port at all
+    assert!(HttpConnector::new(&config(12399)).health_check().is_err());
+}
@@ -0,0 +1,410 @@
+//! Tests for loading credentials from environment variables, key files and
+//! other credential sources, and for external session key providers
+
+#![cfg(feature = "passwords")]
+
+use std::{env, fs, path::PathBuf};
+use yubihsm::authentication::{
+    self, CredentialSource, Credentials, EnvCredentials, FileCredentials,
+};
+
+/// Hex-encoded authentication key used by these tests
+const KEY_HEX: &str = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+
+/// Stand-in for a secret manager client: credentials stored under a name
+#[cfg(feature = "mockhsm")]
+struct SecretManager<'a> {
+    /// Secrets held by the secret manager
+    secrets: &'a std::collections::BTreeMap<&'static str, Credentials>,
+
+    /// Name of the credentials to load
+    name: &'a str,
+}
+
+#[cfg(feature = "mockhsm")]
+impl CredentialSource for SecretManager<'_> {
+    fn credentials(&self) -> Result<Credentials, authentication::Error> {
+        self.secrets.get(self.name).cloned().ok_or_else(|| {
+            authentication::ErrorKind::LoadFailed
+                .context(format!("no secret named {}", self.name))
+                .into()
+        })
+    }
+}
+
+/// Create a scratch directory for a test
+fn scratch_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("yubihsm-credentials-{}-{name}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Write a file with the given permissions
+fn write_file(path: &PathBuf, contents: &str, mode: u32) {
+    fs::write(path, contents).unwrap();
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(mode)).unwrap();
+    }
+
+    #[cfg(not(unix))]
+    let _ = mode;
+}
+
+#[test]
+fn env_credentials_test() {
+    env::set_var("CREDENTIALS_TEST_KEY", KEY_HEX);
+    env::set_var("CREDENTIALS_TEST_PASSWORD", "password");
+    env::set_var("CREDENTIALS_TEST_SHORT_KEY", "0001");
+
+    let credentials = EnvCredentials::key(2, "CREDENTIALS_TEST_KEY")
+        .credentials()
+        .unwrap();
+    assert_eq!(credentials.authentication_key_id, 2);
+    assert_eq!(
+        credentials.authentication_key.as_secret_slice(),
+        (0..32).collect::<Vec<u8>>()
+    );
+
+    let credentials = EnvCredentials::password(1, "CREDENTIALS_TEST_PASSWORD")
+        .credentials()
+        .unwrap();
+    assert_eq!(
+        credentials.authentication_key.as_secret_slice(),
+        Credentials::default().authentication_key.as_secret_slice()
+    );
+
+    let err = EnvCredentials::key(2, "CREDENTIALS_TEST_SHORT_KEY")
+        .credentials()
+        .unwrap_err();
+    assert_eq!(*err.kind(), authentication::ErrorKind::LoadFailed);
+    assert!(
+        err.to_string().contains("expected 64 hex digits"),
+        "{}",
+        err
+    );
+
+    let err = EnvCredentials::key(2, "CREDENTIALS_TEST_MISSING")
+        .credentials()
+        .unwrap_err();
+    assert!(
+        err.to_string().contains("CREDENTIALS_TEST_MISSING"),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn file_credentials_test() {
+    let dir = scratch_dir("file");
+    let key_path = dir.join("key");
+    let password_path = dir.join("password");
+
+    write_file(&key_path, &format!("{KEY_HEX}\n"), 0o600);
+    write_file(&password_path, "password\r\n", 0o400);
+
+    let credentials = FileCredentials::key(2, &key_path).credentials().unwrap();
+    assert_eq!(
+        credentials.authentication_key.as_secret_slice(),
+        (0..32).collect::<Vec<u8>>()
+    );
+
+    let credentials = FileCredentials::password(1, &password_path)
+        .credentials()
+        .unwrap();
+    assert_eq!(
+        credentials.authentication_key.as_secret_slice(),
+        Credentials::default().authentication_key.as_secret_slice()
+    );
+
+    // Files other users can read are refused
+    #[cfg(unix)]
+    for mode in [0o640, 0o604, 0o620] {
+        write_file(&key_path, KEY_HEX, mode);
+        let err = FileCredentials::key(2, &key_path)
+            .credentials()
+            .unwrap_err();
+        assert_eq!(*err.kind(), authentication::ErrorKind::PermissionsInvalid);
+        assert!(err.to_string().contains("chmod 600"), "{}", err);
+    }
+
+    // Permissions are checked on the file which is read, not on a symlink
+    #[cfg(unix)]
+    {
+        let link_path = dir.join("link");
+        let _ = fs::remove_file(&link_path);
+        std::os::unix::fs::symlink(&key_path, &link_path).unwrap();
+
+        let err = FileCredentials::key(2, &link_path)
+            .credentials()
+            .unwrap_err();
+        assert_eq!(*err.kind(), authentication::ErrorKind::PermissionsInvalid);
+
+        write_file(&key_path, KEY_HEX, 0o600);
+        assert!(FileCredentials::key(2, &link_path).credentials().is_ok());
+    }
+
+    let err = FileCredentials::key(2, dir.join("missing"))
+        .credentials()
+        .unwrap_err();
+    assert_eq!(*err.kind(), authentication::ErrorKind::LoadFailed);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(feature = "mockhsm")]
+#[test]
+fn open_with_source_test() {
+    use yubihsm::{Client, Connector};
+
+    let secrets = std::collections::BTreeMap::from([("default", Credentials::default())]);
+    let source = |name| SecretManager {
+        secrets: &secrets,
+        name,
+    };
+
+    let client = Client::open_with_source(Connector::mockhsm(), source("default"), false);
+    assert!(client.unwrap().echo(b"hello").is_ok());
+
+    let err = Client::open_with_source(Connector::mockhsm(), source("missing"), false)
+        .err()
+        .unwrap();
+    assert_eq!(*err.kind(), yubihsm::client::ErrorKind::AuthenticationError);
+
+    // Boxed sources can be chosen at runtime
+    let source: Box<dyn CredentialSource> = Box::new(Credentials::default());
+    assert!(Client::open_with_source(Connector::mockhsm(), source, false).is_ok());
+}
+
+#[cfg(all(feature = "setup", feature = "mockhsm"))]
+#[test]
+fn setup_role_from_source_test() {
+    use yubihsm::{
+        setup::{self, Profile, Role},
+        Capability, Client, Connector, Domain,
+    };
+
+    let dir = scratch_dir("setup");
+    let key_path = dir.join("admin.key");
+    write_file(&key_path, KEY_HEX, 0o600);
+
+    let admin = Role::from_source(FileCredentials::key(2, &key_path))
+        .unwrap()
+        .capabilities(Capability::all())
+        .domains(Domain::all());
+
+    let connector = Connector::mockhsm();
+    let client = Client::open(connector.clone(), Credentials::default(), false).unwrap();
+    setup::init_with_profile(client, Profile::default().roles(vec![admin])).unwrap();
+
+    let client =
+        Client::open_with_source(connector, FileCredentials::key(2, &key_path), false).unwrap();
+    assert!(client.echo(b"hello").is_ok());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+/// Stand-in for an external device holding an authentication key (e.g. a
+/// YubiKey running YubiHSM Auth) which derives SCP03 session keys on
+/// request, independently of this crate
+#[cfg(feature = "mockhsm")]
+struct ExternalDevice {
+    /// Authentication key held by the device (encryption key followed by
+    /// MAC key)
+    key: [u8; 32],
+
+    /// Check the card cryptogram before returning session keys?
+    verify_cryptogram: bool,
+
+    /// Number of session keys derived
+    derivations: std::sync::atomic::AtomicUsize,
+}
+
+#[cfg(feature = "mockhsm")]
+impl ExternalDevice {
+    /// Create a device holding the given authentication key
+    fn new(key: &authentication::Key, verify_cryptogram: bool) -> Self {
+        Self {
+            key: key.as_secret_slice().try_into().unwrap(),
+            verify_cryptogram,
+            derivations: Default::default(),
+        }
+    }
+
+    /// SCP03 KDF: NIST SP 800-108 counter mode with AES-CMAC as the PRF
+    fn derive(key: &[u8], constant: u8, context: &[u8; 16], output: &mut [u8]) {
+        use cmac::{Cmac, Mac};
+        use digest::KeyInit;
+
+        let mut data = [0u8; 32];
+        data[11] = constant;
+        data[13..15].copy_from_slice(&((output.len() * 8) as u16).to_be_bytes());
+        data[15] = 1;
+        data[16..].copy_from_slice(context);
+
+        let mut mac = <Cmac<aes::Aes128> as KeyInit>::new_from_slice(key).unwrap();
+        mac.update(&data);
+        output.copy_from_slice(&mac.finalize().into_bytes()[..output.len()]);
+    }
+}
+
+#[cfg(feature = "mockhsm")]
+impl authentication::SessionKeyProvider for ExternalDevice {
+    fn session_keys(
+        &self,
+        host_challenge: &[u8; 8],
+        card_challenge: &[u8; 8],
+        card_cryptogram: &[u8; 8],
+    ) -> Result<authentication::SessionKeys, authentication::Error> {
+        self.derivations
+            .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+
+        let mut context = [0u8; 16];
+        context[..8].copy_from_slice(host_challenge);
+        context[8..].copy_from_slice(card_challenge);
+
+        let (enc_key, mac_key) = self.key.split_at(16);
+        let (mut s_enc, mut s_mac, mut s_rmac) = ([0u8; 16], [0u8; 16], [0u8; 16]);
+        Self::derive(enc_key, 0b100, &context, &mut s_enc);
+        Self::derive(mac_key, 0b110, &context, &mut s_mac);
+        Self::derive(mac_key, 0b111, &context, &mut s_rmac);
+
+        if self.verify_cryptogram {
+            let mut expected = [0u8; 8];
+            Self::derive(&s_mac, 0, &context, &mut expected);
+
+            if &expected != card_cryptogram {
+                return Err(authentication::ErrorKind::CryptogramMismatch.into());
+            }
+        }
+
+        Ok(authentication::SessionKeys::new(s_enc, s_mac, s_rmac))
+    }
+}
+
+#[cfg(feature = "mockhsm")]
+#[test]
+fn open_with_provider_test() {
+    use std::sync::{atomic::Ordering, Arc};
+    use yubihsm::{authentication::SessionKeyProvider, Client, Connector};
+
+    let device = Arc::new(ExternalDevice::new(&authentication::Key::default(), true));
+
+    let connector = Connector::mockhsm();
+    let mut client =
+        Client::open_with_provider(connector.clone(), 1, device.clone(), true).unwrap();
+    assert!(client.echo(b"hello").is_ok());
+    assert_eq!(device.derivations.load(Ordering::SeqCst), 1);
+
+    // Rekeying asks the provider for fresh session keys
+    client.set_rekey_threshold(1);
+    assert!(client.echo(b"hello").is_ok());
+    assert_eq!(device.derivations.load(Ordering::SeqCst), 2);
+
+    // Keys which don't match the HSM's are rejected, whether or not the
+    // provider checks the card cryptogram itself
+    let wrong_device = ExternalDevice::new(&authentication::Key::random(), false);
+
+    let err = Client::open_with_provider(connector.clone(), 1, wrong_device, false)
+        .err()
+        .unwrap();
+    assert_eq!(*err.kind(), yubihsm::client::ErrorKind::AuthenticationError);
+    assert!(err.to_string().contains("(cryptogram mismatch)"), "{}", err);
+
+    let wrong_device = ExternalDevice::new(&authentication::Key::random(), true);
+
+    let err = Client::open_with_provider(connector.clone(), 1, wrong_device, false)
+        .err()
+        .unwrap();
+    assert_eq!(*err.kind(), yubihsm::client::ErrorKind::AuthenticationError);
+    assert!(
+        err.to_string().contains("card cryptogram mismatch"),
+        "{}",
+        err
+    );
+
+    // Errors from the provider are reported as authentication errors
+    struct Unplugged;
+
+    impl SessionKeyProvider for Unplugged {
+        fn session_keys(
+            &self,
+            _host_challenge: &[u8; 8],
+            _card_challenge: &[u8; 8],
+            _card_cryptogram: &[u8; 8],
+        ) -> Result<authentication::SessionKeys, authentication::Error> {
+            Err(authentication::ErrorKind::LoadFailed
+                .context("device not found")
+                .into())
+        }
+    }
+
+    let err = Client::open_with_provider(connector, 1, Unplugged, false)
+        .err()
+        .unwrap();
+    assert_eq!(*err.kind(), yubihsm::client::ErrorKind::AuthenticationError);
+}
+
+#[test]
+fn kdf_test() {
+    use yubihsm::authentication::Kdf;
+
+    let pbkdf2 = Kdf::default().derive_key(b"password").unwrap();
+    assert_eq!(
+        pbkdf2.as_secret_slice(),
+        authentication::Key::default().as_secret_slice()
+    );
+
+    #[cfg(feature = "argon2")]
+    {
+        use yubihsm::authentication::kdf::Salt;
+
+        let kdf = |salt: &str| Kdf::Argon2id {
+            salt: salt.parse().unwrap(),
+            memory_kib: 1024,
+            iterations: 1,
+            parallelism: 1,
+        };
+
+        let key = kdf("0001020304050607").derive_key(b"password").unwrap();
+        let same = kdf("0001020304050607").derive_key(b"password").unwrap();
+        let other_salt = kdf("0706050403020100").derive_key(b"password").unwrap();
+        assert_eq!(key.as_secret_slice(), same.as_secret_slice());
+        assert_ne!(key.as_secret_slice(), other_salt.as_secret_slice());
+        assert_ne!(key.as_secret_slice(), pbkdf2.as_secret_slice());
+
+        let err = kdf("0001").derive_key(b"password").unwrap_err();
+        assert_eq!(*err.kind(), authentication::ErrorKind::KdfParamsInvalid);
+        assert!("0g".parse::<Salt>().is_err());
+        assert_ne!(Salt::random(), Salt::random());
+    }
+
+    #[cfg(feature = "scrypt")]
+    {
+        let kdf = Kdf::Scrypt {
+            salt: "0001020304050607".parse().unwrap(),
+            log_n: 10,
+            r: 8,
+            p: 1,
+        };
+
+        let key = kdf.derive_key(b"password").unwrap();
+        assert_ne!(key.as_secret_slice(), pbkdf2.as_secret_slice());
+        assert_eq!(
+            key.as_secret_slice(),
+            kdf.derive_key(b"password").unwrap().as_secret_slice()
+        );
+
+        let err = Kdf::Scrypt {
+            salt: "0001020304050607".parse().unwrap(),
+            log_n: 200,
+            r: 8,
+            p: 1,
+        }
+        .derive_key(b"password")
+        .unwrap_err();
+        assert_eq!(*err.kind(), authentication::ErrorKind::KdfParamsInvalid);
+    }
+}
@@ -0,0 +1,168 @@
+//! Tests for failing over between connectors, using `yubihsm-connector`
+//! compatible HTTP servers backed by MockHsms
+
+#![cfg(all(feature = "http-server", feature = "mockhsm"))]
+
+use std::{
+    io::{Read, Write},
+    net::TcpStream,
+    thread,
+    time::Duration,
+};
+use yubihsm::{
+    connector::{http::Server, http::ShutdownHandle, Failback, FailoverConfig},
+    Client, Connector, HttpConfig,
+};
+
+/// Message to send to the HSM
+const TEST_MESSAGE: &[u8] = b"failover test";
+
+/// HTTP connector for the server on the given port
+fn http_connector(port: u16) -> Connector {
+    Connector::http(&HttpConfig {
+        port,
+        ..Default::default()
+    })
+}
+
+/// Start a server on the given port backed by the given connector
+fn start_server(port: u16, connector: Connector) -> ShutdownHandle {
+    let config = HttpConfig {
+        port,
+        ..Default::default()
+    };
+
+    let server = Server::new(&config, connector).unwrap();
+    let shutdown = server.shutdown_handle();
+    thread::spawn(move || server.run());
+    shutdown
+}
+
+/// Fetch the metrics of the server on the given port
+fn metrics(port: u16) -> String {
+    let mut socket = TcpStream::connect(("127.0.0.1", port)).unwrap();
+    socket
+        .write_all(b"GET /metrics HTTP/1.1\r\nConnection: close\r\n\r\n")
+        .unwrap();
+
+    let mut response = String::new();
+    socket.read_to_string(&mut response).unwrap();
+    response
+}
+
+#[test]
+fn unreachable_primary_test() {
+    let connector = Connector::failover(
+        [http_connector(12390), Connector::mockhsm()],
+        FailoverConfig::default(),
+    );
+
+    let client = Client::open(connector, Default::default(), true).unwrap();
+    assert_eq!(client.echo(TEST_MESSAGE).unwrap(), TEST_MESSAGE);
+}
+
+#[test]
+fn health_check_test() {
+    // The primary's server is reachable, but the HSM behind it isn't
+    start_server(12391, http_connector(12390));
+
+    let connector = Connector::failover(
+        [http_connector(12391), Connector::mockhsm()],
+        FailoverConfig::default(),
+    );
+
+    let client = Client::open(connector, Default::default(), true).unwrap();
+    assert_eq!(client.echo(TEST_MESSAGE).unwrap(), TEST_MESSAGE);
+}
+
+#[test]
+fn primary_outage_test() {
+    let primary = start_server(12392, Connector::mockhsm());
+    start_server(12393, Connector::mockhsm());
+
+    let connector = Connector::failover(
+        [http_connector(12392), http_connector(12393)],
+        FailoverConfig {
+            health_check: true,
+            failback: Failback::After(Duration::from_millis(200)),
+        },
+    );
+
+    let client = Client::open(connector, Default::default(), true).unwrap();
+    assert_eq!(client.echo(TEST_MESSAGE).unwrap(), TEST_MESSAGE);
+
+    // Wait for the primary to close its connections
+    primary.shutdown();
+    thread::sleep(Duration::from_millis(300));
+
+    // The request in progress during the outage may fail, but subsequent
+    // requests are sent to the secondary
+    let _ = client.echo(TEST_MESSAGE);
+    assert_eq!(client.echo(TEST_MESSAGE).unwrap(), TEST_MESSAGE);
+}
+
+#[test]
+fn failback_test() {
+    let secondary = start_server(12395, Connector::mockhsm());
+
+    let connector = Connector::failover(
+        [http_connector(12394), http_connector(12395)],
+        FailoverConfig {
+            health_check: true,
+            failback: Failback::After(Duration::from_millis(200)),
+        },
+    );
+
+    // The primary isn't running yet, so the secondary is used
+    let client = Client::open(connector, Default::default(), true).unwrap();
+    assert_eq!(client.echo(TEST_MESSAGE).unwrap(), TEST_MESSAGE);
+
+    // Once it's been long enough, the primary is tried again on reconnect
+    start_server(12394, Connector::mockhsm());
+    thread::sleep(Duration::from_millis(300));
+    secondary.shutdown();
+    thread::sleep(Duration::from_millis(300));
+
+    let _ = client.echo(TEST_MESSAGE);
+    assert_eq!(client.echo(TEST_MESSAGE).unwrap(), TEST_MESSAGE);
+}
+
+#[test]
+fn failback_with_healthy_secondary_test() {
+    start_server(12379, Connector::mockhsm());
+
+    let connector = Connector::failover(
+        [http_connector(12378), http_connector(12379)],
+        FailoverConfig {
+            health_check: true,
+            failback: Failback::After(Duration::from_millis(200)),
+        },
+    );
+
+    // The primary isn't running yet, so the secondary is used
+    let client = Client::open(connector.clone(), Default::default(), true).unwrap();
+    assert_eq!(client.echo(TEST_MESSAGE).unwrap(), TEST_MESSAGE);
+
+    start_server(12378, Connector::mockhsm());
+    thread::sleep(Duration::from_millis(300));
+
+    // The existing session stays on the secondary
+    assert_eq!(client.echo(TEST_MESSAGE).unwrap(), TEST_MESSAGE);
+
+    // New sessions are created on the recovered primary, even though the
+    // secondary is still healthy
+    let client = Client::open(connector, Default::default(), true).unwrap();
+    assert_eq!(client.echo(TEST_MESSAGE).unwrap(), TEST_MESSAGE);
+
+    let primary = metrics(12378);
+    assert!(
+        primary.contains("yubihsm_http_server_requests_total{command=\"CreateSession\"} 1\n"),
+        "{}",
+        primary
+    );
+    assert!(
+        primary.contains("yubihsm_http_server_requests_total{command=\"SessionMessage\"} 1\n"),
+        "{}",
+        primary
+    );
+}
@@ -0,0 +1,443 @@
+//! Tests for the `yubihsm-connector` compatible HTTP server's worker pool,
+//! shutdown, timeouts, request size limits, and access control, backed by a
+//! MockHsm
+
+#![cfg(all(feature = "http-server", feature = "mockhsm"))]
+
+use std::{
+    io::{Read, Write},
+    net::TcpStream,
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
+};
+use yubihsm::{
+    connector::http::{AccessPolicy, Server},
+    Client, Connector, HttpConfig,
+};
+
+/// Start a server on the given port with the given number of workers
+fn start_server(port: u16, timeout_ms: u64, workers: usize) -> Arc<Server> {
+    let config = HttpConfig {
+        port,
+        timeout_ms,
+        ..Default::default()
+    };
+
+    let server = Arc::new(Server::new(&config, Connector::mockhsm()).unwrap());
+    let runner = server.clone();
+    thread::spawn(move || runner.run_workers(workers));
+    server
+}
+
+/// Open a client for the server on the given port
+fn open_client(port: u16) -> Result<Client, yubihsm::client::Error> {
+    open_client_with_token(port, None)
+}
+
+/// Open a client which authenticates with the given bearer token
+fn open_client_with_token(
+    port: u16,
+    bearer_token: Option<&str>,
+) -> Result<Client, yubihsm::client::Error> {
+    let config = HttpConfig {
+        port,
+        bearer_token: bearer_token.map(ToOwned::to_owned),
+        ..Default::default()
+    };
+
+    Client::open(Connector::http(&config), Default::default(), true)
+}
+
+/// Send a raw request and return the response
+fn send_raw(port: u16, request: &[u8]) -> String {
+    let mut socket = TcpStream::connect(("127.0.0.1", port)).unwrap();
+    socket.write_all(request).unwrap();
+
+    let mut response = String::new();
+    socket.read_to_string(&mut response).unwrap();
+    response
+}
+
+/// Send a raw request and return the response's status line
+fn status_line(port: u16, request: &[u8]) -> String {
+    let response = send_raw(port, request);
+    response.lines().next().unwrap_or_default().to_owned()
+}
+
+#[test]
+fn concurrent_clients_test() {
+    start_server(12380, 5000, 4);
+
+    let clients = (0..8u8)
+        .map(|n| {
+            thread::spawn(move || {
+                let client = open_client(12380).unwrap();
+                let message = [n; 32];
+
+                for _ in 0..10 {
+                    assert_eq!(client.echo(message).unwrap(), message);
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+
+    for client in clients {
+        client.join().unwrap();
+    }
+}
+
+#[test]
+fn shutdown_test() {
+    let config = HttpConfig {
+        port: 12381,
+        ..Default::default()
+    };
+
+    let server = Server::new(&config, Connector::mockhsm()).unwrap();
+    let shutdown = server.shutdown_handle();
+    let runner = thread::spawn(move || server.run_workers(2));
+
+    let client = open_client(12381).unwrap();
+    assert_eq!(client.echo(b"before").unwrap(), b"before");
+
+    let started_at = Instant::now();
+    shutdown.shutdown();
+    assert!(shutdown.is_shutdown());
+    assert!(runner.join().unwrap().is_ok());
+    assert!(started_at.elapsed() < Duration::from_secs(2));
+
+    // The listener has stopped, so its port can be reused
+    let server = Server::new(&config, Connector::mockhsm()).unwrap();
+    server.shutdown_handle().shutdown();
+    assert!(server.run().is_ok());
+}
+
+#[test]
+fn request_too_large_test() {
+    let server = start_server(12382, 5000, 1);
+    server.set_max_body_size(16);
+
+    let request = format!(
+        "POST /connector/api HTTP/1.1\r\nHost: localhost\r\nContent-Length: {}\r\n\r\n",
+        17
+    );
+
+    assert_eq!(
+        status_line(12382, request.as_bytes()),
+        "HTTP/1.1 413 Payload Too Large"
+    );
+}
+
+#[test]
+fn malformed_request_test() {
+    start_server(12368, 5000, 1);
+
+    let mut oversized = b"GET /connector/status HTTP/1.1\r\nX-Padding: ".to_vec();
+    oversized.resize(8192, b'a');
+
+    let malformed: &[&[u8]] = &[
+        b"GET /connector/status\r\n\r\n",
+        b"GET /connector/status SPDY/3\r\n\r\n",
+        b"\r\n\r\n",
+        b"GET /connector/status HTTP/1.1\r\nHost localhost\r\n\r\n",
+        b"GET /connector/status HTTP/1.1\r\nHost: \xff\xfe\r\n\r\n",
+        &oversized,
+        b"POST /connector/api HTTP/1.1\r\nContent-Length: ten\r\n\r\n",
+        b"POST /connector/api HTTP/1.1\r\nContent-Length: -1\r\n\r\n",
+        b"POST /connector/api HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n",
+        // Bodies which aren't commands
+        b"POST /connector/api HTTP/1.1\r\nConnection: close\r\nContent-Length: 0\r\n\r\n",
+        b"POST /connector/api HTTP/1.1\r\nConnection: close\r\nContent-Length: 2\r\n\r\n\x01\x00",
+        b"POST /connector/api HTTP/1.1\r\nConnection: close\r\nContent-Length: 5\r\n\r\n\x01\x00\x09ab",
+    ];
+
+    for request in malformed {
+        assert_eq!(
+            status_line(12368, request),
+            "HTTP/1.1 400 Bad Request",
+            "{:?}",
+            String::from_utf8_lossy(request)
+        );
+    }
+
+    // The server is unaffected
+    assert_eq!(
+        status_line(
+            12368,
+            b"GET /connector/status HTTP/1.1\r\nConnection: close\r\n\r\n"
+        ),
+        "HTTP/1.1 200 OK"
+    );
+}
+
+#[test]
+fn garbage_request_test() {
+    start_server(12369, 5000, 1);
+
+    // Deterministic pseudorandom request heads (without line feeds, so the
+    // whole head is read before it's rejected)
+    let mut state = 0x2545_f491_4f6c_dd1du64;
+    let mut next_byte = || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        (state >> 32) as u8
+    };
+
+    for len in (0..256).step_by(8) {
+        let mut request: Vec<u8> = (0..len)
+            .map(|_| next_byte())
+            .filter(|&b| b != b'\n')
+            .collect();
+        request.extend_from_slice(b"\r\n\r\n");
+
+        assert_eq!(
+            status_line(12369, &request),
+            "HTTP/1.1 400 Bad Request",
+            "{:?}",
+            request
+        );
+    }
+
+    let client = open_client(12369).unwrap();
+    assert_eq!(client.echo(b"still alive").unwrap(), b"still alive");
+}
+
+#[test]
+fn slow_client_test() {
+    start_server(12383, 500, 1);
+
+    // Start a request but never finish it
+    let mut slow = TcpStream::connect(("127.0.0.1", 12383)).unwrap();
+    slow.write_all(b"POST /connector/api HTTP/1.1\r\n").unwrap();
+
+    // Other clients are still served
+    let client = open_client(12383).unwrap();
+    assert_eq!(client.echo(b"not blocked").unwrap(), b"not blocked");
+
+    // The slow client is disconnected once the request timeout elapses
+    slow.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let mut response = Vec::new();
+    assert_eq!(slow.read_to_end(&mut response).unwrap(), 0);
+}
+
+#[test]
+fn trickling_client_test() {
+    start_server(12376, 500, 1);
+
+    // Send headers a few bytes at a time, each within the request timeout
+    let mut slow = TcpStream::connect(("127.0.0.1", 12376)).unwrap();
+    slow.write_all(b"GET /connector/status HTTP/1.1\r\n")
+        .unwrap();
+
+    let started_at = Instant::now();
+
+    while started_at.elapsed() < Duration::from_secs(3) {
+        if slow.write_all(b"X: y\r\n").is_err() {
+            break;
+        }
+
+        thread::sleep(Duration::from_millis(100));
+    }
+
+    // The client is disconnected once the request timeout elapses, even
+    // though it never stopped sending
+    slow.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let mut response = Vec::new();
+    assert!(matches!(slow.read_to_end(&mut response), Ok(0) | Err(_)));
+    assert!(started_at.elapsed() < Duration::from_secs(3));
+}
+
+#[test]
+fn max_connections_test() {
+    let server = start_server(12377, 5000, 1);
+    server.set_max_connections(2);
+
+    let first = open_client(12377).unwrap();
+    let _second = TcpStream::connect(("127.0.0.1", 12377)).unwrap();
+
+    // Connections beyond the limit are closed immediately
+    let mut third = TcpStream::connect(("127.0.0.1", 12377)).unwrap();
+    third
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    let mut response = Vec::new();
+    assert_eq!(third.read_to_end(&mut response).unwrap(), 0);
+
+    // Clients already connected are still served
+    assert_eq!(first.echo(b"still served").unwrap(), b"still served");
+
+    // Closing a connection frees up its slot
+    drop(first);
+    thread::sleep(Duration::from_millis(500));
+    assert_eq!(
+        status_line(
+            12377,
+            b"GET /connector/status HTTP/1.1\r\nConnection: close\r\n\r\n"
+        ),
+        "HTTP/1.1 200 OK"
+    );
+}
+
+#[test]
+fn bearer_token_test() {
+    let server = start_server(12384, 5000, 1);
+    let policy = AccessPolicy {
+        bearer_tokens: vec!["correct horse battery staple".to_owned()],
+        ..Default::default()
+    };
+
+    server.set_access_policy(&policy).unwrap();
+
+    // Tokens aren't leaked in debug messages
+    let config = HttpConfig {
+        bearer_token: Some("correct horse battery staple".to_owned()),
+        ..Default::default()
+    };
+
+    for debug in [format!("{policy:?}"), format!("{config:?}")] {
+        assert!(!debug.contains("horse"), "{}", debug);
+    }
+
+    assert!(open_client_with_token(12384, None).is_err());
+    assert!(open_client_with_token(12384, Some("wrong")).is_err());
+
+    let client = open_client_with_token(12384, Some("correct horse battery staple")).unwrap();
+    assert_eq!(client.echo(b"authorized").unwrap(), b"authorized");
+
+    assert_eq!(
+        status_line(
+            12384,
+            b"GET /connector/status HTTP/1.1\r\nConnection: close\r\n\r\n"
+        ),
+        "HTTP/1.1 401 Unauthorized"
+    );
+}
+
+#[test]
+fn allowed_ips_test() {
+    let server = start_server(12385, 5000, 1);
+    let status_request = b"GET /connector/status HTTP/1.1\r\nConnection: close\r\n\r\n";
+
+    server
+        .set_access_policy(&AccessPolicy {
+            allowed_ips: vec!["10.0.0.0/8".to_owned(), "fd00::/8".to_owned()],
+            ..Default::default()
+        })
+        .unwrap();
+
+    assert_eq!(status_line(12385, status_request), "HTTP/1.1 403 Forbidden");
+    assert!(open_client(12385).is_err());
+
+    server
+        .set_access_policy(&AccessPolicy {
+            allowed_ips: vec!["127.0.0.1".to_owned()],
+            ..Default::default()
+        })
+        .unwrap();
+
+    assert_eq!(status_line(12385, status_request), "HTTP/1.1 200 OK");
+    assert!(open_client(12385).is_ok());
+
+    let invalid = AccessPolicy {
+        allowed_ips: vec!["127.0.0.1/33".to_owned()],
+        ..Default::default()
+    };
+    assert!(server.set_access_policy(&invalid).is_err());
+}
+
+#[test]
+fn session_rate_limit_test() {
+    let server = start_server(12386, 5000, 1);
+
+    server
+        .set_access_policy(&AccessPolicy {
+            max_sessions_per_minute: Some(2),
+            ..Default::default()
+        })
+        .unwrap();
+
+    let _first = open_client(12386).unwrap();
+    let _second = open_client(12386).unwrap();
+    assert!(open_client(12386).is_err());
+}
+
+#[test]
+fn metrics_test() {
+    start_server(12387, 5000, 1);
+
+    let client = open_client(12387).unwrap();
+    client.echo(b"measured").unwrap();
+
+    let metrics = send_raw(12387, b"GET /metrics HTTP/1.1\r\nConnection: close\r\n\r\n");
+
+    assert!(metrics.starts_with("HTTP/1.1 200 OK"), "{}", metrics);
+    assert!(metrics.contains("yubihsm_http_server_requests_total{command=\"CreateSession\"} 1\n"));
+    assert!(metrics.contains("yubihsm_http_server_requests_total{command=\"SessionMessage\"} 1\n"));
+    assert!(metrics.contains(
+        "yubihsm_http_server_request_duration_seconds_count{command=\"SessionMessage\"} 1\n"
+    ));
+    assert!(metrics.contains("yubihsm_http_server_requests_in_flight 0\n"));
+    assert!(metrics.contains("yubihsm_http_server_active_sessions 1\n"));
+}
+
+#[test]
+fn connector_error_metrics_test() {
+    // Back the server with a connector which can't connect to anything
+    let unreachable = HttpConfig {
+        port: 12389,
+        ..Default::default()
+    };
+
+    let config = HttpConfig {
+        port: 12388,
+        ..Default::default()
+    };
+
+    let server = Server::new(&config, Connector::http(&unreachable)).unwrap();
+    thread::spawn(move || server.run());
+
+    // Echo command: code 0x01, length 1, data 0x42
+    let echo = b"POST /connector/api HTTP/1.1\r\nContent-Length: 4\r\nConnection: close\r\n\r\n\x01\x00\x01\x42";
+    assert_eq!(
+        status_line(12388, echo),
+        "HTTP/1.1 500 Internal Server Error"
+    );
+
+    let metrics = send_raw(12388, b"GET /metrics HTTP/1.1\r\nConnection: close\r\n\r\n");
+
+    assert!(metrics.contains("yubihsm_http_server_requests_total{command=\"Echo\"} 1\n"));
+    assert!(metrics.contains("yubihsm_http_server_connector_errors_total{kind="));
+}
+
+#[cfg(not(feature = "tls"))]
+#[test]
+fn tls_unsupported_test() {
+    use yubihsm::connector::{self, TlsConfig};
+
+    // Configurations which need TLS are accepted by builds without it, but
+    // rejected when they're used
+    let config = HttpConfig {
+        port: 12375,
+        tls: Some(TlsConfig::default()),
+        ..Default::default()
+    };
+
+    let err = Server::new(&config, Connector::mockhsm()).err().unwrap();
+    assert_eq!(*err.kind(), connector::ErrorKind::TlsError);
+
+    let err = connector::http::HttpConnector::new(&config)
+        .health_check()
+        .unwrap_err();
+    assert_eq!(*err.kind(), connector::ErrorKind::TlsError);
+
+    let server = start_server(12375, 5000, 1);
+    let policy = AccessPolicy {
+        client_names: vec!["client.example.com".to_owned()],
+        ..Default::default()
+    };
+
+    let err = server.set_access_policy(&policy).unwrap_err();
+    assert_eq!(*err.kind(), connector::ErrorKind::TlsError);
+}
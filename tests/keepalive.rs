@@ -0,0 +1,71 @@
+//! Session keepalive and rekeying tests (using MockHsm)
+
+#![cfg(feature = "mockhsm")]
+
+use std::{thread, time::Duration};
+use yubihsm::{client::Pool, session, Client, Connector};
+
+/// Create a client with a short session timeout
+fn create_client() -> Client {
+    let mut client = Client::create(Connector::mockhsm(), Default::default()).unwrap();
+    client.set_session_timeout(session::Timeout::from_secs(4));
+    client.connect().unwrap();
+    client
+}
+
+#[test]
+fn session_expires_without_keepalive() {
+    let client = create_client();
+    let session_id = client.session().unwrap().id();
+
+    thread::sleep(Duration::from_millis(3500));
+    let new_session_id = client.session().unwrap().id();
+    assert_ne!(new_session_id, session_id);
+}
+
+#[test]
+fn keepalive_preserves_session() {
+    let client = create_client();
+    let session_id = client.session().unwrap().id();
+    let keepalive = client.keepalive();
+
+    // Activity shortly before the keepalive would have fired
+    thread::sleep(Duration::from_millis(1500));
+    client.echo(b"keepalive").unwrap();
+
+    thread::sleep(Duration::from_millis(5000));
+    let current_session_id = client.session().unwrap().id();
+    assert_eq!(current_session_id, session_id);
+
+    keepalive.stop();
+}
+
+#[test]
+fn keepalive_preserves_pooled_sessions() {
+    let pool = Pool::create(Connector::mockhsm(), Default::default(), 1).unwrap();
+    pool.set_session_timeout(session::Timeout::from_secs(4));
+
+    let client = pool.client();
+    let session_id = client.session().unwrap().id();
+    let keepalive = client.keepalive();
+
+    thread::sleep(Duration::from_millis(5000));
+    let current_session_id = client.session().unwrap().id();
+    assert_eq!(current_session_id, session_id);
+
+    keepalive.stop();
+}
+
+#[test]
+fn rekey_threshold_test() {
+    let mut client = create_client();
+    client.set_rekey_threshold(4);
+
+    for _ in 0..4 {
+        client.echo(b"rekey").unwrap();
+    }
+
+    // The fourth echo was sent over a new session
+    let messages_sent = client.session().unwrap().messages_sent().unwrap();
+    assert_eq!(messages_sent, 2);
+}
@@ -0,0 +1,124 @@
+//! Tests for routing requests to several devices by serial number, using
+//! the `yubihsm-connector` compatible HTTP server backed by MockHsms
+
+#![cfg(all(feature = "http-server", feature = "mockhsm"))]
+
+use std::{
+    io::{Read, Write},
+    net::TcpStream,
+    sync::Arc,
+    thread,
+};
+use yubihsm::{
+    connector::http::Server, device::SerialNumber, object, opaque, Capability, Client, Connector,
+    Domain, HttpConfig,
+};
+
+/// Opaque object stored on the first device
+const OBJECT_ID: object::Id = 100;
+
+/// Parse a serial number
+fn serial(s: &str) -> SerialNumber {
+    s.parse().unwrap()
+}
+
+/// Start a server on the given port backed by two MockHsms
+fn start_server(port: u16) -> Arc<Server> {
+    let config = HttpConfig {
+        port,
+        ..Default::default()
+    };
+
+    let devices = [
+        (serial("0000000001"), Connector::mockhsm()),
+        (serial("0000000002"), Connector::mockhsm()),
+    ];
+
+    let server = Arc::new(Server::with_devices(&config, devices).unwrap());
+    let runner = server.clone();
+    thread::spawn(move || runner.run());
+    server
+}
+
+/// Open a client for the device with the given serial number
+fn open_client(port: u16, serial_number: Option<&str>) -> Result<Client, yubihsm::client::Error> {
+    let config = HttpConfig {
+        port,
+        serial: serial_number.map(serial),
+        ..Default::default()
+    };
+
+    Client::open(Connector::http(&config), Default::default(), true)
+}
+
+/// Fetch the server's status page
+fn get_status(port: u16, query: &str) -> String {
+    let mut socket = TcpStream::connect(("127.0.0.1", port)).unwrap();
+
+    write!(
+        socket,
+        "GET /connector/status{query} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n"
+    )
+    .unwrap();
+
+    let mut response = String::new();
+    socket.read_to_string(&mut response).unwrap();
+    response.split("\r\n\r\n").nth(1).unwrap().to_owned()
+}
+
+#[test]
+fn routes_by_serial_number_test() {
+    start_server(12370);
+
+    let first = open_client(12370, Some("0000000001")).unwrap();
+    let second = open_client(12370, Some("0000000002")).unwrap();
+
+    first
+        .put_opaque(
+            OBJECT_ID,
+            "routed".into(),
+            Domain::DOM1,
+            Capability::default(),
+            opaque::Algorithm::Data,
+            b"first device only".as_ref(),
+        )
+        .unwrap();
+
+    assert_eq!(first.get_opaque(OBJECT_ID).unwrap(), b"first device only");
+    assert!(second.get_opaque(OBJECT_ID).is_err());
+
+    // Requests must name a serial number when there's more than one device
+    assert!(open_client(12370, None).is_err());
+    assert!(open_client(12370, Some("0000000003")).is_err());
+}
+
+#[test]
+fn add_and_remove_devices_test() {
+    let server = start_server(12371);
+    assert!(open_client(12371, Some("0000000003")).is_err());
+
+    server.add_device(serial("0000000003"), Connector::mockhsm());
+    let client = open_client(12371, Some("0000000003")).unwrap();
+    assert_eq!(client.echo(b"hot added").unwrap(), b"hot added");
+
+    assert!(server.remove_device(serial("0000000003")).is_some());
+    assert_eq!(
+        server.serial_numbers(),
+        [serial("0000000001"), serial("0000000002")]
+    );
+}
+
+#[test]
+fn status_test() {
+    start_server(12372);
+
+    let status = get_status(12372, "");
+    assert!(status.starts_with("status=OK\nserial=*\n"), "{}", status);
+    assert!(status.contains("\nport=12372"), "{}", status);
+
+    let status = get_status(12372, "?serial=0000000002");
+    assert!(status.starts_with("status=OK\nserial=0000000002\n"));
+
+    let status = get_status(12372, "?serial=42");
+    assert!(status.starts_with("status=NO_DEVICE\nserial=0000000042\n"));
+}
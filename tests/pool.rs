@@ -0,0 +1,80 @@
+//! `client::Pool` integration tests (using MockHsm)
+
+#![cfg(feature = "mockhsm")]
+
+use std::{thread, time::Duration};
+use yubihsm::{client::Pool, session, Connector};
+
+/// Number of sessions to open in each test pool
+const POOL_SIZE: usize = 4;
+
+#[test]
+fn open_test() {
+    let pool = Pool::open(Connector::mockhsm(), Default::default(), POOL_SIZE).unwrap();
+
+    assert_eq!(pool.open_sessions(), POOL_SIZE);
+    assert_eq!(pool.idle_sessions(), POOL_SIZE);
+}
+
+#[test]
+fn invalid_size_test() {
+    assert!(Pool::create(Connector::mockhsm(), Default::default(), 0).is_err());
+    assert!(Pool::create(Connector::mockhsm(), Default::default(), 17).is_err());
+}
+
+#[test]
+fn concurrent_commands_test() {
+    let pool = Pool::create(Connector::mockhsm(), Default::default(), POOL_SIZE).unwrap();
+
+    let threads = (0..16u8)
+        .map(|n| {
+            let client = pool.client();
+            thread::spawn(move || {
+                for _ in 0..8 {
+                    assert_eq!(client.echo(vec![n; 32]).unwrap(), vec![n; 32]);
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+
+    for thread in threads {
+        thread.join().unwrap();
+    }
+
+    assert!(pool.open_sessions() <= POOL_SIZE);
+    assert_eq!(pool.idle_sessions(), pool.open_sessions());
+}
+
+#[test]
+fn reset_recycles_sessions_test() {
+    let pool = Pool::open(Connector::mockhsm(), Default::default(), POOL_SIZE).unwrap();
+    let client = pool.client();
+
+    client.reset_device().unwrap();
+    assert_eq!(pool.open_sessions(), 0);
+
+    // New sessions are opened with the (restored) default credentials
+    client.ping().unwrap();
+    assert_eq!(pool.open_sessions(), 1);
+}
+
+#[test]
+fn session_timeout_test() {
+    let pool = Pool::create(Connector::mockhsm(), Default::default(), 1).unwrap();
+    assert_eq!(
+        pool.session_timeout().duration(),
+        session::Timeout::default().duration()
+    );
+
+    let timeout = session::Timeout::new(Duration::from_millis(1100));
+    pool.set_session_timeout(timeout);
+    assert_eq!(pool.session_timeout().duration(), timeout.duration());
+
+    let client = pool.client();
+    assert_eq!(client.echo(b"first").unwrap(), b"first");
+
+    // The idle session times out and is replaced on the next command
+    thread::sleep(Duration::from_millis(200));
+    assert_eq!(client.echo(b"second").unwrap(), b"second");
+    assert_eq!(pool.open_sessions(), 1);
+}
@@ -11,6 +11,17 @@ use yubihsm::setup::{Profile, Role};
 const ROOT_KEY_ID: object::Id = 1;
 const ROOT_KEY_LABEL: &str = "root key";
 
+/// Hex-encoded SHA-256 digest of the given bytes
+#[cfg(all(feature = "setup", feature = "mockhsm"))]
+fn hex_sha256(bytes: &[u8]) -> String {
+    use sha2::{Digest, Sha256};
+
+    Sha256::digest(bytes)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
 #[cfg(feature = "setup")]
 #[test]
 fn setup_test() {
@@ -23,3 +34,1189 @@ fn setup_test() {
     // TODO: actually test provisioning the profile
     let _profile = Profile::default().roles(vec![root_role]);
 }
+
+/// Profile used by the profile loading tests. Each test uses its own
+/// environment variables, as tests run concurrently.
+#[cfg(feature = "setup")]
+fn profile_toml(prefix: &str) -> String {
+    format!(
+        r#"
+audit = "on"
+report_object_id = 0x1000
+
+[[roles]]
+authentication_key_id = 2
+label = "admin"
+credentials = {{ password_env = "{prefix}_ADMIN_PASSWORD" }}
+capabilities = "all"
+delegated_capabilities = "all"
+domains = "all"
+
+[[roles]]
+authentication_key_id = 3
+label = "signer"
+credentials = {{ key_env = "{prefix}_SIGNER_KEY" }}
+capabilities = ["sign-ecdsa", "sign-eddsa"]
+domains = [1, 2]
+
+[[wrap_keys]]
+id = 1
+label = "backup"
+key = {{ key_env = "{prefix}_WRAP_KEY" }}
+capabilities = ["export-wrapped", "import-wrapped"]
+delegated_capabilities = "all"
+domains = "all"
+"#
+    )
+}
+
+/// Set the environment variables used by `profile_toml`
+#[cfg(feature = "setup")]
+fn set_profile_env(prefix: &str) {
+    std::env::set_var(format!("{prefix}_ADMIN_PASSWORD"), "admin password");
+    std::env::set_var(format!("{prefix}_SIGNER_KEY"), "42".repeat(32));
+    std::env::set_var(format!("{prefix}_WRAP_KEY"), "24".repeat(32));
+}
+
+#[cfg(all(feature = "setup", feature = "mockhsm"))]
+#[test]
+fn profile_toml_provisioning_test() {
+    use yubihsm::{setup, Client, Connector};
+
+    set_profile_env("PROVISIONING_TEST");
+    let profile = Profile::from_toml(&profile_toml("PROVISIONING_TEST")).unwrap();
+
+    let connector = Connector::mockhsm();
+    let client = Client::open(connector.clone(), Credentials::default(), false).unwrap();
+    setup::init_with_profile(client, profile.clone()).unwrap();
+
+    let client = Client::open(
+        connector,
+        Credentials::from_password(2, b"admin password"),
+        false,
+    )
+    .unwrap();
+
+    // The wrap key's key material can't be read back to check it
+    let plan = profile.plan(&client).unwrap();
+    assert!(plan.is_empty(), "{}", plan);
+    assert!(plan
+        .unverified()
+        .any(|handle| *handle == object::Handle::new(1, object::Type::WrapKey)));
+    assert!(plan
+        .to_string()
+        .contains("note: wrap-key 0x0001 \"backup\": secret not verifiable\n"));
+
+    let signer = client
+        .get_object_info(3, object::Type::AuthenticationKey)
+        .unwrap();
+    assert_eq!(signer.label.to_string(), "signer");
+    assert_eq!(
+        signer.capabilities,
+        Capability::SIGN_ECDSA | Capability::SIGN_EDDSA
+    );
+    assert_eq!(signer.domains, Domain::DOM1 | Domain::DOM2);
+
+    let wrap_key = client.get_object_info(1, object::Type::WrapKey).unwrap();
+    assert_eq!(wrap_key.label.to_string(), "backup");
+    assert_eq!(wrap_key.domains, Domain::all());
+
+    assert!(client.get_opaque(0x1000).is_ok());
+}
+
+#[cfg(feature = "setup")]
+#[test]
+fn profile_json_test() {
+    std::env::set_var("JSON_TEST_PASSWORD", "password");
+
+    let json = r#"{
+        "delete_setup_auth_key": false,
+        "roles": [{
+            "authentication_key_id": 2,
+            "credentials": { "password_env": "JSON_TEST_PASSWORD" },
+            "capabilities": ["get-log-entries"],
+            "domains": [16]
+        }]
+    }"#;
+
+    assert!(Profile::from_json(json).is_ok());
+
+    let err = Profile::from_json(&json.replace("[16]", "[17]")).unwrap_err();
+    assert!(err.to_string().contains("line 8, column"), "{}", err);
+    assert!(err.to_string().contains("invalid domain: 17"), "{}", err);
+
+    // Capabilities and domains can also be given in yubihsm-shell's syntax
+    let json = json
+        .replace("[\"get-log-entries\"]", "\"get-log-entries,get-option\"")
+        .replace("[16]", "\"1,16\"");
+    assert!(Profile::from_json(&json).is_ok());
+
+    let err = Profile::from_json(&json.replace("1,16", "1,17")).unwrap_err();
+    assert!(
+        err.to_string()
+            .contains("expected \"all\" or a list (got \"1,17\")"),
+        "{}",
+        err
+    );
+}
+
+#[cfg(feature = "setup")]
+#[test]
+fn profile_errors_test() {
+    set_profile_env("ERRORS_TEST");
+    let toml = profile_toml("ERRORS_TEST");
+    assert!(Profile::from_toml(&toml).is_ok());
+
+    let expect_error = |toml: String, expected: &str| {
+        let err = Profile::from_toml(&toml).unwrap_err();
+        assert!(err.to_string().contains(expected), "{}", err);
+    };
+
+    expect_error(
+        toml.replace("\"sign-eddsa\"", "\"sign-everything\""),
+        "line 17, column 16: unknown capability: \"sign-everything\"",
+    );
+    expect_error(
+        toml.replace("domains = [1, 2]", "domains = [1, 20]"),
+        "line 18, column 11: invalid domain: 20",
+    );
+    expect_error(
+        toml.replace("domains = [1, 2]", "domains = \"some\""),
+        "line 18, column 11: expected \"all\" or a list",
+    );
+    expect_error(
+        toml.replace("audit = \"on\"", "audit = \"sometimes\""),
+        "line 2, column 9: invalid value",
+    );
+    expect_error(
+        toml.replace("label = \"admin\"", "lable = \"admin\""),
+        "line 7, column 1: unknown field `lable`",
+    );
+    expect_error(
+        toml.replace(
+            "label = \"admin\"",
+            &format!("label = \"{}\"", "x".repeat(41)),
+        ),
+        "line 7, column 9: invalid label: label too long",
+    );
+    expect_error(
+        toml.replace("ERRORS_TEST_SIGNER_KEY", "ERRORS_TEST_MISSING"),
+        "line 16, column 15: environment variable ERRORS_TEST_MISSING is not set",
+    );
+    expect_error(
+        toml.replace(
+            "key_env = \"ERRORS_TEST_WRAP_KEY\"",
+            "password_env = \"ERRORS_TEST_WRAP_KEY\"",
+        ),
+        "line 23, column 7: wrap keys can't be derived from passwords",
+    );
+    expect_error(
+        toml.replace("authentication_key_id = 3", "authentication_key_id = 2"),
+        "line 14, column 25: duplicate authentication key ID: 0x0002",
+    );
+    expect_error(
+        toml.replace(
+            "domains = [1, 2]",
+            "domains = [1, 2]\nkdf = { algorithm = \"pbkdf2\" }",
+        ),
+        "line 19, column 7: role 0x0003: kdf only applies to password credentials",
+    );
+    expect_error(
+        format!("{toml}\n[[wrap_keys]]\nid = 1\nkey = {{ key_env = \"ERRORS_TEST_WRAP_KEY\" }}\n"),
+        "line 29, column 6: duplicate wrap key ID: 0x0001",
+    );
+    expect_error(
+        format!("{toml}\n[[objects]]\ntype = \"opaque\"\nid = 1\nalgorithm = \"opaque-data\"\n"),
+        "line 28, column 1: opaque 0x0001: missing data_file",
+    );
+    expect_error(
+        format!("{toml}\n[[objects]]\ntype = \"opaque\"\nid = 1\nalgorithm = \"ed25519\"\n"),
+        "line 31, column 13: opaque 0x0001: algorithm",
+    );
+
+    // Errors found in JSON profiles after they're deserialized are located
+    // the same way
+    let json = r#"{
+    "roles": [
+        { "authentication_key_id": 2, "credentials": { "password_env": "ERRORS_TEST_ADMIN_PASSWORD" } },
+        { "authentication_key_id": 3, "credentials": { "key_env": "ERRORS_TEST_SIGNER_KEY" } }
+    ]
+}"#;
+    assert!(Profile::from_json(json).is_ok());
+
+    let expect_json_error = |json: String, expected: &str| {
+        let err = Profile::from_json(&json).unwrap_err();
+        assert!(err.to_string().contains(expected), "{}", err);
+    };
+
+    expect_json_error(
+        json.replace(
+            "\"authentication_key_id\": 3",
+            "\"authentication_key_id\": 2",
+        ),
+        "line 4, column 36: duplicate authentication key ID: 0x0002",
+    );
+    expect_json_error(
+        json.replace(
+            "\"ERRORS_TEST_SIGNER_KEY\" }",
+            "\"ERRORS_TEST_SIGNER_KEY\" }, \"kdf\": { \"algorithm\": \"pbkdf2\" }",
+        ),
+        "line 4, column 102: role 0x0003: kdf only applies to password credentials",
+    );
+}
+
+#[cfg(feature = "setup")]
+#[test]
+fn profile_load_test() {
+    set_profile_env("LOAD_TEST");
+
+    let dir = std::env::temp_dir().join(format!("yubihsm-profile-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let path = dir.join("profile.toml");
+    std::fs::write(&path, profile_toml("LOAD_TEST")).unwrap();
+    assert!(Profile::load(&path).is_ok());
+
+    std::fs::write(&path, "roles = 42\n").unwrap();
+    let err = Profile::load(&path).unwrap_err();
+    assert!(
+        err.to_string()
+            .contains(&format!("{}: line 1, column 9", path.display())),
+        "{}",
+        err
+    );
+
+    assert!(Profile::load(dir.join("profile.yaml")).is_err());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(all(feature = "setup", feature = "mockhsm"))]
+#[test]
+fn profile_plan_apply_test() {
+    use yubihsm::{
+        setup::{self, Change},
+        Client, Connector,
+    };
+
+    let admin_credentials = Credentials::from_password(2, b"admin password");
+    let admin = Role::new(admin_credentials.clone())
+        .authentication_key_label("admin")
+        .capabilities(Capability::all())
+        .delegated_capabilities(Capability::all())
+        .domains(Domain::all());
+
+    let signer = |domains| {
+        Role::new(Credentials::from_password(3, b"signer password"))
+            .authentication_key_label("signer")
+            .capabilities(Capability::SIGN_ECDSA)
+            .domains(domains)
+    };
+
+    let connector = Connector::mockhsm();
+    let client = Client::open(connector.clone(), Credentials::default(), false).unwrap();
+    let profile = Profile::default().roles(vec![admin.clone(), signer(Domain::DOM1)]);
+    setup::init_with_profile(client, profile).unwrap();
+
+    let client = Client::open(connector, admin_credentials, false).unwrap();
+
+    // Add an object which isn't in the profile
+    client
+        .put_authentication_key(
+            6,
+            "stray".into(),
+            Domain::all(),
+            Capability::empty(),
+            Capability::empty(),
+            authentication::Algorithm::YubicoAes,
+            authentication::Key::random(),
+        )
+        .unwrap();
+
+    let auditor = Role::new(Credentials::from_password(5, b"auditor password"))
+        .authentication_key_label("auditor")
+        .capabilities(Capability::GET_LOG_ENTRIES)
+        .domains(Domain::all());
+
+    let profile =
+        Profile::default().roles(vec![admin, signer(Domain::DOM1 | Domain::DOM2), auditor]);
+
+    let plan = profile.plan(&client).unwrap();
+    assert_eq!(plan.changes().len(), 3, "{}", plan);
+    assert!(matches!(plan.changes()[2], Change::Delete { .. }));
+    assert_eq!(
+        plan.to_string(),
+        "replace authentication-key 0x0003 \"signer\" (domains: 1 -> 1,2)\n\
+         create authentication-key 0x0005 \"auditor\"\n\
+         delete authentication-key 0x0006 \"stray\"\n"
+    );
+
+    plan.apply(&client).unwrap();
+    assert!(profile.plan(&client).unwrap().is_empty());
+
+    let signer = client
+        .get_object_info(3, object::Type::AuthenticationKey)
+        .unwrap();
+    assert_eq!(signer.domains, Domain::DOM1 | Domain::DOM2);
+    assert!(client
+        .get_object_info(6, object::Type::AuthenticationKey)
+        .is_err());
+}
+
+#[cfg(all(feature = "setup", feature = "mockhsm"))]
+#[test]
+fn profile_plan_secrets_test() {
+    use yubihsm::{
+        asymmetric,
+        setup::{self, Change, Difference},
+        Client, Connector,
+    };
+
+    let admin_credentials = Credentials::from_password(2, b"admin password");
+    let admin = Role::new(admin_credentials.clone())
+        .authentication_key_label("admin")
+        .capabilities(Capability::all())
+        .delegated_capabilities(Capability::all())
+        .domains(Domain::all());
+
+    let signer = |password: &[u8]| {
+        Role::new(Credentials::from_password(3, password))
+            .authentication_key_label("signer")
+            .capabilities(Capability::SIGN_ECDSA)
+            .domains(Domain::DOM1)
+    };
+
+    let signing_key = |label| {
+        setup::Object::generate_asymmetric(0x100, asymmetric::Algorithm::EcP256)
+            .label(label)
+            .capabilities(Capability::SIGN_ECDSA)
+            .domains(Domain::DOM1)
+    };
+
+    let connector = Connector::mockhsm();
+    let client = Client::open(connector.clone(), Credentials::default(), false).unwrap();
+    let profile = Profile::default()
+        .roles(vec![admin.clone(), signer(b"old password")])
+        .objects(vec![signing_key("signer")]);
+    setup::init_with_profile(client, profile.clone()).unwrap();
+
+    let client = Client::open(connector.clone(), admin_credentials, false).unwrap();
+    assert!(profile.plan(&client).unwrap().is_empty());
+
+    // Changing a role's password replaces its authentication key, and
+    // relabeling a generated key is flagged as destroying it
+    let profile = Profile::default()
+        .roles(vec![admin.clone(), signer(b"new password")])
+        .objects(vec![signing_key("signing key")]);
+
+    let plan = profile.plan(&client).unwrap();
+    assert_eq!(
+        plan.changes()[0],
+        Change::Replace {
+            handle: object::Handle::new(3, object::Type::AuthenticationKey),
+            label: "signer".into(),
+            differences: vec![Difference::AuthenticationKey],
+            destructive: false,
+        }
+    );
+    assert_eq!(
+        plan.to_string(),
+        "replace authentication-key 0x0003 \"signer\" (authentication key differs)\n\
+         replace asymmetric-key 0x0100 \"signer\" (label: \"signer\" -> \"signing key\") \
+         [destroys device-generated key]\n"
+    );
+
+    plan.apply(&client).unwrap();
+    assert!(profile.plan(&client).unwrap().is_empty());
+    assert!(Client::open(
+        connector.clone(),
+        Credentials::from_password(3, b"old password"),
+        false
+    )
+    .is_err());
+    assert!(Client::open(
+        connector,
+        Credentials::from_password(3, b"new password"),
+        false
+    )
+    .is_ok());
+
+    // The key the session is authenticated with is never replaced
+    let profile =
+        Profile::default().roles(vec![admin.domains(Domain::DOM1), signer(b"new password")]);
+    let err = profile.plan(&client).unwrap_err();
+    assert_eq!(*err.kind(), setup::ErrorKind::SetupFailed);
+    assert!(
+        err.to_string()
+            .contains("can't replace authentication-key 0x0002 \"admin\""),
+        "{}",
+        err
+    );
+}
+
+#[cfg(all(feature = "setup", feature = "mockhsm"))]
+#[test]
+fn profile_objects_test() {
+    use yubihsm::{asymmetric, hmac, opaque, setup, Client, Connector};
+
+    let admin_credentials = Credentials::from_password(2, b"admin password");
+    let admin = Role::new(admin_credentials.clone())
+        .capabilities(Capability::all())
+        .domains(Domain::all());
+
+    let objects = vec![
+        setup::Object::generate_asymmetric(0x100, asymmetric::Algorithm::EcP256)
+            .label("signing key")
+            .capabilities(Capability::SIGN_ECDSA)
+            .domains(Domain::DOM1)
+            .attest(None),
+        setup::Object::generate_hmac(0x101, hmac::Algorithm::Sha256)
+            .capabilities(Capability::SIGN_HMAC)
+            .domains(Domain::DOM1),
+        setup::Object::put_opaque(0x100, opaque::Algorithm::Data, b"hello".to_vec())
+            .label("greeting")
+            .capabilities(Capability::GET_OPAQUE)
+            .domains(Domain::DOM1),
+    ];
+
+    let connector = Connector::mockhsm();
+    let client = Client::open(connector.clone(), Credentials::default(), false).unwrap();
+    let profile = Profile::default().roles(vec![admin]).objects(objects);
+    let report = setup::init_with_profile(client, profile.clone()).unwrap();
+
+    let client = Client::open(connector, admin_credentials, false).unwrap();
+    let public_key = client.get_public_key(0x100).unwrap();
+
+    assert_eq!(report.objects.len(), 3);
+    let signing_key = &report.objects[0];
+    assert_eq!(signing_key.object_type, "asymmetric-key");
+    assert_eq!(signing_key.algorithm, "ecp256");
+    assert_eq!(signing_key.label, "signing key");
+    assert_eq!(
+        signing_key.public_key_sha256.as_deref().unwrap(),
+        hex_sha256(public_key.as_ref())
+    );
+    assert!(signing_key.attestation_certificate_sha256.is_some());
+    assert!(report.objects[1].public_key_sha256.is_none());
+    assert_eq!(client.get_opaque(0x100).unwrap(), b"hello");
+
+    // The stored report records the objects too
+    let stored: setup::Report = String::from_utf8(client.get_opaque(0xfffe).unwrap())
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert_eq!(
+        stored.objects[0].public_key_sha256,
+        signing_key.public_key_sha256
+    );
+
+    // Reconciling the same profile is a no-op
+    assert!(profile.plan(&client).unwrap().is_empty());
+}
+
+#[cfg(all(feature = "setup", feature = "mockhsm"))]
+#[test]
+fn report_size_test() {
+    use yubihsm::{
+        asymmetric,
+        setup::{self, report::MAX_REPORT_SIZE, Report},
+        Client, Connector,
+    };
+
+    let admin_credentials = Credentials::from_password(2, b"admin password");
+    let admin = Role::new(admin_credentials.clone())
+        .capabilities(Capability::all())
+        .domains(Domain::all());
+
+    // An RSA key's public key and attestation certificate are recorded by
+    // fingerprint, so they fit in the stored report
+    let objects = vec![
+        setup::Object::generate_asymmetric(0x100, asymmetric::Algorithm::Rsa2048)
+            .label("rsa key")
+            .capabilities(Capability::SIGN_PKCS)
+            .domains(Domain::DOM1)
+            .attest(None),
+    ];
+
+    let connector = Connector::mockhsm();
+    let client = Client::open(connector.clone(), Credentials::default(), false).unwrap();
+    let profile = Profile::default().roles(vec![admin]).objects(objects);
+    let report = setup::init_with_profile(client, profile).unwrap();
+
+    let client = Client::open(connector, admin_credentials, false).unwrap();
+    let stored = Report::load(&client, 0xfffe).unwrap();
+    assert_eq!(
+        stored.objects[0].public_key_sha256.as_deref().unwrap(),
+        hex_sha256(client.get_public_key(0x100).unwrap().as_ref())
+    );
+    assert!(stored.objects[0].attestation_certificate_sha256.is_some());
+    assert!(stored.diff(&client).unwrap().is_empty());
+
+    // Reports which are too large to store are rejected up front
+    let mut large = report.clone();
+    large.objects = vec![report.objects[0].clone(); 20];
+    assert!(large.to_json().len() > MAX_REPORT_SIZE);
+
+    let err = large.store(&client, 0xfffd).unwrap_err();
+    assert_eq!(*err.kind(), setup::ErrorKind::ReportFailed);
+    assert!(err.to_string().contains("too large"), "{}", err);
+
+    // Profiles whose report wouldn't fit are refused before any changes
+    // are made to the device
+    let objects = (0..20)
+        .map(|i| {
+            setup::Object::generate_asymmetric(0x200 + i, asymmetric::Algorithm::Rsa2048)
+                .label("rsa key")
+                .capabilities(Capability::SIGN_PKCS)
+                .domains(Domain::DOM1)
+                .attest(None)
+        })
+        .collect::<Vec<_>>();
+
+    let profile = Profile::default().objects(objects);
+
+    let err = profile.plan(&client).unwrap_err();
+    assert!(err.to_string().contains("too large"), "{}", err);
+
+    let err = profile.provision(&client).unwrap_err();
+    assert!(err.to_string().contains("too large"), "{}", err);
+
+    let connector = Connector::mockhsm();
+    let fresh = Client::open(connector.clone(), Credentials::default(), false).unwrap();
+    let objects = fresh.list_objects(&[]).unwrap().len();
+    let err = setup::init_with_profile(fresh, profile).unwrap_err();
+    assert!(err.to_string().contains("too large"), "{}", err);
+
+    // The default authentication key is still in place, and no objects
+    // were created
+    let fresh = Client::open(connector, Credentials::default(), false).unwrap();
+    assert_eq!(fresh.list_objects(&[]).unwrap().len(), objects);
+    assert!(client
+        .get_object_info(0x200, yubihsm::object::Type::AsymmetricKey)
+        .is_err());
+}
+
+#[cfg(feature = "setup")]
+#[test]
+fn profile_objects_config_test() {
+    let dir = std::env::temp_dir().join(format!("yubihsm-objects-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("cert.der"), b"not really a certificate").unwrap();
+
+    let toml = r#"
+[[objects]]
+type = "asymmetric-key"
+id = 0x100
+algorithm = "ed25519"
+capabilities = ["sign-eddsa"]
+domains = [1]
+attest = true
+
+[[objects]]
+type = "opaque"
+id = 0x100
+algorithm = "opaque-x509-certificate"
+data_file = "cert.der"
+"#;
+
+    let path = dir.join("profile.toml");
+    std::fs::write(&path, toml).unwrap();
+    assert!(Profile::load(&path).is_ok());
+
+    let expect_error = |toml: String, expected: &str| {
+        std::fs::write(&path, toml).unwrap();
+        let err = Profile::load(&path).unwrap_err();
+        assert!(err.to_string().contains(expected), "{}", err);
+    };
+
+    expect_error(
+        toml.replace("\"ed25519\"", "\"ed448\""),
+        "line 5, column 13: unknown algorithm: \"ed448\"",
+    );
+    expect_error(
+        toml.replace("\"ed25519\"", "\"hmac-sha256\""),
+        "asymmetric-key 0x0100: algorithm Hmac(Sha256) doesn't match the object type",
+    );
+    expect_error(
+        toml.replace("\"opaque\"", "\"wrap-key\""),
+        "wrap-key 0x0100: wrap-key objects aren't supported",
+    );
+    expect_error(
+        toml.replace("cert.der", "missing.der"),
+        "opaque 0x0100: couldn't read",
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(all(feature = "setup", feature = "mockhsm"))]
+#[test]
+fn profile_options_test() {
+    use yubihsm::{
+        audit::AuditCommand,
+        command,
+        setup::{self, Change, Setting},
+        AuditOption, Client, Connector,
+    };
+
+    let admin_credentials = Credentials::from_password(2, b"admin password");
+    let admin = Role::new(admin_credentials.clone())
+        .capabilities(Capability::all())
+        .domains(Domain::all());
+
+    let connector = Connector::mockhsm();
+    let client = Client::open(connector.clone(), Credentials::default(), false).unwrap();
+    let profile = Profile::default()
+        .roles(vec![admin])
+        .fips_option(AuditOption::On)
+        .command_audit_options(vec![AuditCommand(
+            command::Code::SignEcdsa,
+            AuditOption::Off,
+        )]);
+    let report = setup::init_with_profile(client, profile.clone()).unwrap();
+
+    let options = report.options.as_ref().unwrap();
+    assert_eq!(options.force_audit, "off");
+    assert_eq!(options.fips.as_deref(), Some("on"));
+    assert_eq!(options.command_audit.len(), 1);
+    assert_eq!(options.command_audit["sign-ecdsa"], "off");
+
+    let client = Client::open(connector, admin_credentials, false).unwrap();
+    assert_eq!(
+        client
+            .get_command_audit_option(command::Code::SignEcdsa)
+            .unwrap(),
+        AuditOption::Off
+    );
+    assert!(profile.plan(&client).unwrap().is_empty());
+
+    let profile = profile.command_audit_options(vec![
+        AuditCommand(command::Code::SignEcdsa, AuditOption::On),
+        AuditCommand(command::Code::SignEddsa, AuditOption::Fix),
+    ]);
+
+    let plan = profile.plan(&client).unwrap();
+    assert!(matches!(
+        plan.changes()[0],
+        Change::Set {
+            setting: Setting::CommandAudit(command::Code::SignEcdsa),
+            ..
+        }
+    ));
+    assert_eq!(
+        plan.to_string(),
+        "set command-audit sign-ecdsa: off -> on\n\
+         set command-audit sign-eddsa: on -> fix\n"
+    );
+
+    plan.apply(&client).unwrap();
+    assert!(profile.plan(&client).unwrap().is_empty());
+}
+
+#[cfg(feature = "setup")]
+#[test]
+fn profile_options_config_test() {
+    let prefix = "PROFILE_OPTIONS";
+    set_profile_env(prefix);
+
+    let toml = profile_toml(prefix).replace(
+        "report_object_id = 0x1000\n",
+        "report_object_id = 0x1000\nfips = \"on\"\n\n\
+         [command_audit]\nsign-ecdsa = \"on\"\nput-authentication-key = \"fix\"\n",
+    );
+    assert!(Profile::from_toml(&toml).is_ok());
+
+    let err = Profile::from_toml(&toml.replace("sign-ecdsa =", "sign-everything =")).unwrap_err();
+    assert!(
+        err.to_string()
+            .contains("unknown command: \"sign-everything\""),
+        "{}",
+        err
+    );
+
+    let err = Profile::from_toml(&toml.replace("fips = \"on\"", "fips = \"maybe\"")).unwrap_err();
+    assert!(err.to_string().contains("line 4"), "{}", err);
+}
+
+#[cfg(all(feature = "setup", feature = "mockhsm"))]
+#[test]
+fn split_wrap_key_test() {
+    use yubihsm::{
+        setup::{Share, SplitWrapKey},
+        wrap, Client, Connector,
+    };
+
+    let split = SplitWrapKey::generate(1, wrap::Algorithm::Aes256Ccm, 3, 5).unwrap();
+    assert_eq!(split.shares().len(), 5);
+    assert_eq!(split.threshold(), 3);
+
+    // Shares survive being written down and typed back in
+    let shares = split
+        .shares()
+        .iter()
+        .map(|share| share.encode().to_uppercase().parse::<Share>().unwrap())
+        .collect::<Vec<_>>();
+
+    assert_eq!(shares[4].index(), 5);
+    assert_eq!(shares[4].key_id(), 1);
+
+    // Objects exported with the original key can be imported with the key
+    // recovered from any subset of at least 3 shares
+    let wrap_capabilities = Capability::EXPORT_WRAPPED | Capability::IMPORT_WRAPPED;
+    let client = Client::open(Connector::mockhsm(), Credentials::default(), false).unwrap();
+    split
+        .key()
+        .clone()
+        .capabilities(wrap_capabilities)
+        .delegated_capabilities(Capability::all())
+        .domains(Domain::all())
+        .create(&client)
+        .unwrap();
+
+    client
+        .put_opaque(
+            0x100,
+            "secret".into(),
+            Domain::DOM1,
+            Capability::GET_OPAQUE | Capability::EXPORTABLE_UNDER_WRAP,
+            yubihsm::opaque::Algorithm::Data,
+            b"backed up".to_vec(),
+        )
+        .unwrap();
+    let backup = client
+        .export_wrapped(1, object::Type::Opaque, 0x100)
+        .unwrap();
+
+    let mut subsets = vec![shares.clone(), shares[1..].to_vec()];
+
+    for a in 0..5 {
+        for b in a + 1..5 {
+            for c in b + 1..5 {
+                // Shares can be given in any order
+                subsets.push(vec![
+                    shares[c].clone(),
+                    shares[a].clone(),
+                    shares[b].clone(),
+                ]);
+            }
+        }
+    }
+
+    assert_eq!(subsets.len(), 12);
+
+    for subset in subsets {
+        let client = Client::open(Connector::mockhsm(), Credentials::default(), false).unwrap();
+        SplitWrapKey::recover(&subset)
+            .unwrap()
+            .capabilities(wrap_capabilities)
+            .delegated_capabilities(Capability::all())
+            .domains(Domain::all())
+            .create(&client)
+            .unwrap();
+
+        client.import_wrapped(1, backup.clone()).unwrap();
+        assert_eq!(client.get_opaque(0x100).unwrap(), b"backed up");
+    }
+
+    let err = SplitWrapKey::recover(&shares[..2]).unwrap_err();
+    assert!(err.to_string().contains("3 shares are needed"), "{}", err);
+
+    let err = SplitWrapKey::recover(&[shares[0].clone(), shares[0].clone(), shares[1].clone()])
+        .unwrap_err();
+    assert!(err.to_string().contains("given more than once"), "{}", err);
+
+    let other = SplitWrapKey::generate(2, wrap::Algorithm::Aes128Ccm, 2, 2).unwrap();
+    let err = SplitWrapKey::recover(&[shares[0].clone(), other.shares()[0].clone()]).unwrap_err();
+    assert!(err.to_string().contains("doesn't belong"), "{}", err);
+
+    // Mistyped shares are caught by the checksum
+    let encoded = split.shares()[0].encode();
+    let last = encoded.len() - 10;
+    let mistyped = format!(
+        "{}{}{}",
+        &encoded[..last],
+        if &encoded[last..=last] == "0" {
+            "1"
+        } else {
+            "0"
+        },
+        &encoded[last + 1..]
+    );
+    let err = mistyped.parse::<Share>().unwrap_err();
+    assert!(err.to_string().contains("checksum mismatch"), "{}", err);
+
+    // Shares with valid checksums but malformed fields are rejected
+    let with_checksum = |body: &str| format!("{}-{}", body, &hex_sha256(body.as_bytes())[..8]);
+    let body = encoded.rsplit_once('-').unwrap().0;
+
+    for malformed in [
+        "0001-3-1-aé".to_owned(),
+        "0001-3-1-éa".to_owned(),
+        format!("{body}-00"),
+        "0001-3-1".to_owned(),
+        "0001-0-1-00".to_owned(),
+    ] {
+        let err = with_checksum(&malformed).parse::<Share>().unwrap_err();
+        assert!(err.to_string().contains("malformed share"), "{}", err);
+    }
+
+    assert!(SplitWrapKey::generate(1, wrap::Algorithm::Aes256Ccm, 3, 2).is_err());
+    assert!(SplitWrapKey::generate(1, wrap::Algorithm::Aes256Ccm, 0, 2).is_err());
+}
+
+#[cfg(all(feature = "setup", feature = "mockhsm"))]
+#[test]
+fn split_wrap_key_ceremony_test() {
+    use yubihsm::{
+        opaque,
+        setup::{self, SplitWrapKey},
+        wrap, Client, Connector,
+    };
+
+    let admin_credentials = Credentials::from_password(2, b"admin password");
+    let admin = || {
+        Role::new(admin_credentials.clone())
+            .capabilities(Capability::all())
+            .delegated_capabilities(Capability::all())
+            .domains(Domain::all())
+    };
+    let wrap_capabilities = Capability::EXPORT_WRAPPED | Capability::IMPORT_WRAPPED;
+
+    let split = SplitWrapKey::generate(0x10, wrap::Algorithm::Aes256Ccm, 2, 3)
+        .unwrap()
+        .label("backup".into())
+        .capabilities(wrap_capabilities)
+        .delegated_capabilities(Capability::all())
+        .domains(Domain::all());
+    let shares = split.shares().to_vec();
+
+    let connector = Connector::mockhsm();
+    let client = Client::open(connector.clone(), Credentials::default(), false).unwrap();
+    let profile = Profile::default()
+        .roles(vec![admin()])
+        .split_wrap_keys(vec![split]);
+    let report = setup::init_with_profile(client, profile.clone()).unwrap();
+
+    assert_eq!(report.split_wrap_keys.len(), 1);
+    assert_eq!(report.split_wrap_keys[0].wrap_key_id, 0x10);
+    assert_eq!(report.split_wrap_keys[0].label, "backup");
+    assert_eq!(report.split_wrap_keys[0].threshold, 2);
+    assert_eq!(report.split_wrap_keys[0].shares, 3);
+
+    // The report never contains the shares
+    for share in &shares {
+        assert!(!report.to_json().contains(share.encode().as_str()));
+    }
+
+    let client = Client::open(connector, admin_credentials.clone(), false).unwrap();
+    assert!(profile.plan(&client).unwrap().is_empty());
+
+    client
+        .put_opaque(
+            0x100,
+            "secret".into(),
+            Domain::DOM1,
+            Capability::GET_OPAQUE | Capability::EXPORTABLE_UNDER_WRAP,
+            opaque::Algorithm::Data,
+            b"backed up".to_vec(),
+        )
+        .unwrap();
+    let backup = client
+        .export_wrapped(0x10, object::Type::Opaque, 0x100)
+        .unwrap();
+
+    // Recover the wrap key from two of the shares on another device, and
+    // restore the backup there
+    let recovered = SplitWrapKey::recover(&[shares[2].clone(), shares[0].clone()])
+        .unwrap()
+        .label("backup".into())
+        .capabilities(wrap_capabilities)
+        .delegated_capabilities(Capability::all())
+        .domains(Domain::all());
+
+    let connector = Connector::mockhsm();
+    let client = Client::open(connector.clone(), Credentials::default(), false).unwrap();
+    let profile = Profile::default()
+        .roles(vec![admin()])
+        .wrap_keys(vec![recovered]);
+    setup::init_with_profile(client, profile).unwrap();
+
+    let client = Client::open(connector, admin_credentials, false).unwrap();
+    client.import_wrapped(0x10, backup).unwrap();
+    assert_eq!(client.get_opaque(0x100).unwrap(), b"backed up");
+}
+
+#[cfg(all(feature = "setup", feature = "mockhsm"))]
+#[test]
+fn report_load_verify_diff_test() {
+    use yubihsm::{
+        asymmetric,
+        setup::{self, report::Discrepancy, Report, Setting},
+        AuditOption, Client, Connector,
+    };
+
+    let admin_credentials = Credentials::from_password(2, b"admin password");
+    let admin = Role::new(admin_credentials.clone())
+        .capabilities(Capability::all())
+        .domains(Domain::all());
+
+    let objects = vec![
+        setup::Object::generate_asymmetric(0x100, asymmetric::Algorithm::Ed25519)
+            .label("signing key")
+            .capabilities(Capability::SIGN_EDDSA)
+            .domains(Domain::DOM1),
+        setup::Object::generate_asymmetric(0x101, asymmetric::Algorithm::EcP256)
+            .label("report signing key")
+            .capabilities(Capability::SIGN_ECDSA)
+            .domains(Domain::DOM1),
+    ];
+
+    let connector = Connector::mockhsm();
+    let client = Client::open(connector.clone(), Credentials::default(), false).unwrap();
+    let profile = Profile::default()
+        .roles(vec![admin])
+        .objects(objects)
+        .report_signing_key_id(Some(0x101));
+    let report = setup::init_with_profile(client, profile).unwrap();
+    assert_eq!(report.signature.as_ref().unwrap().key_id, 0x101);
+
+    let client = Client::open(connector, admin_credentials, false).unwrap();
+    let stored = Report::load(&client, 0xfffe).unwrap();
+    assert_eq!(stored.uuid, report.uuid);
+    stored.verify_signature(&client).unwrap();
+    assert!(stored.diff(&client).unwrap().is_empty());
+
+    // Tampering with the report invalidates its signature
+    let mut tampered = stored.clone();
+    tampered.objects[0].label = "other key".to_owned();
+    let err = tampered.verify_signature(&client).unwrap_err();
+    assert_eq!(*err.kind(), setup::ErrorKind::SignatureInvalid);
+
+    let mut unsigned = stored.clone();
+    unsigned.signature = None;
+    assert!(unsigned.verify_signature(&client).is_err());
+
+    // The stored report is the exact JSON which was signed, so equivalent
+    // JSON which isn't byte-for-byte identical doesn't verify
+    let json = String::from_utf8(client.get_opaque(0xfffe).unwrap()).unwrap();
+    assert_eq!(json, stored.to_json());
+    assert_eq!(json.parse::<Report>().unwrap().to_json(), json);
+
+    let reformatted = json
+        .replacen("{\\\"version\\\"", "{ \\\"version\\\"", 1)
+        .parse::<Report>()
+        .unwrap();
+    assert_ne!(reformatted.to_json(), json);
+    let err = reformatted.verify_signature(&client).unwrap_err();
+    assert_eq!(*err.kind(), setup::ErrorKind::SignatureInvalid);
+
+    // Changes to the device show up in the diff
+    client
+        .delete_object(0x100, object::Type::AsymmetricKey)
+        .unwrap();
+    client
+        .generate_asymmetric_key(
+            0x100,
+            "replaced key".into(),
+            Domain::DOM1,
+            Capability::SIGN_EDDSA,
+            asymmetric::Algorithm::Ed25519,
+        )
+        .unwrap();
+    client.set_force_audit_option(AuditOption::On).unwrap();
+
+    let discrepancies = stored.diff(&client).unwrap();
+    let handle = object::Handle::new(0x100, object::Type::AsymmetricKey);
+    assert_eq!(
+        discrepancies,
+        vec![
+            Discrepancy::Label {
+                handle: handle.clone(),
+                reported: "signing key".to_owned(),
+                actual: "replaced key".to_owned(),
+            },
+            Discrepancy::PublicKey { handle },
+            Discrepancy::Setting {
+                setting: Setting::ForceAudit,
+                reported: "off".to_owned(),
+                actual: "on".to_owned(),
+            },
+        ]
+    );
+    assert_eq!(
+        discrepancies[0].to_string(),
+        "asymmetric-key 0x0100 label: reported \"signing key\", actual \"replaced key\""
+    );
+
+    client
+        .delete_object(0x101, object::Type::AsymmetricKey)
+        .unwrap();
+    assert!(stored
+        .diff(&client)
+        .unwrap()
+        .contains(&Discrepancy::Missing {
+            handle: object::Handle::new(0x101, object::Type::AsymmetricKey)
+        }));
+    assert!(stored.verify_signature(&client).is_err());
+
+    // Reports from newer versions of this crate are rejected
+    let json = unsigned
+        .to_json()
+        .replacen("\"version\":4", "\"version\":99", 1);
+    let err = json.parse::<Report>().unwrap_err();
+    assert!(
+        err.to_string().contains("unsupported report version 99"),
+        "{}",
+        err
+    );
+}
+
+#[cfg(all(feature = "setup", feature = "mockhsm", feature = "argon2"))]
+#[test]
+fn role_password_kdf_test() {
+    use yubihsm::{
+        authentication::{kdf::Salt, Kdf},
+        setup::{self, Report},
+        Client, Connector,
+    };
+
+    std::env::set_var("KDF_TEST_ADMIN_PASSWORD", "correct horse");
+    std::env::set_var("KDF_TEST_AUDITOR_PASSWORD", "battery staple");
+
+    let toml = r#"
+[[roles]]
+authentication_key_id = 2
+credentials = { password_env = "KDF_TEST_ADMIN_PASSWORD" }
+kdf = { algorithm = "argon2id", memory_kib = 1024, iterations = 1 }
+capabilities = "all"
+delegated_capabilities = "all"
+domains = "all"
+
+[[roles]]
+authentication_key_id = 3
+credentials = { password_env = "KDF_TEST_AUDITOR_PASSWORD" }
+capabilities = ["get-log-entries"]
+domains = "all"
+"#;
+
+    let connector = Connector::mockhsm();
+    let client = Client::open(connector.clone(), Credentials::default(), false).unwrap();
+    let profile = Profile::from_toml(toml).unwrap();
+    setup::init_with_profile(client, profile).unwrap();
+
+    // Only roles with a KDF are recorded, along with their generated salt
+    let auditor = Credentials::from_password(3, b"battery staple");
+    let client = Client::open(connector.clone(), auditor, false).unwrap();
+    let report = Report::load(&client, 0xfffe).unwrap();
+    assert_eq!(report.password_kdfs.len(), 1);
+    assert_eq!(report.password_kdfs[0].authentication_key_id, 2);
+
+    let kdf = &report.password_kdfs[0].kdf;
+    match kdf {
+        Kdf::Argon2id {
+            salt,
+            memory_kib,
+            iterations,
+            parallelism,
+        } => {
+            assert_eq!(salt.as_slice().len(), 16);
+            assert_eq!((*memory_kib, *iterations, *parallelism), (1024, 1, 1));
+        }
+        other => panic!("unexpected KDF: {other:?}"),
+    }
+
+    // The key can be derived from the password again using the report
+    let admin = Credentials::from_password_with_kdf(2, b"correct horse", kdf).unwrap();
+    assert!(Client::open(connector.clone(), admin, false).is_ok());
+
+    let pbkdf2_admin = Credentials::from_password(2, b"correct horse");
+    assert!(Client::open(connector, pbkdf2_admin, false).is_err());
+
+    // A given salt is used as-is
+    let profile = Profile::from_toml(&toml.replace(
+        "iterations = 1 }",
+        "iterations = 1, salt = \"000102030405060708090a0b0c0d0e0f\" }",
+    ))
+    .unwrap();
+
+    let connector = Connector::mockhsm();
+    let client = Client::open(connector.clone(), Credentials::default(), false).unwrap();
+    setup::init_with_profile(client, profile).unwrap();
+
+    let kdf = Kdf::Argon2id {
+        salt: Salt::new((0..16).collect::<Vec<u8>>()),
+        memory_kib: 1024,
+        iterations: 1,
+        parallelism: 1,
+    };
+    let admin = Credentials::from_password_with_kdf(2, b"correct horse", &kdf).unwrap();
+    assert!(Client::open(connector, admin, false).is_ok());
+
+    // KDFs only apply to passwords
+    std::env::set_var("KDF_TEST_ADMIN_KEY", "42".repeat(32));
+    let err = Profile::from_toml(&toml.replace(
+        "password_env = \"KDF_TEST_ADMIN_PASSWORD\"",
+        "key_env = \"KDF_TEST_ADMIN_KEY\"",
+    ))
+    .unwrap_err();
+    assert!(
+        err.to_string()
+            .contains("role 0x0002: kdf only applies to password credentials"),
+        "{}",
+        err
+    );
+}
+
+/// Reports recording Argon2id and scrypt KDFs can be read by builds without
+/// the `argon2` and `scrypt` cargo features
+#[cfg(feature = "setup")]
+#[test]
+fn report_password_kdf_features_test() {
+    use yubihsm::{
+        authentication::{kdf::Salt, Kdf},
+        device::SerialNumber,
+        setup::{report::PasswordKdf, Report},
+    };
+
+    let salt = Salt::new((0..16).collect::<Vec<u8>>());
+    let argon2id = Kdf::Argon2id {
+        salt: salt.clone(),
+        memory_kib: 1024,
+        iterations: 1,
+        parallelism: 1,
+    };
+    let scrypt = Kdf::Scrypt {
+        salt,
+        log_n: 10,
+        r: 8,
+        p: 1,
+    };
+
+    let mut report = Report::new("0012345678".parse::<SerialNumber>().unwrap());
+    report.password_kdfs = vec![
+        PasswordKdf {
+            authentication_key_id: 2,
+            kdf: argon2id.clone(),
+        },
+        PasswordKdf {
+            authentication_key_id: 3,
+            kdf: scrypt.clone(),
+        },
+    ];
+
+    let parsed = report.to_json().parse::<Report>().unwrap();
+    assert_eq!(parsed.password_kdfs[0].kdf, argon2id);
+    assert_eq!(parsed.password_kdfs[1].kdf, scrypt);
+
+    for (kdf, enabled) in [
+        (argon2id, cfg!(feature = "argon2")),
+        (scrypt, cfg!(feature = "scrypt")),
+    ] {
+        match kdf.derive_key(b"password") {
+            Ok(_) => assert!(enabled),
+            Err(e) => {
+                assert!(!enabled, "{}", e);
+                assert_eq!(*e.kind(), authentication::ErrorKind::KdfUnsupported);
+            }
+        }
+    }
+}
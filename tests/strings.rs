@@ -0,0 +1,67 @@
+//! Tests for the string forms of capabilities, domains, algorithms and
+//! object types, as used by yubihsm-shell
+
+use yubihsm::{asymmetric, object, wrap, Algorithm, Capability, Domain};
+
+#[test]
+fn capability_string_test() {
+    let caps = Capability::SIGN_ECDSA | Capability::EXPORTABLE_UNDER_WRAP;
+    assert_eq!(caps.to_string(), "sign-ecdsa,exportable-under-wrap");
+    assert_eq!("sign-ecdsa,exportable-under-wrap".parse(), Ok(caps));
+    assert_eq!(" exportable-under-wrap , sign-ecdsa ".parse(), Ok(caps));
+
+    assert_eq!(Capability::all().to_string(), "all");
+    assert_eq!("all".parse(), Ok(Capability::all()));
+    assert_eq!(Capability::empty().to_string(), "none");
+    assert_eq!("none".parse(), Ok(Capability::empty()));
+    assert_eq!("".parse(), Ok(Capability::empty()));
+
+    // Capabilities without a name are written in hex
+    let unknown = Capability::SIGN_PKCS | Capability::UNKNOWN_CAPABILITY_63;
+    assert_eq!(unknown.to_string(), "sign-pkcs,0x8000000000000000");
+    assert_eq!(unknown.to_string().parse(), Ok(unknown));
+
+    assert_eq!("sign-everything".parse::<Capability>(), Err(()));
+    assert_eq!("sign-ecdsa,".parse::<Capability>(), Err(()));
+}
+
+#[test]
+fn domain_string_test() {
+    let domains = Domain::DOM1 | Domain::DOM2 | Domain::DOM5;
+    assert_eq!(domains.to_string(), "1,2,5");
+    assert_eq!("1,2,5".parse::<Domain>().unwrap(), domains);
+    assert_eq!("5, 1, 2".parse::<Domain>().unwrap(), domains);
+
+    assert_eq!(Domain::all().to_string(), "all");
+    assert_eq!("all".parse::<Domain>().unwrap(), Domain::all());
+    assert_eq!(Domain::empty().to_string(), "none");
+    assert_eq!("none".parse::<Domain>().unwrap(), Domain::empty());
+
+    for invalid in ["0", "17", "one", "1,,2"] {
+        assert!(invalid.parse::<Domain>().is_err(), "{}", invalid);
+    }
+}
+
+#[test]
+fn algorithm_string_test() {
+    let ecp256 = Algorithm::Asymmetric(asymmetric::Algorithm::EcP256);
+    assert_eq!(ecp256.to_string(), "ecp256");
+    assert_eq!("ecp256".parse::<Algorithm>().unwrap(), ecp256);
+
+    let wrap = Algorithm::Wrap(wrap::Algorithm::Aes256Ccm);
+    assert_eq!(wrap.to_string(), "aes256-ccm-wrap");
+    assert_eq!("aes256-ccm-wrap".parse::<Algorithm>().unwrap(), wrap);
+
+    let err = "ed448".parse::<Algorithm>().unwrap_err();
+    assert_eq!(*err.kind(), yubihsm::algorithm::ErrorKind::NameInvalid);
+}
+
+#[test]
+fn object_type_string_test() {
+    assert_eq!(object::Type::AsymmetricKey.to_string(), "asymmetric-key");
+    assert_eq!(
+        "asymmetric-key".parse::<object::Type>(),
+        Ok(object::Type::AsymmetricKey)
+    );
+    assert_eq!("symmetric-key".parse::<object::Type>(), Err(()));
+}
@@ -0,0 +1,213 @@
+//! HTTPS and mutual TLS tests, using the `yubihsm-connector` compatible
+//! HTTP server backed by a MockHsm
+
+#![cfg(all(feature = "tls", feature = "http-server", feature = "mockhsm"))]
+
+use rcgen::{
+    BasicConstraints, CertificateParams, CertifiedIssuer, DnType, IsCa, KeyPair, SigningKey,
+};
+use std::{fs, path::PathBuf, process, thread};
+use yubihsm::{
+    connector::{
+        http::{AccessPolicy, Server},
+        TlsConfig,
+    },
+    Client, Connector, HttpConfig,
+};
+
+/// Message to send to the HSM
+const TEST_MESSAGE: &[u8] = b"encrypted and authenticated";
+
+/// Certificates and keys written to a temporary directory
+struct Pki {
+    /// CA certificate
+    ca_cert: PathBuf,
+
+    /// Server certificate and key (for `localhost`)
+    server: (PathBuf, PathBuf),
+
+    /// Client certificate and key
+    client: (PathBuf, PathBuf),
+
+    /// Certificate of an unrelated CA
+    other_ca_cert: PathBuf,
+}
+
+impl Pki {
+    /// Generate a CA which issues a server and client certificate
+    fn generate(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("yubihsm-tls-{}-{}", name, process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let ca = generate_ca("yubihsm.rs test CA");
+        let other_ca = generate_ca("untrusted CA");
+
+        let write = |filename: &str, contents: String| {
+            let path = dir.join(filename);
+            fs::write(&path, contents).unwrap();
+            path
+        };
+
+        let issue = |subject_alt_names: Vec<String>| {
+            let key = KeyPair::generate().unwrap();
+            let cert = CertificateParams::new(subject_alt_names)
+                .unwrap()
+                .signed_by(&key, &ca)
+                .unwrap();
+            (cert.pem(), key.serialize_pem())
+        };
+
+        let (server_cert, server_key) = issue(vec!["localhost".to_owned()]);
+        let (client_cert, client_key) = issue(vec!["client.example.com".to_owned()]);
+
+        Self {
+            ca_cert: write("ca.pem", ca.pem()),
+            server: (
+                write("server.pem", server_cert),
+                write("server.key", server_key),
+            ),
+            client: (
+                write("client.pem", client_cert),
+                write("client.key", client_key),
+            ),
+            other_ca_cert: write("other-ca.pem", other_ca.pem()),
+        }
+    }
+
+    /// Start a server on the given port which requires client certificates
+    fn start_server(&self, port: u16) {
+        self.start_server_with_policy(port, AccessPolicy::default());
+    }
+
+    /// Start a server on the given port with the given access policy
+    fn start_server_with_policy(&self, port: u16, policy: AccessPolicy) {
+        let config = HttpConfig {
+            port,
+            tls: Some(TlsConfig {
+                ca_cert: Some(self.ca_cert.clone()),
+                cert: Some(self.server.0.clone()),
+                key: Some(self.server.1.clone()),
+                server_name: None,
+            }),
+            ..Default::default()
+        };
+
+        let server = Server::new(&config, Connector::mockhsm()).unwrap();
+        server.set_access_policy(&policy).unwrap();
+        thread::spawn(move || server.run());
+    }
+
+    /// Client configuration for a server on the given port
+    fn client_config(&self, port: u16, client_cert: bool) -> HttpConfig {
+        let (cert, key) = if client_cert {
+            (Some(self.client.0.clone()), Some(self.client.1.clone()))
+        } else {
+            (None, None)
+        };
+
+        HttpConfig {
+            port,
+            tls: Some(TlsConfig {
+                ca_cert: Some(self.ca_cert.clone()),
+                cert,
+                key,
+                server_name: Some("localhost".to_owned()),
+            }),
+            ..Default::default()
+        }
+    }
+}
+
+/// Generate a self-signed CA certificate
+fn generate_ca(name: &str) -> CertifiedIssuer<'static, impl SigningKey> {
+    let mut params = CertificateParams::new(vec![]).unwrap();
+    params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    params.distinguished_name.push(DnType::CommonName, name);
+    CertifiedIssuer::self_signed(params, KeyPair::generate().unwrap()).unwrap()
+}
+
+#[test]
+fn mutual_tls_test() {
+    let pki = Pki::generate("mutual");
+    pki.start_server(12361);
+
+    let config = pki.client_config(12361, true);
+    assert_eq!(config.to_string(), "https://127.0.0.1:12361");
+
+    let client = Client::open(Connector::http(&config), Default::default(), true).unwrap();
+    assert_eq!(client.echo(TEST_MESSAGE).unwrap(), TEST_MESSAGE);
+    assert!(client.device_info().is_ok());
+}
+
+#[test]
+fn missing_client_certificate_test() {
+    let pki = Pki::generate("anonymous");
+    pki.start_server(12362);
+
+    let config = pki.client_config(12362, false);
+    assert!(Client::open(Connector::http(&config), Default::default(), true).is_err());
+}
+
+#[test]
+fn untrusted_server_test() {
+    let pki = Pki::generate("untrusted");
+    pki.start_server(12363);
+
+    let mut config = pki.client_config(12363, true);
+    config.tls.as_mut().unwrap().ca_cert = Some(pki.other_ca_cert.clone());
+    assert!(Client::open(Connector::http(&config), Default::default(), true).is_err());
+}
+
+#[test]
+fn server_name_mismatch_test() {
+    let pki = Pki::generate("mismatch");
+    pki.start_server(12364);
+
+    let mut config = pki.client_config(12364, true);
+    config.tls.as_mut().unwrap().server_name = Some("hsm.example.com".to_owned());
+    assert!(Client::open(Connector::http(&config), Default::default(), true).is_err());
+}
+
+#[test]
+fn client_name_test() {
+    let pki = Pki::generate("names");
+
+    pki.start_server_with_policy(
+        12366,
+        AccessPolicy {
+            client_names: vec!["client.example.com".to_owned()],
+            ..Default::default()
+        },
+    );
+
+    pki.start_server_with_policy(
+        12367,
+        AccessPolicy {
+            client_names: vec!["other.example.com".to_owned()],
+            ..Default::default()
+        },
+    );
+
+    let config = pki.client_config(12366, true);
+    let client = Client::open(Connector::http(&config), Default::default(), true).unwrap();
+    assert_eq!(client.echo(TEST_MESSAGE).unwrap(), TEST_MESSAGE);
+
+    let config = pki.client_config(12367, true);
+    assert!(Client::open(Connector::http(&config), Default::default(), true).is_err());
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn async_mutual_tls_test() {
+    use yubihsm::{AsyncClient, AsyncConnector};
+
+    let pki = Pki::generate("async");
+    pki.start_server(12365);
+
+    let config = pki.client_config(12365, true);
+    let client = AsyncClient::open(AsyncConnector::http(&config), Default::default(), true)
+        .await
+        .unwrap();
+
+    assert_eq!(client.echo(TEST_MESSAGE).await.unwrap(), TEST_MESSAGE);
+}
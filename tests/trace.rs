@@ -0,0 +1,104 @@
+//! Tests for decoding captured protocol messages
+
+use yubihsm::{
+    asymmetric, command, device, object, response,
+    session::{
+        self,
+        trace::{self, Code, Value},
+    },
+    Algorithm,
+};
+
+#[test]
+fn create_session_test() {
+    let mut command = vec![0x03, 0x00, 0x0a, 0x00, 0x01];
+    command.extend_from_slice(&[0xaa; 8]);
+
+    let trace = trace::decode_command(&command).unwrap();
+    assert_eq!(trace.code, Code::Command(command::Code::CreateSession));
+    assert_eq!(trace.size, 10);
+    assert_eq!(
+        trace.field("authentication_key_id"),
+        Some(&Value::ObjectId(1))
+    );
+    assert_eq!(trace.field("host_challenge"), Some(&Value::Data(8)));
+    assert_eq!(
+        trace.to_string(),
+        "cmd=CreateSession size=10 authentication_key_id=0x0001 host_challenge=<8 bytes>"
+    );
+
+    let mut response = vec![0x83, 0x00, 0x11, 0x02];
+    response.extend_from_slice(&[0xbb; 16]);
+
+    let trace = trace::decode_response(&response).unwrap();
+    assert_eq!(
+        trace.code,
+        Code::Response(response::Code::Success(command::Code::CreateSession))
+    );
+    assert_eq!(trace.session_id, Some(session::Id::from_u8(2).unwrap()));
+    assert_eq!(trace.field("card_cryptogram"), Some(&Value::Data(8)));
+}
+
+#[test]
+fn device_info_test() {
+    let response = [
+        0x86, 0x00, 0x0b, 0x02, 0x03, 0x00, 0x00, 0x98, 0x96, 0x80, 0x3e, 0x05, 0x2e, 0x2f,
+    ];
+
+    let trace = trace::decode_response(&response).unwrap();
+    assert_eq!(trace.field("major_version"), Some(&Value::Number(2)));
+    assert_eq!(
+        trace.field("serial_number"),
+        Some(&Value::Number(10_000_000))
+    );
+    assert_eq!(trace.field("log_store_used"), Some(&Value::Number(5)));
+    assert_eq!(trace.field("algorithms"), Some(&Value::Data(2)));
+}
+
+#[test]
+fn error_test() {
+    let trace = trace::decode_response(&[0x7f, 0x00, 0x01, 0x0b]).unwrap();
+
+    assert_eq!(
+        trace.field("error"),
+        Some(&Value::Error(device::ErrorKind::ObjectNotFound))
+    );
+    assert_eq!(
+        trace.to_string(),
+        "rsp=MemoryError size=1 error=ObjectNotFound"
+    );
+}
+
+#[test]
+fn redaction_test() {
+    let key = [0x5a; 32];
+
+    let mut command = vec![0x45, 0x00, 0x55, 0x00, 0x64];
+    command.extend_from_slice(&object::Label::from("signing key").0);
+    command.extend_from_slice(&[0x00, 0x01]);
+    command.extend_from_slice(&[0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x80]);
+    command.push(0x2e);
+    command.extend_from_slice(&key);
+
+    let trace = trace::decode_command(&command).unwrap();
+    assert_eq!(trace.field("object_id"), Some(&Value::ObjectId(0x64)));
+    assert_eq!(
+        trace.field("label"),
+        Some(&Value::Label("signing key".to_owned()))
+    );
+    assert_eq!(
+        trace.field("algorithm"),
+        Some(&Value::Algorithm(Algorithm::Asymmetric(
+            asymmetric::Algorithm::Ed25519
+        )))
+    );
+    assert_eq!(trace.field("key"), Some(&Value::Redacted(32)));
+    assert!(trace.to_string().contains("key=<redacted: 32 bytes>"));
+    assert!(!format!("{trace:?}").contains("5a"));
+}
+
+#[test]
+fn malformed_test() {
+    assert!(trace::decode_command(&[0x03, 0x00]).is_err());
+    assert!(trace::decode_response(&[0x86, 0x00, 0x05, 0x02]).is_err());
+}
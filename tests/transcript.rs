@@ -0,0 +1,71 @@
+//! Tests for recording transcripts of exchanges with a MockHsm and replaying
+//! them
+
+#![cfg(feature = "mockhsm")]
+
+use std::{fs, path::PathBuf, process};
+use yubihsm::{
+    connector::transcript::Replay, object, opaque, Capability, Client, Connector, Domain,
+};
+
+/// Opaque object stored during the recording
+const OBJECT_ID: object::Id = 100;
+
+/// Data stored in the opaque object
+const OBJECT_DATA: &[u8] = b"recorded opaque data";
+
+/// Path to a transcript in a temporary directory
+fn transcript_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("yubihsm-{}-{}.transcript", name, process::id()))
+}
+
+/// Operations performed while recording and replaying
+fn exercise(connector: Connector, message: &[u8]) -> Result<(), yubihsm::client::Error> {
+    let client = Client::open(connector, Default::default(), true)?;
+
+    client.put_opaque(
+        OBJECT_ID,
+        "transcript".into(),
+        Domain::DOM1,
+        Capability::default(),
+        opaque::Algorithm::Data,
+        OBJECT_DATA,
+    )?;
+
+    assert_eq!(client.get_opaque(OBJECT_ID)?, OBJECT_DATA);
+    assert_eq!(client.echo(message)?, message);
+    Ok(())
+}
+
+/// Record a transcript of `exercise` against a MockHsm
+fn record(name: &str) -> PathBuf {
+    let path = transcript_path(name);
+    let connector = Connector::record(Connector::mockhsm(), &path).unwrap();
+    exercise(connector, b"hello").unwrap();
+    path
+}
+
+#[test]
+fn replay_test() {
+    let path = record("replay");
+
+    let replay = Replay::open(&path).unwrap();
+    assert!(replay.remaining() > 0);
+
+    exercise(replay.connector(), b"hello").unwrap();
+    replay.finish().unwrap();
+
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn divergence_test() {
+    let path = record("divergence");
+
+    let replay = Replay::open(&path).unwrap();
+    let err = exercise(replay.connector(), b"goodbye").unwrap_err();
+    assert!(err.to_string().contains("diverged"), "{}", err);
+    assert!(replay.finish().is_err());
+
+    fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,153 @@
+//! Tests for speaking the `yubihsm-connector` protocol over a Unix domain
+//! socket, using the HTTP server backed by a MockHsm
+
+#![cfg(all(unix, feature = "http-server", feature = "mockhsm"))]
+
+use std::{
+    fs,
+    os::unix::{fs::PermissionsExt, net::UnixListener},
+    process, thread,
+};
+use yubihsm::{connector::http::Server, Client, Connector, HttpConfig};
+
+/// Message to send to the HSM
+const TEST_MESSAGE: &[u8] = b"over a unix socket";
+
+/// Configuration for a Unix socket in the temp directory
+fn socket_config(name: &str) -> HttpConfig {
+    let socket = std::env::temp_dir().join(format!("yubihsm-{}-{}.sock", name, process::id()));
+
+    HttpConfig {
+        socket: Some(socket),
+        ..Default::default()
+    }
+}
+
+/// Start a MockHsm-backed server on the given socket
+fn start_server(config: &HttpConfig) {
+    let server = Server::new(config, Connector::mockhsm()).unwrap();
+    thread::spawn(move || server.run());
+}
+
+#[test]
+fn unix_socket_test() {
+    let config = socket_config("client");
+    start_server(&config);
+
+    let socket = config.socket.as_ref().unwrap();
+    assert_eq!(config.to_string(), format!("unix://{}", socket.display()));
+
+    let client = Client::open(Connector::http(&config), Default::default(), true).unwrap();
+    assert_eq!(client.echo(TEST_MESSAGE).unwrap(), TEST_MESSAGE);
+    assert!(client.device_info().is_ok());
+}
+
+#[test]
+fn stale_socket_test() {
+    let config = socket_config("stale");
+
+    // Leave a socket behind from a server which is no longer running
+    let socket = config.socket.as_ref().unwrap();
+    drop(UnixListener::bind(socket).unwrap());
+
+    start_server(&config);
+    let client = Client::open(Connector::http(&config), Default::default(), true).unwrap();
+    assert_eq!(client.echo(TEST_MESSAGE).unwrap(), TEST_MESSAGE);
+
+    // Refuse to take over a socket which is in use
+    assert!(Server::new(&config, Connector::mockhsm()).is_err());
+}
+
+#[test]
+fn non_socket_path_test() {
+    let config = socket_config("file");
+    let path = config.socket.as_ref().unwrap();
+    fs::write(path, b"not a socket").unwrap();
+
+    assert!(Server::new(&config, Connector::mockhsm()).is_err());
+    assert_eq!(fs::read(path).unwrap(), b"not a socket");
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn socket_mode_test() {
+    let config = HttpConfig {
+        socket_mode: Some(0o660),
+        ..socket_config("mode")
+    };
+
+    let server = Server::new(&config, Connector::mockhsm()).unwrap();
+    let path = config.socket.as_ref().unwrap();
+    let mode = fs::metadata(path).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o660);
+
+    // The private directory the socket was created in is removed
+    let dir = path.with_file_name(format!(
+        ".{}.{}",
+        path.file_name().unwrap().to_string_lossy(),
+        process::id()
+    ));
+    assert!(!dir.exists());
+
+    let shutdown = server.shutdown_handle();
+    let handle = thread::spawn(move || server.run());
+
+    let client = Client::open(Connector::http(&config), Default::default(), true).unwrap();
+    assert_eq!(client.echo(TEST_MESSAGE).unwrap(), TEST_MESSAGE);
+    drop(client);
+
+    // The socket is removed when the server shuts down
+    shutdown.shutdown();
+    assert!(!path.exists());
+    handle.join().unwrap().unwrap();
+
+    // ...or is dropped
+    let server = Server::new(&config, Connector::mockhsm()).unwrap();
+    assert!(path.exists());
+    drop(server);
+    assert!(!path.exists());
+}
+
+#[test]
+fn socket_mode_bind_error_test() {
+    let config = HttpConfig {
+        socket: Some(
+            std::env::temp_dir().join(format!("yubihsm-missing-{}/s.sock", process::id())),
+        ),
+        socket_mode: Some(0o600),
+        ..Default::default()
+    };
+
+    assert!(Server::new(&config, Connector::mockhsm()).is_err());
+}
+
+#[test]
+fn socket_removed_on_shutdown_test() {
+    let config = socket_config("shutdown");
+    let server = Server::new(&config, Connector::mockhsm()).unwrap();
+    let shutdown = server.shutdown_handle();
+    let handle = thread::spawn(move || server.run());
+
+    let client = Client::open(Connector::http(&config), Default::default(), true).unwrap();
+    assert_eq!(client.echo(TEST_MESSAGE).unwrap(), TEST_MESSAGE);
+
+    shutdown.shutdown();
+    assert!(!config.socket.as_ref().unwrap().exists());
+    drop(client);
+    handle.join().unwrap().unwrap();
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn async_unix_socket_test() {
+    use yubihsm::{AsyncClient, AsyncConnector};
+
+    let config = socket_config("async");
+    start_server(&config);
+
+    let client = AsyncClient::open(AsyncConnector::http(&config), Default::default(), true)
+        .await
+        .unwrap();
+
+    assert_eq!(client.echo(TEST_MESSAGE).await.unwrap(), TEST_MESSAGE);
+}